@@ -2,6 +2,10 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
 use vergen;
 
 fn main() {
@@ -9,4 +13,59 @@ fn main() {
     flags.toggle(vergen::ConstantsFlags::SEMVER_FROM_CARGO_PKG);
     vergen::generate_cargo_keys(vergen::ConstantsFlags::all())
         .expect("Unable to generate the cargo keys!");
+
+    if env::var("CARGO_FEATURE_BPF_CORE").is_ok() {
+        compile_bpf_core("src/samplers/tcp/bpf_core.c", "tcp.bpf.o");
+    }
+}
+
+// compiles a CO-RE bpf program into an object in OUT_DIR so that hosts do not
+// need kernel headers or clang at runtime
+fn compile_bpf_core(source: &str, object: &str) {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let vmlinux = out_dir.join("vmlinux.h");
+    if !vmlinux.exists() {
+        let output = Command::new("bpftool")
+            .args(&[
+                "btf",
+                "dump",
+                "file",
+                "/sys/kernel/btf/vmlinux",
+                "format",
+                "c",
+            ])
+            .output()
+            .expect("Unable to run bpftool to generate vmlinux.h!");
+        if !output.status.success() {
+            panic!("Unable to generate vmlinux.h from kernel BTF!");
+        }
+        std::fs::write(&vmlinux, output.stdout).expect("Unable to write vmlinux.h!");
+    }
+
+    let arch = match env::var("CARGO_CFG_TARGET_ARCH").unwrap().as_str() {
+        "x86_64" => "x86".to_string(),
+        "aarch64" => "arm64".to_string(),
+        other => other.to_string(),
+    };
+
+    let status = Command::new("clang")
+        .arg("-g")
+        .arg("-O2")
+        .arg("-target")
+        .arg("bpf")
+        .arg(format!("-D__TARGET_ARCH_{}", arch))
+        .arg("-I")
+        .arg(&out_dir)
+        .arg("-c")
+        .arg(source)
+        .arg("-o")
+        .arg(out_dir.join(object))
+        .status()
+        .expect("Unable to run clang to compile bpf program!");
+    if !status.success() {
+        panic!("Unable to compile bpf program: {}", source);
+    }
+
+    println!("cargo:rerun-if-changed={}", source);
 }
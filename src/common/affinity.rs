@@ -0,0 +1,72 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+/// The CPUs which are currently online
+const ONLINE: &str = "/sys/devices/system/cpu/online";
+
+/// Restrict the calling thread to the listed CPUs. Threads inherit the
+/// affinity of the thread which starts them, so calling this before the
+/// runtime and exporters are started confines all of Rezolus to these CPUs.
+/// Each CPU must be online, as the kernel would otherwise silently ignore it.
+pub fn set_cpu_affinity(cpus: &[usize]) -> Result<(), anyhow::Error> {
+    let content = std::fs::read_to_string(ONLINE)
+        .map_err(|e| format_err!("failed to read {}: {}", ONLINE, e))?;
+    let online =
+        parse_cpu_list(&content).ok_or_else(|| format_err!("failed to parse {}", ONLINE))?;
+    for cpu in cpus {
+        if !online.contains(cpu) {
+            return Err(format_err!(
+                "general.cpu_affinity contains cpu {} which is not online, the online cpus are: {}",
+                cpu,
+                content.trim()
+            ));
+        }
+    }
+    // safety: the set is a plain bitmask which is fully initialized before the
+    // kernel reads it, and each cpu is below CPU_SETSIZE as checked by the
+    // config validation
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in cpus {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(format_err!(
+            "failed to set cpu affinity: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a list of CPUs in the format the kernel writes them, eg: `0-3,8`
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let start: usize = bounds.next()?.parse().ok()?;
+        let end: usize = match bounds.next() {
+            Some(end) => end.parse().ok()?,
+            None => start,
+        };
+        cpus.extend(start..=end);
+    }
+    Some(cpus)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0\n"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-3\n"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cpu_list("0-1,4,6-7\n"), Some(vec![0, 1, 4, 6, 7]));
+        assert_eq!(parse_cpu_list("\n"), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-a\n"), None);
+    }
+}
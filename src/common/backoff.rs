@@ -0,0 +1,78 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::time::{Duration, Instant};
+
+/// Schedules retries of a failing operation, such as BPF initialization, with
+/// a delay which doubles after each failure up to a maximum.
+pub struct Backoff {
+    delay: Duration,
+    max: Duration,
+    next: Instant,
+}
+
+impl Backoff {
+    /// The first retry is due `base` after `now`
+    pub fn new(now: Instant, base: Duration, max: Duration) -> Self {
+        Self {
+            delay: base,
+            max,
+            next: now + base,
+        }
+    }
+
+    /// Whether the next attempt is due
+    pub fn due(&self, now: Instant) -> bool {
+        now >= self.next
+    }
+
+    /// Record the outcome of an attempt made at `now`. A failure doubles the
+    /// delay before the next attempt, up to the maximum.
+    pub fn record(&mut self, now: Instant, success: bool) {
+        if success {
+            return;
+        }
+        self.delay = std::cmp::min(self.delay * 2, self.max);
+        self.next = now + self.delay;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let start = Instant::now();
+        let mut backoff = Backoff::new(start, Duration::from_secs(1), Duration::from_secs(4));
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // initialization fails twice and then succeeds
+        let mut results = vec![true, false, false];
+        let mut attempts = Vec::new();
+        for secs in 0..10 {
+            if backoff.due(at(secs)) {
+                if let Some(success) = results.pop() {
+                    attempts.push(secs);
+                    backoff.record(at(secs), success);
+                }
+            }
+        }
+
+        // retries at 1s, then 2s later, then 4s later
+        assert_eq!(attempts, vec![1, 3, 7]);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_backoff_max() {
+        let start = Instant::now();
+        let mut backoff = Backoff::new(start, Duration::from_secs(1), Duration::from_secs(4));
+        for _ in 0..10 {
+            backoff.record(start, false);
+        }
+        assert!(!backoff.due(start + Duration::from_secs(3)));
+        assert!(backoff.due(start + Duration::from_secs(4)));
+    }
+}
@@ -2,6 +2,8 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use std::time::Duration;
+
 #[cfg(feature = "bpf")]
 pub struct BPF {
     pub inner: bcc::BPF,
@@ -10,7 +12,99 @@ pub struct BPF {
 #[cfg(not(feature = "bpf"))]
 pub struct BPF {}
 
-#[cfg(feature = "bpf")]
+/// A loaded CO-RE object along with the links which keep its programs
+/// attached. Dropping this detaches the programs.
+#[cfg(feature = "bpf_core")]
+pub struct CoreBPF {
+    pub object: libbpf_rs::Object,
+    pub links: Vec<libbpf_rs::Link>,
+}
+
+/// Self-diagnostic counter for failures to load or read a BPF table, keyed by
+/// the sampler and table name
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+pub struct BpfTableErrorStatistic {
+    name: String,
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+impl BpfTableErrorStatistic {
+    pub fn new(sampler: &str, table: &str) -> Self {
+        Self {
+            name: format!("rezolus/bpf/table_errors/{}/{}", sampler, table),
+        }
+    }
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+impl rustcommon_metrics::Statistic<rustcommon_metrics::AtomicU64, rustcommon_metrics::AtomicU32>
+    for BpfTableErrorStatistic
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> rustcommon_metrics::Source {
+        rustcommon_metrics::Source::Counter
+    }
+}
+
+/// Self-diagnostic gauge of the time, in nanoseconds, between the two most
+/// recent drains of a sampler's BPF maps
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+pub struct BpfDrainStatistic {
+    name: String,
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+impl BpfDrainStatistic {
+    pub fn new(sampler: &str) -> Self {
+        Self {
+            name: format!("rezolus/bpf/drain_elapsed/{}", sampler),
+        }
+    }
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+impl rustcommon_metrics::Statistic<rustcommon_metrics::AtomicU64, rustcommon_metrics::AtomicU32>
+    for BpfDrainStatistic
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> rustcommon_metrics::Source {
+        rustcommon_metrics::Source::Gauge
+    }
+}
+
+/// Whether BPF maps should be drained, see `bpf_drain`
+#[derive(Debug, PartialEq)]
+pub enum BpfDrain {
+    Wait,
+    Drain,
+    /// Drain, but the maps have accumulated for longer than the maximum
+    Stale,
+}
+
+/// Decide whether BPF maps last drained `elapsed` ago should be drained now.
+/// They are drained once the interval has passed, or as soon as they are
+/// checked once `max_staleness` has passed, which happens if the sampler loop
+/// was delayed or if the maximum is shorter than the interval.
+#[allow(dead_code)]
+pub fn bpf_drain(
+    elapsed: Duration,
+    interval: Duration,
+    max_staleness: Option<Duration>,
+) -> BpfDrain {
+    match max_staleness {
+        Some(max) if elapsed > max => BpfDrain::Stale,
+        _ if elapsed >= interval => BpfDrain::Drain,
+        _ => BpfDrain::Wait,
+    }
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core", test))]
 pub fn key_to_value(index: u64) -> Option<u64> {
     let index = index;
     if index < 100 {
@@ -53,46 +147,111 @@ pub fn symbol_lookup(name: &str) -> Option<String> {
     None
 }
 
+/// A BPF histogram map which is read, and cleared, one bucket at a time. The
+/// key of each bucket is its index, see `key_to_value`
+#[cfg(any(feature = "bpf", feature = "bpf_core", test))]
+pub trait HistogramMap {
+    /// The index and count of each bucket
+    fn buckets(&mut self) -> Vec<(u32, u64)>;
+
+    /// Reset the count of a bucket to zero
+    fn clear(&mut self, index: u32);
+}
+
 #[cfg(feature = "bpf")]
-pub fn map_from_table(table: &mut bcc::table::Table) -> std::collections::HashMap<u64, u32> {
+impl HistogramMap for bcc::table::Table {
+    fn buckets(&mut self) -> Vec<(u32, u64)> {
+        let mut buckets = Vec::new();
+        for (id, entry) in self.iter().enumerate() {
+            if entry.key.len() != 4 || entry.value.len() != 8 {
+                // log and skip processing if the entry size is unexpected
+                debug!(
+                    "unexpected size of the entry, entry id: {} key length: {} value length: {}",
+                    id,
+                    entry.key.len(),
+                    entry.value.len()
+                );
+                continue;
+            }
+            buckets.push((parse_u32(entry.key), parse_u64(entry.value)));
+        }
+        buckets
+    }
+
+    fn clear(&mut self, index: u32) {
+        let _ = self.set(&mut index.to_ne_bytes(), &mut [0_u8; 8]);
+    }
+}
+
+#[cfg(feature = "bpf_core")]
+impl HistogramMap for libbpf_rs::Map {
+    fn buckets(&mut self) -> Vec<(u32, u64)> {
+        use libbpf_rs::MapFlags;
+
+        let keys: Vec<Vec<u8>> = self.keys().collect();
+        let mut buckets = Vec::new();
+        for key in keys {
+            if let Ok(Some(value)) = self.lookup(&key, MapFlags::ANY) {
+                buckets.push((parse_u32(key), parse_u64(value)));
+            }
+        }
+        buckets
+    }
+
+    fn clear(&mut self, index: u32) {
+        let _ = self.update(&index.to_ne_bytes(), &[0_u8; 8], libbpf_rs::MapFlags::ANY);
+    }
+}
+
+/// Reads the non-zero buckets of a histogram map as a map of bucket value to
+/// count.
+///
+/// By default each bucket is cleared once it is read, so a read returns only
+/// the values recorded since the previous one. A `cumulative` map is never
+/// cleared, which is for maps whose counts only ever increase until they are
+/// reset by something other than rezolus. For these the counts at this read
+/// are kept in `previous` and the increase since the last read is returned. A
+/// bucket which has gone backwards was reset, and all of its count is new.
+///
+/// The histograms are not per-cpu maps, so there is a single count to read
+/// and clear for each bucket.
+#[cfg(any(feature = "bpf", feature = "bpf_core", test))]
+pub fn drain_histogram<M: HistogramMap>(
+    map: &mut M,
+    previous: Option<&mut std::collections::HashMap<u32, u64>>,
+) -> std::collections::HashMap<u64, u32> {
     use std::collections::HashMap;
 
     let mut current = HashMap::new();
 
     trace!("transferring data to userspace");
-    for (id, mut entry) in table.iter().enumerate() {
-        let mut key = [0; 4];
-        if key.len() != entry.key.len() {
-            // log and skip processing if the key length is unexpected
-            debug!(
-                "unexpected length of the entry's key, entry id: {} key length: {}",
-                id,
-                entry.key.len()
-            );
-            continue;
+    let buckets = map.buckets();
+    match previous {
+        Some(previous) => {
+            for (index, count) in buckets {
+                let last = previous.insert(index, count).unwrap_or(0);
+                let delta = if count >= last { count - last } else { count };
+                if delta == 0 {
+                    continue;
+                }
+                if let Some(value) = key_to_value(index as u64) {
+                    current.insert(value, delta as u32);
+                }
+            }
         }
-        key.copy_from_slice(&entry.key);
-        let key = u32::from_ne_bytes(key);
-
-        let mut value = [0; 8];
-        if value.len() != entry.value.len() {
-            // log and skip processing if the value length is unexpected
-            debug!(
-                "unexpected length of the entry's value, entry id: {} value length: {}",
-                id,
-                entry.value.len()
-            );
-            continue;
-        }
-        value.copy_from_slice(&entry.value);
-        let value = u64::from_ne_bytes(value);
+        None => {
+            for (index, count) in buckets {
+                if count == 0 {
+                    continue;
+                }
+                if let Some(value) = key_to_value(index as u64) {
+                    current.insert(value, count as u32);
+                }
 
-        if let Some(key) = key_to_value(key as u64) {
-            current.insert(key, value as u32);
+                // clear the source counter
+                map.clear(index);
+            }
         }
-
-        // clear the source counter
-        let _ = table.set(&mut entry.key, &mut [0_u8; 8]);
     }
     current
 }
@@ -111,7 +270,53 @@ pub fn perf_table_to_map(table: &bcc::table::Table) -> std::collections::HashMap
     map
 }
 
+/// Reads a per-cpu counter, which is an array with a slot for each possible
+/// cpu, as the sum of the slots. Each cpu counts into its own slot, which
+/// avoids contention on the counters of frequent events. A `BPF_PERCPU_ARRAY`
+/// is not used, as bcc sizes the values it reads to a single cpu.
 #[cfg(feature = "bpf")]
+pub fn per_cpu_total(table: &bcc::table::Table) -> u64 {
+    sum_per_cpu_entries(table.iter().map(|entry| entry.value))
+}
+
+/// Sums the slots of a per-cpu counter. Each slot is padded to a cache line,
+/// of which the count is the first u64.
+#[cfg(any(feature = "bpf", test))]
+fn sum_per_cpu_entries<I>(entries: I) -> u64
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    entries
+        .into_iter()
+        .map(parse_u64)
+        .fold(0_u64, |sum, count| sum.wrapping_add(count))
+}
+
+/// The number of cpus which may ever be brought online, which sizes the
+/// per-cpu counters of the BPF programs. Falls back to the cpus which are
+/// configured.
+#[cfg(feature = "bpf")]
+pub fn possible_cpus() -> usize {
+    std::fs::read_to_string("/sys/devices/system/cpu/possible")
+        .ok()
+        .and_then(|content| parse_cpu_list(&content))
+        // safety: sysconf only reads the configuration
+        .unwrap_or_else(|| unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as usize)
+}
+
+/// The number of cpus in a cpu list such as `0-63` or `0,2-3`, which is one
+/// more than the highest cpu listed
+#[cfg(any(feature = "bpf", test))]
+fn parse_cpu_list(content: &str) -> Option<usize> {
+    let mut highest = None;
+    for range in content.trim().split(',') {
+        let last = range.rsplit('-').next()?.parse::<usize>().ok()?;
+        highest = highest.max(Some(last));
+    }
+    highest.map(|cpu| cpu + 1)
+}
+
+#[cfg(any(feature = "bpf", feature = "bpf_core", test))]
 pub fn parse_u32(x: Vec<u8>) -> u32 {
     let mut v = [0_u8; 4];
     for (i, byte) in v.iter_mut().enumerate() {
@@ -121,7 +326,7 @@ pub fn parse_u32(x: Vec<u8>) -> u32 {
     u32::from_ne_bytes(v)
 }
 
-#[cfg(feature = "bpf")]
+#[cfg(any(feature = "bpf", feature = "bpf_core", test))]
 pub fn parse_u64(x: Vec<u8>) -> u64 {
     let mut v = [0_u8; 8];
     for (i, byte) in v.iter_mut().enumerate() {
@@ -130,3 +335,177 @@ pub fn parse_u64(x: Vec<u8>) -> u64 {
 
     u64::from_ne_bytes(v)
 }
+
+/// The `bpf` syscall command which turns on the run statistics of every BPF
+/// program, added in kernel 5.8
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+const BPF_ENABLE_STATS: libc::c_int = 32;
+
+/// The statistics of `BPF_ENABLE_STATS` which count the runs of each program
+/// and their total time
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+const BPF_STATS_RUN_TIME: u32 = 0;
+
+/// Turn on the run count and time of every BPF program for as long as the
+/// returned file is open, so that they are turned off again when Rezolus
+/// exits. Older kernels only have the global `kernel.bpf_stats_enabled` sysctl.
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+pub fn enable_run_time_stats() -> Result<std::fs::File, std::io::Error> {
+    use std::os::unix::io::FromRawFd;
+
+    #[repr(C)]
+    struct EnableStatsAttr {
+        kind: u32,
+    }
+
+    let attr = EnableStatsAttr {
+        kind: BPF_STATS_RUN_TIME,
+    };
+    // safety: the attr outlives the call, which reads no more than its size
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_ENABLE_STATS,
+            &attr as *const EnableStatsAttr,
+            std::mem::size_of::<EnableStatsAttr>() as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // safety: the descriptor was just created by the kernel and is only
+    // owned by the file
+    Ok(unsafe { std::fs::File::from_raw_fd(fd as libc::c_int) })
+}
+
+/// Checks `/proc/filesystems` for a filesystem type, which is only registered
+/// once the filesystem is built in or its module is loaded
+#[cfg(feature = "bpf")]
+pub fn filesystem_available(name: &str) -> bool {
+    std::fs::read_to_string("/proc/filesystems")
+        .map(|content| filesystems_contain(&content, name))
+        .unwrap_or(false)
+}
+
+/// Each line of `/proc/filesystems` is an optional `nodev` flag followed by
+/// the filesystem type
+#[allow(dead_code)]
+fn filesystems_contain(content: &str, name: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.split_whitespace().last() == Some(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bpf_drain() {
+        let interval = Duration::from_secs(1);
+        let max = Some(Duration::from_secs(3));
+        // a loop which ticks every 500ms until a stage blocks it for 4s
+        let mut last = 0;
+        let mut drains = Vec::new();
+        for now in &[500, 1000, 1500, 2000, 6000, 6500, 7000] {
+            let elapsed = Duration::from_millis(now - last);
+            let drain = bpf_drain(elapsed, interval, max);
+            if drain != BpfDrain::Wait {
+                last = *now;
+            }
+            drains.push(drain);
+        }
+        use BpfDrain::*;
+        assert_eq!(drains, vec![Wait, Drain, Wait, Drain, Stale, Wait, Drain]);
+
+        // without a maximum, a delayed drain is not reported
+        let elapsed = Duration::from_secs(4);
+        assert_eq!(bpf_drain(elapsed, interval, None), BpfDrain::Drain);
+        // a maximum shorter than the interval drains early
+        let elapsed = Duration::from_millis(600);
+        let max = Some(Duration::from_millis(500));
+        assert_eq!(bpf_drain(elapsed, interval, max), BpfDrain::Stale);
+    }
+
+    impl HistogramMap for std::collections::HashMap<u32, u64> {
+        fn buckets(&mut self) -> Vec<(u32, u64)> {
+            self.iter().map(|(index, count)| (*index, *count)).collect()
+        }
+
+        fn clear(&mut self, index: u32) {
+            self.insert(index, 0);
+        }
+    }
+
+    #[test]
+    fn test_drain_histogram() {
+        use std::collections::HashMap;
+
+        // indexes below 100 are their own value
+        let mut map: HashMap<u32, u64> = vec![(1, 5), (2, 0), (3, 7)].into_iter().collect();
+        let drained = drain_histogram(&mut map, None);
+        assert_eq!(drained, vec![(1, 5), (3, 7)].into_iter().collect());
+        assert!(map.values().all(|count| *count == 0));
+
+        // a reread only returns what was recorded since the clear
+        *map.get_mut(&1).unwrap() += 2;
+        let drained = drain_histogram(&mut map, None);
+        assert_eq!(drained, vec![(1, 2)].into_iter().collect());
+        assert!(drain_histogram(&mut map, None).is_empty());
+    }
+
+    #[test]
+    fn test_drain_cumulative_histogram() {
+        use std::collections::HashMap;
+
+        let mut previous = HashMap::new();
+        let mut map: HashMap<u32, u64> = vec![(1, 5), (3, 7)].into_iter().collect();
+        let drained = drain_histogram(&mut map, Some(&mut previous));
+        assert_eq!(drained, vec![(1, 5), (3, 7)].into_iter().collect());
+        // the map is left as it was
+        assert_eq!(map.get(&1), Some(&5));
+
+        // only the increase since the last read is returned
+        map.insert(1, 8);
+        let drained = drain_histogram(&mut map, Some(&mut previous));
+        assert_eq!(drained, vec![(1, 3)].into_iter().collect());
+        assert!(drain_histogram(&mut map, Some(&mut previous)).is_empty());
+
+        // a bucket which went backwards was reset
+        map.insert(3, 2);
+        let drained = drain_histogram(&mut map, Some(&mut previous));
+        assert_eq!(drained, vec![(3, 2)].into_iter().collect());
+    }
+
+    #[test]
+    fn test_sum_per_cpu_entries() {
+        // the slot of each of 4 cpus, padded to a cache line
+        let slot = |count: u64| -> Vec<u8> {
+            let mut slot = count.to_ne_bytes().to_vec();
+            slot.resize(64, 0);
+            slot
+        };
+        let entries = vec![slot(1), slot(2), slot(0), slot(7)];
+        assert_eq!(sum_per_cpu_entries(entries), 10);
+        assert_eq!(sum_per_cpu_entries(Vec::new()), 0);
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-63\n"), Some(64));
+        assert_eq!(parse_cpu_list("0"), Some(1));
+        assert_eq!(parse_cpu_list("0,2-3,8"), Some(9));
+        assert_eq!(parse_cpu_list(""), None);
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn test_filesystems_contain() {
+        let content = "nodev\tsysfs\nnodev\ttmpfs\n\text4\n\txfs\nnodev\text4fake\n";
+        assert!(filesystems_contain(content, "ext4"));
+        assert!(filesystems_contain(content, "xfs"));
+        assert!(filesystems_contain(content, "tmpfs"));
+        assert!(!filesystems_contain(content, "btrfs"));
+        assert!(!filesystems_contain(content, "nodev"));
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+
+use dashmap::DashMap;
+
+/// The running total of each histogram bucket of the distributions recorded by
+/// the samplers. The registry only keeps the percentiles over its window, so
+/// exporters with delta temporality take the difference of these totals
+/// between exports to find the percentiles of each interval.
+pub struct Buckets {
+    totals: DashMap<String, BTreeMap<u64, u64>>,
+}
+
+impl Buckets {
+    pub fn new() -> Self {
+        Self {
+            totals: DashMap::new(),
+        }
+    }
+
+    /// Add the count to the total of the bucket with the value
+    pub fn record(&self, name: &str, value: u64, count: u64) {
+        if let Some(mut totals) = self.totals.get_mut(name) {
+            *totals.entry(value).or_insert(0) += count;
+        } else {
+            let mut totals = BTreeMap::new();
+            totals.insert(value, count);
+            self.totals.insert(name.to_string(), totals);
+        }
+    }
+
+    /// The bucket totals of the distribution, if it has been recorded
+    pub fn totals(&self, name: &str) -> Option<BTreeMap<u64, u64>> {
+        self.totals.get(name).map(|totals| totals.value().clone())
+    }
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The increase of each bucket since the previous totals, leaving out the
+/// buckets which did not change
+pub fn delta(
+    current: &BTreeMap<u64, u64>,
+    previous: Option<&BTreeMap<u64, u64>>,
+) -> BTreeMap<u64, u64> {
+    current
+        .iter()
+        .filter_map(|(value, total)| {
+            let before = previous.and_then(|p| p.get(value)).copied().unwrap_or(0);
+            let increase = total.saturating_sub(before);
+            if increase > 0 {
+                Some((*value, increase))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The number of values in the buckets, and their sum, taking each value to be
+/// that of its bucket
+pub fn count_and_sum(buckets: &BTreeMap<u64, u64>) -> (u64, f64) {
+    buckets.iter().fold((0, 0.0), |(count, sum), (value, n)| {
+        (count + n, sum + *value as f64 * *n as f64)
+    })
+}
+
+/// The value of the bucket containing the percentile, or `None` if there are
+/// no counts in the buckets
+pub fn percentile(buckets: &BTreeMap<u64, u64>, percentile: f64) -> Option<u64> {
+    let total: u64 = buckets.values().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = ((percentile / 100.0) * total as f64).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (value, count) in buckets {
+        seen += count;
+        if seen >= rank {
+            return Some(*value);
+        }
+    }
+    buckets.keys().next_back().copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delta() {
+        let buckets = Buckets::new();
+        buckets.record("disk/read/latency", 10, 3);
+        buckets.record("disk/read/latency", 20, 1);
+        let first = buckets.totals("disk/read/latency").unwrap();
+        assert_eq!(delta(&first, None), first);
+
+        buckets.record("disk/read/latency", 20, 2);
+        buckets.record("disk/read/latency", 40, 1);
+        let second = buckets.totals("disk/read/latency").unwrap();
+        let interval = delta(&second, Some(&first));
+        assert_eq!(
+            interval.into_iter().collect::<Vec<_>>(),
+            vec![(20, 2), (40, 1)]
+        );
+        assert!(delta(&second, Some(&second)).is_empty());
+        assert_eq!(buckets.totals("disk/write/latency"), None);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut buckets = BTreeMap::new();
+        assert_eq!(percentile(&buckets, 50.0), None);
+        buckets.insert(10, 3);
+        buckets.insert(20, 1);
+        assert_eq!(percentile(&buckets, 0.0), Some(10));
+        assert_eq!(percentile(&buckets, 50.0), Some(10));
+        assert_eq!(percentile(&buckets, 75.0), Some(10));
+        assert_eq!(percentile(&buckets, 99.0), Some(20));
+        assert_eq!(percentile(&buckets, 100.0), Some(20));
+    }
+
+    #[test]
+    fn test_count_and_sum() {
+        let mut buckets = BTreeMap::new();
+        assert_eq!(count_and_sum(&buckets), (0, 0.0));
+        buckets.insert(10, 3);
+        buckets.insert(20, 1);
+        assert_eq!(count_and_sum(&buckets), (4, 50.0));
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Locates the cgroup v2 directory of the Rezolus process, so that samplers
+//! can report the usage of the container Rezolus runs in rather than the host.
+
+use std::path::{Path, PathBuf};
+
+/// Where the cgroup v2 hierarchy is mounted
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The directory of the cgroup which Rezolus belongs to, or `None` if the
+/// cgroup v2 hierarchy is not in use
+pub fn own_cgroup() -> Option<PathBuf> {
+    let content = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = parse_self_cgroup(&content)?;
+    let directory = Path::new(CGROUP_ROOT).join(path.trim_start_matches('/'));
+    if directory.join("cgroup.controllers").exists() {
+        Some(directory)
+    } else {
+        None
+    }
+}
+
+/// Find the cgroup v2 entry in `/proc/self/cgroup`, which has a hierarchy id
+/// of 0 and no controllers, eg: `0::/system.slice/rezolus.service`
+fn parse_self_cgroup(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_self_cgroup() {
+        assert_eq!(
+            parse_self_cgroup("0::/system.slice/rezolus.service\n"),
+            Some("/system.slice/rezolus.service")
+        );
+        // hybrid hierarchies list the v1 controllers first
+        assert_eq!(
+            parse_self_cgroup(
+                "12:memory:/docker/abc\n1:name=systemd:/docker/abc\n0::/docker/abc\n"
+            ),
+            Some("/docker/abc")
+        );
+        // within a cgroup namespace the path is the root
+        assert_eq!(parse_self_cgroup("0::/\n"), Some("/"));
+        assert_eq!(parse_self_cgroup("12:memory:/docker/abc\n"), None);
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use dashmap::DashMap;
+
+/// Tracks the previous raw reading of each counter so that a counter which
+/// goes backwards, due to a wrap or a reset in the kernel, does not produce a
+/// garbage rate. Readings are offset so that the value handed to the metrics
+/// library stays monotonic across a reset. An increase larger than `max_delta`
+/// is clamped to `max_delta`, so that a single bogus reading does not produce a
+/// spike which corrupts the percentiles.
+pub struct CounterTracker {
+    max_delta: Option<u64>,
+    state: DashMap<String, CounterState>,
+}
+
+#[derive(Clone, Copy)]
+struct CounterState {
+    previous: u64,
+    offset: u64,
+}
+
+impl CounterTracker {
+    pub fn new(max_delta: Option<u64>) -> Self {
+        Self {
+            max_delta,
+            state: DashMap::new(),
+        }
+    }
+
+    /// Returns the value which should be recorded for this raw reading, or
+    /// `None` if the counter went backwards and this interval should be
+    /// skipped. The offset arithmetic wraps, so readings near `u64::MAX` never
+    /// panic.
+    pub fn adjust(&self, name: &str, raw: u64) -> Option<u64> {
+        if let Some(mut state) = self.state.get_mut(name) {
+            if raw < state.previous {
+                // continue from the last value recorded so the next interval
+                // only sees the increase since the reset
+                state.offset = state.offset.wrapping_add(state.previous - raw);
+                state.previous = raw;
+                return None;
+            }
+            let delta = raw.wrapping_sub(state.previous);
+            if let Some(max) = self.max_delta.filter(|max| delta > *max) {
+                // only record the maximum increase, so the next interval
+                // continues from there
+                warn!(
+                    "counter: {} increased by {} which is clamped to {}",
+                    name, delta, max
+                );
+                state.offset = state.offset.wrapping_sub(delta - max);
+            }
+            state.previous = raw;
+            Some(raw.wrapping_add(state.offset))
+        } else {
+            self.state.insert(
+                name.to_string(),
+                CounterState {
+                    previous: raw,
+                    offset: 0,
+                },
+            );
+            Some(raw)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_monotonic() {
+        let tracker = CounterTracker::new(None);
+        assert_eq!(tracker.adjust("a", 1), Some(1));
+        assert_eq!(tracker.adjust("a", 5), Some(5));
+        assert_eq!(tracker.adjust("a", 5), Some(5));
+        assert_eq!(tracker.adjust("b", 2), Some(2));
+    }
+
+    #[test]
+    fn test_reset() {
+        let tracker = CounterTracker::new(None);
+        let mut recorded = Vec::new();
+        for raw in &[100, 200, 300, 10, 20, 5, 50] {
+            if let Some(value) = tracker.adjust("a", *raw) {
+                recorded.push(value);
+            }
+        }
+        // the intervals containing a reset are skipped
+        assert_eq!(recorded, vec![100, 200, 300, 310, 355]);
+        // and no consecutive recorded values would produce a negative rate
+        for pair in recorded.windows(2) {
+            assert!(pair[1] >= pair[0]);
+        }
+    }
+
+    #[test]
+    fn test_boundary() {
+        let tracker = CounterTracker::new(None);
+        assert_eq!(tracker.adjust("a", u64::MAX - 10), Some(u64::MAX - 10));
+        assert_eq!(tracker.adjust("a", u64::MAX), Some(u64::MAX));
+        // a reset from the maximum does not overflow the offset
+        assert_eq!(tracker.adjust("a", 0), None);
+        assert_eq!(tracker.adjust("a", 0), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_max_delta() {
+        let tracker = CounterTracker::new(Some(1000));
+        assert_eq!(tracker.adjust("a", 100), Some(100));
+        assert_eq!(tracker.adjust("a", 600), Some(600));
+        // an implausible increase is clamped and the excess does not appear
+        // later
+        assert_eq!(tracker.adjust("a", u64::MAX), Some(1600));
+        assert_eq!(tracker.adjust("a", u64::MAX), Some(1600));
+
+        // an increase of exactly the maximum is recorded in full
+        assert_eq!(tracker.adjust("b", 0), Some(0));
+        assert_eq!(tracker.adjust("b", 1000), Some(1000));
+        assert_eq!(tracker.adjust("b", 2001), Some(2000));
+        assert_eq!(tracker.adjust("b", 2011), Some(2010));
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Bucket weights below this are dropped so that values which are no longer
+/// seen do not accumulate
+const MIN_WEIGHT: f64 = 0.001;
+
+/// A histogram whose bucket counts decay exponentially with a half-life, so
+/// that percentiles are dominated by recent values rather than weighting every
+/// value in a fixed window equally.
+pub struct EwmaHistogram {
+    buckets: BTreeMap<u64, f64>,
+    half_life: Duration,
+}
+
+impl EwmaHistogram {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            buckets: BTreeMap::new(),
+            half_life,
+        }
+    }
+
+    /// Decay every bucket by the time elapsed since the previous decay
+    pub fn decay(&mut self, elapsed: Duration) {
+        if self.half_life.as_nanos() == 0 {
+            self.buckets.clear();
+            return;
+        }
+        let factor = 0.5_f64.powf(elapsed.as_secs_f64() / self.half_life.as_secs_f64());
+        for weight in self.buckets.values_mut() {
+            *weight *= factor;
+        }
+        self.buckets.retain(|_, weight| *weight >= MIN_WEIGHT);
+    }
+
+    pub fn increment(&mut self, value: u64, count: u32) {
+        *self.buckets.entry(value).or_insert(0.0) += count as f64;
+    }
+
+    /// The smallest bucket value at or below which `percentile` of the decayed
+    /// weight lies, or `None` if the histogram is empty
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        let total: f64 = self.buckets.values().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let target = total * percentile / 100.0;
+        let mut seen = 0.0;
+        for (value, weight) in &self.buckets {
+            seen += weight;
+            if seen >= target {
+                return Some(*value);
+            }
+        }
+        self.buckets.keys().next_back().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let mut histogram = EwmaHistogram::new(Duration::from_secs(10));
+        assert_eq!(histogram.percentile(50.0), None);
+        for value in 1..=100 {
+            histogram.increment(value, 1);
+        }
+        assert_eq!(histogram.percentile(50.0), Some(50));
+        assert_eq!(histogram.percentile(99.0), Some(99));
+        assert_eq!(histogram.percentile(100.0), Some(100));
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut histogram = EwmaHistogram::new(Duration::from_secs(10));
+        histogram.increment(10, 100);
+        // after one half-life a spike of equal size dominates the old values
+        histogram.decay(Duration::from_secs(10));
+        histogram.increment(1000, 100);
+        assert_eq!(histogram.percentile(50.0), Some(1000));
+        assert_eq!(histogram.percentile(25.0), Some(10));
+        // old values eventually disappear entirely
+        histogram.decay(Duration::from_secs(200));
+        assert_eq!(histogram.percentile(50.0), None);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use json::JsonValue;
+
+#[derive(Clone, Copy, Default)]
+struct Status {
+    init_failed: bool,
+    last_success: Option<Instant>,
+}
+
+/// Tracks when each spawned sampler last completed a sample without errors,
+/// which is reported by the readiness endpoint
+pub struct Health {
+    samplers: Mutex<BTreeMap<&'static str, Status>>,
+    staleness: Duration,
+    strict: bool,
+}
+
+impl Health {
+    /// A sampler is stale once `staleness` has passed since its last
+    /// successful sample. Samplers which failed to initialize only fail
+    /// readiness if `strict` is set.
+    pub fn new(staleness: Duration, strict: bool) -> Self {
+        Self {
+            samplers: Mutex::new(BTreeMap::new()),
+            staleness,
+            strict,
+        }
+    }
+
+    /// Track a sampler which has been spawned but has not yet sampled
+    pub fn register(&self, sampler: &'static str) {
+        self.samplers
+            .lock()
+            .unwrap()
+            .entry(sampler)
+            .or_insert_with(Status::default);
+    }
+
+    /// Stop tracking a sampler which was stopped by a config reload
+    pub fn deregister(&self, sampler: &'static str) {
+        self.samplers.lock().unwrap().remove(sampler);
+    }
+
+    pub fn init_failed(&self, sampler: &'static str) {
+        self.samplers
+            .lock()
+            .unwrap()
+            .entry(sampler)
+            .or_insert_with(Status::default)
+            .init_failed = true;
+    }
+
+    pub fn success(&self, sampler: &'static str, time: Instant) {
+        self.samplers
+            .lock()
+            .unwrap()
+            .entry(sampler)
+            .or_insert_with(Status::default)
+            .last_success = Some(time);
+    }
+
+    /// Whether every sampler is ready, and a JSON body with the status of each
+    /// sampler, which is one of `ok`, `pending`, `stale`, or `failed`
+    pub fn readiness(&self, now: Instant) -> (bool, String) {
+        let mut ready = true;
+        let mut samplers = JsonValue::new_object();
+        for (name, status) in self.samplers.lock().unwrap().iter() {
+            let mut entry = JsonValue::new_object();
+            let state = if status.init_failed {
+                ready &= !self.strict;
+                "failed"
+            } else {
+                match status.last_success {
+                    Some(time) => {
+                        let age = now.saturating_duration_since(time);
+                        entry["last_success_ms"] = (age.as_millis() as u64).into();
+                        if age <= self.staleness {
+                            "ok"
+                        } else {
+                            ready = false;
+                            "stale"
+                        }
+                    }
+                    None => {
+                        ready = false;
+                        "pending"
+                    }
+                }
+            };
+            entry["status"] = state.into();
+            samplers[*name] = entry;
+        }
+        let mut content = JsonValue::new_object();
+        content["ready"] = ready.into();
+        content["samplers"] = samplers;
+        (ready, content.dump())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let health = Health::new(Duration::from_secs(10), false);
+        let start = Instant::now();
+        health.register("cpu");
+        health.register("disk");
+        assert!(!health.readiness(start).0);
+
+        health.success("cpu", start);
+        health.init_failed("disk");
+        let (ready, content) = health.readiness(start + Duration::from_secs(1));
+        assert!(ready);
+        assert_eq!(
+            content,
+            r#"{"ready":true,"samplers":{"cpu":{"last_success_ms":1000,"status":"ok"},"disk":{"status":"failed"}}}"#
+        );
+
+        let (ready, content) = health.readiness(start + Duration::from_secs(11));
+        assert!(!ready);
+        assert!(content.contains(r#""cpu":{"last_success_ms":11000,"status":"stale"}"#));
+
+        // a stopped sampler no longer affects readiness
+        health.deregister("cpu");
+        assert!(health.readiness(start + Duration::from_secs(11)).0);
+    }
+
+    #[test]
+    fn test_readiness_strict() {
+        let health = Health::new(Duration::from_secs(10), true);
+        health.init_failed("disk");
+        assert!(!health.readiness(Instant::now()).0);
+    }
+}
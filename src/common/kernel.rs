@@ -0,0 +1,120 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::fmt;
+use core::str::FromStr;
+use std::ffi::CStr;
+
+/// The first kernel which can attach BPF programs to kprobes
+pub const KPROBES: KernelVersion = KernelVersion::new(4, 1, 0);
+/// The first kernel which can attach BPF programs to tracepoints
+pub const TRACEPOINTS: KernelVersion = KernelVersion::new(4, 7, 0);
+/// The first kernel which reports the run count and time of BPF programs
+pub const BPF_RUN_STATS: KernelVersion = KernelVersion::new(5, 1, 0);
+/// The first kernel which can turn on the BPF run statistics with the
+/// `BPF_ENABLE_STATS` command, rather than only the sysctl
+pub const BPF_ENABLE_STATS: KernelVersion = KernelVersion::new(5, 8, 0);
+/// The first kernel which has the `block_io_start` and `block_io_done`
+/// tracepoints
+pub const BLOCK_IO_TRACEPOINTS: KernelVersion = KernelVersion::new(6, 5, 0);
+
+/// A kernel release, compared by its major, minor, and patch versions
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct KernelVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl KernelVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The release of the running kernel, as reported by `uname`
+    pub fn current() -> Option<Self> {
+        // safety: uname only writes the nul-terminated fields of the struct,
+        // which is zeroed beforehand
+        let release = unsafe {
+            let mut name: libc::utsname = std::mem::zeroed();
+            if libc::uname(&mut name) != 0 {
+                return None;
+            }
+            CStr::from_ptr(name.release.as_ptr()).to_owned()
+        };
+        release.to_str().ok()?.parse().ok()
+    }
+}
+
+impl FromStr for KernelVersion {
+    type Err = anyhow::Error;
+
+    /// Parse a release such as `5.4.0-42-generic`, where anything following
+    /// the numeric versions is ignored and a missing patch version is zero
+    fn from_str(release: &str) -> Result<Self, Self::Err> {
+        let mut versions = [0; 3];
+        let mut parts = release.trim().splitn(3, '.');
+        for (index, version) in versions.iter_mut().enumerate() {
+            let part = match parts.next() {
+                Some(part) => part,
+                None if index == 2 => break,
+                None => return Err(format_err!("malformed kernel release: {}", release)),
+            };
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            *version = part[..digits]
+                .parse()
+                .map_err(|_| format_err!("malformed kernel release: {}", release))?;
+            // eg: the `-rc3` of `6.1-rc3` ends the versions
+            if digits < part.len() {
+                if index == 0 {
+                    return Err(format_err!("malformed kernel release: {}", release));
+                }
+                break;
+            }
+        }
+        Ok(Self::new(versions[0], versions[1], versions[2]))
+    }
+}
+
+impl fmt::Display for KernelVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parse = |release: &str| release.parse::<KernelVersion>().ok();
+        assert_eq!(parse("5.4.0-42-generic"), Some(KernelVersion::new(5, 4, 0)));
+        assert_eq!(parse("4.19.112+"), Some(KernelVersion::new(4, 19, 112)));
+        assert_eq!(parse("5.10"), Some(KernelVersion::new(5, 10, 0)));
+        assert_eq!(parse("6.1-rc3"), Some(KernelVersion::new(6, 1, 0)));
+        assert_eq!(
+            parse("3.10.0-1160.el7.x86_64"),
+            Some(KernelVersion::new(3, 10, 0))
+        );
+        assert_eq!(parse("5"), None);
+        assert_eq!(parse("5-rc1.2"), None);
+        assert_eq!(parse("linux"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(KernelVersion::new(4, 6, 9) < TRACEPOINTS);
+        assert!(KernelVersion::new(4, 7, 0) >= TRACEPOINTS);
+        assert!(KernelVersion::new(4, 19, 0) > KernelVersion::new(4, 7, 0));
+        assert_eq!(TRACEPOINTS.to_string(), "4.7.0");
+    }
+}
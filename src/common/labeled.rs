@@ -0,0 +1,173 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use dashmap::DashMap;
+use rustcommon_metrics::*;
+
+use crate::common::unit::{StatisticUnit, Unit};
+
+/// A statistic of a single entity, such as a cpu, numa node, or network
+/// interface, which is registered when the entity is first seen. It is
+/// exported as the statistic it breaks down, its family, with labels which
+/// identify the entity, eg: `cpu/usage/user` with `cpu="0"`. Its name in the
+/// registry has the label values appended so that it is unique, eg:
+/// `cpu/usage/user/0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabeledStatistic {
+    name: String,
+    family: String,
+    labels: Vec<(String, String)>,
+    source: Source,
+    unit: Option<Unit>,
+}
+
+impl LabeledStatistic {
+    /// The statistic for the entity with a single label, with the source and
+    /// unit of the statistic it breaks down
+    pub fn new<S>(statistic: &S, label: &str, value: &str) -> Self
+    where
+        S: Statistic<AtomicU64, AtomicU32> + StatisticUnit,
+    {
+        Self::with_labels(statistic, &[(label, value.to_string())])
+    }
+
+    /// The statistic for an entity which is identified by several labels, in
+    /// the order their values appear in the registry name
+    pub fn with_labels<S>(statistic: &S, labels: &[(&str, String)]) -> Self
+    where
+        S: Statistic<AtomicU64, AtomicU32> + StatisticUnit,
+    {
+        Self::build(
+            statistic.name(),
+            statistic.source(),
+            statistic.unit(),
+            labels,
+        )
+    }
+
+    /// A statistic whose family is not a statistic of its own, eg: the count
+    /// of connections for each port and state
+    pub fn with_name(family: &str, source: Source, labels: &[(&str, String)]) -> Self {
+        Self::build(family, source, None, labels)
+    }
+
+    fn build(family: &str, source: Source, unit: Option<Unit>, labels: &[(&str, String)]) -> Self {
+        let mut name = family.to_string();
+        for (_, value) in labels {
+            name.push('/');
+            name.push_str(value);
+        }
+        Self {
+            name,
+            family: family.to_string(),
+            labels: labels
+                .iter()
+                .map(|(label, value)| (label.to_string(), value.clone()))
+                .collect(),
+            source,
+            unit,
+        }
+    }
+
+    /// The name of the statistic this breaks down, which is the name it is
+    /// exported with
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// The labels which identify the entity
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for LabeledStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        self.source
+    }
+}
+
+impl StatisticUnit for LabeledStatistic {
+    fn unit(&self) -> Option<Unit> {
+        self.unit
+    }
+}
+
+/// The labeled statistics in the registry, keyed by statistic name, so that
+/// exporters can render each as its family with labels without knowing the
+/// statistic types
+pub struct Entities {
+    statistics: DashMap<String, LabeledStatistic>,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self {
+            statistics: DashMap::new(),
+        }
+    }
+
+    pub fn insert(&self, statistic: &LabeledStatistic) {
+        self.statistics
+            .insert(statistic.name().to_string(), statistic.clone());
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.statistics.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<LabeledStatistic> {
+        self.statistics.get(name).map(|v| v.value().clone())
+    }
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::samplers::slab::SlabStatistic;
+
+    #[test]
+    fn test_labeled_statistic() {
+        let statistic = LabeledStatistic::new(&SlabStatistic::Size, "cache", "dentry");
+        assert_eq!(statistic.name(), "slab/size/dentry");
+        assert_eq!(statistic.family(), "slab/size");
+        assert_eq!(
+            statistic.labels(),
+            &[("cache".to_string(), "dentry".to_string())]
+        );
+        assert_eq!(statistic.source(), Source::Gauge);
+        assert_eq!(statistic.unit(), Some(Unit::Bytes));
+
+        let statistic = LabeledStatistic::with_name(
+            "tcp/connections",
+            Source::Gauge,
+            &[("port", "443".to_string()), ("state", "listen".to_string())],
+        );
+        assert_eq!(statistic.name(), "tcp/connections/443/listen");
+        assert_eq!(statistic.family(), "tcp/connections");
+        assert_eq!(statistic.labels().len(), 2);
+        assert_eq!(statistic.unit(), None);
+    }
+
+    #[test]
+    fn test_entities() {
+        let entities = Entities::new();
+        let statistic = LabeledStatistic::new(&SlabStatistic::Size, "cache", "dentry");
+        entities.insert(&statistic);
+        assert_eq!(entities.get("slab/size/dentry"), Some(statistic));
+        assert_eq!(entities.get("slab/size"), None);
+        entities.remove("slab/size/dentry");
+        assert_eq!(entities.get("slab/size/dentry"), None);
+    }
+}
@@ -4,14 +4,26 @@
 
 use std::collections::HashMap;
 use std::io::BufRead;
-use std::io::SeekFrom;
 
 use dashmap::DashMap;
 use rustcommon_atomics::AtomicU64;
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, BufReader};
 
+pub mod affinity;
+pub mod backoff;
 pub mod bpf;
+pub mod buckets;
+pub mod cgroup;
+pub mod clock;
+pub mod counter;
+pub mod ewma;
+pub mod health;
+pub mod kernel;
+pub mod labeled;
+pub mod proc;
+pub mod ratelimit;
+pub mod staleness;
+pub mod unit;
+pub mod unwind;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const NAME: &str = env!("CARGO_PKG_NAME");
@@ -66,6 +78,12 @@ impl HardwareInfo {
     }
 }
 
+impl Default for HardwareInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// helper function to discover the number of hardware threads
 pub fn hardware_threads() -> Result<u64, ()> {
     let path = "/sys/devices/system/cpu/present";
@@ -89,38 +107,147 @@ pub fn hardware_threads() -> Result<u64, ()> {
 /// pkey1 lkey1 lkey2 ... lkeyN
 /// pkey1 value1 value2 ... valueN
 /// pkey2 ...
-pub async fn nested_map_from_file(
-    file: &mut File,
-) -> Result<HashMap<String, HashMap<String, u64>>, std::io::Error> {
-    file.seek(SeekFrom::Start(0)).await?;
+///
+/// A section is skipped, with a warning, if its header has no values row or
+/// the two rows have a different number of columns, as the values cannot be
+/// matched to their keys. Each values row starts with the same pkey as its
+/// header, so a missing row does not shift the pairing of later sections.
+pub fn nested_map_from_str(content: &str) -> HashMap<String, HashMap<String, u64>> {
     let mut ret = HashMap::<String, HashMap<String, u64>>::new();
-    let mut reader = BufReader::new(file);
-    let mut keys = String::new();
-    let mut values = String::new();
-    while reader.read_line(&mut keys).await? > 0 {
-        if reader.read_line(&mut values).await? > 0 {
-            let mut keys_split = keys.trim().split_whitespace();
-            let mut values_split = values.trim().split_whitespace();
-
-            if let Some(pkey) = keys_split.next() {
-                let _ = values_split.next();
-                if !ret.contains_key(pkey) {
-                    ret.insert(pkey.to_string(), Default::default());
-                }
-                let inner = ret.get_mut(pkey).unwrap();
-                for key in keys_split {
-                    if let Some(Ok(value)) = values_split.next().map(|v| v.parse()) {
-                        inner.insert(key.to_owned(), value);
-                    }
+    let mut lines = content.lines().peekable();
+    while let Some(keys) = lines.next() {
+        let keys: Vec<&str> = keys.split_whitespace().collect();
+        let pkey = match keys.first() {
+            Some(pkey) => *pkey,
+            None => continue,
+        };
+        let values: Vec<&str> = match lines.peek() {
+            Some(values) if values.split_whitespace().next() == Some(pkey) => {
+                lines.next().unwrap().split_whitespace().collect()
+            }
+            _ => {
+                warn!("no values for section: {}", pkey);
+                continue;
+            }
+        };
+        if keys.len() != values.len() {
+            warn!(
+                "section: {} has {} keys but {} values, skipping",
+                pkey,
+                keys.len() - 1,
+                values.len() - 1
+            );
+            continue;
+        }
+        let inner = ret.entry(pkey.to_string()).or_default();
+        for (key, value) in keys.iter().zip(values.iter()).skip(1) {
+            if let Ok(value) = value.parse() {
+                inner.insert((*key).to_owned(), value);
+            }
+        }
+    }
+    ret
+}
+
+/// helper function to create a map from files with one key and value per line,
+/// such as `/proc/net/snmp6`
+/// key1 value1
+/// key2 value2
+pub fn flat_map_from_str(content: &str) -> HashMap<String, u64> {
+    let mut ret = HashMap::new();
+    for line in content.lines() {
+        let mut split = line.split_whitespace();
+        if let (Some(key), Some(Ok(value))) = (split.next(), split.next().map(|v| v.parse())) {
+            ret.insert(key.to_owned(), value);
+        }
+    }
+    ret
+}
+
+/// helper function to create a nested map from files with a section per line
+/// followed by pairs of keys and values, such as `/proc/net/sockstat`
+/// pkey1 key1 value1 key2 value2 ... keyN valueN
+/// pkey2 ...
+pub fn paired_map_from_str(content: &str) -> HashMap<String, HashMap<String, u64>> {
+    let mut ret = HashMap::<String, HashMap<String, u64>>::new();
+    for line in content.lines() {
+        let mut split = line.split_whitespace();
+        if let Some(pkey) = split.next() {
+            let inner = ret.entry(pkey.to_string()).or_default();
+            while let (Some(key), Some(value)) = (split.next(), split.next()) {
+                if let Ok(value) = value.parse() {
+                    inner.insert(key.to_owned(), value);
                 }
             }
-            keys.clear();
-            values.clear();
         }
     }
-    Ok(ret)
+    ret
 }
 
 pub fn default_percentiles() -> Vec<f64> {
     vec![1.0, 10.0, 50.0, 90.0, 99.0]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nested_map_from_str() {
+        let parsed = nested_map_from_str(
+            "Tcp: InSegs OutSegs RetransSegs\nTcp: 100 200 3\nUdp: InDatagrams OutDatagrams\nUdp: 10 20\n",
+        );
+        assert_eq!(parsed.get("Tcp:").and_then(|m| m.get("InSegs")), Some(&100));
+        assert_eq!(
+            parsed.get("Udp:").and_then(|m| m.get("OutDatagrams")),
+            Some(&20)
+        );
+    }
+
+    #[test]
+    fn test_nested_map_from_str_mismatch() {
+        // the Tcp values row is missing its last column
+        let parsed = nested_map_from_str(
+            "Tcp: InSegs OutSegs RetransSegs\nTcp: 100 200\nUdp: InDatagrams OutDatagrams\nUdp: 10 20\n",
+        );
+        assert!(parsed.get("Tcp:").is_none());
+        assert_eq!(
+            parsed.get("Udp:").and_then(|m| m.get("InDatagrams")),
+            Some(&10)
+        );
+
+        // the Ip values row is missing entirely
+        let parsed =
+            nested_map_from_str("Ip: Forwarding DefaultTTL\nTcp: InSegs OutSegs\nTcp: 100 200\n");
+        assert!(parsed.get("Ip:").is_none());
+        assert_eq!(
+            parsed.get("Tcp:").and_then(|m| m.get("OutSegs")),
+            Some(&200)
+        );
+    }
+
+    #[test]
+    fn test_flat_map_from_str() {
+        let parsed = flat_map_from_str(
+            "Ip6InReceives                   \t1234\nUdp6InDatagrams                 \t56\n\n",
+        );
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed.get("Ip6InReceives"), Some(&1234));
+        assert_eq!(parsed.get("Udp6InDatagrams"), Some(&56));
+    }
+
+    #[test]
+    fn test_paired_map_from_str() {
+        let parsed = paired_map_from_str(
+            "sockets: used 294\nTCP: inuse 5 orphan 0 tw 2 alloc 8 mem 1\nUDP: inuse 3 mem 2\n",
+        );
+        assert_eq!(
+            parsed.get("sockets:").and_then(|m| m.get("used")),
+            Some(&294)
+        );
+        assert_eq!(parsed.get("TCP:").and_then(|m| m.get("inuse")), Some(&5));
+        assert_eq!(parsed.get("TCP:").and_then(|m| m.get("tw")), Some(&2));
+        assert_eq!(parsed.get("TCP:").and_then(|m| m.get("mem")), Some(&1));
+        assert_eq!(parsed.get("UDP:").map(|m| m.len()), Some(2));
+    }
+}
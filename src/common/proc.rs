@@ -0,0 +1,58 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use async_trait::async_trait;
+
+/// Reads the files which samplers parse, such as `/proc/net/snmp`. Samplers
+/// read through this, rather than opening the files themselves, so that tests
+/// can provide fixtures in place of the real files.
+#[async_trait]
+pub trait ProcSource: Send + Sync {
+    async fn read_to_string(&self, path: &str) -> Result<String, std::io::Error>;
+}
+
+/// Reads the real files
+pub struct Procfs;
+
+#[async_trait]
+impl ProcSource for Procfs {
+    async fn read_to_string(&self, path: &str) -> Result<String, std::io::Error> {
+        tokio::fs::read_to_string(path).await
+    }
+}
+
+#[cfg(test)]
+pub use self::fixtures::Fixtures;
+
+#[cfg(test)]
+mod fixtures {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Serves fixture content by path. Any other path is not found.
+    #[derive(Default)]
+    pub struct Fixtures {
+        files: HashMap<String, String>,
+    }
+
+    impl Fixtures {
+        pub fn with(mut self, path: &str, content: &str) -> Self {
+            self.files.insert(path.to_string(), content.to_string());
+            self
+        }
+    }
+
+    #[async_trait]
+    impl ProcSource for Fixtures {
+        async fn read_to_string(&self, path: &str) -> Result<String, std::io::Error> {
+            self.files.get(path).cloned().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no fixture for {}", path),
+                )
+            })
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/// Limits how often the same error is logged, so that an error which recurs
+/// on every sample, eg: a missing `/proc` file, doesn't flood the log. The
+/// first error at each site is logged, and later ones are only counted until
+/// the interval has passed. The next one is then logged along with the number
+/// which were suppressed.
+pub struct LogLimiter {
+    interval: Duration,
+    sites: DashMap<String, (Instant, u64)>,
+}
+
+impl LogLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            sites: DashMap::new(),
+        }
+    }
+
+    /// Whether an error at the site should be logged now, in which case the
+    /// number of errors suppressed since it was last logged is returned
+    pub fn check(&self, site: &str, now: Instant) -> Option<u64> {
+        if let Some(mut entry) = self.sites.get_mut(site) {
+            let (logged, suppressed) = *entry;
+            if now.saturating_duration_since(logged) < self.interval {
+                *entry = (logged, suppressed + 1);
+                return None;
+            }
+            *entry = (now, 0);
+            return Some(suppressed);
+        }
+        self.sites.insert(site.to_string(), (now, 0));
+        Some(0)
+    }
+}
+
+/// The note appended to a logged error about the identical errors before it
+/// which were suppressed
+pub fn suppressed(count: u64) -> String {
+    if count > 0 {
+        format!(" (suppressed {} identical errors)", count)
+    } else {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limiter = LogLimiter::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // an error every 10s is logged once a minute
+        let logged: Vec<(u64, u64)> = (0..14)
+            .filter_map(|i| {
+                limiter
+                    .check("cpu//proc/stat", at(i * 10))
+                    .map(|suppressed| (i * 10, suppressed))
+            })
+            .collect();
+        assert_eq!(logged, vec![(0, 0), (60, 5), (120, 5)]);
+
+        // each site is limited separately
+        assert_eq!(limiter.check("tcp//proc/net/snmp", at(130)), Some(0));
+        assert_eq!(limiter.check("tcp//proc/net/snmp", at(131)), None);
+    }
+
+    #[test]
+    fn test_suppressed() {
+        assert_eq!(suppressed(0), "");
+        assert_eq!(suppressed(5), " (suppressed 5 identical errors)");
+    }
+}
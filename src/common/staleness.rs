@@ -0,0 +1,83 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+/// Tracks when each statistic was last recorded by a sampler, along with the
+/// interval of that sampler, so that exporters can omit series which have
+/// stopped being updated, eg: for an interface which has gone away, rather
+/// than repeating the last value indefinitely.
+pub struct Updates {
+    stale_after: Option<u32>,
+    updated: DashMap<String, (Instant, Duration)>,
+}
+
+impl Updates {
+    /// A series is stale once `stale_after` intervals have passed without an
+    /// update. Nothing is ever stale if it is `None`.
+    pub fn new(stale_after: Option<u32>) -> Self {
+        Self {
+            stale_after,
+            updated: DashMap::new(),
+        }
+    }
+
+    /// Record that the statistic was updated by a sampler with the given
+    /// interval
+    pub fn update(&self, name: &str, time: Instant, interval: Duration) {
+        if let Some(mut updated) = self.updated.get_mut(name) {
+            *updated = (time, interval);
+        } else {
+            self.updated.insert(name.to_string(), (time, interval));
+        }
+    }
+
+    /// Whether more than `stale_after` of the recording sampler's intervals
+    /// have passed since the statistic was last updated. Statistics which are
+    /// not recorded by a sampler, such as the rezolus diagnostics, are never
+    /// stale.
+    pub fn is_stale(&self, name: &str, now: Instant) -> bool {
+        let intervals = match self.stale_after {
+            Some(intervals) => intervals,
+            None => return false,
+        };
+        self.updated
+            .get(name)
+            .map(|updated| {
+                let (time, interval) = *updated.value();
+                now.saturating_duration_since(time) > interval * intervals
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_stale() {
+        let updates = Updates::new(Some(3));
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        updates.update("network/receive/bytes/eth0", start, second);
+        updates.update("system/uptime", start, second * 10);
+
+        let now = start + second * 5;
+        assert!(updates.is_stale("network/receive/bytes/eth0", now));
+        assert!(!updates.is_stale("system/uptime", now));
+        assert!(!updates.is_stale("rezolus/cpu/user", now));
+
+        // a new reading makes the series current again
+        updates.update("network/receive/bytes/eth0", now, second);
+        assert!(!updates.is_stale("network/receive/bytes/eth0", now));
+
+        // by default series are never stale
+        let updates = Updates::new(None);
+        updates.update("network/receive/bytes/eth0", start, second);
+        assert!(!updates.is_stale("network/receive/bytes/eth0", now));
+    }
+}
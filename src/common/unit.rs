@@ -0,0 +1,68 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use dashmap::DashMap;
+
+/// The unit of a statistic, which exporters may use to describe its readings
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Unit {
+    Bytes,
+    Count,
+    Hertz,
+    Nanoseconds,
+    Percent,
+}
+
+impl Unit {
+    /// The lowercase name of the unit as used in exposition formats
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bytes => "bytes",
+            Self::Count => "count",
+            Self::Hertz => "hertz",
+            Self::Nanoseconds => "nanoseconds",
+            Self::Percent => "percent",
+        }
+    }
+}
+
+/// Implemented by each sampler statistic. Statistics without a known unit use
+/// the default, which leaves their exposition unchanged.
+pub trait StatisticUnit {
+    fn unit(&self) -> Option<Unit> {
+        None
+    }
+}
+
+/// The units of registered statistics, keyed by statistic name, so that
+/// exporters can look them up without knowing the statistic types
+pub struct Units {
+    units: DashMap<String, Unit>,
+}
+
+impl Units {
+    pub fn new() -> Self {
+        Self {
+            units: DashMap::new(),
+        }
+    }
+
+    pub fn insert(&self, name: &str, unit: Unit) {
+        self.units.insert(name.to_string(), unit);
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.units.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Unit> {
+        self.units.get(name).map(|v| *v.value())
+    }
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self::new()
+    }
+}
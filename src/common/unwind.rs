@@ -0,0 +1,74 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A future which completes with an error holding the panic payload if the
+/// inner future panics while it is polled, rather than unwinding through the
+/// task which polls it. This lets a sampler loop carry on with its next sample
+/// after a bug, eg: an index out of bounds when parsing a `/proc` file whose
+/// format has changed.
+pub struct CatchUnwind<F> {
+    future: F,
+}
+
+/// Catch any panic while polling the future
+pub fn catch_panic<F: Future + Unpin>(future: F) -> CatchUnwind<F> {
+    CatchUnwind { future }
+}
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = &mut self.future;
+        match catch_unwind(AssertUnwindSafe(|| Pin::new(future).poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+/// The message a panic was raised with, if it was a string
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let ok: Pin<Box<dyn Future<Output = u64> + Send>> = Box::pin(async { 1 });
+        assert_eq!(catch_panic(ok).await.unwrap(), 1);
+
+        let fields: Vec<&str> = Vec::new();
+        let panics: Pin<Box<dyn Future<Output = usize> + Send>> =
+            Box::pin(async move { fields[3].len() });
+        let payload = catch_panic(panics).await.unwrap_err();
+        assert!(panic_message(payload.as_ref()).contains("index out of bounds"));
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload: Box<dyn Any + Send> = Box::new("bad line");
+        assert_eq!(panic_message(payload.as_ref()), "bad line");
+        let payload: Box<dyn Any + Send> = Box::new(format!("bad line {}", 3));
+        assert_eq!(panic_message(payload.as_ref()), "bad line 3");
+        let payload: Box<dyn Any + Send> = Box::new(3);
+        assert_eq!(panic_message(payload.as_ref()), "unknown cause");
+    }
+}
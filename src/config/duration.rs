@@ -0,0 +1,222 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Deserializers for durations which may be given either as a bare integer in
+//! the unit of the setting, or as a string with a unit suffix, eg: `"250ms"`,
+//! `"2s"`, `"1m"`, or `"1h"`. The number may have a fractional part, eg:
+//! `"0.5s"`.
+
+use std::fmt;
+use std::time::Duration;
+
+use rustcommon_atomics::*;
+use serde::de::{self, Deserializer, Visitor};
+
+/// Parse a duration string which is a number followed by one of the `ms`, `s`,
+/// `m`, or `h` suffixes
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let unit = match suffix.trim() {
+        "ms" => Duration::from_millis(1),
+        "s" => Duration::from_secs(1),
+        "m" => Duration::from_secs(60),
+        "h" => Duration::from_secs(3600),
+        "" => {
+            return Err(format!(
+                "duration \"{}\" is missing a unit, use one of: ms, s, m, h",
+                value
+            ))
+        }
+        _ => {
+            return Err(format!(
+                "duration \"{}\" has an unknown unit, use one of: ms, s, m, h",
+                value
+            ))
+        }
+    };
+    // the fraction is applied in integer nanoseconds, as floating point would
+    // turn eg: "0.3s" into 299.999999ms
+    let invalid = || format!("invalid duration: \"{}\"", value);
+    let mut parts = number.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    // nanoseconds are the finest resolution of a duration
+    if (whole.is_empty() && fraction.is_empty()) || fraction.len() > 9 || fraction.contains('.') {
+        return Err(invalid());
+    }
+    let unit = unit.as_nanos() as u64;
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole.parse::<u64>().map_err(|_| invalid())?
+    };
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        // less than one unit, but the product may not fit in a u64
+        let digits = fraction.parse::<u128>().map_err(|_| invalid())?;
+        (digits * unit as u128 / 10_u128.pow(fraction.len() as u32)) as u64
+    };
+    whole
+        .checked_mul(unit)
+        .and_then(|nanos| nanos.checked_add(fraction))
+        .map(Duration::from_nanos)
+        .ok_or_else(|| format!("duration is too large: \"{}\"", value))
+}
+
+/// Accepts either an integer, which is a count of `unit`, or a duration string
+struct DurationVisitor {
+    unit: Duration,
+}
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer or a duration such as \"250ms\" or \"2s\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        (self.unit.as_nanos() as u64)
+            .checked_mul(value)
+            .map(Duration::from_nanos)
+            .ok_or_else(|| E::custom(format!("duration is too large: {}", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        if value < 0 {
+            return Err(E::custom(format!(
+                "duration must not be negative: {}",
+                value
+            )));
+        }
+        self.visit_u64(value as u64)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse_duration(value).map_err(E::custom)
+    }
+}
+
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    unit: Duration,
+) -> Result<Duration, D::Error> {
+    deserializer.deserialize_any(DurationVisitor { unit })
+}
+
+/// Deserialize a duration into milliseconds, bare integers are milliseconds.
+/// Durations which are not a whole number of milliseconds are rejected.
+pub fn milliseconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AtomicUsize, D::Error> {
+    let duration = duration(deserializer, Duration::from_millis(1))?;
+    if duration.subsec_nanos() % 1_000_000 != 0 {
+        return Err(de::Error::custom(format!(
+            "duration must be a whole number of milliseconds: {:?}",
+            duration
+        )));
+    }
+    Ok(AtomicUsize::new(duration.as_millis() as usize))
+}
+
+/// The same as `milliseconds` for settings which are optional
+pub fn optional_milliseconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<AtomicUsize>, D::Error> {
+    milliseconds(deserializer).map(Some)
+}
+
+/// Deserialize an optional duration into nanoseconds. Bare integers are taken
+/// as is, so that limits on values which are not latencies, such as sizes in
+/// bytes, may also be given.
+pub fn optional_nanoseconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    let duration = duration(deserializer, Duration::from_nanos(1))?;
+    Ok(Some(duration.as_nanos() as u64))
+}
+
+/// Deserialize a duration into seconds, bare integers are seconds. Durations
+/// which are not a whole number of seconds are rejected.
+pub fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AtomicUsize, D::Error> {
+    let duration = duration(deserializer, Duration::from_secs(1))?;
+    if duration.subsec_nanos() != 0 {
+        return Err(de::Error::custom(format!(
+            "duration must be a whole number of seconds: {:?}",
+            duration
+        )));
+    }
+    Ok(AtomicUsize::new(duration.as_secs() as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_derive::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Durations {
+        #[serde(default, deserialize_with = "optional_milliseconds")]
+        interval: Option<AtomicUsize>,
+        #[serde(default, deserialize_with = "seconds")]
+        window: AtomicUsize,
+        #[serde(default, deserialize_with = "optional_nanoseconds")]
+        max_value: Option<u64>,
+    }
+
+    fn parse(content: &str) -> Result<(Option<usize>, usize), String> {
+        let durations: Durations = toml::from_str(content).map_err(|e| e.to_string())?;
+        Ok((
+            durations.interval.map(|v| v.load(Ordering::Relaxed)),
+            durations.window.load(Ordering::Relaxed),
+        ))
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert_eq!(parse_duration("0.5s"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("0.3s"), Ok(Duration::from_millis(300)));
+        assert_eq!(parse_duration(".25s"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2.5ms"), Ok(Duration::from_micros(2500)));
+        assert!(parse_duration(".s").is_err());
+        assert!(parse_duration("1.2.3s").is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(parse("interval = \"250ms\""), Ok((Some(250), 0)));
+        assert_eq!(parse("interval = \"2s\""), Ok((Some(2000), 0)));
+        assert_eq!(parse("interval = 5"), Ok((Some(5), 0)));
+        assert_eq!(parse("window = \"2s\""), Ok((None, 2)));
+        assert_eq!(parse("window = \"1m\""), Ok((None, 60)));
+        assert_eq!(parse("window = 5"), Ok((None, 5)));
+        assert!(parse("window = \"250ms\"").is_err());
+        assert!(parse("interval = -1").is_err());
+        assert_eq!(parse("interval = \"0.01s\""), Ok((Some(10), 0)));
+        assert!(parse("interval = \"2.5ms\"").is_err());
+    }
+
+    #[test]
+    fn test_nanoseconds() {
+        let max_value = |content: &str| {
+            toml::from_str::<Durations>(content)
+                .map(|d| d.max_value)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(max_value(""), Ok(None));
+        assert_eq!(max_value("max_value = \"10s\""), Ok(Some(10_000_000_000)));
+        assert_eq!(max_value("max_value = 65536"), Ok(Some(65536)));
+    }
+}
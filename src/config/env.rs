@@ -0,0 +1,115 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use toml::value::{Table, Value};
+
+use crate::config::samplers::SAMPLER_NAMES;
+
+/// Environment variables with this prefix override a setting of a sampler, eg:
+/// `REZOLUS_SAMPLERS_TCP_ENABLED=true` sets `enabled` in `[samplers.tcp]`
+pub const PREFIX: &str = "REZOLUS_SAMPLERS_";
+
+/// The overrides among the environment variables, as pairs of variable name
+/// and value
+pub fn overrides<I>(vars: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    vars.into_iter()
+        .filter(|(key, _)| key.starts_with(PREFIX))
+        .collect()
+}
+
+/// Set each overridden sampler setting in the parsed config file, so that the
+/// environment takes precedence over the file. The rest of the variable name is
+/// lowercased and split into the sampler, which may itself contain underscores,
+/// eg: `PAGE_CACHE`, and the setting. Values are parsed as TOML, eg: `true` or
+/// `1000`, and otherwise used as a string.
+pub fn apply(config: &mut Value, overrides: &[(String, String)]) -> Result<(), anyhow::Error> {
+    for (key, raw) in overrides {
+        let path = key[PREFIX.len()..].to_lowercase();
+        let (sampler, setting) = SAMPLER_NAMES
+            .iter()
+            .filter_map(|name| {
+                path.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix('_'))
+                    .map(|setting| (*name, setting))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .ok_or_else(|| format_err!("{} does not name a sampler", key))?;
+        if setting.is_empty() {
+            return Err(format_err!("{} does not name a sampler setting", key));
+        }
+        let samplers = section(config, "samplers", key)?;
+        let sampler = section(samplers, sampler, key)?;
+        sampler
+            .as_table_mut()
+            .ok_or_else(|| format_err!("{} overrides a setting which is not a table", key))?
+            .insert(setting.to_string(), parse_value(raw));
+    }
+    Ok(())
+}
+
+/// The named table within the value, which is created if it is missing
+fn section<'a>(
+    value: &'a mut Value,
+    name: &str,
+    key: &str,
+) -> Result<&'a mut Value, anyhow::Error> {
+    Ok(value
+        .as_table_mut()
+        .ok_or_else(|| format_err!("{} overrides a setting which is not a table", key))?
+        .entry(name.to_string())
+        .or_insert_with(|| Value::Table(Table::new())))
+}
+
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(parse_value("1000"), Value::Integer(1000));
+        assert_eq!(parse_value("eth0"), Value::String("eth0".to_string()));
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config: Value = toml::from_str("[samplers.cpu]\nenabled = false\n").unwrap();
+        let overrides = overrides(vec![
+            (
+                "REZOLUS_SAMPLERS_CPU_ENABLED".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "REZOLUS_SAMPLERS_PAGE_CACHE_INTERVAL".to_string(),
+                "500".to_string(),
+            ),
+            ("HOME".to_string(), "/root".to_string()),
+        ]);
+        assert_eq!(overrides.len(), 2);
+        apply(&mut config, &overrides).unwrap();
+        assert_eq!(config["samplers"]["cpu"]["enabled"], Value::Boolean(true));
+        assert_eq!(
+            config["samplers"]["page_cache"]["interval"],
+            Value::Integer(500)
+        );
+
+        let overrides = vec![(
+            "REZOLUS_SAMPLERS_NOPE_ENABLED".to_string(),
+            "true".to_string(),
+        )];
+        assert!(apply(&mut config, &overrides).is_err());
+        let overrides = vec![("REZOLUS_SAMPLERS_TCP".to_string(), "true".to_string())];
+        assert!(apply(&mut config, &overrides).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DebugBuffer {
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    #[serde(default = "default_interval")]
+    interval: AtomicUsize,
+    #[serde(default = "default_samples")]
+    samples: AtomicUsize,
+}
+
+impl Default for DebugBuffer {
+    fn default() -> DebugBuffer {
+        DebugBuffer {
+            enabled: default_enabled(),
+            interval: default_interval(),
+            samples: default_samples(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_interval() -> AtomicUsize {
+    AtomicUsize::new(1_000)
+}
+
+fn default_samples() -> AtomicUsize {
+    AtomicUsize::new(60)
+}
+
+impl DebugBuffer {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// interval in ms between readings of the registry
+    pub fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// number of readings retained for each statistic
+    pub fn samples(&self) -> usize {
+        self.samples.load(Ordering::Relaxed)
+    }
+}
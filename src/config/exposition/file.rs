@@ -0,0 +1,91 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct File {
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    #[serde(default)]
+    emit: Emit,
+    #[serde(default = "default_interval")]
+    interval: AtomicUsize,
+    path: Option<String>,
+    #[serde(default = "default_max_bytes")]
+    max_bytes: AtomicUsize,
+    #[serde(default = "default_max_files")]
+    max_files: AtomicUsize,
+    #[serde(default)]
+    temporality: Temporality,
+}
+
+impl Default for File {
+    fn default() -> File {
+        File {
+            enabled: default_enabled(),
+            emit: Default::default(),
+            interval: default_interval(),
+            path: Default::default(),
+            max_bytes: default_max_bytes(),
+            max_files: default_max_files(),
+            temporality: Default::default(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_interval() -> AtomicUsize {
+    AtomicUsize::new(60_000)
+}
+
+fn default_max_bytes() -> AtomicUsize {
+    AtomicUsize::new(100 * 1024 * 1024)
+}
+
+fn default_max_files() -> AtomicUsize {
+    AtomicUsize::new(5)
+}
+
+impl File {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// whether counters are exported as their reading or as a per-second rate
+    pub fn emit(&self) -> Emit {
+        self.emit
+    }
+
+    /// whether counters are exported as their running total or as the
+    /// increase since the previous export
+    pub fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+
+    /// interval in ms between writing the metrics to the file
+    pub fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// path of the file which is currently being written
+    pub fn path(&self) -> Option<String> {
+        self.path.clone()
+    }
+
+    /// size in bytes at which the file is rotated
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// total number of files to keep, including the current file
+    pub fn max_files(&self) -> usize {
+        self.max_files.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Graphite {
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    #[serde(default)]
+    emit: Emit,
+    #[serde(default = "default_interval")]
+    interval: AtomicUsize,
+    address: Option<String>,
+    #[serde(default = "default_retries")]
+    retries: AtomicUsize,
+    #[serde(default)]
+    temporality: Temporality,
+}
+
+impl Default for Graphite {
+    fn default() -> Graphite {
+        Graphite {
+            enabled: default_enabled(),
+            emit: Default::default(),
+            interval: default_interval(),
+            address: Default::default(),
+            retries: default_retries(),
+            temporality: Default::default(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_interval() -> AtomicUsize {
+    AtomicUsize::new(10_000)
+}
+
+fn default_retries() -> AtomicUsize {
+    AtomicUsize::new(3)
+}
+
+impl Graphite {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// whether counters are exported as their reading or as a per-second rate
+    pub fn emit(&self) -> Emit {
+        self.emit
+    }
+
+    /// whether counters are exported as their running total or as the
+    /// increase since the previous export
+    pub fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+
+    /// interval in ms between pushes to the carbon server
+    pub fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// `host:port` of the carbon server, which is resolved on each connect
+    pub fn address(&self) -> Option<String> {
+        self.address.clone()
+    }
+
+    /// number of times to reconnect and resend before giving up on a push
+    pub fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
@@ -10,19 +10,25 @@ use rustcommon_atomics::*;
 pub struct Kafka {
     #[serde(default = "default_enabled")]
     enabled: AtomicBool,
+    #[serde(default)]
+    emit: Emit,
     #[serde(default = "default_interval")]
     interval: AtomicUsize,
     hosts: Vec<String>,
     topic: Option<String>,
+    #[serde(default)]
+    temporality: Temporality,
 }
 
 impl Default for Kafka {
     fn default() -> Kafka {
         Kafka {
             enabled: default_enabled(),
+            emit: Default::default(),
             interval: default_interval(),
             hosts: Default::default(),
             topic: Default::default(),
+            temporality: Default::default(),
         }
     }
 }
@@ -41,6 +47,17 @@ impl Kafka {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    /// whether counters are exported as their reading or as a per-second rate
+    pub fn emit(&self) -> Emit {
+        self.emit
+    }
+
+    /// whether counters are exported as their running total or as the
+    /// increase since the previous export
+    pub fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+
     pub fn interval(&self) -> usize {
         self.interval.load(Ordering::Relaxed)
     }
@@ -4,20 +4,194 @@
 
 use serde_derive::*;
 
+mod debug;
+mod file;
+mod graphite;
 mod kafka;
+mod otlp;
+mod prometheus;
+mod statsd;
 
+use self::debug::*;
+use self::file::*;
+use self::graphite::*;
 use self::kafka::*;
+use self::otlp::*;
+use self::prometheus::*;
+pub use self::prometheus::{Auth, ListenAddress};
+use self::statsd::*;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Exposition {
+    #[serde(default)]
+    allow_reload: bool,
+    #[serde(default)]
+    debug: DebugBuffer,
+    #[serde(default)]
+    file: File,
+    #[serde(default)]
+    graphite: Graphite,
     #[serde(default)]
     kafka: Kafka,
+    #[serde(default)]
+    otlp: Otlp,
+    #[serde(default)]
+    percentile_precision: Option<i32>,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    prometheus: Prometheus,
+    #[serde(default = "default_separator")]
+    separator: String,
+    #[serde(default)]
+    stale_after: Option<u32>,
+    #[serde(default)]
+    statsd: Statsd,
+}
+
+impl Default for Exposition {
+    fn default() -> Self {
+        Self {
+            allow_reload: Default::default(),
+            debug: Default::default(),
+            file: Default::default(),
+            graphite: Default::default(),
+            kafka: Default::default(),
+            otlp: Default::default(),
+            percentile_precision: Default::default(),
+            prefix: Default::default(),
+            prometheus: Default::default(),
+            separator: default_separator(),
+            stale_after: Default::default(),
+            statsd: Default::default(),
+        }
+    }
+}
+
+/// The largest power of ten which fits in a `u64`, as rounding to a coarser
+/// precision would overflow
+pub const MAX_PERCENTILE_SCALE: i32 = 19;
+
+fn default_separator() -> String {
+    "/".to_string()
 }
 
 impl Exposition {
+    /// Whether the prometheus listener accepts `POST /-/reload`, which reloads
+    /// the config in the same way as a SIGHUP
+    pub fn allow_reload(&self) -> bool {
+        self.allow_reload
+    }
+
+    pub fn debug(&self) -> &DebugBuffer {
+        &self.debug
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn graphite(&self) -> &Graphite {
+        &self.graphite
+    }
+
     #[cfg(feature = "push_kafka")]
     pub fn kafka(&self) -> &Kafka {
         &self.kafka
     }
+
+    #[cfg(feature = "push_otlp")]
+    pub fn otlp(&self) -> &Otlp {
+        &self.otlp
+    }
+
+    /// The number of decimal places to which percentile values are rounded on
+    /// output. Readings are whole numbers, so only a negative precision has any
+    /// effect, eg: `-3` rounds a latency in nanoseconds to whole microseconds.
+    /// Percentiles are output at full precision if this is not set
+    pub fn percentile_precision(&self) -> Option<i32> {
+        self.percentile_precision
+    }
+
+    /// Prepended to each metric name on output
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn prometheus(&self) -> &Prometheus {
+        &self.prometheus
+    }
+
+    /// Joins the prefix and the components of each metric name on output
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// The number of sampler intervals after which a series which has not
+    /// been updated is omitted by the exporters. Series are always exported
+    /// if this is not set
+    pub fn stale_after(&self) -> Option<u32> {
+        self.stale_after
+    }
+
+    pub fn statsd(&self) -> &Statsd {
+        &self.statsd
+    }
+
+    /// Whether any exporter is enabled in addition to the main http listener
+    pub fn any_enabled(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut enabled = self.file.enabled()
+            || self.graphite.enabled()
+            || self.prometheus.enabled()
+            || self.statsd.enabled();
+        #[cfg(feature = "push_kafka")]
+        {
+            enabled |= self.kafka.enabled();
+        }
+        #[cfg(feature = "push_otlp")]
+        {
+            enabled |= self.otlp.enabled();
+        }
+        enabled
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        #[allow(unused_mut)]
+        let mut intervals = vec![
+            ("debug", self.debug.interval()),
+            ("file", self.file.interval()),
+            ("graphite", self.graphite.interval()),
+            ("statsd", self.statsd.interval()),
+        ];
+        #[cfg(feature = "push_kafka")]
+        intervals.push(("kafka", self.kafka.interval()));
+        #[cfg(feature = "push_otlp")]
+        intervals.push(("otlp", self.otlp.interval()));
+        for (name, interval) in intervals {
+            if interval == 0 {
+                errors.push(format!(
+                    "exposition.{}.interval must be greater than zero",
+                    name
+                ));
+            }
+        }
+        if self.debug.samples() == 0 {
+            errors.push("exposition.debug.samples must be greater than zero".to_string());
+        }
+        if let Some(precision) = self.percentile_precision {
+            if precision < -MAX_PERCENTILE_SCALE {
+                errors.push(format!(
+                    "exposition.percentile_precision must be at least -{}",
+                    MAX_PERCENTILE_SCALE
+                ));
+            }
+        }
+        if self.stale_after == Some(0) {
+            errors.push("exposition.stale_after must be greater than zero".to_string());
+        }
+        self.prometheus.validate(errors);
+    }
 }
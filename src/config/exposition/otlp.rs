@@ -0,0 +1,72 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Otlp {
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    #[serde(default)]
+    emit: Emit,
+    #[serde(default = "default_interval")]
+    interval: AtomicUsize,
+    endpoint: Option<String>,
+    #[serde(default = "default_temporality")]
+    temporality: Temporality,
+}
+
+impl Default for Otlp {
+    fn default() -> Otlp {
+        Otlp {
+            enabled: default_enabled(),
+            emit: Default::default(),
+            interval: default_interval(),
+            endpoint: Default::default(),
+            temporality: default_temporality(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_temporality() -> Temporality {
+    Temporality::Delta
+}
+
+fn default_interval() -> AtomicUsize {
+    AtomicUsize::new(10_000)
+}
+
+#[cfg(feature = "push_otlp")]
+impl Otlp {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// whether counters are exported as their reading or as a per-second rate
+    pub fn emit(&self) -> Emit {
+        self.emit
+    }
+
+    /// whether counters are exported as their running total or as the
+    /// increase since the previous export
+    pub fn temporality(&self) -> Temporality {
+        self.temporality
+    }
+
+    /// interval in ms between exports to the collector
+    pub fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// collector endpoint, eg: `http://localhost:4317`
+    pub fn endpoint(&self) -> Option<String> {
+        self.endpoint.clone()
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::path::PathBuf;
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+/// Prefix of a listen address which is the path of a Unix domain socket
+const UNIX_PREFIX: &str = "unix:";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Prometheus {
+    auth: Option<Auth>,
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    listen: Option<String>,
+    #[serde(default = "default_max_request_size")]
+    max_request_size: usize,
+    #[serde(default)]
+    openmetrics: bool,
+    #[serde(
+        default = "default_timeout",
+        deserialize_with = "crate::config::duration::milliseconds"
+    )]
+    read_timeout: AtomicUsize,
+    #[serde(default = "default_socket_mode")]
+    socket_mode: u32,
+    tls: Option<Tls>,
+    #[serde(
+        default = "default_timeout",
+        deserialize_with = "crate::config::duration::milliseconds"
+    )]
+    write_timeout: AtomicUsize,
+}
+
+impl Default for Prometheus {
+    fn default() -> Prometheus {
+        Prometheus {
+            auth: Default::default(),
+            enabled: default_enabled(),
+            listen: Default::default(),
+            max_request_size: default_max_request_size(),
+            openmetrics: Default::default(),
+            read_timeout: default_timeout(),
+            socket_mode: default_socket_mode(),
+            tls: Default::default(),
+            write_timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_socket_mode() -> u32 {
+    0o660
+}
+
+fn default_max_request_size() -> usize {
+    8192
+}
+
+fn default_timeout() -> AtomicUsize {
+    AtomicUsize::new(5000)
+}
+
+/// Where the dedicated prometheus listener is bound
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+/// Parse either a `host:port` socket address or `unix:/path/to/socket`
+fn parse_listen(listen: &str) -> Result<ListenAddress, String> {
+    if let Some(path) = listen.strip_prefix(UNIX_PREFIX) {
+        if path.is_empty() {
+            return Err("unix socket path is empty".to_string());
+        }
+        return Ok(ListenAddress::Unix(PathBuf::from(path)));
+    }
+    listen
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .map(ListenAddress::Tcp)
+        .ok_or_else(|| "address did not resolve".to_string())
+}
+
+impl Prometheus {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// address for the dedicated prometheus listener
+    pub fn listen(&self) -> Option<ListenAddress> {
+        self.listen.as_ref().map(|v| parse_listen(v).unwrap())
+    }
+
+    /// file mode of the socket when listening on a unix socket
+    pub fn socket_mode(&self) -> u32 {
+        self.socket_mode
+    }
+
+    /// credentials required of each request, if absent the listener is open
+    pub fn auth(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+
+    /// tls settings, if absent the listener serves plain http
+    pub fn tls(&self) -> Option<&Tls> {
+        self.tls.as_ref()
+    }
+
+    /// largest request line and headers, in bytes, which are accepted
+    pub fn max_request_size(&self) -> usize {
+        self.max_request_size
+    }
+
+    /// whether OpenMetrics is served to scrapers which ask for it, rather than
+    /// always serving the text format
+    pub fn openmetrics(&self) -> bool {
+        self.openmetrics
+    }
+
+    /// time in ms to wait for a request to be read before dropping the
+    /// connection
+    pub fn read_timeout(&self) -> usize {
+        self.read_timeout.load(Ordering::Relaxed)
+    }
+
+    /// time in ms to wait for a response to be written before dropping the
+    /// connection
+    pub fn write_timeout(&self) -> usize {
+        self.write_timeout.load(Ordering::Relaxed)
+    }
+
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if let Some(listen) = &self.listen {
+            match parse_listen(listen) {
+                Ok(ListenAddress::Unix(_)) => {
+                    if self.tls.is_some() {
+                        errors.push(
+                            "exposition.prometheus.tls is not supported on a unix socket"
+                                .to_string(),
+                        );
+                    }
+                }
+                Ok(ListenAddress::Tcp(_)) => {}
+                Err(e) => {
+                    errors.push(format!(
+                        "exposition.prometheus.listen is invalid: {}: {}",
+                        listen, e
+                    ));
+                }
+            }
+        }
+        for (name, timeout) in &[
+            ("read_timeout", self.read_timeout()),
+            ("write_timeout", self.write_timeout()),
+        ] {
+            if *timeout == 0 {
+                errors.push(format!(
+                    "exposition.prometheus.{} must be greater than zero",
+                    name
+                ));
+            }
+        }
+        if self.max_request_size == 0 {
+            errors.push(
+                "exposition.prometheus.max_request_size must be greater than zero".to_string(),
+            );
+        }
+        if let Some(auth) = &self.auth {
+            auth.validate(errors);
+        }
+        if self.socket_mode > 0o7777 {
+            errors.push(format!(
+                "exposition.prometheus.socket_mode is not a valid file mode: {:o}",
+                self.socket_mode
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    cert_path: String,
+    key_path: String,
+    client_ca_path: Option<String>,
+}
+
+impl Tls {
+    /// path to the PEM encoded certificate chain
+    pub fn cert_path(&self) -> &str {
+        &self.cert_path
+    }
+
+    /// path to the PEM encoded private key
+    pub fn key_path(&self) -> &str {
+        &self.key_path
+    }
+
+    /// path to the PEM encoded CA certificates used to verify clients. If set,
+    /// clients are required to present a certificate
+    pub fn client_ca_path(&self) -> Option<&str> {
+        self.client_ca_path.as_deref()
+    }
+}
+
+/// Either a username and password for HTTP Basic authentication, or a static
+/// bearer token
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Auth {
+    username: Option<String>,
+    password: Option<String>,
+    token: Option<String>,
+}
+
+impl Auth {
+    /// username and password required by HTTP Basic authentication
+    pub fn basic(&self) -> Option<(&str, &str)> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            _ => None,
+        }
+    }
+
+    /// token required by bearer authentication
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        let basic = self.username.is_some() || self.password.is_some();
+        if basic && self.token.is_some() {
+            errors.push(
+                "exposition.prometheus.auth must set either username and password, or token, not both"
+                    .to_string(),
+            );
+        } else if basic && self.basic().is_none() {
+            errors
+                .push("exposition.prometheus.auth requires both username and password".to_string());
+        } else if !basic && self.token.is_none() {
+            errors.push(
+                "exposition.prometheus.auth must set either username and password, or token"
+                    .to_string(),
+            );
+        }
+        if self.username.as_deref().map_or(false, |u| u.contains(':')) {
+            errors.push("exposition.prometheus.auth.username may not contain ':'".to_string());
+        }
+        if self.token.as_deref() == Some("") {
+            errors.push("exposition.prometheus.auth.token may not be empty".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen() {
+        assert_eq!(
+            parse_listen("127.0.0.1:4243"),
+            Ok(ListenAddress::Tcp("127.0.0.1:4243".parse().unwrap()))
+        );
+        assert_eq!(
+            parse_listen("unix:/run/rezolus.sock"),
+            Ok(ListenAddress::Unix(PathBuf::from("/run/rezolus.sock")))
+        );
+        assert!(parse_listen("unix:").is_err());
+        assert!(parse_listen("localhost").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let prometheus = Prometheus::default();
+        assert_eq!(prometheus.read_timeout(), 5000);
+        assert_eq!(prometheus.write_timeout(), 5000);
+        assert_eq!(prometheus.max_request_size(), 8192);
+
+        let prometheus: Prometheus =
+            toml::from_str("read_timeout = \"2s\"\nwrite_timeout = 0\n").unwrap();
+        assert_eq!(prometheus.read_timeout(), 2000);
+        let mut errors = Vec::new();
+        prometheus.validate(&mut errors);
+        assert_eq!(
+            errors,
+            vec!["exposition.prometheus.write_timeout must be greater than zero".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_auth() {
+        let auth = |content: &str| {
+            let prometheus: Prometheus = toml::from_str(content).unwrap();
+            let mut errors = Vec::new();
+            prometheus.validate(&mut errors);
+            errors
+        };
+        assert!(auth("").is_empty());
+        assert!(auth("[auth]\nusername = \"prom\"\npassword = \"secret\"\n").is_empty());
+        assert!(auth("[auth]\ntoken = \"secret\"\n").is_empty());
+        assert_eq!(auth("[auth]\nusername = \"prom\"\n").len(), 1);
+        assert_eq!(auth("[auth]\n").len(), 1);
+        assert_eq!(
+            auth("[auth]\nusername = \"prom\"\npassword = \"a\"\ntoken = \"b\"\n").len(),
+            1
+        );
+        assert_eq!(
+            auth("[auth]\nusername = \"a:b\"\npassword = \"c\"\n").len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+
+use crate::config::*;
+use rustcommon_atomics::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Statsd {
+    #[serde(default = "default_enabled")]
+    enabled: AtomicBool,
+    #[serde(default)]
+    emit: Emit,
+    #[serde(default = "default_interval")]
+    interval: AtomicUsize,
+    address: Option<String>,
+    #[serde(default = "default_mtu")]
+    mtu: AtomicUsize,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl Default for Statsd {
+    fn default() -> Statsd {
+        Statsd {
+            enabled: default_enabled(),
+            emit: Default::default(),
+            interval: default_interval(),
+            address: Default::default(),
+            mtu: default_mtu(),
+            tags: Default::default(),
+        }
+    }
+}
+
+fn default_enabled() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn default_interval() -> AtomicUsize {
+    AtomicUsize::new(10_000)
+}
+
+fn default_mtu() -> AtomicUsize {
+    AtomicUsize::new(1432)
+}
+
+impl Statsd {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// whether counters are exported as their reading or as a per-second rate
+    pub fn emit(&self) -> Emit {
+        self.emit
+    }
+
+    /// interval in ms between pushes to the statsd server
+    pub fn interval(&self) -> usize {
+        self.interval.load(Ordering::Relaxed)
+    }
+
+    /// socket address of the statsd server
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address
+            .as_ref()
+            .map(|v| v.to_socket_addrs().unwrap().next().unwrap())
+    }
+
+    /// maximum size in bytes of each datagram
+    pub fn mtu(&self) -> usize {
+        self.mtu.load(Ordering::Relaxed)
+    }
+
+    /// DogStatsD tags which are added to every metric
+    pub fn tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
+    }
+}
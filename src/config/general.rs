@@ -13,16 +13,52 @@ pub struct General {
     #[serde(with = "LevelDef")]
     #[serde(default = "default_logging_level")]
     logging: Level,
-    #[serde(default = "default_interval")]
+    #[serde(
+        default = "default_interval",
+        deserialize_with = "crate::config::duration::milliseconds"
+    )]
     interval: AtomicUsize,
+    #[serde(default)]
+    jitter: f64,
+    #[serde(default)]
+    skip_if_behind: bool,
     #[serde(default = "default_threads")]
     threads: usize,
-    #[serde(default = "default_window")]
+    #[serde(default)]
+    cpu_affinity: Vec<usize>,
+    #[serde(
+        default = "default_window",
+        deserialize_with = "crate::config::duration::seconds"
+    )]
     window: AtomicUsize,
     #[serde(default = "default_fault_tolerant")]
     fault_tolerant: AtomicBool,
     #[serde(default = "default_reading_suffix")]
     reading_suffix: String,
+    #[serde(
+        default = "default_readiness_staleness",
+        deserialize_with = "crate::config::duration::seconds"
+    )]
+    readiness_staleness: AtomicUsize,
+    #[serde(default)]
+    readiness_strict: bool,
+    #[serde(
+        default = "default_reload_grace_period",
+        deserialize_with = "crate::config::duration::seconds"
+    )]
+    reload_grace_period: AtomicUsize,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    max_bpf_staleness: Option<AtomicUsize>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    bpf_poll_interval: Option<AtomicUsize>,
+    #[serde(default)]
+    max_counter_delta: Option<u64>,
 }
 
 impl General {
@@ -43,10 +79,28 @@ impl General {
         self.interval.load(Ordering::Relaxed)
     }
 
+    /// fraction of the interval, from 0.0 to 1.0, by which to randomly offset
+    /// the first sample of each sampler
+    pub fn jitter(&self) -> f64 {
+        self.jitter.max(0.0).min(1.0)
+    }
+
+    /// whether a sampler which has fallen more than an interval behind skips
+    /// the missed intervals, rather than sampling once for each of them in
+    /// quick succession to catch up
+    pub fn skip_if_behind(&self) -> bool {
+        self.skip_if_behind
+    }
+
     pub fn threads(&self) -> usize {
         self.threads
     }
 
+    /// the CPUs which Rezolus is confined to, or all of them if empty
+    pub fn cpu_affinity(&self) -> &[usize] {
+        &self.cpu_affinity
+    }
+
     /// windows for histogram lookback
     pub fn window(&self) -> usize {
         self.window.load(Ordering::Relaxed) as usize
@@ -56,6 +110,102 @@ impl General {
         self.fault_tolerant.load(Ordering::Relaxed)
     }
 
+    /// seconds since its last successful sample after which a sampler fails
+    /// the readiness check
+    pub fn readiness_staleness(&self) -> usize {
+        self.readiness_staleness.load(Ordering::Relaxed)
+    }
+
+    /// whether samplers which failed to initialize fail the readiness check
+    pub fn readiness_strict(&self) -> bool {
+        self.readiness_strict
+    }
+
+    /// seconds for which the statistics of a sampler disabled by a config
+    /// reload keep their last values before they are deregistered
+    pub fn reload_grace_period(&self) -> usize {
+        self.reload_grace_period.load(Ordering::Relaxed)
+    }
+
+    /// ms after which BPF maps are drained even if the sampler interval has not
+    /// passed, with a warning logged if this happens
+    pub fn max_bpf_staleness(&self) -> Option<usize> {
+        self.max_bpf_staleness
+            .as_ref()
+            .map(|v| v.load(Ordering::Relaxed))
+    }
+
+    /// ms between drains of the BPF histogram maps into the in-process
+    /// histograms, independent of the sampling interval, which samplers wake
+    /// up for in between their samples if it is shorter
+    pub fn bpf_poll_interval(&self) -> Option<usize> {
+        self.bpf_poll_interval
+            .as_ref()
+            .map(|v| v.load(Ordering::Relaxed))
+    }
+
+    /// the largest plausible increase of a counter between two consecutive
+    /// readings, larger increases are clamped to it as spurious
+    pub fn max_counter_delta(&self) -> Option<u64> {
+        self.max_counter_delta
+    }
+
+    /// Apply the hot-reloadable fields from a newly loaded config, adding a
+    /// description of each change to `changes`
+    pub fn reload(&self, other: &General, changes: &mut Changes) {
+        if self.interval() != other.interval() {
+            changes.applied.push(format!(
+                "general.interval: {} -> {}",
+                self.interval(),
+                other.interval()
+            ));
+        }
+        if self.fault_tolerant() != other.fault_tolerant() {
+            changes.applied.push(format!(
+                "general.fault_tolerant: {} -> {}",
+                self.fault_tolerant(),
+                other.fault_tolerant()
+            ));
+        }
+        self.interval.store(other.interval(), Ordering::Relaxed);
+        self.fault_tolerant
+            .store(other.fault_tolerant(), Ordering::Relaxed);
+        if format!("{:?}", self) != format!("{:?}", other) {
+            changes.restart.push("general".to_string());
+        }
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.interval() == 0 {
+            errors.push("general.interval must be greater than zero".to_string());
+        }
+        if self.window() == 0 {
+            errors.push("general.window must be greater than zero".to_string());
+        }
+        if self.max_bpf_staleness() == Some(0) {
+            errors.push("general.max_bpf_staleness must be greater than zero".to_string());
+        }
+        if self.bpf_poll_interval() == Some(0) {
+            errors.push("general.bpf_poll_interval must be greater than zero".to_string());
+        }
+        if self.max_counter_delta() == Some(0) {
+            errors.push("general.max_counter_delta must be greater than zero".to_string());
+        }
+        if self.readiness_staleness() == 0 {
+            errors.push("general.readiness_staleness must be greater than zero".to_string());
+        }
+        for cpu in self.cpu_affinity() {
+            if *cpu >= libc::CPU_SETSIZE as usize {
+                errors.push(format!(
+                    "general.cpu_affinity contains cpu {} which is not below {}",
+                    cpu,
+                    libc::CPU_SETSIZE
+                ));
+            }
+        }
+    }
+
     pub fn reading_suffix(&self) -> Option<&str> {
         if self.reading_suffix.len() == 0 {
             None
@@ -71,10 +221,19 @@ impl Default for General {
             listen: None,
             logging: default_logging_level(),
             interval: default_interval(),
+            jitter: Default::default(),
+            skip_if_behind: Default::default(),
             threads: default_threads(),
+            cpu_affinity: Default::default(),
             window: default_window(),
             fault_tolerant: default_fault_tolerant(),
             reading_suffix: default_reading_suffix(),
+            readiness_staleness: default_readiness_staleness(),
+            readiness_strict: Default::default(),
+            reload_grace_period: default_reload_grace_period(),
+            max_bpf_staleness: Default::default(),
+            bpf_poll_interval: Default::default(),
+            max_counter_delta: Default::default(),
         }
     }
 }
@@ -99,6 +258,14 @@ fn default_reading_suffix() -> String {
     "count".to_string()
 }
 
+fn default_readiness_staleness() -> AtomicUsize {
+    AtomicUsize::new(60)
+}
+
+fn default_reload_grace_period() -> AtomicUsize {
+    AtomicUsize::new(60)
+}
+
 #[derive(Clone, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
 #[serde(remote = "Level")]
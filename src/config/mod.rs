@@ -2,20 +2,24 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+pub mod duration;
+mod env;
 mod exposition;
 mod general;
 mod samplers;
 
+use std::collections::BTreeMap;
 use std::io::Read;
 use std::net::{SocketAddr, ToSocketAddrs};
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgGroup, ErrorKind};
 use rustcommon_logger::Level;
 use serde_derive::*;
 
 use crate::*;
 
 use config::exposition::*;
+pub use config::exposition::{Auth, ListenAddress};
 pub use config::general::General;
 use config::samplers::*;
 
@@ -30,7 +34,17 @@ pub struct Config {
     #[serde(default)]
     general: General,
     #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
     samplers: Samplers,
+    #[serde(skip)]
+    format: ConfigFormat,
+    #[serde(skip)]
+    list_statistics: Option<ListStatistics>,
+    #[serde(skip)]
+    once: Option<OutputFormat>,
+    #[serde(skip)]
+    path: Option<String>,
 }
 
 impl Config {
@@ -44,9 +58,53 @@ impl Config {
                 Arg::with_name("config")
                     .long("config")
                     .value_name("FILE")
-                    .help("TOML config file")
+                    .help("TOML or YAML config file")
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("config-format")
+                    .long("config-format")
+                    .value_name("FORMAT")
+                    .help(
+                        "Format of the config file, by default yaml for .yaml or .yml files \
+                         and toml otherwise",
+                    )
+                    .possible_values(&["toml", "yaml"])
+                    .takes_value(true)
+                    .requires("config"),
+            )
+            .arg(
+                Arg::with_name("check-config")
+                    .long("check-config")
+                    .help("Validate the config file and exit without sampling")
+                    .requires("config"),
+            )
+            .arg(
+                Arg::with_name("once")
+                    .long("once")
+                    .help("Take a single sample, print the metrics to stdout, and exit"),
+            )
+            .arg(
+                Arg::with_name("list-statistics")
+                    .long("list-statistics")
+                    .value_name("SAMPLER")
+                    .help("List the statistics of every sampler, or only the given one, and exit")
+                    .takes_value(true)
+                    .min_values(0)
+                    .max_values(1),
+            )
+            .group(ArgGroup::with_name("mode").args(&["once", "list-statistics"]))
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .help(
+                        "Output format for --once, json (default) or prometheus, or for \
+                         --list-statistics, table (default) or json",
+                    )
+                    .possible_values(&["json", "prometheus", "table"])
+                    .requires("mode"),
+            )
             .arg(
                 Arg::with_name("verbose")
                     .short("v")
@@ -57,13 +115,65 @@ impl Config {
 
         let matches = app.get_matches();
 
+        if matches.is_present("list-statistics") {
+            let format = match matches.value_of("format") {
+                Some("json") => ListFormat::Json,
+                Some("prometheus") => clap::Error::with_description(
+                    "--list-statistics does not support the prometheus format",
+                    ErrorKind::InvalidValue,
+                )
+                .exit(),
+                _ => ListFormat::Table,
+            };
+            return Config {
+                list_statistics: Some(ListStatistics {
+                    sampler: matches.value_of("list-statistics").map(|v| v.to_string()),
+                    format,
+                }),
+                ..Default::default()
+            };
+        }
+
+        let format = match matches.value_of("config-format") {
+            Some("yaml") => ConfigFormat::Yaml,
+            Some(_) => ConfigFormat::Toml,
+            None => matches
+                .value_of("config")
+                .map(ConfigFormat::from_path)
+                .unwrap_or_default(),
+        };
+
+        if matches.is_present("check-config") {
+            Config::check(matches.value_of("config").unwrap(), format);
+        }
+
         let mut config = if let Some(file) = matches.value_of("config") {
-            Config::load_from_file(file)
+            let mut config = Config::load_from_file(file, format);
+            config.path = Some(file.to_string());
+            config
         } else {
-            println!("NOTE: using builtin base configuration");
+            if !matches.is_present("once") {
+                println!("NOTE: using builtin base configuration");
+            }
             Default::default()
         };
 
+        if matches.is_present("once") {
+            config.once = match matches.value_of("format") {
+                Some("prometheus") => Some(OutputFormat::Prometheus),
+                Some("table") => clap::Error::with_description(
+                    "--once does not support the table format",
+                    ErrorKind::InvalidValue,
+                )
+                .exit(),
+                _ => Some(OutputFormat::Json),
+            };
+            // keep stdout for the metrics unless more logging is requested
+            if matches.occurrences_of("verbose") == 0 {
+                config.general.set_logging(Level::Warn);
+            }
+        }
+
         match matches.occurrences_of("verbose") {
             0 => {} // don't do anything, default is Info
             1 => {
@@ -89,7 +199,6 @@ impl Config {
         self.general.logging()
     }
 
-    #[allow(dead_code)]
     pub fn exposition(&self) -> &Exposition {
         &self.exposition
     }
@@ -98,6 +207,12 @@ impl Config {
         &self.general
     }
 
+    /// Labels which are attached to every metric by the exporters which
+    /// support them, eg: `env = "prod"`
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
     pub fn samplers(&self) -> &Samplers {
         &self.samplers
     }
@@ -106,15 +221,192 @@ impl Config {
         self.general().fault_tolerant()
     }
 
-    fn load_from_file(filename: &str) -> Config {
-        let mut file = std::fs::File::open(filename).expect("failed to open workload file");
+    /// Whether errors of the sampler are tolerated, which is its own
+    /// `fault_tolerant` setting if it has one and the general setting otherwise
+    pub fn sampler_fault_tolerant<T>(&self, sampler: &dyn SamplerConfig<Statistic = T>) -> bool {
+        sampler
+            .fault_tolerant()
+            .unwrap_or_else(|| self.fault_tolerant())
+    }
+
+    /// The output format if `--once` was given, in which case each sampler
+    /// takes a single sample and the metrics are printed before exiting
+    pub fn once(&self) -> Option<OutputFormat> {
+        self.once
+    }
+
+    /// The statistics to print if `--list-statistics` was given, in which
+    /// case nothing is sampled
+    pub fn list_statistics(&self) -> Option<&ListStatistics> {
+        self.list_statistics.as_ref()
+    }
+
+    /// Re-read the config file and apply the hot-reloadable fields in place.
+    /// Only `enabled` and `interval` for each sampler, and `interval` and
+    /// `fault_tolerant` in the general section are applied. A sampler interval
+    /// can only be changed if one was set when Rezolus was started. Any other
+    /// change, such as to the statistics or percentiles of a sampler, to the
+    /// exposition section, or to the labels, is reported as requiring a
+    /// restart and is not applied.
+    pub fn reload(&self) -> Result<Changes, anyhow::Error> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| format_err!("no config file to reload"))?;
+        let mut other = Config::from_file_with_format(path, self.format)?;
+        // the logging level may have been set on the command line
+        other.general.set_logging(self.general.logging());
+        let mut changes = Changes::default();
+        self.general.reload(&other.general, &mut changes);
+        self.samplers.reload(&other.samplers, &mut changes);
+        if format!("{:?}", self.exposition) != format!("{:?}", other.exposition) {
+            changes.restart.push("exposition".to_string());
+        }
+        if self.labels != other.labels {
+            changes.restart.push("labels".to_string());
+        }
+        Ok(changes)
+    }
+
+    /// Check for settings which deserialize but cannot be used, such as an
+    /// interval of zero. The error lists every problem found.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let mut errors = Vec::new();
+        self.general.validate(&mut errors);
+        self.exposition.validate(&mut errors);
+        self.samplers.validate(&mut errors);
+        for key in self.labels.keys() {
+            if !valid_label_name(key) {
+                errors.push(format!(
+                    "labels.{} is not a valid label name, which must match [a-zA-Z_][a-zA-Z0-9_]* and not start with __",
+                    key
+                ));
+            } else if key == "percentile" {
+                errors.push("labels.percentile is reserved for percentile outputs".to_string());
+            }
+        }
+        if self.exposition.graphite().enabled() {
+            for (key, value) in &self.labels {
+                if !valid_graphite_tag_value(value) {
+                    errors.push(format!(
+                        "labels.{} is not a valid graphite tag value, which must not be empty, contain ; or start with ~",
+                        key
+                    ));
+                }
+            }
+        }
+        if self.general.listen().is_none() && self.exposition.debug().enabled() {
+            errors.push("exposition.debug requires general.listen to be set".to_string());
+        }
+        if self.general.listen().is_none() && !self.exposition.any_enabled() {
+            errors.push(
+                "no exporter is configured, set general.listen or enable an exposition".to_string(),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format_err!("invalid config:\n  {}", errors.join("\n  ")))
+        }
+    }
+
+    /// Settings which are valid but will have no effect, such as BPF
+    /// statistics in a build without BPF support
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.general.interval() < MIN_SAFE_INTERVAL {
+            warnings.push(format!(
+                "general.interval of {}ms is below {}ms and may overwhelm the host",
+                self.general.interval(),
+                MIN_SAFE_INTERVAL
+            ));
+        }
+        self.samplers.warnings(&mut warnings);
+        warnings
+    }
+
+    /// Load and validate the config file, report the result, and exit with a
+    /// status to match. Used by `--check-config` to lint configs in CI.
+    fn check(filename: &str, format: ConfigFormat) -> ! {
+        match Config::from_file_with_format(filename, format) {
+            Ok(config) => {
+                for warning in config.warnings() {
+                    println!("warning: {}", warning);
+                }
+                println!("config OK");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Load the config from the file, with the environment overrides applied.
+    /// Unlike `new`, this does not parse the command line, so it is how a
+    /// process embedding the samplers loads the config. The format is
+    /// detected from the file's extension.
+    pub fn from_file(filename: &str) -> Result<Config, anyhow::Error> {
+        Config::from_file_with_format(filename, ConfigFormat::from_path(filename))
+    }
+
+    /// Load the config from a file in the given format, with the environment
+    /// overrides applied
+    pub fn from_file_with_format(
+        filename: &str,
+        format: ConfigFormat,
+    ) -> Result<Config, anyhow::Error> {
+        let mut file = std::fs::File::open(filename)?;
         let mut content = String::new();
-        file.read_to_string(&mut content).expect("failed to read");
-        let toml = toml::from_str(&content);
-        match toml {
-            Ok(toml) => toml,
+        file.read_to_string(&mut content)?;
+        let mut config =
+            Config::parse_with_overrides(&content, format, env::overrides(std::env::vars()))?;
+        config.format = format;
+        Ok(config)
+    }
+
+    /// Parse the config from a TOML string, without any environment overrides
+    pub(crate) fn parse(content: &str) -> Result<Config, anyhow::Error> {
+        Config::parse_with_overrides(content, ConfigFormat::Toml, Vec::new())
+    }
+
+    /// Parse the config and apply the sampler settings overridden by
+    /// environment variables, eg: `REZOLUS_SAMPLERS_TCP_ENABLED=true`
+    fn parse_with_overrides(
+        content: &str,
+        format: ConfigFormat,
+        overrides: Vec<(String, String)>,
+    ) -> Result<Config, anyhow::Error> {
+        let config: Config = if overrides.is_empty() {
+            match format {
+                ConfigFormat::Toml => toml::from_str(content).map_err(|e| format_err!("{}", e))?,
+                ConfigFormat::Yaml => {
+                    serde_yaml::from_str(content).map_err(|e| format_err!("{}", e))?
+                }
+            }
+        } else {
+            // the file is parsed directly otherwise, which keeps the line
+            // numbers in any error. YAML is read into the same tree as TOML,
+            // so that the overrides are applied alike.
+            let mut value: toml::Value = match format {
+                ConfigFormat::Toml => toml::from_str(content).map_err(|e| format_err!("{}", e))?,
+                ConfigFormat::Yaml => {
+                    serde_yaml::from_str(content).map_err(|e| format_err!("{}", e))?
+                }
+            };
+            env::apply(&mut value, &overrides)?;
+            value.try_into().map_err(|e| format_err!("{}", e))?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn load_from_file(filename: &str, format: ConfigFormat) -> Config {
+        match Config::from_file_with_format(filename, format) {
+            Ok(config) => config,
             Err(e) => {
-                println!("Failed to parse TOML config: {}", filename);
+                println!("Failed to load config: {}", filename);
                 println!("{}", e);
                 std::process::exit(1);
             }
@@ -122,18 +414,475 @@ impl Config {
     }
 }
 
+/// Whether the name is a valid Prometheus label name. Names starting with `__`
+/// are reserved for internal use by Prometheus.
+fn valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.starts_with("__")
+}
+
+/// Graphite tag values may contain any character but `;`, as long as they are
+/// not empty and do not start with `~`
+fn valid_graphite_tag_value(value: &str) -> bool {
+    !value.is_empty() && !value.contains(';') && !value.starts_with('~')
+}
+
+/// The format of the config file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// The format implied by the extension of the file, which is YAML for
+    /// `.yaml` and `.yml` files and TOML otherwise
+    pub fn from_path(path: &str) -> Self {
+        match std::path::Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("yaml") | Some("yml") => Self::Yaml,
+            _ => Self::Toml,
+        }
+    }
+}
+
+impl Default for ConfigFormat {
+    fn default() -> Self {
+        Self::Toml
+    }
+}
+
+/// How the metrics are printed with `--once`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Prometheus,
+}
+
+/// How the statistics are printed with `--list-statistics`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListFormat {
+    Table,
+    Json,
+}
+
+/// The sampler, if any, whose statistics `--list-statistics` prints
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListStatistics {
+    pub sampler: Option<String>,
+    pub format: ListFormat,
+}
+
+/// How the percentiles of a distribution are computed
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PercentileMode {
+    /// Every value in the window is weighted equally
+    Window,
+    /// Additionally export percentiles from a histogram which decays with a
+    /// half-life, so that recent values dominate
+    Ewma,
+}
+
+impl Default for PercentileMode {
+    fn default() -> Self {
+        Self::Window
+    }
+}
+
+/// How push exporters emit the readings of counters
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Emit {
+    /// The raw monotonic reading
+    Counter,
+    /// The per-second rate since the previous export
+    Rate,
+}
+
+impl Default for Emit {
+    fn default() -> Self {
+        Self::Counter
+    }
+}
+
+/// Whether push exporters send the running total of each counter or only its
+/// increase since the previous export
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Temporality {
+    Cumulative,
+    Delta,
+}
+
+impl Default for Temporality {
+    fn default() -> Self {
+        Self::Cumulative
+    }
+}
+
+/// The default maximum value of distribution histograms, which is one second
+/// for latencies in nanoseconds
+pub const DEFAULT_MAX_VALUE: u64 = 1_000_000_000;
+
+/// The default significant figures of precision of distribution histograms
+pub const DEFAULT_SIGFIGS: u8 = 2;
+
+/// The shortest sampling interval, in milliseconds, which is not warned about.
+/// Sampling more often, such as to profile with perf events, can overwhelm the
+/// host.
+pub const MIN_SAFE_INTERVAL: usize = 10;
+
 pub trait SamplerConfig {
     type Statistic;
     fn bpf(&self) -> bool {
         false
     }
+    /// Whether the sampler's BPF histograms are cumulative, in which case they
+    /// are not cleared after each read
+    fn cumulative(&self) -> bool {
+        false
+    }
     fn enabled(&self) -> bool {
         false
     }
+    /// Overrides the general `fault_tolerant` setting for the sampler
+    fn fault_tolerant(&self) -> Option<bool> {
+        None
+    }
     fn interval(&self) -> Option<usize>;
+    /// The largest value the histograms of the sampler's distributions track
+    fn max_value(&self) -> u64 {
+        DEFAULT_MAX_VALUE
+    }
     fn percentiles(&self) -> &[f64];
     fn perf_events(&self) -> bool {
         false
     }
+    /// Apply the hot-reloadable fields from a newly loaded config
+    fn reload(&self, other: &Self)
+    where
+        Self: Sized;
+    /// The significant figures of precision of the sampler's histograms
+    fn sigfigs(&self) -> u8 {
+        DEFAULT_SIGFIGS
+    }
     fn statistics(&self) -> Vec<<Self as config::SamplerConfig>::Statistic>;
 }
+
+/// The differences found when the config is reloaded
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// a description of each change which was applied
+    pub applied: Vec<String>,
+    /// the sections with changes which are only applied by a restart
+    pub restart: Vec<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn errors(content: &str) -> String {
+        Config::parse(content)
+            .map(|_| String::new())
+            .unwrap_or_else(|e| e.to_string())
+    }
+
+    const LISTEN: &str = "[general]\nlisten = \"0.0.0.0:4242\"\n";
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(errors(LISTEN), "");
+        assert_eq!(
+            errors("[exposition.file]\nenabled = true\npath = \"rezolus.jsonl\"\n"),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_validate_interval() {
+        let config = format!("{}interval = 0\n", LISTEN);
+        assert!(errors(&config).contains("general.interval must be greater than zero"));
+        let config = format!("{}[samplers.cpu]\ninterval = 0\n", LISTEN);
+        assert!(errors(&config).contains("samplers.cpu.interval must be greater than zero"));
+        let config = format!("{}[exposition.statsd]\ninterval = 0\n", LISTEN);
+        assert!(errors(&config).contains("exposition.statsd.interval must be greater than zero"));
+    }
+
+    #[test]
+    fn test_validate_percentiles() {
+        for percentile in &["0.0", "-1.0", "100.1"] {
+            let config = format!("{}[samplers.tcp]\npercentiles = [{}]\n", LISTEN, percentile);
+            assert!(errors(&config).contains("samplers.tcp.percentiles contains"));
+        }
+        let config = format!("{}[samplers.tcp]\npercentiles = [0.1, 100.0]\n", LISTEN);
+        assert_eq!(errors(&config), "");
+    }
+
+    #[test]
+    fn test_validate_window() {
+        let config = format!("{}window = 0\n", LISTEN);
+        assert!(errors(&config).contains("general.window must be greater than zero"));
+    }
+
+    #[test]
+    fn test_validate_exporter() {
+        assert!(errors("").contains("no exporter is configured"));
+    }
+
+    #[test]
+    fn test_validate_debug() {
+        let config = "[exposition.debug]\nenabled = true\n[exposition.file]\nenabled = true\n";
+        assert!(errors(config).contains("exposition.debug requires general.listen"));
+        let config = format!("{}[exposition.debug]\nsamples = 0\n", LISTEN);
+        assert!(errors(&config).contains("exposition.debug.samples must be greater than zero"));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let overrides = vec![
+            (
+                "REZOLUS_SAMPLERS_TCP_ENABLED".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "REZOLUS_SAMPLERS_TCP_INTERVAL".to_string(),
+                "500".to_string(),
+            ),
+            (
+                "REZOLUS_SAMPLERS_PAGE_CACHE_ENABLED".to_string(),
+                "true".to_string(),
+            ),
+        ];
+        let content = format!(
+            "{}[samplers.tcp]\nenabled = false\ninterval = 1000\n",
+            LISTEN
+        );
+        let config = Config::parse_with_overrides(&content, overrides).unwrap();
+        assert!(config.samplers().tcp().enabled());
+        assert_eq!(config.samplers().tcp().interval(), Some(500));
+        assert!(config.samplers().page_cache().enabled());
+        assert!(!config.samplers().cpu().enabled());
+
+        // overrides are checked the same as the file
+        let overrides = vec![("REZOLUS_SAMPLERS_TCP_INTERVAL".to_string(), "0".to_string())];
+        let e = Config::parse_with_overrides(LISTEN, overrides).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("samplers.tcp.interval must be greater than zero"));
+        let overrides = vec![(
+            "REZOLUS_SAMPLERS_TCP_ENABLD".to_string(),
+            "true".to_string(),
+        )];
+        assert!(Config::parse_with_overrides(LISTEN, overrides).is_err());
+    }
+
+    #[test]
+    fn test_sampler_fault_tolerant() {
+        // a sampler may be strict when the general setting is tolerant
+        let content = format!("{}[samplers.cpu]\nfault_tolerant = false\n", LISTEN);
+        let config = Config::parse(&content).unwrap();
+        assert!(config.fault_tolerant());
+        assert!(!config.sampler_fault_tolerant(config.samplers().cpu()));
+        assert!(config.sampler_fault_tolerant(config.samplers().tcp()));
+
+        // and tolerant when the general setting is strict
+        let content = format!(
+            "{}fault_tolerant = false\n[samplers.tcp]\nfault_tolerant = true\n",
+            LISTEN
+        );
+        let config = Config::parse(&content).unwrap();
+        assert!(!config.fault_tolerant());
+        assert!(config.sampler_fault_tolerant(config.samplers().tcp()));
+        assert!(!config.sampler_fault_tolerant(config.samplers().cpu()));
+    }
+
+    #[test]
+    fn test_validate_stale_after() {
+        let config = format!("{}[exposition]\nstale_after = 0\n", LISTEN);
+        assert!(errors(&config).contains("exposition.stale_after must be greater than zero"));
+        let config = format!("{}[exposition]\nstale_after = 3\n", LISTEN);
+        assert_eq!(errors(&config), "");
+    }
+
+    #[test]
+    fn test_validate_labels() {
+        let config = format!(
+            "{}[labels]\nenv = \"prod\"\n_region = \"us-east-1\"\n",
+            LISTEN
+        );
+        assert_eq!(errors(&config), "");
+        for key in &["\"0day\"", "\"__name\"", "\"a-b\"", "percentile"] {
+            let config = format!("{}[labels]\n{} = \"x\"\n", LISTEN, key);
+            assert!(errors(&config).contains("labels."), "{}", key);
+        }
+
+        // values are only restricted when they are used as graphite tags
+        for value in &["", "a;b", "~x"] {
+            let labels = format!("[labels]\nenv = \"{}\"\n", value);
+            let config = format!("{}{}", LISTEN, labels);
+            assert_eq!(errors(&config), "");
+            let config = format!(
+                "{}{}[exposition.graphite]\nenabled = true\n",
+                LISTEN, labels
+            );
+            assert!(
+                errors(&config).contains("labels.env is not a valid graphite tag value"),
+                "{}",
+                value
+            );
+        }
+        let config = format!(
+            "{}[labels]\nenv = \"prod-1~a\"\n[exposition.graphite]\nenabled = true\n",
+            LISTEN
+        );
+        assert_eq!(errors(&config), "");
+    }
+
+    #[test]
+    fn test_warnings() {
+        let config = Config::parse(&format!(
+            "{}[samplers.tcp]\nenabled = true\nbpf = true\n",
+            LISTEN
+        ))
+        .unwrap();
+        let expected = if cfg!(any(feature = "bpf", feature = "bpf_core")) {
+            0
+        } else {
+            1
+        };
+        assert_eq!(config.warnings().len(), expected);
+        assert!(Config::parse(LISTEN).unwrap().warnings().is_empty());
+    }
+
+    #[test]
+    fn test_interval_warnings() {
+        let config = Config::parse(&format!(
+            "{}interval = \"5ms\"\n[samplers.cpu]\nenabled = true\ninterval = \"0.001s\"\n",
+            LISTEN
+        ))
+        .unwrap();
+        assert_eq!(config.warnings().len(), 2);
+        let config = Config::parse(&format!(
+            "{}[samplers.cpu]\nenabled = true\ninterval = \"10ms\"\n",
+            LISTEN
+        ))
+        .unwrap();
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_validate_aggregate() {
+        let errors = errors("[general]\ninterval = 0\nwindow = 0\n");
+        assert_eq!(errors.matches("\n  ").count(), 3);
+    }
+
+    #[test]
+    fn test_reload_changes() {
+        let config = Config::parse(&format!(
+            "{}[samplers.tcp]\nenabled = true\ninterval = 1000\n",
+            LISTEN
+        ))
+        .unwrap();
+        let other = Config::parse(&format!(
+            "{}interval = 500\n[samplers.tcp]\nenabled = true\ninterval = 2000\n[samplers.udp]\nenabled = true\n",
+            LISTEN
+        ))
+        .unwrap();
+        let mut changes = Changes::default();
+        config.general.reload(&other.general, &mut changes);
+        config.samplers.reload(&other.samplers, &mut changes);
+        assert_eq!(
+            changes.applied,
+            vec![
+                "general.interval: 1000 -> 500",
+                "samplers.tcp.interval: 1000 -> 2000",
+                "samplers.udp.enabled: false -> true",
+            ]
+        );
+        assert!(changes.restart.is_empty());
+        assert_eq!(config.general().interval(), 500);
+        assert!(config.samplers().udp().enabled());
+
+        // applying the same config again changes nothing
+        let mut changes = Changes::default();
+        config.samplers.reload(&other.samplers, &mut changes);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_reload_restart() {
+        let config = Config::parse(&format!(
+            "{}[samplers.tcp]
+enabled = true
+",
+            LISTEN
+        ))
+        .unwrap();
+        let other = Config::parse(&format!(
+            "{}threads = 2
+[samplers.tcp]
+enabled = false
+percentiles = [50.0]
+",
+            LISTEN
+        ))
+        .unwrap();
+        let mut changes = Changes::default();
+        config.general.reload(&other.general, &mut changes);
+        config.samplers.reload(&other.samplers, &mut changes);
+        assert_eq!(changes.applied, vec!["samplers.tcp.enabled: true -> false"]);
+        assert_eq!(changes.restart, vec!["general", "samplers.tcp"]);
+        assert!(!config.samplers().tcp().enabled());
+        assert_eq!(config.general().threads(), 1);
+    }
+
+    #[test]
+    fn test_config_format() {
+        assert_eq!(ConfigFormat::from_path("rezolus.yaml"), ConfigFormat::Yaml);
+        assert_eq!(
+            ConfigFormat::from_path("/etc/rezolus.yml"),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(ConfigFormat::from_path("rezolus.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("rezolus"), ConfigFormat::Toml);
+    }
+
+    #[test]
+    fn test_yaml() {
+        let toml = format!(
+            "{}interval = \"500ms\"\n[labels]\nenv = \"prod\"\n[samplers.tcp]\nenabled = true\npercentiles = [50.0, 99.9]\n",
+            LISTEN
+        );
+        let yaml = "general:\n  listen: \"0.0.0.0:4242\"\n  interval: 500ms\nlabels:\n  env: prod\nsamplers:\n  tcp:\n    enabled: true\n    percentiles: [50.0, 99.9]\n";
+        let toml = Config::parse(&toml).unwrap();
+        let yaml = Config::parse_with_overrides(yaml, ConfigFormat::Yaml, Vec::new()).unwrap();
+        assert_eq!(format!("{:?}", yaml), format!("{:?}", toml));
+
+        // unknown fields are rejected as they are in toml
+        let yaml = "general:\n  listen: \"0.0.0.0:4242\"\n  nope: true\n";
+        assert!(Config::parse_with_overrides(yaml, ConfigFormat::Yaml, Vec::new()).is_err());
+
+        // the environment overrides apply to yaml too
+        let yaml = "general:\n  listen: \"0.0.0.0:4242\"\n";
+        let overrides = vec![(
+            "REZOLUS_SAMPLERS_TCP_ENABLED".to_string(),
+            "true".to_string(),
+        )];
+        let config = Config::parse_with_overrides(yaml, ConfigFormat::Yaml, overrides).unwrap();
+        assert!(config.samplers().tcp().enabled());
+    }
+}
@@ -2,30 +2,73 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use std::fmt::Debug;
+
 use crate::config::*;
 
+use samplers::cgroup::CgroupConfig;
 use samplers::cpu::CpuConfig;
+use samplers::custom::CustomConfig;
 use samplers::disk::DiskConfig;
 use samplers::ext4::Ext4Config;
 use samplers::http::HttpConfig;
 use samplers::interrupt::InterruptConfig;
 use samplers::memcache::MemcacheConfig;
 use samplers::memory::MemoryConfig;
+use samplers::neighbor::NeighborConfig;
 use samplers::network::NetworkConfig;
+use samplers::nvidia::NvidiaConfig;
 use samplers::page_cache::PageCacheConfig;
+use samplers::pressure::PressureConfig;
 use samplers::rezolus::RezolusConfig;
 use samplers::scheduler::SchedulerConfig;
+use samplers::slab::SlabConfig;
+use samplers::softirq::SoftirqConfig;
 use samplers::softnet::SoftnetConfig;
+use samplers::system::SystemConfig;
 use samplers::tcp::TcpConfig;
 use samplers::udp::UdpConfig;
+use samplers::xdp::XdpConfig;
 use samplers::xfs::XfsConfig;
 
+/// The name of each sampler's section, eg: `[samplers.page_cache]`
+pub const SAMPLER_NAMES: &[&str] = &[
+    "cgroup",
+    "cpu",
+    "custom",
+    "disk",
+    "ext4",
+    "http",
+    "interrupt",
+    "memcache",
+    "memory",
+    "neighbor",
+    "network",
+    "nvidia",
+    "page_cache",
+    "pressure",
+    "rezolus",
+    "scheduler",
+    "slab",
+    "softirq",
+    "softnet",
+    "system",
+    "tcp",
+    "udp",
+    "xdp",
+    "xfs",
+];
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Samplers {
+    #[serde(default)]
+    cgroup: CgroupConfig,
     #[serde(default)]
     cpu: CpuConfig,
     #[serde(default)]
+    custom: CustomConfig,
+    #[serde(default)]
     disk: DiskConfig,
     #[serde(default)]
     ext4: Ext4Config,
@@ -38,28 +81,152 @@ pub struct Samplers {
     #[serde(default)]
     memory: MemoryConfig,
     #[serde(default)]
+    neighbor: NeighborConfig,
+    #[serde(default)]
     network: NetworkConfig,
     #[serde(default)]
+    nvidia: NvidiaConfig,
+    #[serde(default)]
     page_cache: PageCacheConfig,
     #[serde(default)]
+    pressure: PressureConfig,
+    #[serde(default)]
     rezolus: RezolusConfig,
     #[serde(default)]
     scheduler: SchedulerConfig,
     #[serde(default)]
+    slab: SlabConfig,
+    #[serde(default)]
+    softirq: SoftirqConfig,
+    #[serde(default)]
     softnet: SoftnetConfig,
     #[serde(default)]
+    system: SystemConfig,
+    #[serde(default)]
     tcp: TcpConfig,
     #[serde(default)]
     udp: UdpConfig,
     #[serde(default)]
+    xdp: XdpConfig,
+    #[serde(default)]
     xfs: XfsConfig,
 }
 
 impl Samplers {
+    /// Apply the hot-reloadable fields of each sampler config, adding a
+    /// description of each change to `changes`
+    pub fn reload(&self, other: &Samplers, changes: &mut Changes) {
+        reload("cgroup", &self.cgroup, &other.cgroup, changes);
+        reload("cpu", &self.cpu, &other.cpu, changes);
+        reload("custom", &self.custom, &other.custom, changes);
+        reload("disk", &self.disk, &other.disk, changes);
+        reload("ext4", &self.ext4, &other.ext4, changes);
+        reload("http", &self.http, &other.http, changes);
+        reload("interrupt", &self.interrupt, &other.interrupt, changes);
+        reload("memcache", &self.memcache, &other.memcache, changes);
+        reload("memory", &self.memory, &other.memory, changes);
+        reload("neighbor", &self.neighbor, &other.neighbor, changes);
+        reload("network", &self.network, &other.network, changes);
+        reload("nvidia", &self.nvidia, &other.nvidia, changes);
+        reload("page_cache", &self.page_cache, &other.page_cache, changes);
+        reload("pressure", &self.pressure, &other.pressure, changes);
+        reload("rezolus", &self.rezolus, &other.rezolus, changes);
+        reload("scheduler", &self.scheduler, &other.scheduler, changes);
+        reload("slab", &self.slab, &other.slab, changes);
+        reload("softirq", &self.softirq, &other.softirq, changes);
+        reload("softnet", &self.softnet, &other.softnet, changes);
+        reload("system", &self.system, &other.system, changes);
+        reload("tcp", &self.tcp, &other.tcp, changes);
+        reload("udp", &self.udp, &other.udp, changes);
+        reload("xdp", &self.xdp, &other.xdp, changes);
+        reload("xfs", &self.xfs, &other.xfs, changes);
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        validate("cgroup", &self.cgroup, errors);
+        self.cgroup.validate(errors);
+        validate("cpu", &self.cpu, errors);
+        validate("custom", &self.custom, errors);
+        self.custom.validate(errors);
+        validate("disk", &self.disk, errors);
+        validate("ext4", &self.ext4, errors);
+        validate("http", &self.http, errors);
+        validate("interrupt", &self.interrupt, errors);
+        validate("memcache", &self.memcache, errors);
+        validate("memory", &self.memory, errors);
+        validate("neighbor", &self.neighbor, errors);
+        validate("network", &self.network, errors);
+        validate("nvidia", &self.nvidia, errors);
+        validate("page_cache", &self.page_cache, errors);
+        validate("pressure", &self.pressure, errors);
+        validate("rezolus", &self.rezolus, errors);
+        validate("scheduler", &self.scheduler, errors);
+        validate("slab", &self.slab, errors);
+        self.slab.validate(errors);
+        validate("softirq", &self.softirq, errors);
+        validate("softnet", &self.softnet, errors);
+        validate("system", &self.system, errors);
+        validate("tcp", &self.tcp, errors);
+        validate("udp", &self.udp, errors);
+        validate("xdp", &self.xdp, errors);
+        validate("xfs", &self.xfs, errors);
+    }
+
+    /// Collect settings which are valid but will have no effect
+    pub fn warnings(&self, warnings: &mut Vec<String>) {
+        warn_interval("cgroup", &self.cgroup, warnings);
+        warn_interval("cpu", &self.cpu, warnings);
+        warn_interval("custom", &self.custom, warnings);
+        warn_interval("disk", &self.disk, warnings);
+        warn_interval("ext4", &self.ext4, warnings);
+        warn_interval("http", &self.http, warnings);
+        warn_interval("interrupt", &self.interrupt, warnings);
+        warn_interval("memcache", &self.memcache, warnings);
+        warn_interval("memory", &self.memory, warnings);
+        warn_interval("neighbor", &self.neighbor, warnings);
+        warn_interval("network", &self.network, warnings);
+        warn_interval("nvidia", &self.nvidia, warnings);
+        warn_interval("page_cache", &self.page_cache, warnings);
+        warn_interval("pressure", &self.pressure, warnings);
+        warn_interval("rezolus", &self.rezolus, warnings);
+        warn_interval("scheduler", &self.scheduler, warnings);
+        warn_interval("slab", &self.slab, warnings);
+        warn_interval("softirq", &self.softirq, warnings);
+        warn_interval("softnet", &self.softnet, warnings);
+        warn_interval("system", &self.system, warnings);
+        warn_interval("tcp", &self.tcp, warnings);
+        warn_interval("udp", &self.udp, warnings);
+        warn_interval("xdp", &self.xdp, warnings);
+        warn_interval("xfs", &self.xfs, warnings);
+        warn_bpf("disk", &self.disk, warnings);
+        warn_bpf("ext4", &self.ext4, warnings);
+        warn_bpf("interrupt", &self.interrupt, warnings);
+        warn_bpf("network", &self.network, warnings);
+        warn_bpf("page_cache", &self.page_cache, warnings);
+        warn_bpf("scheduler", &self.scheduler, warnings);
+        warn_bpf("tcp", &self.tcp, warnings);
+        warn_bpf("xdp", &self.xdp, warnings);
+        warn_bpf("xfs", &self.xfs, warnings);
+        if self.nvidia.enabled() && cfg!(not(feature = "nvml")) {
+            warnings.push(
+                "samplers.nvidia is enabled but rezolus was built without nvml support".to_string(),
+            );
+        }
+    }
+
+    pub fn cgroup(&self) -> &CgroupConfig {
+        &self.cgroup
+    }
+
     pub fn cpu(&self) -> &CpuConfig {
         &self.cpu
     }
 
+    pub fn custom(&self) -> &CustomConfig {
+        &self.custom
+    }
+
     pub fn disk(&self) -> &DiskConfig {
         &self.disk
     }
@@ -84,14 +251,26 @@ impl Samplers {
         &self.memory
     }
 
+    pub fn neighbor(&self) -> &NeighborConfig {
+        &self.neighbor
+    }
+
     pub fn network(&self) -> &NetworkConfig {
         &self.network
     }
 
+    pub fn nvidia(&self) -> &NvidiaConfig {
+        &self.nvidia
+    }
+
     pub fn page_cache(&self) -> &PageCacheConfig {
         &self.page_cache
     }
 
+    pub fn pressure(&self) -> &PressureConfig {
+        &self.pressure
+    }
+
     pub fn rezolus(&self) -> &RezolusConfig {
         &self.rezolus
     }
@@ -100,10 +279,22 @@ impl Samplers {
         &self.scheduler
     }
 
+    pub fn slab(&self) -> &SlabConfig {
+        &self.slab
+    }
+
+    pub fn softirq(&self) -> &SoftirqConfig {
+        &self.softirq
+    }
+
     pub fn softnet(&self) -> &SoftnetConfig {
         &self.softnet
     }
 
+    pub fn system(&self) -> &SystemConfig {
+        &self.system
+    }
+
     pub fn tcp(&self) -> &TcpConfig {
         &self.tcp
     }
@@ -112,7 +303,84 @@ impl Samplers {
         &self.udp
     }
 
+    pub fn xdp(&self) -> &XdpConfig {
+        &self.xdp
+    }
+
     pub fn xfs(&self) -> &XfsConfig {
         &self.xfs
     }
 }
+
+/// Describe the hot-reloadable fields which differ in the newly loaded config,
+/// then apply them. The sampler is reported as requiring a restart if any
+/// other field differs.
+fn reload<T: SamplerConfig + Debug>(name: &str, config: &T, other: &T, changes: &mut Changes) {
+    if config.enabled() != other.enabled() {
+        changes.applied.push(format!(
+            "samplers.{}.enabled: {} -> {}",
+            name,
+            config.enabled(),
+            other.enabled()
+        ));
+    }
+    // the interval can only be changed if one was set at startup
+    if let (Some(current), Some(interval)) = (config.interval(), other.interval()) {
+        if current != interval {
+            changes.applied.push(format!(
+                "samplers.{}.interval: {} -> {}",
+                name, current, interval
+            ));
+        }
+    }
+    config.reload(other);
+    if format!("{:?}", config) != format!("{:?}", other) {
+        changes.restart.push(format!("samplers.{}", name));
+    }
+}
+
+fn validate<T: SamplerConfig>(name: &str, config: &T, errors: &mut Vec<String>) {
+    if config.interval() == Some(0) {
+        errors.push(format!(
+            "samplers.{}.interval must be greater than zero",
+            name
+        ));
+    }
+    if config.max_value() == 0 {
+        errors.push(format!(
+            "samplers.{}.max_value must be greater than zero",
+            name
+        ));
+    }
+    if !(1..=5).contains(&config.sigfigs()) {
+        errors.push(format!("samplers.{}.sigfigs must be between 1 and 5", name));
+    }
+    for percentile in config.percentiles() {
+        if !(*percentile > 0.0 && *percentile <= 100.0) {
+            errors.push(format!(
+                "samplers.{}.percentiles contains {} which is not within (0, 100]",
+                name, percentile
+            ));
+        }
+    }
+}
+
+fn warn_interval<T: SamplerConfig>(name: &str, config: &T, warnings: &mut Vec<String>) {
+    if let Some(interval) = config.interval() {
+        if config.enabled() && interval < MIN_SAFE_INTERVAL {
+            warnings.push(format!(
+                "samplers.{}.interval of {}ms is below {}ms and may overwhelm the host",
+                name, interval, MIN_SAFE_INTERVAL
+            ));
+        }
+    }
+}
+
+fn warn_bpf<T: SamplerConfig>(name: &str, config: &T, warnings: &mut Vec<String>) {
+    if config.enabled() && config.bpf() && cfg!(not(any(feature = "bpf", feature = "bpf_core"))) {
+        warnings.push(format!(
+            "samplers.{}.bpf is enabled but rezolus was built without bpf support",
+            name
+        ));
+    }
+}
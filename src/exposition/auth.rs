@@ -0,0 +1,131 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::config::Auth as AuthConfig;
+
+const REALM: &str = "rezolus";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The credentials a listener requires of each request, checked against the
+/// `Authorization` header
+#[derive(Clone, Debug)]
+pub struct Auth {
+    scheme: &'static str,
+    credentials: Vec<u8>,
+}
+
+impl Auth {
+    pub fn new(config: &AuthConfig) -> Self {
+        match config.basic() {
+            // the header carries `username:password` in base64, so the expected
+            // value is encoded once up front rather than decoding each request
+            Some((username, password)) => Self {
+                scheme: "Basic",
+                credentials: base64(format!("{}:{}", username, password).as_bytes()).into_bytes(),
+            },
+            None => Self {
+                scheme: "Bearer",
+                credentials: config.token().unwrap_or_default().as_bytes().to_vec(),
+            },
+        }
+    }
+
+    /// Whether the value of the request's `Authorization` header, if it had
+    /// one, carries the expected credentials. The scheme is matched without
+    /// regard to case, as required by RFC 7235.
+    pub fn authorized(&self, header: Option<&str>) -> bool {
+        let header = match header {
+            Some(header) => header.trim(),
+            None => return false,
+        };
+        let mut parts = header.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some(scheme), Some(credentials)) if scheme.eq_ignore_ascii_case(self.scheme) => {
+                constant_time_eq(credentials.trim().as_bytes(), &self.credentials)
+            }
+            _ => false,
+        }
+    }
+
+    /// The value of the `WWW-Authenticate` header sent with a 401
+    pub fn challenge(&self) -> String {
+        format!("{} realm=\"{}\"", self.scheme, REALM)
+    }
+}
+
+/// Compare the bytes in time which depends only on their lengths, so that the
+/// time taken to reject a guess reveals nothing about how much of it matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Encode the bytes as padded standard base64
+fn base64(input: &[u8]) -> String {
+    let mut output = String::with_capacity((input.len() + 2) / 3 * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn auth(content: &str) -> Auth {
+        Auth::new(&toml::from_str(content).unwrap())
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(
+            base64(b"Aladdin:open sesame"),
+            "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+
+    #[test]
+    fn test_basic() {
+        let auth = auth("username = \"Aladdin\"\npassword = \"open sesame\"\n");
+        assert!(auth.authorized(Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")));
+        assert!(auth.authorized(Some("basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")));
+        // Aladdin:open sesamE
+        assert!(!auth.authorized(Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtRQ==")));
+        assert!(!auth.authorized(Some("Bearer QWxhZGRpbjpvcGVuIHNlc2FtZQ==")));
+        assert!(!auth.authorized(Some("Basic")));
+        assert!(!auth.authorized(None));
+        assert_eq!(auth.challenge(), "Basic realm=\"rezolus\"");
+    }
+
+    #[test]
+    fn test_bearer() {
+        let auth = auth("token = \"s3cr3t\"\n");
+        assert!(auth.authorized(Some("Bearer s3cr3t")));
+        assert!(!auth.authorized(Some("Bearer s3cr3")));
+        assert!(!auth.authorized(Some("Bearer s3cr3tt")));
+        assert!(!auth.authorized(Some("Basic s3cr3t")));
+        assert!(!auth.authorized(Some("")));
+        assert_eq!(auth.challenge(), "Bearer realm=\"rezolus\"");
+    }
+}
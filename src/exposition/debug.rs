@@ -0,0 +1,132 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use json::JsonValue;
+use rustcommon_atomics::AtomicBool;
+use rustcommon_metrics::*;
+
+use super::{sleep_until, PushExporter};
+use crate::config::Config;
+
+/// A bounded buffer of the most recent `(timestamp, value)` readings of each
+/// statistic, which is served over HTTP for live troubleshooting
+pub struct RecentReadings {
+    capacity: usize,
+    readings: Mutex<HashMap<String, VecDeque<(u64, u64)>>>,
+}
+
+impl RecentReadings {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            readings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Append a reading, dropping the oldest once the buffer is full
+    pub fn record(&self, name: &str, timestamp: u64, value: u64) {
+        let mut readings = self.readings.lock().unwrap();
+        if !readings.contains_key(name) {
+            readings.insert(name.to_string(), VecDeque::with_capacity(self.capacity));
+        }
+        if let Some(buffer) = readings.get_mut(name) {
+            if buffer.len() >= self.capacity {
+                buffer.pop_front();
+            }
+            buffer.push_back((timestamp, value));
+        }
+    }
+
+    /// Render the readings of one statistic as JSON, oldest first. The name may
+    /// be given as is, eg: `tcp/connect/latency`, or with `_` separating each
+    /// component, eg: `tcp_connect_latency`, so that it needs no escaping in a
+    /// url.
+    pub fn json(&self, name: &str) -> Option<String> {
+        let readings = self.readings.lock().unwrap();
+        let (name, buffer) = readings
+            .iter()
+            .find(|(k, _)| *k == name || k.replace('/', "_") == name)?;
+        let mut samples = JsonValue::new_array();
+        for (timestamp, value) in buffer {
+            let mut sample = JsonValue::new_object();
+            sample["timestamp"] = (*timestamp).into();
+            sample["value"] = (*value).into();
+            let _ = samples.push(sample);
+        }
+        let mut content = JsonValue::new_object();
+        content["name"] = name.as_str().into();
+        content["samples"] = samples;
+        Some(content.dump())
+    }
+}
+
+/// Periodically copies the current reading of every statistic in the registry
+/// into the `RecentReadings`. Percentiles are not retained.
+pub struct DebugRecorder {
+    interval: Duration,
+    metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+    readings: Arc<RecentReadings>,
+    runnable: Arc<AtomicBool>,
+}
+
+impl DebugRecorder {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        readings: Arc<RecentReadings>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            interval: Duration::from_millis(config.exposition().debug().interval() as u64),
+            metrics,
+            readings,
+            runnable,
+        }
+    }
+}
+
+impl PushExporter for DebugRecorder {
+    fn run(&mut self) {
+        let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Append the current readings to the buffers
+    fn flush(&mut self) {
+        // timestamps are in milliseconds since the epoch
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_millis() as u64)
+            .unwrap_or(0);
+        for (metric, value) in self.metrics.snapshot() {
+            if let Output::Reading = metric.output() {
+                self.readings
+                    .record(metric.statistic().name(), timestamp, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_recent_readings() {
+        let readings = RecentReadings::new(2);
+        assert_eq!(readings.json("tcp/connect/latency"), None);
+        for (timestamp, value) in &[(1, 10), (2, 20), (3, 30)] {
+            readings.record("tcp/connect/latency", *timestamp, *value);
+        }
+        let expected = r#"{"name":"tcp/connect/latency","samples":[{"timestamp":2,"value":20},{"timestamp":3,"value":30}]}"#;
+        assert_eq!(readings.json("tcp/connect/latency").unwrap(), expected);
+        assert_eq!(readings.json("tcp_connect_latency").unwrap(), expected);
+        assert_eq!(readings.json("tcp_connect"), None);
+    }
+}
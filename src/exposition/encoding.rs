@@ -0,0 +1,112 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::Write;
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+/// A content coding which responses may be compressed with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    /// The name used in the `Content-Encoding` header
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    pub fn encode(self, body: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Choose an encoding from the value of an `Accept-Encoding` header, preferring
+/// the highest quality and gzip over deflate when they are equal. Returns
+/// `None` if the client accepts neither, in which case the body is sent as is.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f64)> = None;
+    for coding in accept_encoding.split(',') {
+        let mut fields = coding.split(';');
+        let name = fields.next().unwrap_or("").trim().to_lowercase();
+        let quality = fields
+            .filter_map(|f| f.trim().strip_prefix("q="))
+            .next()
+            .and_then(|q| q.trim().parse::<f64>().ok())
+            .unwrap_or(1.0);
+        let encoding = match name.as_str() {
+            "gzip" | "x-gzip" | "*" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            _ => continue,
+        };
+        if quality <= 0.0 {
+            continue;
+        }
+        let better = match best {
+            None => true,
+            Some((current, q)) => {
+                quality > q || (quality == q && encoding == Encoding::Gzip && current != encoding)
+            }
+        };
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(""), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, identity"), None);
+        assert_eq!(negotiate("*"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_encode() {
+        let body = "# TYPE cpu_usage_user counter\ncpu_usage_user 42\n".repeat(100);
+
+        let mut decoded = String::new();
+        let compressed = Encoding::Gzip.encode(body.as_bytes()).unwrap();
+        assert!(compressed.len() < body.len());
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+
+        let mut decoded = String::new();
+        let compressed = Encoding::Deflate.encode(body.as_bytes()).unwrap();
+        ZlibDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rustcommon_atomics::AtomicBool;
+use rustcommon_logger::*;
+use rustcommon_metrics::*;
+
+use super::{sleep_until, MetricsSnapshot, Naming, PushExporter};
+use crate::common::buckets::Buckets;
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::config::Config;
+
+/// Time allowed to connect to, or write to, the carbon server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first reconnect, which doubles with each further attempt
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Periodically pushes the metrics registry to a Graphite carbon server using
+/// the plaintext protocol over TCP. Counters and gauges are sent as their
+/// current reading, and percentiles with a `.pXX` suffix. If labels are
+/// configured, each path is sent as a tagged series, eg: `cpu.user;env=prod`.
+/// The entity of a labeled statistic, such as the cpu, is always sent as a
+/// tag, eg: `cpu.usage.user;cpu=0`.
+pub struct GraphiteExporter {
+    address: String,
+    buffer: Option<String>,
+    fault_tolerant: bool,
+    interval: Duration,
+    retries: usize,
+    runnable: Arc<AtomicBool>,
+    snapshot: MetricsSnapshot,
+    stream: Option<TcpStream>,
+}
+
+impl GraphiteExporter {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
+        buckets: Arc<Buckets>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
+        let graphite = config.exposition().graphite();
+        Self {
+            address: graphite.address().expect("no graphite address configured"),
+            buffer: None,
+            fault_tolerant: config.general().fault_tolerant(),
+            interval: Duration::from_millis(graphite.interval() as u64),
+            retries: graphite.retries(),
+            runnable,
+            snapshot: MetricsSnapshot::new(metrics, None, Naming::new(&config))
+                .with_emit(graphite.emit())
+                .with_temporality(graphite.temporality(), buckets)
+                .with_staleness(updates)
+                .with_entities(entities),
+            stream: None,
+        }
+    }
+
+    fn render(&self, timestamp: u64) -> String {
+        let mut lines = Vec::new();
+        for (metric, value) in &self.snapshot.snapshot {
+            let name = path(&self.snapshot.family(metric), metric.output());
+            let tags = tags(&self.snapshot.metric_labels(metric));
+            let value = self.snapshot.format_value(metric, *value);
+            lines.push(format!("{}{} {} {}\n", name, tags, value, timestamp));
+        }
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Send the payload, reconnecting with an increasing delay if the
+    /// connection cannot be established or has dropped
+    fn send(&mut self, payload: &str) -> Result<(), std::io::Error> {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match self.try_send(payload) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    self.stream = None;
+                    if attempt >= self.retries {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    debug!("graphite send failed, reconnecting: {}", e);
+                    sleep_until(Instant::now() + delay, &self.runnable);
+                    delay *= 2;
+                }
+            }
+        }
+    }
+
+    fn try_send(&mut self, payload: &str) -> Result<(), std::io::Error> {
+        if self.stream.is_none() {
+            self.stream = Some(connect(&self.address)?);
+        }
+        if let Some(stream) = &mut self.stream {
+            stream.write_all(payload.as_bytes())?;
+            stream.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl PushExporter for GraphiteExporter {
+    fn run(&mut self) {
+        let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Send the current readings to the server. If the previous push failed,
+    /// its readings are sent first so that a brief disconnect leaves no gap.
+    /// Only one interval is buffered, older readings are dropped.
+    fn flush(&mut self) {
+        self.snapshot.refresh();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let content = self.render(timestamp);
+        let payload = match self.buffer.take() {
+            Some(previous) => previous + &content,
+            None => content.clone(),
+        };
+        if let Err(e) = self.send(&payload) {
+            self.buffer = Some(content);
+            if self.fault_tolerant {
+                error!("failed to send to graphite: {}", e);
+            } else {
+                fatal!("failed to send to graphite: {}", e);
+            }
+        }
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream, std::io::Error> {
+    let mut result = Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("could not resolve: {}", address),
+    ));
+    for address in address.to_socket_addrs()? {
+        result = TcpStream::connect_timeout(&address, TIMEOUT);
+        if result.is_ok() {
+            break;
+        }
+    }
+    let stream = result?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    debug!("connected to graphite: {}", address);
+    Ok(stream)
+}
+
+/// Graphite uses `.` to separate the components of a metric path, and each
+/// percentile is a child of the statistic, eg: `tcp.connect.latency.p99`
+fn path(name: &str, output: Output) -> String {
+    let name = name.replace('/', ".");
+    match output {
+        Output::Reading => name,
+        Output::Percentile(percentile) => {
+            // fractional percentiles such as 99.9 become `p99_9`
+            let suffix = format!("p{}", percentile).replace('.', "_");
+            format!("{}.{}", name, suffix)
+        }
+    }
+}
+
+/// Format the labels as graphite tags, eg: `;env=prod;role=cache`
+fn tags(labels: &BTreeMap<String, String>) -> String {
+    labels
+        .iter()
+        .map(|(k, v)| format!(";{}={}", k, v))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exposition::TestMetrics;
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            path("tcp/receive/segment", Output::Reading),
+            "tcp.receive.segment"
+        );
+        assert_eq!(
+            path("tcp/connect/latency", Output::Percentile(99.0)),
+            "tcp.connect.latency.p99"
+        );
+        assert_eq!(
+            path("tcp/connect/latency", Output::Percentile(99.9)),
+            "tcp.connect.latency.p99_9"
+        );
+    }
+
+    #[test]
+    fn test_render_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record_cpus(&[10, 20]);
+        let config = Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nenv = \"prod\"\n\
+             [exposition.graphite]\naddress = \"127.0.0.1:2003\"\n",
+        )
+        .unwrap();
+        let mut exporter = GraphiteExporter::new(
+            Arc::new(config),
+            test.metrics.clone(),
+            Arc::new(Updates::new(None)),
+            test.entities.clone(),
+            Arc::new(Buckets::new()),
+            Arc::new(AtomicBool::new(true)),
+        );
+        exporter.snapshot.refresh();
+        assert!(exporter.render(1).starts_with(
+            "cpu.usage.user;cpu=0;env=prod 10 1\n\
+             cpu.usage.user;cpu=1;env=prod 20 1\n"
+        ));
+    }
+}
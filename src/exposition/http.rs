@@ -2,82 +2,140 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use rustcommon_logger::*;
 use rustcommon_metrics::*;
-use tiny_http::{Method, Response, Server};
 
-use super::MetricsSnapshot;
+use super::prometheus::Format;
+use super::stream::Limits;
+use super::tcp::HttpListener;
+use super::{MetricsSnapshot, Naming, RecentReadings};
+use crate::common::health::Health;
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::config::Config;
 
+/// Serves the admin endpoints, eg: the health checks and the human and machine
+/// readable stats. Connections are bounded by the same limits as those of the
+/// Prometheus listener.
 pub struct Http {
+    health: Arc<Health>,
+    listener: HttpListener,
+    readings: Option<Arc<RecentReadings>>,
     snapshot: MetricsSnapshot,
-    server: Server,
     updated: Instant,
 }
 
 impl Http {
     pub fn new(
-        address: SocketAddr,
+        config: &Config,
         metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
-        count_label: Option<&str>,
+        readings: Option<Arc<RecentReadings>>,
+        health: Arc<Health>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
     ) -> Self {
-        let server = tiny_http::Server::http(address);
-        if server.is_err() {
-            fatal!("Failed to open {} for HTTP Stats listener", address);
+        let address = config.listen().expect("no listen address");
+        let listener = HttpListener::new(address, Limits::new(config), None);
+        if let Err(ref e) = listener {
+            fatal!("Failed to open {} for HTTP Stats listener: {}", address, e);
         }
         Self {
-            snapshot: MetricsSnapshot::new(metrics, count_label),
-            server: server.unwrap(),
+            health,
+            listener: listener.unwrap(),
+            readings,
+            snapshot: MetricsSnapshot::new(
+                metrics,
+                config.general().reading_suffix(),
+                Naming::new(config),
+            )
+            .with_staleness(updates)
+            .with_entities(entities),
             updated: Instant::now(),
         }
     }
 
     pub fn run(&mut self) {
-        if let Ok(Some(request)) = self.server.try_recv() {
-            if self.updated.elapsed() >= Duration::from_millis(500) {
-                self.snapshot.refresh();
-                self.updated = Instant::now();
+        let health = &self.health;
+        let readings = self.readings.as_deref();
+        let snapshot = &mut self.snapshot;
+        let updated = &mut self.updated;
+        if let Err(e) = self.listener.try_serve(|method, url, _| {
+            let (status, body) = respond(health, readings, snapshot, updated, method, url);
+            (status, Format::Text.content_type(), body)
+        }) {
+            debug!("failed to serve http request: {}", e);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+fn respond(
+    health: &Health,
+    readings: Option<&RecentReadings>,
+    snapshot: &mut MetricsSnapshot,
+    updated: &mut Instant,
+    method: &str,
+    url: &str,
+) -> (u16, String) {
+    if updated.elapsed() >= Duration::from_millis(500) {
+        snapshot.refresh();
+        *updated = Instant::now();
+    }
+    match method {
+        "GET" => match url {
+            "/" => {
+                debug!("Serving GET on index");
+                (
+                    200,
+                    format!(
+                        "Welcome to {}\nVersion: {}\n",
+                        crate::config::NAME,
+                        crate::config::VERSION,
+                    ),
+                )
+            }
+            "/healthz" => {
+                debug!("Serving liveness");
+                (200, "ok\n".to_string())
             }
-            let url = request.url();
-            let parts: Vec<&str> = url.split('?').collect();
-            let url = parts[0];
-            match request.method() {
-                Method::Get => match url {
-                    "/" => {
-                        debug!("Serving GET on index");
-                        let _ = request.respond(Response::from_string(format!(
-                            "Welcome to {}\nVersion: {}\n",
-                            crate::config::NAME,
-                            crate::config::VERSION,
-                        )));
-                    }
-                    "/metrics" => {
-                        debug!("Serving Prometheus compatible stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.prometheus()));
-                    }
-                    "/metrics.json" | "/vars.json" | "/admin/metrics.json" => {
-                        debug!("Serving machine readable stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.json(false)));
-                    }
-                    "/vars" => {
-                        debug!("Serving human readable stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.human()));
-                    }
-                    url => {
-                        debug!("GET on non-existent url: {}", url);
-                        debug!("Serving machine readable stats");
-                        let _ = request.respond(Response::from_string(self.snapshot.json(false)));
-                    }
-                },
-                method => {
-                    debug!("unsupported request method: {}", method);
-                    let _ = request.respond(Response::empty(404));
+            "/readyz" => {
+                debug!("Serving readiness");
+                let (ready, content) = health.readiness(Instant::now());
+                let status = if ready { 200 } else { 503 };
+                (status, content)
+            }
+            "/metrics" => {
+                debug!("Serving Prometheus compatible stats");
+                (200, snapshot.prometheus())
+            }
+            "/metrics.json" | "/vars.json" | "/admin/metrics.json" => {
+                debug!("Serving machine readable stats");
+                (200, snapshot.json(false))
+            }
+            "/vars" => {
+                debug!("Serving human readable stats");
+                (200, snapshot.human())
+            }
+            url if url.starts_with("/raw/") => {
+                debug!("Serving recent readings");
+                let name = url.trim_start_matches("/raw/");
+                match readings.and_then(|r| r.json(name)) {
+                    Some(content) => (200, content),
+                    None => (404, String::new()),
                 }
             }
+            url => {
+                debug!("GET on non-existent url: {}", url);
+                debug!("Serving machine readable stats");
+                (200, snapshot.json(false))
+            }
+        },
+        method => {
+            debug!("unsupported request method: {}", method);
+            (404, String::new())
         }
-        std::thread::sleep(std::time::Duration::from_millis(1));
     }
 }
@@ -0,0 +1,235 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use json::JsonValue;
+use rustcommon_atomics::AtomicBool;
+use rustcommon_logger::*;
+use rustcommon_metrics::*;
+
+use super::{sleep_until, MetricsSnapshot, Naming, PushExporter};
+use crate::common::buckets::Buckets;
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::config::Config;
+
+/// Periodically appends the metrics registry to a local file as JSON Lines, one
+/// object per statistic, rotating the file once it reaches a configured size.
+pub struct JsonlExporter {
+    snapshot: MetricsSnapshot,
+    writer: RotatingFile,
+    interval: Duration,
+    runnable: Arc<AtomicBool>,
+}
+
+impl JsonlExporter {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
+        buckets: Arc<Buckets>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
+        let file = config.exposition().file();
+        let path = file.path().expect("no path for file exposition");
+        Self {
+            snapshot: MetricsSnapshot::new(metrics, None, Naming::new(&config))
+                .with_emit(file.emit())
+                .with_temporality(file.temporality(), buckets)
+                .with_staleness(updates)
+                .with_entities(entities),
+            writer: RotatingFile::new(
+                PathBuf::from(path),
+                file.max_bytes() as u64,
+                file.max_files(),
+            ),
+            interval: Duration::from_millis(file.interval() as u64),
+            runnable,
+        }
+    }
+}
+
+impl PushExporter for JsonlExporter {
+    fn run(&mut self) {
+        let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Write the current readings to the file
+    fn flush(&mut self) {
+        self.snapshot.refresh();
+        if let Err(e) = self.writer.write(&render(&self.snapshot)) {
+            error!("failed to write metrics to file: {}", e);
+        }
+    }
+}
+
+/// Render the snapshot as JSON Lines. Each line has the timestamp in
+/// milliseconds since the epoch, the statistic name, its latest reading, and
+/// the computed percentiles, along with the configured labels if there are any.
+/// A labeled statistic is named for its family, with the labels of its entity.
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_millis() as u64)
+        .unwrap_or(0);
+
+    // group outputs by statistic so each statistic gets a single line
+    let mut statistics = BTreeMap::<&str, Line>::new();
+    for (metric, value) in &snapshot.snapshot {
+        let entry = statistics
+            .entry(metric.statistic().name())
+            .or_insert_with(|| Line {
+                name: snapshot.family(metric),
+                labels: snapshot.metric_labels(metric),
+                value: None,
+                percentiles: Vec::new(),
+            });
+        match metric.output() {
+            Output::Reading => {
+                entry.value = Some(match snapshot.rate(metric) {
+                    Some(rate) => rate.into(),
+                    None => (*value).into(),
+                })
+            }
+            Output::Percentile(percentile) => entry.percentiles.push((percentile, *value)),
+        }
+    }
+
+    let mut content = String::new();
+    for (_, mut statistic) in statistics {
+        statistic
+            .percentiles
+            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut line = JsonValue::new_object();
+        line["timestamp"] = timestamp.into();
+        line["name"] = statistic.name.into();
+        line["value"] = statistic.value.into();
+        let mut object = JsonValue::new_object();
+        for (percentile, value) in statistic.percentiles {
+            object[format!("p{:02}", percentile).as_str()] = value.into();
+        }
+        line["percentiles"] = object;
+        if !statistic.labels.is_empty() {
+            let mut labels = JsonValue::new_object();
+            for (key, value) in &statistic.labels {
+                labels[key.as_str()] = value.as_str().into();
+            }
+            line["labels"] = labels;
+        }
+        content += &line.dump();
+        content += "\n";
+    }
+    content
+}
+
+/// The outputs of a statistic, which are rendered as one line
+struct Line {
+    name: String,
+    labels: BTreeMap<String, String>,
+    value: Option<JsonValue>,
+    percentiles: Vec<(f64, u64)>,
+}
+
+/// A file which is rotated once it reaches `max_bytes`. Rotated files are
+/// suffixed with `.1` for the most recent through `.N` for the oldest, with at
+/// most `max_files` in total including the file currently being written.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn new(path: PathBuf, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            max_files,
+            file: None,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, content: &str) -> Result<(), std::io::Error> {
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.written = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        if self.written > 0 && self.written + content.len() as u64 > self.max_bytes {
+            self.rotate()?;
+            return self.write(content);
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(content.as_bytes())?;
+            self.written += content.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), std::io::Error> {
+        debug!("rotating file: {}", self.path.display());
+        self.file = None;
+        self.written = 0;
+        if self.max_files <= 1 {
+            return std::fs::remove_file(&self.path);
+        }
+        for index in (1..self.max_files).rev() {
+            let source = if index == 1 {
+                self.path.clone()
+            } else {
+                rotated(&self.path, index - 1)
+            };
+            if source.exists() {
+                std::fs::rename(source, rotated(&self.path, index))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", index));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("rezolus-jsonl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metrics.jsonl");
+
+        let mut file = RotatingFile::new(path.clone(), 10, 3);
+        for line in &["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line).unwrap();
+        }
+
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), "fourth\n");
+        assert_eq!(read(rotated(&path, 1)), "third\n");
+        assert_eq!(read(rotated(&path, 2)), "second\n");
+        assert!(!rotated(&path, 3).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -7,22 +7,39 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use kafka::producer::{Producer, Record};
+use rustcommon_atomics::AtomicBool;
 use rustcommon_metrics_legacy::*;
 
+use crate::common::buckets::Buckets;
+use crate::common::staleness::Updates;
 use crate::config::Config;
-use crate::exposition::MetricsSnapshot;
+use crate::exposition::{sleep_until, MetricsSnapshot, Naming, PushExporter};
 
 pub struct KafkaProducer {
     snapshot: MetricsSnapshot,
     producer: Producer,
     topic: String,
     interval: Duration,
+    runnable: Arc<AtomicBool>,
 }
 
 impl KafkaProducer {
-    pub fn new(config: Arc<Config>, metrics: Arc<Metrics<AtomicU32>>) -> Self {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU32>>,
+        updates: Arc<Updates>,
+        buckets: Arc<Buckets>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
         Self {
-            snapshot: MetricsSnapshot::new(metrics, config.general().reading_suffix()),
+            snapshot: MetricsSnapshot::new(
+                metrics,
+                config.general().reading_suffix(),
+                Naming::new(&config),
+            )
+            .with_emit(config.exposition().kafka().emit())
+            .with_temporality(config.exposition().kafka().temporality(), buckets)
+            .with_staleness(updates),
             producer: Producer::from_hosts(config.exposition().kafka().hosts())
                 .create()
                 .unwrap(),
@@ -30,18 +47,23 @@ impl KafkaProducer {
             interval: Duration::from_millis(
                 config.exposition().kafka().interval().try_into().unwrap(),
             ),
+            runnable,
         }
     }
+}
 
-    pub fn run(&mut self) {
+impl PushExporter for KafkaProducer {
+    fn run(&mut self) {
         let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Send the current readings to the topic
+    fn flush(&mut self) {
         self.snapshot.refresh();
         let _ = self
             .producer
             .send(&Record::from_value(&self.topic, self.snapshot.json(false)));
-        let stop = Instant::now();
-        if start + self.interval > stop {
-            std::thread::sleep(self.interval - (stop - start));
-        }
     }
 }
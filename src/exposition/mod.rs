@@ -2,86 +2,569 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use std::time::Instant;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
+use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
 use rustcommon_metrics::*;
 
+use crate::common::buckets::{self, Buckets};
+use crate::common::labeled::{Entities, LabeledStatistic};
+use crate::common::staleness::Updates;
+use crate::common::unit::Units;
+use crate::config::{Config, Emit, OutputFormat, Temporality};
+
+mod auth;
+mod debug;
+mod encoding;
+mod graphite;
 mod http;
+mod jsonl;
 #[cfg(feature = "push_kafka")]
 mod kafka;
+#[cfg(feature = "push_otlp")]
+mod otlp;
+mod prometheus;
+mod registry;
+mod statsd;
+mod stream;
+mod tcp;
+#[cfg(feature = "tls")]
+mod tls;
+mod uds;
 
+pub use self::debug::{DebugRecorder, RecentReadings};
+pub use self::graphite::GraphiteExporter;
 pub use self::http::Http;
+pub use self::jsonl::JsonlExporter;
 #[cfg(feature = "push_kafka")]
 pub use self::kafka::KafkaProducer;
+#[cfg(feature = "push_otlp")]
+pub use self::otlp::OtlpExporter;
+pub use self::prometheus::Prometheus;
+pub use self::registry::{MetricSample, Registry};
+pub use self::statsd::StatsdExporter;
+
+/// How often push exporters check for shutdown while waiting to export
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Sleep until the deadline, returning early once shutdown has been requested
+/// so that the final export is not delayed by a full interval
+pub fn sleep_until(deadline: Instant, runnable: &AtomicBool) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline || !runnable.load(Ordering::Relaxed) {
+            return;
+        }
+        std::thread::sleep(std::cmp::min(deadline - now, SHUTDOWN_POLL));
+    }
+}
+
+/// An exporter which pushes the readings to a backend at its own interval
+pub trait PushExporter: Send + 'static {
+    /// Export the current readings, then wait until the next export is due
+    fn run(&mut self);
+
+    /// Export the current readings immediately
+    fn flush(&mut self);
+}
+
+/// Run the exporter on its own thread until shutdown, after which it makes one
+/// final export. Each exporter has a dedicated thread, rather than a task on
+/// the sampler runtime, so that a backend which is slow or unreachable cannot
+/// delay the samplers or any of the other exporters.
+pub fn spawn_exporter<T: PushExporter>(
+    name: &str,
+    mut exporter: T,
+    runnable: Arc<AtomicBool>,
+) -> Option<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            while runnable.load(Ordering::Relaxed) {
+                exporter.run();
+            }
+            exporter.flush();
+        })
+        .map_err(|e| error!("failed to start {} exporter: {}", name, e))
+        .ok()
+}
+
+/// Render the current readings in the given format, for `--once`
+pub fn render_once(
+    config: &Config,
+    metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+    units: &Units,
+    entities: Arc<Entities>,
+    format: OutputFormat,
+) -> String {
+    let mut snapshot =
+        MetricsSnapshot::new(metrics, None, Naming::new(config)).with_entities(entities);
+    snapshot.refresh();
+    match format {
+        OutputFormat::Json => snapshot.json(true) + "\n",
+        OutputFormat::Prometheus => prometheus::render(&snapshot, units, prometheus::Format::Text),
+    }
+}
+
+/// Renders a statistic name for output by joining the prefix, if any, and each
+/// component of the name with the separator. The statistic name itself, eg:
+/// `tcp/connect/latency`, remains the canonical internal identifier.
+pub fn render_name(prefix: &str, separator: &str, name: &str) -> String {
+    let name = name.replace('/', separator);
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}{}{}", prefix, separator, name)
+    }
+}
+
+/// Rounds a percentile value to the given number of decimal places. Values are
+/// whole numbers, so a precision of zero or more leaves them unchanged, while a
+/// negative precision rounds to the nearest power of ten, eg: `-3` rounds to the
+/// nearest thousand.
+pub fn round_percentile(value: u64, precision: Option<i32>) -> u64 {
+    let places = match precision {
+        Some(precision) if precision < 0 => -precision as u32,
+        _ => return value,
+    };
+    match 10_u64.checked_pow(places) {
+        Some(scale) => {
+            let rounded = (value / scale).saturating_mul(scale);
+            if value % scale >= scale / 2 {
+                rounded.saturating_add(scale)
+            } else {
+                rounded
+            }
+        }
+        None => 0,
+    }
+}
+
+/// The configured naming convention, the labels attached to every metric, and
+/// the precision of percentile values, used by all exporters
+#[derive(Clone, Debug)]
+pub struct Naming {
+    labels: BTreeMap<String, String>,
+    percentile_precision: Option<i32>,
+    prefix: String,
+    separator: String,
+}
+
+impl Naming {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            labels: config.labels().clone(),
+            percentile_precision: config.exposition().percentile_precision(),
+            prefix: config.exposition().prefix().to_string(),
+            separator: config.exposition().separator().to_string(),
+        }
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
+    /// The percentile value rounded to the configured precision
+    pub fn percentile(&self, value: u64) -> u64 {
+        round_percentile(value, self.percentile_precision)
+    }
+
+    pub fn render(&self, name: &str) -> String {
+        render_name(&self.prefix, &self.separator, name)
+    }
+
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+}
+
+/// Converts counter readings into per-second rates using the previous reading
+/// of each counter and the time at which it was taken
+#[derive(Default)]
+pub struct CounterRates {
+    previous: HashMap<String, (u64, Instant)>,
+}
+
+impl CounterRates {
+    /// The rate since the previous reading, which is `None` for the first
+    /// reading of a counter. A counter which has decreased is treated as having
+    /// been reset to zero in the meantime.
+    pub fn rate(&mut self, name: &str, value: u64, time: Instant) -> Option<f64> {
+        let (previous, then) = self.previous.insert(name.to_string(), (value, time))?;
+        let elapsed = time.saturating_duration_since(then).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        let delta = if value >= previous {
+            value - previous
+        } else {
+            value
+        };
+        Some(delta as f64 / elapsed)
+    }
+}
+
+/// Rounds a rate to three decimal places, which keeps low rates such as one
+/// event every ten seconds from being exported as zero
+pub fn round_rate(rate: f64) -> f64 {
+    (rate * 1000.0).round() / 1000.0
+}
 
 pub struct MetricsSnapshot {
     metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
     snapshot: HashMap<Metric<AtomicU64, AtomicU32>, u64>,
     refreshed: Instant,
+    buckets: Option<Arc<Buckets>>,
     count_label: Option<String>,
+    deltas: Option<HashMap<String, u64>>,
+    distributions: HashMap<String, BTreeMap<u64, u64>>,
+    entities: Option<Arc<Entities>>,
+    fractional: HashMap<String, f64>,
+    previous_buckets: Option<HashMap<String, BTreeMap<u64, u64>>>,
+    naming: Naming,
+    rates: Option<CounterRates>,
+    updates: Option<Arc<Updates>>,
 }
 
 impl MetricsSnapshot {
-    pub fn new(metrics: Arc<Metrics<AtomicU64, AtomicU32>>, count_label: Option<&str>) -> Self {
+    pub fn new(
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        count_label: Option<&str>,
+        naming: Naming,
+    ) -> Self {
         Self {
             metrics,
             snapshot: HashMap::new(),
             refreshed: Instant::now(),
+            buckets: None,
             count_label: count_label.map(std::string::ToString::to_string),
+            deltas: None,
+            distributions: HashMap::new(),
+            entities: None,
+            fractional: HashMap::new(),
+            previous_buckets: None,
+            naming,
+            rates: None,
+            updates: None,
+        }
+    }
+
+    /// In `Emit::Rate` mode each refresh replaces counter readings with their
+    /// per-second rate, rounded to the nearest integer, while `rate` keeps the
+    /// fractional part for exporters which render it. A counter is omitted
+    /// until it has a previous reading to compute the rate from.
+    pub fn with_emit(mut self, emit: Emit) -> Self {
+        self.rates = match emit {
+            Emit::Counter => None,
+            Emit::Rate => Some(CounterRates::default()),
+        };
+        self
+    }
+
+    /// With `Temporality::Delta` each refresh replaces counter readings with
+    /// their increase since the previous refresh, which is the whole reading
+    /// the first time, and the percentiles of distributions with those of the
+    /// buckets recorded since the previous refresh. A distribution without
+    /// any is left out. The registry itself is shared by every exporter, so
+    /// only this snapshot's view is reset. Counters emitted as rates are
+    /// unaffected. With either temporality, each refresh also keeps the
+    /// buckets of the distributions, see `distribution`.
+    pub fn with_temporality(mut self, temporality: Temporality, buckets: Arc<Buckets>) -> Self {
+        self.buckets = Some(buckets);
+        match temporality {
+            Temporality::Cumulative => {
+                self.deltas = None;
+                self.previous_buckets = None;
+            }
+            Temporality::Delta => {
+                self.deltas = Some(HashMap::new());
+                self.previous_buckets = Some(HashMap::new());
+            }
         }
+        self
+    }
+
+    /// Each refresh omits the series which have not been updated within the
+    /// configured `stale_after` intervals
+    pub fn with_staleness(mut self, updates: Arc<Updates>) -> Self {
+        self.updates = Some(updates);
+        self
+    }
+
+    /// Labeled statistics, such as those of each cpu, are exported as the
+    /// statistic they break down with the labels of the entity, see `family`
+    /// and `metric_labels`, by the exporters which support labels
+    pub fn with_entities(mut self, entities: Arc<Entities>) -> Self {
+        self.entities = Some(entities);
+        self
+    }
+
+    /// The source of the metric's statistic as it appears in the snapshot,
+    /// counters which are emitted as rates are gauges
+    pub fn source(&self, metric: &Metric<AtomicU64, AtomicU32>) -> Source {
+        match metric.statistic().source() {
+            Source::Counter if self.rates.is_some() => Source::Gauge,
+            source => source,
+        }
+    }
+
+    /// The per-second rate of a counter reading which is emitted as a rate,
+    /// rounded to three decimal places rather than to an integer
+    pub fn rate(&self, metric: &Metric<AtomicU64, AtomicU32>) -> Option<f64> {
+        match metric.output() {
+            Output::Reading => self.fractional.get(metric.statistic().name()).copied(),
+            Output::Percentile(_) => None,
+        }
+    }
+
+    /// The value of the metric as text, which is the fractional rate for
+    /// counters emitted as rates and the reading otherwise
+    pub fn format_value(&self, metric: &Metric<AtomicU64, AtomicU32>, value: u64) -> String {
+        match self.rate(metric) {
+            Some(rate) => rate.to_string(),
+            None => value.to_string(),
+        }
+    }
+
+    /// The buckets of the distribution of a percentile's statistic as of the
+    /// latest refresh, which are those recorded since the previous refresh
+    /// with delta temporality, and all of them otherwise. This is `None` for
+    /// statistics which are not distributions, or if the temporality was not
+    /// set.
+    pub fn distribution(
+        &self,
+        metric: &Metric<AtomicU64, AtomicU32>,
+    ) -> Option<&BTreeMap<u64, u64>> {
+        match metric.output() {
+            Output::Reading => None,
+            Output::Percentile(_) => self.distributions.get(metric.statistic().name()),
+        }
+    }
+
+    /// The configured labels, which exporters attach to every metric
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        self.naming.labels()
+    }
+
+    /// The rendered output name of the metric's statistic
+    pub fn name(&self, metric: &Metric<AtomicU64, AtomicU32>) -> String {
+        self.naming.render(metric.statistic().name())
+    }
+
+    /// The rendered output name of the metric's family, which for a labeled
+    /// statistic is the statistic it breaks down, eg: `cpu/usage/user` rather
+    /// than `cpu/usage/user/0`, and is the same as `name` otherwise
+    pub fn family(&self, metric: &Metric<AtomicU64, AtomicU32>) -> String {
+        match self.entity(metric) {
+            Some(statistic) => self.naming.render(statistic.family()),
+            None => self.name(metric),
+        }
+    }
+
+    /// The labels which identify the entity of a labeled statistic, which are
+    /// empty for any other statistic
+    pub fn entity_labels(&self, metric: &Metric<AtomicU64, AtomicU32>) -> Vec<(String, String)> {
+        self.entity(metric)
+            .map(|statistic| statistic.labels().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// The configured labels along with those of the metric's entity, which
+    /// take precedence over a configured label with the same key
+    pub fn metric_labels(&self, metric: &Metric<AtomicU64, AtomicU32>) -> BTreeMap<String, String> {
+        let mut labels = self.labels().clone();
+        labels.extend(self.entity_labels(metric));
+        labels
+    }
+
+    fn entity(&self, metric: &Metric<AtomicU64, AtomicU32>) -> Option<LabeledStatistic> {
+        self.entities.as_ref()?.get(metric.statistic().name())
+    }
+
+    /// The configured naming, for exporters which add to the statistic names
+    pub fn naming(&self) -> &Naming {
+        &self.naming
+    }
+
+    /// The readings in order of statistic name, with the reading of each
+    /// statistic ahead of its percentiles in ascending order, so that the
+    /// output of each refresh is laid out the same way
+    pub fn sorted(&self) -> Vec<(&Metric<AtomicU64, AtomicU32>, u64)> {
+        let mut sorted: Vec<(&Metric<AtomicU64, AtomicU32>, u64)> = self
+            .snapshot
+            .iter()
+            .map(|(metric, value)| (metric, *value))
+            .collect();
+        sorted.sort_by(|(a, _), (b, _)| {
+            a.statistic()
+                .name()
+                .cmp(b.statistic().name())
+                .then_with(|| compare_outputs(a.output(), b.output()))
+        });
+        sorted
     }
 
     pub fn refresh(&mut self) {
+        let now = Instant::now();
         self.snapshot = self.metrics.snapshot();
-        self.refreshed = Instant::now();
+        self.refreshed = now;
+        let naming = &self.naming;
+        for (metric, value) in self.snapshot.iter_mut() {
+            if let Output::Percentile(_) = metric.output() {
+                *value = naming.percentile(*value);
+            }
+        }
+        if let Some(updates) = &self.updates {
+            self.snapshot
+                .retain(|metric, _| !updates.is_stale(metric.statistic().name(), now));
+        }
+        if let Some(rates) = &mut self.rates {
+            let fractional = &mut self.fractional;
+            fractional.clear();
+            self.snapshot.retain(|metric, value| {
+                if let (Output::Reading, Source::Counter) =
+                    (metric.output(), metric.statistic().source())
+                {
+                    match rates.rate(metric.statistic().name(), *value, now) {
+                        Some(rate) => {
+                            let rate = round_rate(rate);
+                            fractional.insert(metric.statistic().name().to_string(), rate);
+                            *value = rate.round() as u64;
+                        }
+                        None => return false,
+                    }
+                }
+                true
+            });
+        } else if let Some(previous) = &mut self.deltas {
+            for (metric, value) in self.snapshot.iter_mut() {
+                if let (Output::Reading, Source::Counter) =
+                    (metric.output(), metric.statistic().source())
+                {
+                    let last = previous
+                        .insert(metric.statistic().name().to_string(), *value)
+                        .unwrap_or(0);
+                    // a counter which has decreased was reset in the meantime
+                    if *value >= last {
+                        *value -= last;
+                    }
+                }
+            }
+        }
+        if let Some(totals) = &self.buckets {
+            // only distributions have bucket totals
+            let mut current = HashMap::new();
+            for metric in self.snapshot.keys() {
+                let name = metric.statistic().name();
+                if let Output::Percentile(_) = metric.output() {
+                    if !current.contains_key(name) {
+                        if let Some(buckets) = totals.totals(name) {
+                            current.insert(name.to_string(), buckets);
+                        }
+                    }
+                }
+            }
+            self.distributions = match &mut self.previous_buckets {
+                Some(previous) => current
+                    .into_iter()
+                    .map(|(name, buckets)| {
+                        let interval = buckets::delta(&buckets, previous.get(&name));
+                        previous.insert(name.clone(), buckets);
+                        (name, interval)
+                    })
+                    .collect(),
+                None => current,
+            };
+            if self.previous_buckets.is_some() {
+                let distributions = &self.distributions;
+                self.snapshot.retain(|metric, value| {
+                    if let Output::Percentile(percentile) = metric.output() {
+                        if let Some(interval) = distributions.get(metric.statistic().name()) {
+                            match buckets::percentile(interval, percentile) {
+                                Some(v) => *value = naming.percentile(v),
+                                None => return false,
+                            }
+                        }
+                    }
+                    true
+                });
+            }
+        }
     }
 
+    /// Render the readings as Prometheus text with the original naming, where
+    /// each percentile is a gauge of its own, eg: `tcp_connect_latency_histogram_p99`.
+    /// Metrics are in name order and each has a single TYPE line. A labeled
+    /// statistic is a sample of its family with the labels of its entity.
     pub fn prometheus(&self) -> String {
-        let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
-            let output = metric.output();
-            match output {
+        let mut families = BTreeMap::<String, (&'static str, Vec<(String, u64)>)>::new();
+        for (metric, value) in self.sorted() {
+            // the name is sanitized below, which replaces any separator which
+            // is not valid in prometheus, such as the default `/`, with `_`
+            let label = self.family(metric);
+            let separator = self.naming.separator();
+            let (name, kind) = match metric.output() {
                 Output::Reading => {
+                    let kind = match self.source(metric) {
+                        Source::Counter => "counter",
+                        _ => "gauge",
+                    };
                     if let Some(ref count_label) = self.count_label {
-                        data.push(format!("{}/{} {}", label, count_label, value));
+                        (format!("{}{}{}", label, separator, count_label), kind)
                     } else {
-                        data.push(format!("{} {}", label, value));
+                        (label, kind)
                     }
                 }
-                Output::Percentile(percentile) => {
-                    data.push(format!("{}/histogram/p{:02} {}", label, percentile, value));
-                }
+                Output::Percentile(percentile) => (
+                    format!(
+                        "{}{}histogram{}p{:02}",
+                        label, separator, separator, percentile
+                    ),
+                    "gauge",
+                ),
+            };
+            let labels = prometheus::label_set(&self.metric_labels(metric), None);
+            families
+                .entry(prometheus::sanitize(&name))
+                .or_insert((kind, Vec::new()))
+                .1
+                .push((labels, value));
+        }
+        let mut content = String::new();
+        for (name, (kind, samples)) in families {
+            content += &format!("# TYPE {} {}\n", name, kind);
+            for (labels, value) in samples {
+                content += &format!("{}{} {}\n", name, labels, value);
             }
         }
-        data.sort();
-        let mut content = data.join("\n");
-        content += "\n";
-        let parts: Vec<&str> = content.split('/').collect();
-        parts.join("_")
+        content
     }
 
     pub fn human(&self) -> String {
         let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
+        let separator = self.naming.separator();
+        for (metric, value) in self.sorted() {
+            let label = self.name(metric);
             let output = metric.output();
             match output {
                 Output::Reading => {
                     if let Some(ref count_label) = self.count_label {
-                        data.push(format!("{}/{}: {}", label, count_label, value));
+                        data.push(format!("{}{}{}: {}", label, separator, count_label, value));
                     } else {
                         data.push(format!("{}: {}", label, value));
                     }
                 }
                 Output::Percentile(percentile) => {
-                    data.push(format!("{}/histogram/p{:02}: {}", label, percentile, value));
+                    data.push(format!(
+                        "{}{}histogram{}p{:02}: {}",
+                        label, separator, separator, percentile, value
+                    ));
                 }
             }
         }
-        data.sort();
         let mut content = data.join("\n");
         content += "\n";
         content
@@ -93,26 +576,36 @@ impl MetricsSnapshot {
             head += "\n  ";
         }
         let mut data = Vec::new();
-        for (metric, value) in &self.snapshot {
-            let label = metric.statistic().name();
+        let separator = self.naming.separator();
+        for (metric, value) in self.sorted() {
+            let label = self.name(metric);
             let output = metric.output();
             match output {
                 Output::Reading => {
                     if let Some(ref count_label) = self.count_label {
-                        data.push(format!("\"{}/{}\": {}", label, count_label, value));
+                        data.push(format!(
+                            "\"{}{}{}\": {}",
+                            label,
+                            separator,
+                            count_label,
+                            self.format_value(metric, value)
+                        ));
                     } else {
-                        data.push(format!("\"{}\": {}", label, value));
+                        data.push(format!(
+                            "\"{}\": {}",
+                            label,
+                            self.format_value(metric, value)
+                        ));
                     }
                 }
                 Output::Percentile(percentile) => {
                     data.push(format!(
-                        "\"{}/histogram/p{:02}\": {}",
-                        label, percentile, value
+                        "\"{}{}histogram{}p{:02}\": {}",
+                        label, separator, separator, percentile, value
                     ));
                 }
             }
         }
-        data.sort();
         let body = if pretty {
             data.join(",\n  ")
         } else {
@@ -127,3 +620,369 @@ impl MetricsSnapshot {
         content
     }
 }
+
+/// A registry with a counter, `system/lockup/soft`, and a gauge,
+/// `system/entropy/available`, for testing the exporters
+#[cfg(test)]
+pub(crate) struct TestMetrics {
+    pub metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+    pub counter: crate::samplers::system::SystemStatistic,
+    pub entities: Arc<Entities>,
+    pub gauge: crate::samplers::system::SystemStatistic,
+    pub start: Instant,
+}
+
+#[cfg(test)]
+impl TestMetrics {
+    /// Registers the readings of both, and the given percentiles of the gauge
+    pub fn new(percentiles: &[f64]) -> Self {
+        use crate::samplers::system::SystemStatistic;
+        let metrics = Arc::new(Metrics::new());
+        let counter = SystemStatistic::SoftLockups;
+        let gauge = SystemStatistic::EntropyAvailable;
+        for statistic in &[counter, gauge] {
+            metrics.register(statistic);
+            metrics.add_output(statistic, Output::Reading);
+        }
+        if !percentiles.is_empty() {
+            metrics.add_summary(&gauge, Summary::stream(10));
+        }
+        for percentile in percentiles {
+            metrics.add_output(&gauge, Output::Percentile(*percentile));
+        }
+        Self {
+            metrics,
+            counter,
+            entities: Arc::new(Entities::new()),
+            gauge,
+            start: Instant::now(),
+        }
+    }
+
+    /// Registers and records the labeled `cpu/usage/user` counter of each cpu,
+    /// eg: `cpu/usage/user/0` with `cpu="0"`
+    pub fn record_cpus(&self, values: &[u64]) {
+        for (cpu, value) in values.iter().enumerate() {
+            let statistic = LabeledStatistic::new(
+                &crate::samplers::cpu::CpuStatistic::UsageUser,
+                "cpu",
+                &cpu.to_string(),
+            );
+            self.metrics.register(&statistic);
+            self.metrics.add_output(&statistic, Output::Reading);
+            self.entities.insert(&statistic);
+            let _ = self.metrics.record_counter(&statistic, self.start, *value);
+        }
+    }
+
+    /// Records the counter at the start, and each of the gauge values a second
+    /// apart from the start
+    pub fn record(&self, counter: u64, gauges: &[u64]) {
+        let _ = self
+            .metrics
+            .record_counter(&self.counter, self.start, counter);
+        for (i, value) in gauges.iter().enumerate() {
+            let time = self.start + Duration::from_secs(i as u64);
+            let _ = self.metrics.record_gauge(&self.gauge, time, *value);
+        }
+    }
+
+    /// A refreshed snapshot, named as configured
+    pub fn snapshot(&self, config: &Config) -> MetricsSnapshot {
+        let mut snapshot = MetricsSnapshot::new(self.metrics.clone(), None, Naming::new(config))
+            .with_entities(self.entities.clone());
+        snapshot.refresh();
+        snapshot
+    }
+}
+
+/// Readings come ahead of percentiles, which are in ascending order
+fn compare_outputs(a: Output, b: Output) -> std::cmp::Ordering {
+    match (a, b) {
+        (Output::Reading, Output::Reading) => std::cmp::Ordering::Equal,
+        (Output::Reading, Output::Percentile(_)) => std::cmp::Ordering::Less,
+        (Output::Percentile(_), Output::Reading) => std::cmp::Ordering::Greater,
+        (Output::Percentile(a), Output::Percentile(b)) => {
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::samplers::system::SystemStatistic;
+
+    #[test]
+    fn test_render_name() {
+        // the defaults preserve the statistic names
+        assert_eq!(
+            render_name("", "/", "tcp/connect/latency"),
+            "tcp/connect/latency"
+        );
+        assert_eq!(
+            render_name("rezolus", ".", "tcp/connect/latency"),
+            "rezolus.tcp.connect.latency"
+        );
+        assert_eq!(
+            render_name("rezolus", "_", "tcp/connect/latency"),
+            "rezolus_tcp_connect_latency"
+        );
+        assert_eq!(render_name("", "_", "cpu/usage/user"), "cpu_usage_user");
+    }
+
+    #[test]
+    fn test_round_percentile() {
+        assert_eq!(round_percentile(123_456_789, None), 123_456_789);
+        assert_eq!(round_percentile(123_456_789, Some(2)), 123_456_789);
+        assert_eq!(round_percentile(123_456_789, Some(0)), 123_456_789);
+        assert_eq!(round_percentile(123_456_789, Some(-3)), 123_457_000);
+        assert_eq!(round_percentile(123_456_489, Some(-3)), 123_456_000);
+        assert_eq!(round_percentile(499, Some(-3)), 0);
+        // rounding up saturates rather than overflowing
+        assert_eq!(round_percentile(u64::MAX, Some(-1)), u64::MAX);
+        assert_eq!(round_percentile(u64::MAX, Some(-19)), u64::MAX);
+    }
+
+    #[test]
+    fn test_counter_rates() {
+        let mut rates = CounterRates::default();
+        let start = Instant::now();
+        assert_eq!(rates.rate("tcp/receive/segment", 1000, start), None);
+        let later = start + Duration::from_secs(10);
+        assert_eq!(rates.rate("tcp/receive/segment", 1100, later), Some(10.0));
+        // a reset counter is assumed to have started again from zero
+        let reset = later + Duration::from_secs(10);
+        assert_eq!(rates.rate("tcp/receive/segment", 50, reset), Some(5.0));
+
+        // a rate below one per second is kept rather than rounded to zero
+        let slow = reset + Duration::from_secs(30);
+        let rate = rates.rate("tcp/receive/segment", 60, slow).unwrap();
+        assert_eq!(round_rate(rate), 0.333);
+        assert_eq!(round_rate(rate).to_string(), "0.333");
+        assert_eq!(round_rate(10.0).to_string(), "10");
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let test = TestMetrics::new(&[50.0, 99.9, 5.0]);
+        test.record(3, &[100, 200, 300]);
+
+        let snapshot = test.snapshot(&Config::default());
+        let outputs: Vec<String> = snapshot
+            .sorted()
+            .iter()
+            .map(|(metric, _)| match metric.output() {
+                Output::Reading => metric.statistic().name().to_string(),
+                Output::Percentile(percentile) => format!("p{}", percentile),
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                "system/entropy/available",
+                "p5",
+                "p50",
+                "p99.9",
+                "system/lockup/soft"
+            ]
+        );
+
+        // each metric has a single TYPE line, and the order is independent of
+        // the readings, which are left out here
+        let rendered = snapshot.prometheus();
+        let lines: Vec<&str> = rendered
+            .lines()
+            .map(|line| {
+                if line.starts_with('#') {
+                    line
+                } else {
+                    line.rsplitn(2, ' ').nth(1).unwrap()
+                }
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "# TYPE system_entropy_available gauge",
+                "system_entropy_available",
+                "# TYPE system_entropy_available_histogram_p05 gauge",
+                "system_entropy_available_histogram_p05",
+                "# TYPE system_entropy_available_histogram_p50 gauge",
+                "system_entropy_available_histogram_p50",
+                "# TYPE system_entropy_available_histogram_p99_9 gauge",
+                "system_entropy_available_histogram_p99_9",
+                "# TYPE system_lockup_soft counter",
+                "system_lockup_soft",
+            ]
+        );
+        assert!(rendered.ends_with("system_lockup_soft 3\n"));
+
+        // a prefix is joined with the configured separator and sanitized
+        let config = Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[exposition]\nprefix = \"rezolus\"\nseparator = \".\"\n",
+        )
+        .unwrap();
+        let snapshot = test.snapshot(&config);
+        assert!(snapshot
+            .prometheus()
+            .starts_with("# TYPE rezolus_system_entropy_available gauge\n"));
+    }
+
+    #[test]
+    fn test_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record(3, &[7]);
+        test.record_cpus(&[10, 20]);
+        let config =
+            Config::parse("[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nenv = \"prod\"\n")
+                .unwrap();
+        let snapshot = test.snapshot(&config);
+        let (metric, _) = snapshot
+            .sorted()
+            .into_iter()
+            .find(|(metric, _)| metric.statistic().name() == "cpu/usage/user/1")
+            .unwrap();
+        assert_eq!(snapshot.name(metric), "cpu/usage/user/1");
+        assert_eq!(snapshot.family(metric), "cpu/usage/user");
+        assert_eq!(
+            snapshot.entity_labels(metric),
+            vec![("cpu".to_string(), "1".to_string())]
+        );
+        assert_eq!(snapshot.metric_labels(metric).len(), 2);
+
+        // the cpus are samples of one family, while the names in the other
+        // formats keep the entity as a path component
+        let rendered = snapshot.prometheus();
+        assert!(rendered.starts_with(
+            "# TYPE cpu_usage_user counter\n\
+             cpu_usage_user{cpu=\"0\",env=\"prod\"} 10\n\
+             cpu_usage_user{cpu=\"1\",env=\"prod\"} 20\n"
+        ));
+        assert!(snapshot.human().starts_with("cpu/usage/user/0: 10\n"));
+    }
+
+    #[test]
+    fn test_staleness() {
+        let metrics = Arc::new(Metrics::new());
+        for statistic in &[SystemStatistic::Uptime, SystemStatistic::EntropyAvailable] {
+            metrics.register(statistic);
+            metrics.add_output(statistic, Output::Reading);
+            let _ = metrics.record_gauge(statistic, Instant::now(), 1);
+        }
+        let updates = Arc::new(Updates::new(Some(2)));
+        // uptime was last updated 3 intervals ago and entropy has just been
+        let interval = Duration::from_secs(1);
+        updates.update("system/uptime", Instant::now() - interval * 3, interval);
+        updates.update("system/entropy/available", Instant::now(), interval);
+
+        let mut snapshot = MetricsSnapshot::new(metrics, None, Naming::new(&Config::default()))
+            .with_staleness(updates);
+        snapshot.refresh();
+        assert_eq!(snapshot.human(), "system/entropy/available: 1\n");
+    }
+
+    #[test]
+    fn test_delta_temporality() {
+        let test = TestMetrics::new(&[]);
+        let mut snapshot = test
+            .snapshot(&Config::default())
+            .with_temporality(Temporality::Delta, Arc::new(Buckets::new()));
+        test.record(100, &[7]);
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\nsystem/lockup/soft: 100\n"
+        );
+
+        // only the increase is exported, while gauges are unchanged
+        let later = test.start + Duration::from_secs(1);
+        let _ = test.metrics.record_counter(&test.counter, later, 130);
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\nsystem/lockup/soft: 30\n"
+        );
+
+        // and the count starts from zero again in each interval
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\nsystem/lockup/soft: 0\n"
+        );
+    }
+
+    #[test]
+    fn test_delta_percentiles() {
+        let test = TestMetrics::new(&[50.0, 100.0]);
+        let buckets = Arc::new(Buckets::new());
+        let mut snapshot = test
+            .snapshot(&Config::default())
+            .with_temporality(Temporality::Delta, buckets.clone());
+        let name = test.gauge.name();
+        test.record(0, &[7]);
+        buckets.record(name, 10, 3);
+        buckets.record(name, 20, 1);
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\n\
+             system/entropy/available/histogram/p50: 10\n\
+             system/entropy/available/histogram/p100: 20\n\
+             system/lockup/soft: 0\n"
+        );
+        assert_eq!(distribution(&snapshot), vec![(10, 3), (20, 1)]);
+
+        // only the buckets recorded since the previous refresh are counted
+        buckets.record(name, 40, 2);
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\n\
+             system/entropy/available/histogram/p50: 40\n\
+             system/entropy/available/histogram/p100: 40\n\
+             system/lockup/soft: 0\n"
+        );
+        assert_eq!(distribution(&snapshot), vec![(40, 2)]);
+
+        // and an interval without any leaves the percentiles out
+        snapshot.refresh();
+        assert_eq!(
+            snapshot.human(),
+            "system/entropy/available: 7\nsystem/lockup/soft: 0\n"
+        );
+    }
+
+    #[test]
+    fn test_cumulative_distribution() {
+        let test = TestMetrics::new(&[50.0]);
+        let buckets = Arc::new(Buckets::new());
+        let mut snapshot = test
+            .snapshot(&Config::default())
+            .with_temporality(Temporality::Cumulative, buckets.clone());
+        let name = test.gauge.name();
+        test.record(0, &[7]);
+        buckets.record(name, 10, 3);
+        snapshot.refresh();
+        assert_eq!(distribution(&snapshot), vec![(10, 3)]);
+
+        // every bucket recorded so far is kept, and the percentiles are those
+        // of the registry
+        buckets.record(name, 40, 2);
+        snapshot.refresh();
+        assert_eq!(distribution(&snapshot), vec![(10, 3), (40, 2)]);
+        assert!(snapshot
+            .human()
+            .contains("system/entropy/available/histogram/p50: 7\n"));
+    }
+
+    /// The buckets of the only distribution in the snapshot
+    fn distribution(snapshot: &MetricsSnapshot) -> Vec<(u64, u64)> {
+        snapshot
+            .sorted()
+            .iter()
+            .find_map(|(metric, _)| snapshot.distribution(metric))
+            .map(|buckets| buckets.iter().map(|(v, c)| (*v, *c)).collect())
+            .unwrap_or_default()
+    }
+}
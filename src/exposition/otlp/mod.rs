@@ -0,0 +1,558 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use http::uri::PathAndQuery;
+use rustcommon_atomics::AtomicBool;
+use rustcommon_metrics::{AtomicU32, AtomicU64, Metrics, Output, Source, Statistic};
+use tokio::runtime::{Builder, Runtime};
+use tonic::client::Grpc;
+use tonic::codec::ProstCodec;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::common::buckets::{self, Buckets};
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::config::{Config, Temporality};
+use crate::exposition::{sleep_until, MetricsSnapshot, Naming, PushExporter};
+
+mod proto;
+
+use self::proto::*;
+
+/// Periodically pushes the metrics registry to an OpenTelemetry collector
+/// using the OTLP gRPC metrics service. The gRPC client runs on a runtime of
+/// the exporter's own, so that a slow collector does not hold up the samplers.
+pub struct OtlpExporter {
+    client: Option<Grpc<Channel>>,
+    endpoint: String,
+    fault_tolerant: bool,
+    hostname: String,
+    interval: Duration,
+    /// The end of the previous export, which starts the period covered by
+    /// delta sums
+    previous: u64,
+    runnable: Arc<AtomicBool>,
+    runtime: Runtime,
+    snapshot: MetricsSnapshot,
+    started: u64,
+    temporality: Temporality,
+}
+
+impl OtlpExporter {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
+        buckets: Arc<Buckets>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|v| v.trim().to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let started = unix_nanos();
+        let temporality = config.exposition().otlp().temporality();
+        let runtime = Builder::new()
+            .basic_scheduler()
+            .enable_io()
+            .enable_time()
+            .build();
+        if let Err(ref e) = runtime {
+            fatal!("failed to create the otlp exporter runtime: {}", e);
+        }
+        Self {
+            client: None,
+            endpoint: config
+                .exposition()
+                .otlp()
+                .endpoint()
+                .expect("no otlp endpoint configured"),
+            fault_tolerant: config.general().fault_tolerant(),
+            hostname,
+            interval: Duration::from_millis(
+                config.exposition().otlp().interval().try_into().unwrap(),
+            ),
+            previous: started,
+            runnable,
+            runtime: runtime.unwrap(),
+            snapshot: MetricsSnapshot::new(metrics, None, Naming::new(&config))
+                .with_emit(config.exposition().otlp().emit())
+                .with_temporality(temporality, buckets)
+                .with_staleness(updates)
+                .with_entities(entities),
+            started,
+            temporality,
+        }
+    }
+
+    /// Connect if needed, then send the readings since the previous export
+    fn export(&mut self) -> Result<(), anyhow::Error> {
+        if self.client.is_none() {
+            debug!("connecting to otlp collector: {}", self.endpoint);
+            let channel = self.runtime.block_on(connect(&self.endpoint))?;
+            self.client = Some(Grpc::new(channel));
+        }
+
+        self.snapshot.refresh();
+        let now = unix_nanos();
+        let request = self.request(now);
+        // the snapshot has already moved on, so with delta temporality the
+        // increase in an export which fails is not sent again
+        self.previous = now;
+
+        if let Some(client) = &mut self.client {
+            self.runtime.block_on(send(client, request))?;
+        }
+        Ok(())
+    }
+
+    /// Build the export request. Counter readings map to monotonic sums, which
+    /// are cumulative or cover only the time since the previous export
+    /// depending on the temporality, gauge readings and counter rates map to
+    /// gauges, and percentiles to a summary. The count and sum of a summary
+    /// are those of the histogram buckets over the same period as the sums.
+    /// Labeled statistics are data points of their family, with the labels of
+    /// the entity as attributes.
+    fn request(&self, now: u64) -> ExportMetricsServiceRequest {
+        let (start, temporality) = match self.temporality {
+            Temporality::Cumulative => (self.started, AggregationTemporality::Cumulative),
+            Temporality::Delta => (self.previous, AggregationTemporality::Delta),
+        };
+        // the data points of each family are kept in order of statistic name
+        let mut readings = BTreeMap::<String, (Source, BTreeMap<&str, NumberDataPoint>)>::new();
+        let mut summaries = BTreeMap::<String, BTreeMap<&str, SummaryDataPoint>>::new();
+
+        for (metric, value) in &self.snapshot.snapshot {
+            let attributes = || -> Vec<KeyValue> {
+                self.snapshot
+                    .entity_labels(metric)
+                    .iter()
+                    .map(|(key, value)| attribute(key, value))
+                    .collect()
+            };
+            match metric.output() {
+                Output::Reading => {
+                    // counters emitted as rates keep their fractional part
+                    let value = match self.snapshot.rate(metric) {
+                        Some(rate) => number_data_point::Value::AsDouble(rate),
+                        None => number_data_point::Value::AsInt(*value as i64),
+                    };
+                    readings
+                        .entry(self.snapshot.family(metric))
+                        .or_insert_with(|| (self.snapshot.source(metric), BTreeMap::new()))
+                        .1
+                        .insert(
+                            metric.statistic().name(),
+                            NumberDataPoint {
+                                attributes: attributes(),
+                                start_time_unix_nano: start,
+                                time_unix_nano: now,
+                                value: Some(value),
+                            },
+                        );
+                }
+                Output::Percentile(percentile) => {
+                    // the summary is named like the percentiles of the other
+                    // exporters, eg: `tcp/connect/latency/histogram`
+                    let name = format!(
+                        "{}{}histogram",
+                        self.snapshot.family(metric),
+                        self.snapshot.naming().separator()
+                    );
+                    let point = summaries
+                        .entry(name)
+                        .or_default()
+                        .entry(metric.statistic().name())
+                        .or_insert_with(|| {
+                            let (count, sum) = self
+                                .snapshot
+                                .distribution(metric)
+                                .map(buckets::count_and_sum)
+                                .unwrap_or((0, 0.0));
+                            SummaryDataPoint {
+                                attributes: attributes(),
+                                start_time_unix_nano: start,
+                                time_unix_nano: now,
+                                count,
+                                sum,
+                                quantile_values: Vec::new(),
+                            }
+                        });
+                    point.quantile_values.push(ValueAtQuantile {
+                        quantile: percentile / 100.0,
+                        value: *value as f64,
+                    });
+                }
+            }
+        }
+
+        let mut metrics = Vec::new();
+        for (name, (source, points)) in readings {
+            let data_points = points.into_iter().map(|(_, point)| point).collect();
+            let data = match source {
+                Source::Counter => metric::Data::Sum(Sum {
+                    data_points,
+                    aggregation_temporality: temporality as i32,
+                    is_monotonic: true,
+                }),
+                _ => metric::Data::Gauge(Gauge { data_points }),
+            };
+            metrics.push(Metric {
+                name,
+                data: Some(data),
+            });
+        }
+        for (name, points) in summaries {
+            let data_points = points
+                .into_iter()
+                .map(|(_, mut point)| {
+                    point
+                        .quantile_values
+                        .sort_by(|a, b| a.quantile.partial_cmp(&b.quantile).unwrap());
+                    point
+                })
+                .collect();
+            metrics.push(Metric {
+                name,
+                data: Some(metric::Data::Summary(Summary { data_points })),
+            });
+        }
+
+        // the configured labels are attached to the resource rather than to
+        // each data point, since they apply to every metric
+        let mut attributes = vec![
+            attribute("host.name", &self.hostname),
+            attribute("service.name", crate::config::NAME),
+            attribute("service.version", crate::config::VERSION),
+        ];
+        for (key, value) in self.snapshot.labels() {
+            attributes.push(attribute(key, value));
+        }
+
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource { attributes }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: crate::config::NAME.to_string(),
+                        version: crate::config::VERSION.to_string(),
+                    }),
+                    metrics,
+                }],
+            }],
+        }
+    }
+}
+
+impl PushExporter for OtlpExporter {
+    fn run(&mut self) {
+        let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Send the current readings to the collector
+    fn flush(&mut self) {
+        if let Err(e) = self.export() {
+            // drop the channel so that we reconnect on the next export
+            self.client = None;
+            if self.fault_tolerant {
+                error!("failed to export to otlp collector: {}", e);
+            } else {
+                fatal!("failed to export to otlp collector: {}", e);
+            }
+        }
+    }
+}
+
+async fn connect(endpoint: &str) -> Result<Channel, anyhow::Error> {
+    Ok(Endpoint::from_shared(endpoint.to_string())?
+        .connect()
+        .await?)
+}
+
+async fn send(
+    client: &mut Grpc<Channel>,
+    request: ExportMetricsServiceRequest,
+) -> Result<(), anyhow::Error> {
+    client
+        .ready()
+        .await
+        .map_err(|e| format_err!("collector not ready: {}", e))?;
+    let codec: ProstCodec<ExportMetricsServiceRequest, ExportMetricsServiceResponse> =
+        ProstCodec::default();
+    client
+        .unary(
+            tonic::Request::new(request),
+            PathAndQuery::from_static(EXPORT_PATH),
+            codec,
+        )
+        .await?;
+    Ok(())
+}
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exposition::TestMetrics;
+
+    /// An exporter with the given temporality, and the settings of the
+    /// `[exposition]` section, if any
+    fn exporter(
+        test: &TestMetrics,
+        buckets: Arc<Buckets>,
+        temporality: &str,
+        exposition: &str,
+    ) -> OtlpExporter {
+        let config = Config::parse(&format!(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nenv = \"prod\"\n\
+             [exposition]\n{}\n\
+             [exposition.otlp]\nendpoint = \"http://127.0.0.1:4317\"\ntemporality = \"{}\"\n",
+            exposition, temporality
+        ))
+        .unwrap();
+        OtlpExporter::new(
+            Arc::new(config),
+            test.metrics.clone(),
+            Arc::new(Updates::new(None)),
+            test.entities.clone(),
+            buckets,
+            Arc::new(AtomicBool::new(true)),
+        )
+    }
+
+    /// The data of the metric with the name in the request
+    fn data<'a>(request: &'a ExportMetricsServiceRequest, name: &str) -> &'a metric::Data {
+        request.resource_metrics[0].scope_metrics[0]
+            .metrics
+            .iter()
+            .find(|metric| metric.name == name)
+            .and_then(|metric| metric.data.as_ref())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_request_types() {
+        let test = TestMetrics::new(&[]);
+        test.record(3, &[7]);
+        let mut exporter = exporter(&test, Arc::new(Buckets::new()), "cumulative", "");
+        exporter.snapshot.refresh();
+        let request = exporter.request(exporter.started + 100);
+
+        match data(&request, "system/lockup/soft") {
+            metric::Data::Sum(sum) => {
+                assert!(sum.is_monotonic);
+                assert_eq!(
+                    sum.aggregation_temporality,
+                    AggregationTemporality::Cumulative as i32
+                );
+                assert_eq!(
+                    sum.data_points[0].value,
+                    Some(number_data_point::Value::AsInt(3))
+                );
+                assert_eq!(sum.data_points[0].start_time_unix_nano, exporter.started);
+            }
+            _ => panic!("a counter is not exported as a sum"),
+        }
+        match data(&request, "system/entropy/available") {
+            metric::Data::Gauge(gauge) => assert_eq!(
+                gauge.data_points[0].value,
+                Some(number_data_point::Value::AsInt(7))
+            ),
+            _ => panic!("a gauge is not exported as a gauge"),
+        }
+    }
+
+    #[test]
+    fn test_request_delta() {
+        let test = TestMetrics::new(&[]);
+        test.record(3, &[7]);
+        let mut exporter = exporter(&test, Arc::new(Buckets::new()), "delta", "");
+        exporter.snapshot.refresh();
+        let first = exporter.started + 100;
+        let request = exporter.request(first);
+        match data(&request, "system/lockup/soft") {
+            metric::Data::Sum(sum) => {
+                assert_eq!(
+                    sum.aggregation_temporality,
+                    AggregationTemporality::Delta as i32
+                );
+                assert_eq!(
+                    sum.data_points[0].value,
+                    Some(number_data_point::Value::AsInt(3))
+                );
+            }
+            _ => panic!("a counter is not exported as a sum"),
+        }
+
+        // the next export only covers the increase since the previous one
+        let later = test.start + Duration::from_secs(1);
+        let _ = test.metrics.record_counter(&test.counter, later, 10);
+        exporter.previous = first;
+        exporter.snapshot.refresh();
+        let request = exporter.request(first + 100);
+        match data(&request, "system/lockup/soft") {
+            metric::Data::Sum(sum) => {
+                assert_eq!(
+                    sum.data_points[0].value,
+                    Some(number_data_point::Value::AsInt(7))
+                );
+                assert_eq!(sum.data_points[0].start_time_unix_nano, first);
+                assert_eq!(sum.data_points[0].time_unix_nano, first + 100);
+            }
+            _ => panic!("a counter is not exported as a sum"),
+        }
+    }
+
+    #[test]
+    fn test_request_naming() {
+        let test = TestMetrics::new(&[50.0]);
+        let buckets = Arc::new(Buckets::new());
+        let mut exporter = exporter(
+            &test,
+            buckets.clone(),
+            "delta",
+            "prefix = \"rezolus\"\nseparator = \".\"",
+        );
+        test.record(3, &[7]);
+        buckets.record(test.gauge.name(), 10, 1);
+        exporter.snapshot.refresh();
+        let request = exporter.request(exporter.started + 100);
+        let names: Vec<&str> = request.resource_metrics[0].scope_metrics[0]
+            .metrics
+            .iter()
+            .map(|metric| metric.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "rezolus.system.entropy.available",
+                "rezolus.system.lockup.soft",
+                "rezolus.system.entropy.available.histogram",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_resource() {
+        let test = TestMetrics::new(&[]);
+        let exporter = exporter(&test, Arc::new(Buckets::new()), "cumulative", "");
+        let request = exporter.request(exporter.started);
+        let attributes = &request.resource_metrics[0]
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes;
+        let value = |key: &str| {
+            attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .and_then(|attribute| attribute.value.clone())
+                .and_then(|value| value.value)
+        };
+        assert_eq!(
+            value("service.name"),
+            Some(any_value::Value::StringValue(
+                crate::config::NAME.to_string()
+            ))
+        );
+        assert!(value("host.name").is_some());
+        // the configured labels are resource attributes
+        assert_eq!(
+            value("env"),
+            Some(any_value::Value::StringValue("prod".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_request_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record_cpus(&[10, 20]);
+        let mut exporter = exporter(&test, Arc::new(Buckets::new()), "cumulative", "");
+        exporter.snapshot.refresh();
+        let request = exporter.request(exporter.started + 100);
+
+        // the cpus are data points of one sum, with the cpu as an attribute
+        match data(&request, "cpu/usage/user") {
+            metric::Data::Sum(sum) => {
+                let points: Vec<(Vec<KeyValue>, Option<number_data_point::Value>)> = sum
+                    .data_points
+                    .iter()
+                    .map(|point| (point.attributes.clone(), point.value.clone()))
+                    .collect();
+                assert_eq!(
+                    points,
+                    vec![
+                        (
+                            vec![attribute("cpu", "0")],
+                            Some(number_data_point::Value::AsInt(10))
+                        ),
+                        (
+                            vec![attribute("cpu", "1")],
+                            Some(number_data_point::Value::AsInt(20))
+                        ),
+                    ]
+                );
+            }
+            _ => panic!("a counter is not exported as a sum"),
+        }
+    }
+
+    #[test]
+    fn test_request_summary() {
+        let test = TestMetrics::new(&[99.0, 50.0]);
+        let buckets = Arc::new(Buckets::new());
+        let mut exporter = exporter(&test, buckets.clone(), "delta", "");
+        test.record(0, &[7]);
+        buckets.record(test.gauge.name(), 10, 3);
+        buckets.record(test.gauge.name(), 20, 1);
+        exporter.snapshot.refresh();
+        let request = exporter.request(exporter.started + 100);
+
+        match data(&request, "system/entropy/available/histogram") {
+            metric::Data::Summary(summary) => {
+                let point = &summary.data_points[0];
+                // the quantiles are in ascending order
+                assert_eq!(
+                    point.quantile_values,
+                    vec![
+                        ValueAtQuantile {
+                            quantile: 0.5,
+                            value: 10.0,
+                        },
+                        ValueAtQuantile {
+                            quantile: 0.99,
+                            value: 20.0,
+                        },
+                    ]
+                );
+                assert_eq!(point.count, 4);
+                assert_eq!(point.sum, 50.0);
+                assert_eq!(point.start_time_unix_nano, exporter.started);
+            }
+            _ => panic!("percentiles are not exported as a summary"),
+        }
+    }
+}
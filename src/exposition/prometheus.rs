@@ -0,0 +1,523 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustcommon_logger::*;
+use rustcommon_metrics::*;
+
+use super::auth::Auth;
+use super::stream::Limits;
+use super::tcp::HttpListener;
+#[cfg(feature = "tls")]
+use super::tls::TlsListener;
+use super::uds::UnixSocketListener;
+use super::{MetricsSnapshot, Naming};
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::common::unit::{Unit, Units};
+use crate::config::{Config, ListenAddress};
+use crate::samplers::Reloader;
+
+/// Serves the metrics registry in the Prometheus text exposition format, or in
+/// OpenMetrics if enabled and the scraper asks for it, on a dedicated listener,
+/// optionally
+/// over TLS or on a Unix domain socket. The body is compressed with gzip or
+/// deflate if the scraper accepts it. Connections
+/// are bounded by the configured timeouts and request size, and require the
+/// configured credentials, if any. If allowed, the
+/// config is reloaded on `POST /-/reload`, over the same transport.
+pub struct Prometheus {
+    listener: Listener,
+    openmetrics: bool,
+    reloader: Option<Arc<Reloader>>,
+    snapshot: MetricsSnapshot,
+    units: Arc<Units>,
+    updated: Instant,
+}
+
+enum Listener {
+    Http(HttpListener),
+    #[cfg(feature = "tls")]
+    Https(TlsListener),
+    Unix(UnixSocketListener),
+}
+
+impl Prometheus {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        units: Arc<Units>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
+        reloader: Option<Arc<Reloader>>,
+    ) -> Self {
+        let prometheus = config.exposition().prometheus();
+        let limits = Limits::new(&config);
+        let auth = prometheus.auth().map(Auth::new);
+        let listener = match prometheus.listen().expect("no prometheus listen address") {
+            ListenAddress::Unix(path) => {
+                let listener =
+                    UnixSocketListener::new(&path, prometheus.socket_mode(), limits, auth);
+                if let Err(ref e) = listener {
+                    fatal!(
+                        "Failed to open {} for Prometheus listener: {}",
+                        path.display(),
+                        e
+                    );
+                }
+                Listener::Unix(listener.unwrap())
+            }
+            ListenAddress::Tcp(address) => {
+                // serve https if tls is configured, otherwise plain http
+                #[allow(unused_mut)]
+                let mut listener = None;
+                if let Some(tls) = prometheus.tls() {
+                    #[cfg(feature = "tls")]
+                    {
+                        let https = TlsListener::new(
+                            address,
+                            tls.cert_path(),
+                            tls.key_path(),
+                            tls.client_ca_path(),
+                            limits,
+                            auth.clone(),
+                        );
+                        if let Err(ref e) = https {
+                            fatal!("Failed to initialize TLS for Prometheus listener: {}", e);
+                        }
+                        listener = Some(Listener::Https(https.unwrap()));
+                    }
+                    #[cfg(not(feature = "tls"))]
+                    {
+                        let _ = tls;
+                        fatal!("Prometheus TLS requires building with the `tls` feature");
+                    }
+                }
+                listener.unwrap_or_else(|| {
+                    let http = HttpListener::new(address, limits, auth);
+                    if let Err(ref e) = http {
+                        fatal!("Failed to open {} for Prometheus listener: {}", address, e);
+                    }
+                    Listener::Http(http.unwrap())
+                })
+            }
+        };
+
+        Self {
+            listener,
+            openmetrics: prometheus.openmetrics(),
+            reloader,
+            snapshot: MetricsSnapshot::new(metrics, None, Naming::new(&config))
+                .with_staleness(updates)
+                .with_entities(entities),
+            units,
+            updated: Instant::now(),
+        }
+    }
+
+    pub fn run(&mut self) {
+        let openmetrics = self.openmetrics;
+        match &self.listener {
+            Listener::Http(listener) => {
+                let snapshot = &mut self.snapshot;
+                let units = &self.units;
+                let updated = &mut self.updated;
+                let reloader = self.reloader.as_deref();
+                if let Err(e) = listener.try_serve(|method, url, accept| {
+                    let format = Format::negotiate(accept, openmetrics);
+                    respond(snapshot, units, updated, reloader, method, url, format)
+                }) {
+                    debug!("failed to serve prometheus request: {}", e);
+                }
+            }
+            #[cfg(feature = "tls")]
+            Listener::Https(listener) => {
+                let snapshot = &mut self.snapshot;
+                let units = &self.units;
+                let updated = &mut self.updated;
+                let reloader = self.reloader.as_deref();
+                if let Err(e) = listener.try_serve(|method, url, accept| {
+                    let format = Format::negotiate(accept, openmetrics);
+                    respond(snapshot, units, updated, reloader, method, url, format)
+                }) {
+                    debug!("failed to serve prometheus tls request: {}", e);
+                }
+            }
+            Listener::Unix(listener) => {
+                let snapshot = &mut self.snapshot;
+                let units = &self.units;
+                let updated = &mut self.updated;
+                let reloader = self.reloader.as_deref();
+                if let Err(e) = listener.try_serve(|method, url, accept| {
+                    let format = Format::negotiate(accept, openmetrics);
+                    respond(snapshot, units, updated, reloader, method, url, format)
+                }) {
+                    debug!("failed to serve prometheus unix socket request: {}", e);
+                }
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+fn respond(
+    snapshot: &mut MetricsSnapshot,
+    units: &Units,
+    updated: &mut Instant,
+    reloader: Option<&Reloader>,
+    method: &str,
+    url: &str,
+    format: Format,
+) -> (u16, &'static str, String) {
+    if updated.elapsed() >= Duration::from_millis(500) {
+        snapshot.refresh();
+        *updated = Instant::now();
+    }
+    match method {
+        "GET" => match url {
+            "/metrics" => {
+                debug!("Serving Prometheus exposition as: {:?}", format);
+                (200, format.content_type(), render(snapshot, units, format))
+            }
+            url => {
+                debug!("GET on non-existent url: {}", url);
+                (404, Format::Text.content_type(), String::new())
+            }
+        },
+        "POST" if url == "/-/reload" && reloader.is_some() => {
+            debug!("Serving config reload");
+            let (status, body) = reloader.unwrap().respond();
+            (status, Format::Text.content_type(), body)
+        }
+        method => {
+            debug!("unsupported request method: {}", method);
+            (404, Format::Text.content_type(), String::new())
+        }
+    }
+}
+
+/// The exposition format of the metrics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// The Prometheus text format, version 0.0.4
+    Text,
+    /// OpenMetrics 1.0.0, which adds the unit of each family
+    OpenMetrics,
+}
+
+impl Format {
+    /// The value of the `Content-Type` header for the format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Text => "text/plain; version=0.0.4",
+            Self::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        }
+    }
+
+    /// Choose the format from the value of an `Accept` header. OpenMetrics is
+    /// only served if it is enabled and the client prefers it over the text
+    /// format, and the text format is served otherwise.
+    pub fn negotiate(accept: &str, enabled: bool) -> Self {
+        if !enabled {
+            return Self::Text;
+        }
+        let mut openmetrics = 0.0;
+        let mut text = 0.0;
+        for range in accept.split(',') {
+            let mut fields = range.split(';');
+            let media_type = fields.next().unwrap_or("").trim().to_lowercase();
+            let mut version = None;
+            let mut quality = 1.0;
+            for field in fields {
+                let mut pair = field.splitn(2, '=');
+                match (pair.next().map(str::trim), pair.next().map(str::trim)) {
+                    (Some("version"), Some(v)) => version = Some(v.to_string()),
+                    (Some("q"), Some(q)) => quality = q.parse::<f64>().unwrap_or(0.0),
+                    _ => {}
+                }
+            }
+            match media_type.as_str() {
+                "application/openmetrics-text"
+                    if version.as_deref().map(|v| v == "1.0.0").unwrap_or(true) =>
+                {
+                    openmetrics = f64::max(openmetrics, quality)
+                }
+                "text/plain" => text = f64::max(text, quality),
+                _ => {}
+            }
+        }
+        if openmetrics > 0.0 && openmetrics >= text {
+            Self::OpenMetrics
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Render the snapshot in the given format. Readings become counters or gauges
+/// depending on the statistic source, and percentiles become a gauge family
+/// with a `percentile` label. A labeled statistic is a sample of its family
+/// with the labels of its entity, eg: `cpu_usage_user{cpu="0"}`. In OpenMetrics, families whose statistic has a
+/// known unit get a UNIT line and the unit as a suffix of their name, counter
+/// samples get the `_total` suffix, and the exposition ends with `# EOF`, as
+/// the format requires.
+pub fn render(snapshot: &MetricsSnapshot, units: &Units, format: Format) -> String {
+    // group samples by family so each family gets a single TYPE line
+    let mut families = BTreeMap::<String, (&'static str, Vec<String>)>::new();
+    let mut family_units = BTreeMap::new();
+    for (metric, value) in snapshot.sorted() {
+        let name = sanitize(&snapshot.family(metric));
+        let labels = snapshot.metric_labels(metric);
+        let unit = match format {
+            Format::Text => None,
+            Format::OpenMetrics => units.get(metric.statistic().name()),
+        };
+        match metric.output() {
+            Output::Reading => {
+                let kind = match metric.statistic().source() {
+                    Source::Counter => "counter",
+                    _ => "gauge",
+                };
+                let name = with_unit(name, unit);
+                if let Some(unit) = unit {
+                    family_units.insert(name.clone(), unit);
+                }
+                let sample = match (format, kind) {
+                    (Format::OpenMetrics, "counter") => format!("{}_total", name),
+                    _ => name.clone(),
+                };
+                let family = families.entry(name).or_insert((kind, Vec::new()));
+                family
+                    .1
+                    .push(format!("{}{} {}", sample, label_set(&labels, None), value));
+            }
+            Output::Percentile(percentile) => {
+                let name = with_unit(format!("{}_percentile", name), unit);
+                if let Some(unit) = unit {
+                    family_units.insert(name.clone(), unit);
+                }
+                let family = families
+                    .entry(name.clone())
+                    .or_insert(("gauge", Vec::new()));
+                family.1.push(format!(
+                    "{}{} {}",
+                    name,
+                    label_set(&labels, Some(percentile)),
+                    value
+                ));
+            }
+        }
+    }
+
+    let mut content = String::new();
+    // samples were added in order, so the percentiles of each family are in
+    // ascending order rather than sorted as text
+    for (name, (kind, samples)) in families {
+        content += &format!("# TYPE {} {}\n", name, kind);
+        if let Some(unit) = family_units.get(&name) {
+            content += &format!("# UNIT {} {}\n", name, unit.name());
+        }
+        for sample in samples {
+            content += &sample;
+            content += "\n";
+        }
+    }
+    if format == Format::OpenMetrics {
+        content += "# EOF\n";
+    }
+    content
+}
+
+/// Add the unit as a suffix of the family name, unless the name already ends
+/// with it
+fn with_unit(name: String, unit: Option<Unit>) -> String {
+    match unit {
+        Some(unit) if !name.ends_with(&format!("_{}", unit.name())) => {
+            format!("{}_{}", name, unit.name())
+        }
+        _ => name,
+    }
+}
+
+/// Render the labels of a sample, and its percentile if it is a percentile, as
+/// a label set, eg: `{cpu="0",env="prod",percentile="99"}`. This is empty if there are
+/// no labels to render.
+pub fn label_set(labels: &BTreeMap<String, String>, percentile: Option<f64>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    if let Some(percentile) = percentile {
+        pairs.push(format!("percentile=\"{}\"", percentile));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+/// Escape a label value as required by the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Convert a statistic name into a valid Prometheus metric identifier
+pub fn sanitize(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit()) {
+            result.push(c);
+        } else {
+            result.push('_');
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exposition::TestMetrics;
+
+    #[test]
+    fn test_render_types() {
+        let test = TestMetrics::new(&[99.0]);
+        // a gauge may go down between readings, and the latest is rendered
+        test.record(3, &[3754, 3012]);
+
+        let snapshot = test.snapshot(&Config::default());
+        let rendered = render(&snapshot, &Units::new(), Format::Text);
+        let types: Vec<&str> = rendered
+            .lines()
+            .filter(|line| line.starts_with("# TYPE"))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "# TYPE system_entropy_available gauge",
+                "# TYPE system_entropy_available_percentile gauge",
+                "# TYPE system_lockup_soft counter",
+            ]
+        );
+        assert!(rendered.contains("\nsystem_entropy_available 3012\n"));
+        assert!(rendered.ends_with("\nsystem_lockup_soft 3\n"));
+    }
+
+    #[test]
+    fn test_render_openmetrics() {
+        let test = TestMetrics::new(&[99.0]);
+        test.record(3, &[3754, 3012]);
+        let units = Units::new();
+        units.insert(test.gauge.name(), Unit::Bytes);
+
+        let snapshot = test.snapshot(&Config::default());
+        let rendered = render(&snapshot, &units, Format::OpenMetrics);
+        assert!(rendered.starts_with(
+            "# TYPE system_entropy_available_bytes gauge\n\
+             # UNIT system_entropy_available_bytes bytes\n\
+             system_entropy_available_bytes 3012\n\
+             # TYPE system_entropy_available_percentile_bytes gauge\n\
+             # UNIT system_entropy_available_percentile_bytes bytes\n"
+        ));
+        assert!(rendered
+            .ends_with("# TYPE system_lockup_soft counter\nsystem_lockup_soft_total 3\n# EOF\n"));
+
+        // the text format has no unit lines, and the names are unchanged
+        let rendered = render(&snapshot, &units, Format::Text);
+        assert!(!rendered.contains("# UNIT"));
+        assert!(rendered.contains("\nsystem_entropy_available 3012\n"));
+        assert!(!rendered.contains("# EOF"));
+    }
+
+    #[test]
+    fn test_render_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record_cpus(&[10, 20]);
+        let units = Units::new();
+        units.insert("cpu/usage/user/0", Unit::Nanoseconds);
+        units.insert("cpu/usage/user/1", Unit::Nanoseconds);
+        let config =
+            Config::parse("[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nenv = \"prod\"\n")
+                .unwrap();
+        let snapshot = test.snapshot(&config);
+
+        // each cpu is a sample of the family, with the configured labels
+        let rendered = render(&snapshot, &units, Format::Text);
+        assert!(rendered.starts_with(
+            "# TYPE cpu_usage_user counter\n\
+             cpu_usage_user{cpu=\"0\",env=\"prod\"} 10\n\
+             cpu_usage_user{cpu=\"1\",env=\"prod\"} 20\n"
+        ));
+
+        let rendered = render(&snapshot, &units, Format::OpenMetrics);
+        assert!(rendered.starts_with(
+            "# TYPE cpu_usage_user_nanoseconds counter\n\
+             # UNIT cpu_usage_user_nanoseconds nanoseconds\n\
+             cpu_usage_user_nanoseconds_total{cpu=\"0\",env=\"prod\"} 10\n\
+             cpu_usage_user_nanoseconds_total{cpu=\"1\",env=\"prod\"} 20\n"
+        ));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        // as sent by Prometheus by default
+        let prometheus = "application/openmetrics-text;version=1.0.0,\
+                          application/openmetrics-text;version=0.0.1;q=0.75,\
+                          text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+        assert_eq!(Format::negotiate(prometheus, true), Format::OpenMetrics);
+        // the text format is served unless OpenMetrics is enabled
+        assert_eq!(Format::negotiate(prometheus, false), Format::Text);
+
+        assert_eq!(Format::negotiate("", true), Format::Text);
+        assert_eq!(Format::negotiate("*/*", true), Format::Text);
+        assert_eq!(
+            Format::negotiate("text/plain;version=0.0.4;q=1.0,*/*;q=0.1", true),
+            Format::Text
+        );
+        assert_eq!(
+            Format::negotiate(
+                "application/openmetrics-text; version=1.0.0; q=0.4, text/plain",
+                true
+            ),
+            Format::Text
+        );
+        // only version 1.0.0 of OpenMetrics is served
+        assert_eq!(
+            Format::negotiate("application/openmetrics-text;version=0.0.1", true),
+            Format::Text
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("tcp/connect/latency"), "tcp_connect_latency");
+        assert_eq!(sanitize("memory/slab-total"), "memory_slab_total");
+        assert_eq!(sanitize("cpu/cstate/c1e/time"), "cpu_cstate_c1e_time");
+        assert_eq!(sanitize("0day"), "_day");
+    }
+
+    #[test]
+    fn test_label_set() {
+        let mut labels = BTreeMap::new();
+        assert_eq!(label_set(&labels, None), "");
+        assert_eq!(label_set(&labels, Some(99.9)), "{percentile=\"99.9\"}");
+        labels.insert("role".to_string(), "cache \"a\"".to_string());
+        labels.insert("env".to_string(), "prod".to_string());
+        assert_eq!(
+            label_set(&labels, None),
+            "{env=\"prod\",role=\"cache \\\"a\\\"\"}"
+        );
+        assert_eq!(
+            label_set(&labels, Some(50.0)),
+            "{env=\"prod\",role=\"cache \\\"a\\\"\",percentile=\"50\"}"
+        );
+    }
+}
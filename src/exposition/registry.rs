@@ -0,0 +1,161 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustcommon_metrics::*;
+
+use crate::common::labeled::Entities;
+use crate::exposition::Naming;
+
+/// A statistic as it was read from the registry, with its reading and each of
+/// its percentiles together
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricSample {
+    /// The name rendered with the configured prefix and separator. For a
+    /// labeled statistic this is the statistic it breaks down, eg:
+    /// `cpu/usage/user`, if the registry was given the entities.
+    pub name: String,
+    /// The configured labels, which are shared by every sample, along with
+    /// the labels of the entity for a labeled statistic, eg: `cpu="0"`
+    pub labels: Arc<BTreeMap<String, String>>,
+    /// Whether the statistic is a counter, gauge, or distribution
+    pub source: Source,
+    /// The most recent reading, if the statistic exports one
+    pub value: Option<u64>,
+    /// Pairs of percentile and value, in increasing order of percentile
+    pub percentiles: Vec<(f64, u64)>,
+    /// When the registry was read. Readings are taken by each sampler at its
+    /// own interval, so this is not the time of the reading itself.
+    pub timestamp: SystemTime,
+}
+
+/// A handle on the metrics registry for reading the metrics programmatically,
+/// such as when the samplers are embedded in another process. It is cheap to
+/// clone and may be shared between threads. The samplers write to the registry
+/// concurrently with a snapshot, so while each value is a complete reading,
+/// the values of different statistics may come from different samples.
+#[derive(Clone)]
+pub struct Registry {
+    entities: Option<Arc<Entities>>,
+    labels: Arc<BTreeMap<String, String>>,
+    metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+    naming: Naming,
+}
+
+impl Registry {
+    pub fn new(metrics: Arc<Metrics<AtomicU64, AtomicU32>>, naming: Naming) -> Self {
+        Self {
+            entities: None,
+            labels: Arc::new(naming.labels().clone()),
+            metrics,
+            naming,
+        }
+    }
+
+    /// Read labeled statistics as the statistic they break down with the
+    /// labels of their entity, see `samplers::Common::entities`
+    pub fn with_entities(mut self, entities: Arc<Entities>) -> Self {
+        self.entities = Some(entities);
+        self
+    }
+
+    /// Read every statistic in the registry, ordered by name
+    pub fn snapshot(&self) -> Vec<MetricSample> {
+        let timestamp = SystemTime::now();
+        let mut samples = BTreeMap::<String, MetricSample>::new();
+        for (metric, value) in self.metrics.snapshot() {
+            let statistic = metric.statistic();
+            let sample = samples
+                .entry(statistic.name().to_string())
+                .or_insert_with(|| {
+                    let entity = self
+                        .entities
+                        .as_ref()
+                        .and_then(|entities| entities.get(statistic.name()));
+                    let (name, labels) = match entity {
+                        Some(entity) => {
+                            let mut labels = (*self.labels).clone();
+                            labels.extend(entity.labels().iter().cloned());
+                            (self.naming.render(entity.family()), Arc::new(labels))
+                        }
+                        None => (self.naming.render(statistic.name()), self.labels.clone()),
+                    };
+                    MetricSample {
+                        name,
+                        labels,
+                        source: statistic.source(),
+                        value: None,
+                        percentiles: Vec::new(),
+                        timestamp,
+                    }
+                });
+            match metric.output() {
+                Output::Reading => sample.value = Some(value),
+                Output::Percentile(percentile) => sample
+                    .percentiles
+                    .push((percentile, self.naming.percentile(value))),
+            }
+        }
+        samples
+            .into_iter()
+            .map(|(_, mut sample)| {
+                sample
+                    .percentiles
+                    .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                sample
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::exposition::TestMetrics;
+
+    #[test]
+    fn test_snapshot() {
+        let test = TestMetrics::new(&[]);
+        test.record(3, &[3754]);
+
+        let config = Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nhost = \"a\"\n[exposition]\nseparator = \"_\"\n",
+        )
+        .unwrap();
+        let registry = Registry::new(test.metrics, Naming::new(&config));
+        let samples = registry.snapshot();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "system_entropy_available");
+        assert_eq!(samples[0].source, Source::Gauge);
+        assert_eq!(samples[0].value, Some(3754));
+        assert!(samples[0].percentiles.is_empty());
+        assert_eq!(samples[1].name, "system_lockup_soft");
+        assert_eq!(samples[1].source, Source::Counter);
+        assert_eq!(samples[1].value, Some(3));
+        assert_eq!(samples[1].labels.get("host").map(String::as_str), Some("a"));
+        // the labels are shared rather than copied into each sample
+        assert!(Arc::ptr_eq(&samples[0].labels, &samples[1].labels));
+    }
+
+    #[test]
+    fn test_snapshot_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record_cpus(&[10]);
+
+        let config =
+            Config::parse("[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nhost = \"a\"\n")
+                .unwrap();
+        let registry =
+            Registry::new(test.metrics, Naming::new(&config)).with_entities(test.entities);
+        let samples = registry.snapshot();
+        assert_eq!(samples[0].name, "cpu/usage/user");
+        assert_eq!(samples[0].value, Some(10));
+        assert_eq!(samples[0].labels.get("cpu").map(String::as_str), Some("0"));
+        assert_eq!(samples[0].labels.get("host").map(String::as_str), Some("a"));
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustcommon_atomics::AtomicBool;
+use rustcommon_logger::*;
+use rustcommon_metrics::*;
+
+use super::{sleep_until, MetricsSnapshot, Naming, PushExporter};
+use crate::common::labeled::Entities;
+use crate::common::staleness::Updates;
+use crate::config::Config;
+
+/// Periodically pushes the metrics registry to a StatsD server over UDP.
+/// Counters are sent as the change since the previous push, or as a gauge of
+/// their rate, gauges as their current reading, and percentiles as gauges with
+/// a `.pXX` suffix. The entity of a labeled statistic, such as the cpu, is
+/// sent as a tag, eg: `cpu.usage.user:10|c|#cpu:0`.
+pub struct StatsdExporter {
+    address: SocketAddr,
+    fault_tolerant: bool,
+    interval: Duration,
+    mtu: usize,
+    previous: HashMap<String, u64>,
+    runnable: Arc<AtomicBool>,
+    snapshot: MetricsSnapshot,
+    socket: UdpSocket,
+    tags: BTreeMap<String, String>,
+}
+
+impl StatsdExporter {
+    pub fn new(
+        config: Arc<Config>,
+        metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+        updates: Arc<Updates>,
+        entities: Arc<Entities>,
+        runnable: Arc<AtomicBool>,
+    ) -> Self {
+        let statsd = config.exposition().statsd();
+        let socket = UdpSocket::bind("0.0.0.0:0").expect("failed to bind statsd socket");
+        Self {
+            address: statsd.address().expect("no statsd address configured"),
+            fault_tolerant: config.general().fault_tolerant(),
+            interval: Duration::from_millis(statsd.interval() as u64),
+            mtu: statsd.mtu(),
+            previous: HashMap::new(),
+            runnable,
+            snapshot: MetricsSnapshot::new(metrics, None, Naming::new(&config))
+                .with_emit(statsd.emit())
+                .with_staleness(updates)
+                .with_entities(entities),
+            socket,
+            tags: statsd.tags().clone(),
+        }
+    }
+
+    fn lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (metric, value) in &self.snapshot.snapshot {
+            let name = sanitize(&self.snapshot.family(metric));
+            let tags = tags(&merge(&self.snapshot.metric_labels(metric), &self.tags));
+            match metric.output() {
+                Output::Reading => match self.snapshot.source(metric) {
+                    Source::Counter => {
+                        // statsd counters are deltas, so the first reading of
+                        // each counter only establishes the baseline. Those of
+                        // each entity share a name, so they are kept by
+                        // statistic.
+                        if let Some(previous) = self
+                            .previous
+                            .insert(metric.statistic().name().to_string(), *value)
+                        {
+                            lines.push(format!(
+                                "{}:{}|c{}",
+                                name,
+                                value.wrapping_sub(previous),
+                                tags
+                            ));
+                        }
+                    }
+                    _ => {
+                        let value = self.snapshot.format_value(metric, *value);
+                        lines.push(format!("{}:{}|g{}", name, value, tags));
+                    }
+                },
+                Output::Percentile(percentile) => {
+                    // fractional percentiles such as 99.9 become `p99_9`
+                    let suffix = format!("p{}", percentile).replace('.', "_");
+                    lines.push(format!("{}.{}:{}|g{}", name, suffix, value, tags));
+                }
+            }
+        }
+        lines.sort();
+        lines
+    }
+}
+
+impl PushExporter for StatsdExporter {
+    fn run(&mut self) {
+        let start = Instant::now();
+        self.flush();
+        sleep_until(start + self.interval, &self.runnable);
+    }
+
+    /// Send the current readings to the server
+    fn flush(&mut self) {
+        self.snapshot.refresh();
+        let lines = self.lines();
+        for datagram in batch(&lines, self.mtu) {
+            if let Err(e) = self.socket.send_to(datagram.as_bytes(), self.address) {
+                if self.fault_tolerant {
+                    error!("failed to send to statsd: {}", e);
+                } else {
+                    fatal!("failed to send to statsd: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Format the tags in the DogStatsD style, eg: `|#key:value,key:value`
+fn tags(tags: &BTreeMap<String, String>) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+    let tags: Vec<String> = tags.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+    format!("|#{}", tags.join(","))
+}
+
+/// The labels of a metric with the statsd specific tags applied on top, so
+/// that a tag overrides a label with the same key
+fn merge(
+    labels: &BTreeMap<String, String>,
+    tags: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut merged = labels.clone();
+    merged.extend(tags.iter().map(|(k, v)| (k.clone(), v.clone())));
+    merged
+}
+
+/// Join lines into newline delimited datagrams which are each at most `mtu`
+/// bytes. A line which exceeds the mtu on its own is sent by itself.
+fn batch(lines: &[String], mtu: usize) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > mtu {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+/// Statsd uses `.` to separate the components of a metric name
+fn sanitize(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exposition::TestMetrics;
+
+    #[test]
+    fn test_batch() {
+        let lines: Vec<String> = vec!["a:1|c", "b:2|c", "c:3|c"]
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(batch(&lines, 1432), vec!["a:1|c\nb:2|c\nc:3|c"]);
+        assert_eq!(batch(&lines, 11), vec!["a:1|c\nb:2|c", "c:3|c"]);
+        assert_eq!(batch(&lines, 1), vec!["a:1|c", "b:2|c", "c:3|c"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut map = BTreeMap::new();
+        assert_eq!(tags(&map), "");
+        map.insert("env".to_string(), "prod".to_string());
+        map.insert("dc".to_string(), "west".to_string());
+        assert_eq!(tags(&map), "|#dc:west,env:prod");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut labels = BTreeMap::new();
+        labels.insert("env".to_string(), "prod".to_string());
+        labels.insert("role".to_string(), "cache".to_string());
+        let mut map = BTreeMap::new();
+        map.insert("env".to_string(), "staging".to_string());
+        assert_eq!(tags(&merge(&labels, &map)), "|#env:staging,role:cache");
+    }
+
+    #[test]
+    fn test_lines_entities() {
+        let test = TestMetrics::new(&[]);
+        test.record_cpus(&[10, 20]);
+        let config = Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[labels]\nenv = \"prod\"\n\
+             [exposition.statsd]\naddress = \"127.0.0.1:8125\"\n",
+        )
+        .unwrap();
+        let mut exporter = StatsdExporter::new(
+            Arc::new(config),
+            test.metrics.clone(),
+            Arc::new(Updates::new(None)),
+            test.entities.clone(),
+            Arc::new(AtomicBool::new(true)),
+        );
+        exporter.snapshot.refresh();
+        exporter.lines();
+
+        // each cpu is tagged, and its change is tracked on its own
+        test.record_cpus(&[15, 30]);
+        exporter.snapshot.refresh();
+        let lines: Vec<String> = exporter
+            .lines()
+            .into_iter()
+            .filter(|line| line.starts_with("cpu."))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "cpu.usage.user:5|c|#cpu:0,env:prod",
+                "cpu.usage.user:10|c|#cpu:1,env:prod"
+            ]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+use super::auth::Auth;
+use super::encoding::negotiate;
+use crate::config::Config;
+
+/// Bounds on the connections of a listener, so that a slow or misbehaving
+/// client cannot hold the listener, which serves one connection at a time
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// The connection is dropped if the request is not read within this time
+    pub read_timeout: Duration,
+    /// The connection is dropped if the response is not written within this
+    /// time
+    pub write_timeout: Duration,
+    /// The largest request line and headers, in bytes, which are accepted.
+    /// Larger requests are rejected with a 431.
+    pub max_request_size: usize,
+}
+
+impl Limits {
+    /// The limits configured in the `[exposition.prometheus]` section, which
+    /// apply to every listener
+    pub fn new(config: &Config) -> Self {
+        let prometheus = config.exposition().prometheus();
+        Self {
+            read_timeout: Duration::from_millis(prometheus.read_timeout() as u64),
+            write_timeout: Duration::from_millis(prometheus.write_timeout() as u64),
+            max_request_size: prometheus.max_request_size(),
+        }
+    }
+}
+
+/// A connection whose timeouts can be changed between reads and writes
+pub trait Timeouts {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Timeouts for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl Timeouts for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Bounds the whole of a connection by the timeouts of the limits. The
+/// request must be read within the read timeout of the connection being
+/// accepted, and the response written within the write timeout of it being
+/// started. A timeout on each read or write alone would let a client which
+/// sends a byte at a time hold the listener for as long as it likes.
+pub struct Deadlines<S> {
+    stream: S,
+    read: Instant,
+    write: Option<Instant>,
+    write_timeout: Duration,
+}
+
+impl<S: Timeouts> Deadlines<S> {
+    pub fn new(stream: S, limits: &Limits) -> Self {
+        Self {
+            stream,
+            read: Instant::now() + limits.read_timeout,
+            write: None,
+            write_timeout: limits.write_timeout,
+        }
+    }
+
+    /// The time left until the deadline, which is an error once it has passed
+    fn remaining(deadline: Instant) -> std::io::Result<Duration> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                "connection deadline exceeded",
+            ))
+        } else {
+            Ok(remaining)
+        }
+    }
+
+    fn write_deadline(&mut self) -> std::io::Result<()> {
+        let timeout = self.write_timeout;
+        let deadline = *self.write.get_or_insert_with(|| Instant::now() + timeout);
+        self.stream
+            .set_write_timeout(Some(Self::remaining(deadline)?))
+    }
+}
+
+impl<S: Timeouts + Read> Read for Deadlines<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream
+            .set_read_timeout(Some(Self::remaining(self.read)?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl<S: Timeouts + Write> Write for Deadlines<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_deadline()?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_deadline()?;
+        self.stream.flush()
+    }
+}
+
+/// Read a single HTTP request from the stream and write the status, content
+/// type, and body returned by the handler, which is given the method, the path,
+/// and the `Accept` header of the request. The body is compressed if the
+/// request's `Accept-Encoding` allows.
+/// The connection is not kept alive. If auth is given, requests without the
+/// expected credentials get a 401 and are not handled. This is shared by the
+/// listeners, so that each only handles its transport. The stream must already
+/// be bounded by the timeouts of the limits, see `Deadlines`.
+pub fn serve<S, F>(
+    stream: &mut S,
+    limits: &Limits,
+    auth: Option<&Auth>,
+    handler: F,
+) -> Result<(), std::io::Error>
+where
+    S: Read + Write,
+    F: FnOnce(&str, &str, &str) -> (u16, &'static str, String),
+{
+    // read the request line and keep only the accepted media types and
+    // encodings and the credentials from the headers, stopping at the size
+    // limit
+    let mut request = String::new();
+    let mut accept = String::new();
+    let mut accept_encoding = String::new();
+    let mut authorization = None;
+    let oversized = {
+        let mut reader = BufReader::new(&mut *stream).take(limits.max_request_size as u64);
+        reader.read_line(&mut request)?;
+        let mut header = String::new();
+        let mut complete = false;
+        while reader.read_line(&mut header)? > 0 {
+            if header.trim_end() == "" {
+                complete = true;
+                break;
+            }
+            let mut parts = header.splitn(2, ':');
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                let name = name.trim();
+                if name.eq_ignore_ascii_case("accept") {
+                    accept = value.trim().to_string();
+                } else if name.eq_ignore_ascii_case("accept-encoding") {
+                    accept_encoding = value.trim().to_string();
+                } else if name.eq_ignore_ascii_case("authorization") {
+                    authorization = Some(value.trim().to_string());
+                }
+            }
+            header.clear();
+        }
+        !complete && reader.limit() == 0
+    };
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+
+    let mut headers = String::new();
+    let (status, content_type, body) = if oversized {
+        debug!("request exceeds {} bytes", limits.max_request_size);
+        (431, "text/plain", String::new())
+    } else if let Some(auth) = auth.filter(|a| !a.authorized(authorization.as_deref())) {
+        debug!("rejecting request without valid credentials");
+        headers = format!("WWW-Authenticate: {}\r\n", auth.challenge());
+        (401, "text/plain", String::new())
+    } else {
+        handler(method, path, &accept)
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let mut body = body.into_bytes();
+    if let Some(encoding) = negotiate(&accept_encoding) {
+        if !body.is_empty() {
+            body = encoding.encode(&body)?;
+            headers += &format!("Content-Encoding: {}\r\n", encoding.name());
+        }
+    }
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        headers,
+        body.len(),
+    )?;
+    stream.write_all(&body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// An in-memory stream which reads the request and records the response
+    struct Stream {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Timeouts for Stream {
+        fn set_read_timeout(&self, _: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn set_write_timeout(&self, _: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const LIMITS: Limits = Limits {
+        read_timeout: Duration::from_secs(5),
+        write_timeout: Duration::from_secs(5),
+        max_request_size: 8192,
+    };
+
+    #[test]
+    fn test_serve() {
+        let mut stream = Stream {
+            request: Cursor::new(
+                b"GET /metrics?x=1 HTTP/1.1\r\nHost: localhost\r\nAccept: text/plain\r\n\r\n"
+                    .to_vec(),
+            ),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &LIMITS, None, |method, path, accept| {
+            assert_eq!(method, "GET");
+            assert_eq!(path, "/metrics");
+            assert_eq!(accept, "text/plain");
+            (200, "text/plain; charset=utf-8", "ok".to_string())
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nContent-Type: text/plain; charset=utf-8\r\n"));
+        assert!(response.ends_with("Content-Length: 2\r\nConnection: close\r\n\r\nok"));
+    }
+
+    #[test]
+    fn test_serve_gzip() {
+        let body = "# TYPE cpu_usage_user counter\ncpu_usage_user 42\n".repeat(10);
+        let mut stream = Stream {
+            request: Cursor::new(
+                b"GET /metrics HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n".to_vec(),
+            ),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &LIMITS, None, |_, _, _| {
+            (200, "text/plain", body.clone())
+        })
+        .unwrap();
+        let split = stream
+            .response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .unwrap();
+        let head = String::from_utf8(stream.response[..split].to_vec()).unwrap();
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&stream.response[split + 4..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn test_serve_oversized() {
+        let request = format!(
+            "GET /metrics HTTP/1.1\r\nCookie: {}\r\n\r\n",
+            "x".repeat(LIMITS.max_request_size)
+        );
+        let mut stream = Stream {
+            request: Cursor::new(request.into_bytes()),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &LIMITS, None, |_, _, _| {
+            panic!("an oversized request must not be handled")
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        // a request which fits exactly is served
+        let request = b"GET /metrics HTTP/1.1\r\n\r\n".to_vec();
+        let limits = Limits {
+            max_request_size: request.len(),
+            ..LIMITS
+        };
+        let mut stream = Stream {
+            request: Cursor::new(request),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &limits, None, |_, _, _| {
+            (200, "text/plain", String::new())
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_serve_auth() {
+        let auth = Auth::new(&toml::from_str("token = \"s3cr3t\"\n").unwrap());
+        let mut stream = Stream {
+            request: Cursor::new(b"GET /metrics HTTP/1.1\r\n\r\n".to_vec()),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &LIMITS, Some(&auth), |_, _, _| {
+            panic!("an unauthenticated request must not be handled")
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("WWW-Authenticate: Bearer realm=\"rezolus\"\r\n"));
+
+        let mut stream = Stream {
+            request: Cursor::new(
+                b"GET /metrics HTTP/1.1\r\nauthorization: Bearer s3cr3t\r\n\r\n".to_vec(),
+            ),
+            response: Vec::new(),
+        };
+        serve(&mut stream, &LIMITS, Some(&auth), |_, _, _| {
+            (200, "text/plain", "ok".to_string())
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_deadlines() {
+        let stream = Stream {
+            request: Cursor::new(b"GET /metrics HTTP/1.1\r\n\r\n".to_vec()),
+            response: Vec::new(),
+        };
+        let mut stream = Deadlines::new(stream, &LIMITS);
+        serve(&mut stream, &LIMITS, None, |_, _, _| {
+            (200, "text/plain", String::new())
+        })
+        .unwrap();
+        let response = String::from_utf8(stream.stream.response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // the deadline is for the whole request, however slowly it arrives
+        let stream = Stream {
+            request: Cursor::new(b"GET /metrics HTTP/1.1\r\n\r\n".to_vec()),
+            response: Vec::new(),
+        };
+        let limits = Limits {
+            read_timeout: Duration::from_millis(10),
+            ..LIMITS
+        };
+        let mut stream = Deadlines::new(stream, &limits);
+        std::thread::sleep(Duration::from_millis(20));
+        let error = serve(&mut stream, &limits, None, |_, _, _| {
+            panic!("a request past its deadline must not be handled")
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(stream.stream.response.is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener};
+
+use super::auth::Auth;
+use super::stream::{serve, Deadlines, Limits};
+
+/// A minimal HTTP listener which serves one request per connection. This is
+/// used in place of tiny_http, which cannot bound how long a client may take
+/// or how large its request may be.
+pub struct HttpListener {
+    auth: Option<Auth>,
+    limits: Limits,
+    listener: TcpListener,
+}
+
+impl HttpListener {
+    pub fn new(
+        address: SocketAddr,
+        limits: Limits,
+        auth: Option<Auth>,
+    ) -> Result<Self, anyhow::Error> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            auth,
+            limits,
+            listener,
+        })
+    }
+
+    /// Accept a pending connection, if any, and respond to its request with
+    /// the status, content type, and body returned by the handler
+    pub fn try_serve<F>(&self, handler: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&str, &str, &str) -> (u16, &'static str, String),
+    {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        let mut stream = Deadlines::new(stream, &self.limits);
+        serve(&mut stream, &self.limits, self.auth.as_ref(), handler)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, NoClientAuth, PrivateKey, RootCertStore,
+    ServerConfig, ServerSession, Session, StreamOwned,
+};
+
+use super::auth::Auth;
+use super::stream::{serve, Deadlines, Limits};
+
+/// A minimal HTTPS listener which serves one request per connection. This is
+/// used in place of tiny_http, which only supports TLS through openssl.
+pub struct TlsListener {
+    auth: Option<Auth>,
+    config: Arc<ServerConfig>,
+    limits: Limits,
+    listener: TcpListener,
+}
+
+impl TlsListener {
+    /// Load the certificates and bind the listener. If a client CA is given,
+    /// clients must present a certificate signed by it. Returns an error if any
+    /// of the files are missing or cannot be parsed
+    pub fn new(
+        address: SocketAddr,
+        cert_path: &str,
+        key_path: &str,
+        client_ca_path: Option<&str>,
+        limits: Limits,
+        auth: Option<Auth>,
+    ) -> Result<Self, anyhow::Error> {
+        let config = server_config(cert_path, key_path, client_ca_path)?;
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            auth,
+            config: Arc::new(config),
+            limits,
+            listener,
+        })
+    }
+
+    /// Accept a pending connection, if any, and respond to its request with
+    /// the status, content type, and body returned by the handler
+    pub fn try_serve<F>(&self, handler: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&str, &str, &str) -> (u16, &'static str, String),
+    {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        let stream = Deadlines::new(stream, &self.limits);
+        let mut stream = StreamOwned::new(ServerSession::new(&self.config), stream);
+        serve(&mut stream, &self.limits, self.auth.as_ref(), handler)?;
+        stream.sess.send_close_notify();
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+fn server_config(
+    cert_path: &str,
+    key_path: &str,
+    client_ca_path: Option<&str>,
+) -> Result<ServerConfig, anyhow::Error> {
+    let mut config = if let Some(path) = client_ca_path {
+        let mut roots = RootCertStore::empty();
+        for certificate in load_certs(path)? {
+            roots
+                .add(&certificate)
+                .map_err(|e| format_err!("invalid client ca certificate in {}: {:?}", path, e))?;
+        }
+        ServerConfig::new(AllowAnyAuthenticatedClient::new(roots))
+    } else {
+        ServerConfig::new(NoClientAuth::new())
+    };
+    config.set_single_cert(load_certs(cert_path)?, load_key(key_path)?)?;
+    Ok(config)
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, anyhow::Error> {
+    let file = File::open(path).map_err(|e| format_err!("failed to open {}: {}", path, e))?;
+    let certs = certs(&mut BufReader::new(file))
+        .map_err(|_| format_err!("failed to parse certificates in {}", path))?;
+    if certs.is_empty() {
+        return Err(format_err!("no certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKey, anyhow::Error> {
+    // keys may be in either pkcs8 or rsa format
+    for parse in &[pkcs8_private_keys, rsa_private_keys] {
+        let file = File::open(path).map_err(|e| format_err!("failed to open {}: {}", path, e))?;
+        if let Ok(mut keys) = parse(&mut BufReader::new(file)) {
+            if !keys.is_empty() {
+                return Ok(keys.remove(0));
+            }
+        }
+    }
+    Err(format_err!("no private key found in {}", path))
+}
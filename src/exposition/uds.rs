@@ -0,0 +1,76 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::fs::Permissions;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use super::auth::Auth;
+use super::stream::{serve, Deadlines, Limits};
+
+/// A minimal HTTP listener on a Unix domain socket which serves one request
+/// per connection. Access is controlled by the permissions of the socket file.
+pub struct UnixSocketListener {
+    auth: Option<Auth>,
+    limits: Limits,
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocketListener {
+    /// Bind the socket and set the mode of the socket file. A socket file left
+    /// behind by a previous run is removed, but it is an error if the path is
+    /// some other kind of file or another process is still accepting on it.
+    pub fn new(
+        path: &Path,
+        mode: u32,
+        limits: Limits,
+        auth: Option<Auth>,
+    ) -> Result<Self, anyhow::Error> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(format_err!("{} exists and is not a socket", path.display()));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(format_err!("{} is in use", path.display()));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            auth,
+            limits,
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Accept a pending connection, if any, and respond to its request with
+    /// the status, content type, and body returned by the handler
+    pub fn try_serve<F>(&self, handler: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&str, &str, &str) -> (u16, &'static str, String),
+    {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        let mut stream = Deadlines::new(stream, &self.limits);
+        serve(&mut stream, &self.limits, self.auth.as_ref(), handler)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
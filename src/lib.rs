@@ -0,0 +1,26 @@
+// Copyright 2019-2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The sampling engine behind the `rezolus` binary, which may also be embedded
+//! in another process. Spawn the samplers with a `samplers::Common` built from
+//! a `config::Config`, then read their metrics with `exposition::Registry`
+//! rather than over HTTP.
+
+#[macro_use]
+extern crate rustcommon_logger;
+
+#[macro_use]
+extern crate anyhow;
+
+use rustcommon_metrics::*;
+
+pub mod common;
+pub mod config;
+pub mod exposition;
+pub mod samplers;
+
+use common::*;
+use samplers::*;
+
+pub use exposition::{MetricSample, Registry};
@@ -5,30 +5,31 @@
 #[macro_use]
 extern crate rustcommon_logger;
 
-#[macro_use]
-extern crate anyhow;
-
 use rustcommon_atomics::{Atomic, Ordering};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use rezolus::common::{self, *};
+use rezolus::config::Config;
+use rezolus::exposition;
+use rezolus::samplers::*;
 use rustcommon_atomics::AtomicBool;
 use rustcommon_logger::Logger;
 use rustcommon_metrics::*;
 use tokio::runtime::Builder;
-
-mod common;
-mod config;
-mod exposition;
-mod samplers;
-
-use common::*;
-use config::Config;
-use samplers::*;
+use tokio::signal::unix::{signal, SignalKind};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // get config
     let config = Arc::new(Config::new());
 
+    // print the statistics each sampler offers and exit
+    if let Some(list) = config.list_statistics() {
+        let statistics = list_statistics(list.sampler.as_deref())?;
+        print!("{}", render_statistics(&statistics, list.format));
+        return Ok(());
+    }
+
     // initialize logging
     Logger::new()
         .label(common::NAME)
@@ -45,6 +46,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         env!("VERGEN_TARGET_TRIPLE")
     );
     debug!("host cores: {}", hardware_threads().unwrap_or(1));
+    for warning in config.warnings() {
+        warn!("{}", warning);
+    }
+
+    // pin before any other threads are started, so that they all inherit it
+    let cpus = config.general().cpu_affinity();
+    if !cpus.is_empty() {
+        affinity::set_cpu_affinity(cpus)?;
+        info!("confined to cpus: {:?}", cpus);
+    }
 
     let runnable = Arc::new(AtomicBool::new(true));
     let r = runnable.clone();
@@ -59,11 +70,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // initialize metrics
     debug!("initializing metrics");
     let metrics = Arc::new(Metrics::<AtomicU64, AtomicU32>::new());
+    let units = Arc::new(unit::Units::new());
 
     // initialize async runtime
     debug!("initializing async runtime");
-    let runtime = Builder::new()
+    #[allow(unused_mut)]
+    let mut runtime = Builder::new()
         .threaded_scheduler()
+        .enable_io()
         .enable_time()
         .core_threads(config.general().threads())
         .max_threads(config.general().threads() * 2) // extra threads for block_on
@@ -73,46 +87,193 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // spawn samplers
     debug!("spawning samplers");
-    let common = Common::new(config.clone(), metrics.clone(), runtime.handle().clone());
-    Cpu::spawn(common.clone());
-    Disk::spawn(common.clone());
-    Ext4::spawn(common.clone());
-    Http::spawn(common.clone());
-    Interrupt::spawn(common.clone());
-    Memcache::spawn(common.clone());
-    Memory::spawn(common.clone());
-    PageCache::spawn(common.clone());
-    Network::spawn(common.clone());
-    Rezolus::spawn(common.clone());
-    Scheduler::spawn(common.clone());
-    Softnet::spawn(common.clone());
-    Tcp::spawn(common.clone());
-    Udp::spawn(common.clone());
-    Xfs::spawn(common);
+    let common = Common::new(
+        config.clone(),
+        metrics.clone(),
+        runtime.handle().clone(),
+        runnable.clone(),
+        units.clone(),
+    );
+    let health = common.health().clone();
+    let updates = common.updates().clone();
+    let entities = common.entities().clone();
+    let buckets = common.buckets().clone();
+
+    // take a single sample with each sampler, print the readings, and exit
+    if let Some(format) = config.once() {
+        for sampler in sample_enabled_once(&common) {
+            let _ = runtime.block_on(sampler);
+        }
+        print!(
+            "{}",
+            exposition::render_once(&config, metrics, &units, entities, format)
+        );
+        return Ok(());
+    }
+
+    let mut spawned = HashMap::new();
+    spawn_enabled(&common, &mut spawned);
+    let reloader = Arc::new(Reloader::new(common, spawned));
+
+    // reload config on SIGHUP
+    {
+        let reloader = reloader.clone();
+        runtime.spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    error!("failed to set handler for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                if let Err(e) = reloader.reload() {
+                    error!("failed to reload config: {}", e);
+                }
+            }
+        });
+    }
+
+    // each exporter reads from the same registry on its own thread, so any
+    // number of them may be enabled at once. push exporters run until
+    // shutdown, then make one final export
+    let mut exporters = Vec::new();
 
     #[cfg(feature = "push_kafka")]
     {
         if config.exposition().kafka().enabled() {
-            let mut kafka_producer =
-                exposition::KafkaProducer::new(config.clone(), metrics.clone());
-            let _ = std::thread::Builder::new()
-                .name("kafka".to_string())
-                .spawn(move || loop {
-                    kafka_producer.run();
-                });
+            exporters.extend(exposition::spawn_exporter(
+                "kafka",
+                exposition::KafkaProducer::new(
+                    config.clone(),
+                    metrics.clone(),
+                    updates.clone(),
+                    buckets.clone(),
+                    runnable.clone(),
+                ),
+                runnable.clone(),
+            ));
         }
     }
 
+    #[cfg(feature = "push_otlp")]
+    {
+        if config.exposition().otlp().enabled() {
+            exporters.extend(exposition::spawn_exporter(
+                "otlp",
+                exposition::OtlpExporter::new(
+                    config.clone(),
+                    metrics.clone(),
+                    updates.clone(),
+                    entities.clone(),
+                    buckets.clone(),
+                    runnable.clone(),
+                ),
+                runnable.clone(),
+            ));
+        }
+    }
+
+    // the prometheus listener only reloads the config on request if allowed,
+    // as it is the one which can require tls and credentials
+    let reloader = if config.exposition().allow_reload() {
+        Some(reloader)
+    } else {
+        None
+    };
+
+    if config.exposition().prometheus().enabled() {
+        let mut prometheus = exposition::Prometheus::new(
+            config.clone(),
+            metrics.clone(),
+            units.clone(),
+            updates.clone(),
+            entities.clone(),
+            reloader,
+        );
+        let _ = std::thread::Builder::new()
+            .name("prometheus".to_string())
+            .spawn(move || loop {
+                prometheus.run();
+            });
+    }
+
+    if config.exposition().file().enabled() {
+        exporters.extend(exposition::spawn_exporter(
+            "jsonl",
+            exposition::JsonlExporter::new(
+                config.clone(),
+                metrics.clone(),
+                updates.clone(),
+                entities.clone(),
+                buckets.clone(),
+                runnable.clone(),
+            ),
+            runnable.clone(),
+        ));
+    }
+
+    if config.exposition().graphite().enabled() {
+        exporters.extend(exposition::spawn_exporter(
+            "graphite",
+            exposition::GraphiteExporter::new(
+                config.clone(),
+                metrics.clone(),
+                updates.clone(),
+                entities.clone(),
+                buckets.clone(),
+                runnable.clone(),
+            ),
+            runnable.clone(),
+        ));
+    }
+
+    if config.exposition().statsd().enabled() {
+        exporters.extend(exposition::spawn_exporter(
+            "statsd",
+            exposition::StatsdExporter::new(
+                config.clone(),
+                metrics.clone(),
+                updates.clone(),
+                entities.clone(),
+                runnable.clone(),
+            ),
+            runnable.clone(),
+        ));
+    }
+
+    let readings = if config.exposition().debug().enabled() {
+        let readings = Arc::new(exposition::RecentReadings::new(
+            config.exposition().debug().samples(),
+        ));
+        exporters.extend(exposition::spawn_exporter(
+            "debug",
+            exposition::DebugRecorder::new(
+                config.clone(),
+                metrics.clone(),
+                readings.clone(),
+                runnable.clone(),
+            ),
+            runnable.clone(),
+        ));
+        Some(readings)
+    } else {
+        None
+    };
+
     debug!("beginning stats exposition");
-    let mut http = exposition::Http::new(
-        config.listen().expect("no listen address"),
-        metrics,
-        config.general().reading_suffix(),
-    );
+    let mut http = exposition::Http::new(&config, metrics, readings, health, updates, entities);
 
     while runnable.load(Ordering::Relaxed) {
         http.run();
     }
 
+    // the samplers stop at their next interval, wait for the push exporters to
+    // deliver the final readings before exiting
+    info!("shutting down");
+    for exporter in exporters {
+        let _ = exporter.join();
+    }
+
     Ok(())
 }
@@ -0,0 +1,118 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::*;
+use serde_derive::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CgroupConfig {
+    #[serde(default)]
+    disabled: Vec<CgroupStatistic>,
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default)]
+    max_depth: Option<usize>,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+    #[serde(default = "default_statistics")]
+    statistics: Vec<CgroupStatistic>,
+    #[serde(default = "default_subtree")]
+    subtree: String,
+}
+
+impl Default for CgroupConfig {
+    fn default() -> Self {
+        Self {
+            disabled: Default::default(),
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            interval: Default::default(),
+            max_depth: Default::default(),
+            percentiles: crate::common::default_percentiles(),
+            statistics: default_statistics(),
+            subtree: default_subtree(),
+        }
+    }
+}
+
+fn default_statistics() -> Vec<CgroupStatistic> {
+    CgroupStatistic::iter().collect()
+}
+
+fn default_subtree() -> String {
+    "/".to_string()
+}
+
+impl CgroupConfig {
+    /// How many levels below the subtree are walked, eg: `1` counts only the
+    /// children of the subtree. The whole subtree is walked if this is not set
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// The path within the cgroup hierarchy whose descendants are counted, eg:
+    /// `/kubepods.slice` to count only the cgroups of kubernetes pods
+    pub fn subtree(&self) -> &str {
+        &self.subtree
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.max_depth == Some(0) {
+            errors.push("samplers.cgroup.max_depth must be greater than zero".to_string());
+        }
+        if !self.subtree.starts_with('/') || self.subtree.split('/').any(|c| c == "..") {
+            errors.push(format!(
+                "samplers.cgroup.subtree is {} which is not an absolute path within the cgroup hierarchy",
+                self.subtree
+            ));
+        }
+    }
+}
+
+impl SamplerConfig for CgroupConfig {
+    type Statistic = CgroupStatistic;
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
+    }
+}
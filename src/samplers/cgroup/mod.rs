@@ -0,0 +1,247 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use rustcommon_metrics::*;
+
+use crate::common::cgroup::CGROUP_ROOT;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// Counts the cgroups in a subtree of the cgroup v2 hierarchy, and how many
+/// were created and removed since the previous sample, to show the churn of
+/// containers on the host. A cgroup which is created and removed between two
+/// samples is not seen.
+pub struct Cgroup {
+    common: Common,
+    created: u64,
+    known: Option<HashSet<u64>>,
+    max_depth: Option<usize>,
+    removed: u64,
+    root: PathBuf,
+    statistics: Vec<CgroupStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Cgroup {
+    type Statistic = CgroupStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let config = common.config().samplers().cgroup();
+        let root = Path::new(CGROUP_ROOT).join(config.subtree().trim_start_matches('/'));
+        if config.enabled() && !root.join("cgroup.controllers").exists() {
+            return Err(format_err!(
+                "{} is not a directory of the cgroup v2 hierarchy",
+                root.display()
+            ));
+        }
+        let max_depth = config.max_depth();
+        let statistics = config.statistics();
+        let sampler = Self {
+            common,
+            created: 0,
+            known: None,
+            max_depth,
+            removed: 0,
+            root,
+            statistics,
+        };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().cgroup().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().cgroup())
+            {
+                fatal!("failed to initialize cgroup sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize cgroup sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().cgroup()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_cgroups().await;
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl Cgroup {
+    async fn sample_cgroups(&mut self) -> Result<(), std::io::Error> {
+        let capacity = self.known.as_ref().map(|known| known.len()).unwrap_or(0);
+        let current = walk(&self.root, self.max_depth, capacity).await?;
+        self.record_cgroups(current);
+        Ok(())
+    }
+
+    /// Record the count of the cgroups found by a walk, and the churn since
+    /// the previous one
+    fn record_cgroups(&mut self, current: HashSet<u64>) {
+        let time = self.common.now();
+
+        // the first walk is the baseline, as the cgroups which already exist
+        // were not created in the meantime
+        if let Some(known) = &self.known {
+            let (created, removed) = churn(known, &current);
+            self.created += created;
+            self.removed += removed;
+        }
+
+        for statistic in &self.statistics {
+            let value = match statistic {
+                CgroupStatistic::Total => current.len() as u64,
+                CgroupStatistic::Created => self.created,
+                CgroupStatistic::Removed => self.removed,
+            };
+            match statistic.source() {
+                Source::Counter => {
+                    self.record_counter(statistic, time, value);
+                }
+                Source::Gauge => {
+                    self.record_gauge(statistic, time, value);
+                }
+                _ => {}
+            }
+        }
+
+        self.known = Some(current);
+    }
+}
+
+/// The number of cgroups which were created and removed between two walks
+fn churn(known: &HashSet<u64>, current: &HashSet<u64>) -> (u64, u64) {
+    let created = current.difference(known).count() as u64;
+    let removed = known.difference(current).count() as u64;
+    (created, removed)
+}
+
+/// The inode numbers of the cgroups below the root, down to the maximum depth
+/// if there is one. Each cgroup is a directory, and the cgroup filesystem
+/// allocates inode numbers cyclically, so a cgroup which is removed and created
+/// again with the same name is told apart from the original. Cgroups which are
+/// removed during the walk are skipped.
+async fn walk(
+    root: &Path,
+    max_depth: Option<usize>,
+    capacity: usize,
+) -> Result<HashSet<u64>, std::io::Error> {
+    let mut cgroups = HashSet::with_capacity(capacity);
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((directory, depth)) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&directory).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && depth > 0 => continue,
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            cgroups.insert(entry.ino());
+            if max_depth.map(|max| depth + 1 < max).unwrap_or(true) {
+                pending.push((entry.path(), depth + 1));
+            }
+        }
+    }
+    Ok(cgroups)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+
+    #[tokio::test]
+    async fn test_walk() {
+        let root = std::env::temp_dir().join(format!("rezolus-cgroup-walk-{}", std::process::id()));
+        for path in &["a/b/c", "a/d", "e"] {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+        }
+        // only directories are cgroups
+        std::fs::write(root.join("a/cgroup.procs"), "").unwrap();
+
+        assert_eq!(walk(&root, None, 0).await.unwrap().len(), 5);
+        assert_eq!(walk(&root, Some(1), 0).await.unwrap().len(), 2);
+        assert_eq!(walk(&root, Some(2), 0).await.unwrap().len(), 4);
+        assert!(walk(&root.join("missing"), None, 0).await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_churn() {
+        let set = |inodes: &[u64]| inodes.iter().copied().collect::<HashSet<u64>>();
+        assert_eq!(churn(&set(&[1, 2, 3]), &set(&[1, 2, 3])), (0, 0));
+        assert_eq!(churn(&set(&[1, 2, 3]), &set(&[1, 2, 3, 4, 5])), (2, 0));
+        assert_eq!(churn(&set(&[1, 2, 3]), &set(&[1, 4])), (1, 2));
+        assert_eq!(churn(&set(&[]), &set(&[7])), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_record_cgroups() {
+        let set = |inodes: &[u64]| inodes.iter().copied().collect::<HashSet<u64>>();
+        let common = Common::with_fixtures(Fixtures::default());
+        let mut sampler = Cgroup::new(common).unwrap();
+        sampler.register();
+        let readings = |sampler: &Cgroup| {
+            [
+                CgroupStatistic::Total,
+                CgroupStatistic::Created,
+                CgroupStatistic::Removed,
+            ]
+            .iter()
+            .map(|statistic| sampler.common().reading(statistic.name()))
+            .collect::<Vec<_>>()
+        };
+
+        sampler.record_cgroups(set(&[10, 11, 12]));
+        assert_eq!(readings(&sampler), vec![Some(3), Some(0), Some(0)]);
+
+        // a container starts while a slice is replaced by another
+        sampler.record_cgroups(set(&[10, 11, 13, 14]));
+        assert_eq!(readings(&sampler), vec![Some(4), Some(2), Some(1)]);
+
+        sampler.record_cgroups(set(&[10, 11, 14]));
+        assert_eq!(readings(&sampler), vec![Some(3), Some(2), Some(2)]);
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum CgroupStatistic {
+    /// The number of cgroups within the configured subtree, not counting the
+    /// root of the subtree itself
+    #[strum(serialize = "cgroup/total")]
+    Total,
+    /// Cgroups which appeared between samples
+    #[strum(serialize = "cgroup/created")]
+    Created,
+    /// Cgroups which disappeared between samples
+    #[strum(serialize = "cgroup/removed")]
+    Removed,
+}
+
+impl Statistic<AtomicU64, AtomicU32> for CgroupStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        match self {
+            Self::Total => Source::Gauge,
+            _ => Source::Counter,
+        }
+    }
+}
+
+impl StatisticUnit for CgroupStatistic {
+    fn unit(&self) -> Option<Unit> {
+        Some(Unit::Count)
+    }
+}
+
+impl TryFrom<&str> for CgroupStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        CgroupStatistic::from_str(s)
+    }
+}
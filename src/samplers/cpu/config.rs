@@ -13,10 +13,21 @@ use super::stat::*;
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct CpuConfig {
+    #[serde(default)]
+    cgroup: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<CpuStatistic>,
     #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(default)]
+    per_cpu: AtomicBool,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
     #[serde(default)]
@@ -28,8 +39,12 @@ pub struct CpuConfig {
 impl Default for CpuConfig {
     fn default() -> Self {
         Self {
+            cgroup: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
+            per_cpu: Default::default(),
             percentiles: crate::common::default_percentiles(),
             perf_events: Default::default(),
             statistics: default_statistics(),
@@ -37,6 +52,19 @@ impl Default for CpuConfig {
     }
 }
 
+impl CpuConfig {
+    /// whether to report the usage of the cgroup which rezolus runs in, from
+    /// its `cpu.stat`, rather than of the host
+    pub fn cgroup(&self) -> bool {
+        self.cgroup.load(Ordering::Relaxed)
+    }
+
+    /// whether to also export the usage counters for each cpu
+    pub fn per_cpu(&self) -> bool {
+        self.per_cpu.load(Ordering::Relaxed)
+    }
+}
+
 fn default_statistics() -> Vec<CpuStatistic> {
     CpuStatistic::iter().collect()
 }
@@ -47,6 +75,10 @@ impl SamplerConfig for CpuConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -59,6 +91,13 @@ impl SamplerConfig for CpuConfig {
         self.perf_events.load(Ordering::Relaxed)
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         let mut enabled = Vec::new();
         for statistic in self.statistics.iter() {
@@ -70,6 +109,36 @@ impl SamplerConfig for CpuConfig {
                 enabled.push(statistic.clone());
             }
         }
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
         enabled
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disabled() {
+        let config: CpuConfig = toml::from_str(
+            r#"
+            perf_events = true
+            disabled = ["cpu/usage/guest", "cpu/usage/steal", "cpu/cycles"]
+            "#,
+        )
+        .unwrap();
+        let statistics = config.statistics();
+        assert!(statistics.contains(&CpuStatistic::UsageUser));
+        assert!(statistics.contains(&CpuStatistic::CacheMiss));
+        assert!(!statistics.contains(&CpuStatistic::UsageGuest));
+        assert!(!statistics.contains(&CpuStatistic::UsageSteal));
+        assert!(!statistics.contains(&CpuStatistic::Cycles));
+
+        // the deny-list composes with the perf events gating
+        let config: CpuConfig = toml::from_str(r#"disabled = ["cpu/usage/guest"]"#).unwrap();
+        let statistics = config.statistics();
+        assert!(!statistics.contains(&CpuStatistic::UsageGuest));
+        assert!(!statistics.contains(&CpuStatistic::CacheMiss));
+        assert!(statistics.contains(&CpuStatistic::UsageUser));
+    }
+}
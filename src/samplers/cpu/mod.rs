@@ -4,9 +4,9 @@
 
 use std::collections::{HashMap, HashSet};
 use std::io::SeekFrom;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::time::*;
 
 use async_trait::async_trait;
 #[cfg(feature = "bpf")]
@@ -14,11 +14,14 @@ use bcc::perf_event::{Event, SoftwareEvent};
 #[cfg(feature = "bpf")]
 use bcc::{PerfEvent, PerfEventArray};
 use regex::Regex;
+use rustcommon_metrics::*;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::prelude::*;
 
 use crate::common::bpf::BPF;
+use crate::common::cgroup::own_cgroup;
+use crate::common::labeled::LabeledStatistic;
 use crate::common::*;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
@@ -32,6 +35,7 @@ pub use stat::*;
 
 #[allow(dead_code)]
 pub struct Cpu {
+    cgroup: Option<PathBuf>,
     common: Common,
     cpus: HashSet<String>,
     cstates: HashMap<String, String>,
@@ -55,8 +59,17 @@ impl Sampler for Cpu {
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
         let statistics = common.config().samplers().cpu().statistics();
+        let cgroup = if common.config().samplers().cpu().cgroup() {
+            Some(
+                own_cgroup()
+                    .ok_or_else(|| format_err!("cpu sampler cgroup mode requires cgroup v2"))?,
+            )
+        } else {
+            None
+        };
         #[allow(unused_mut)]
         let mut sampler = Self {
+            cgroup,
             common,
             cpus: HashSet::new(),
             cstates: HashMap::new(),
@@ -77,7 +90,7 @@ impl Sampler for Cpu {
             #[cfg(feature = "bpf")]
             {
                 if let Err(e) = sampler.initialize_bpf_perf() {
-                    if !sampler.common().config().general().fault_tolerant() {
+                    if !sampler.fault_tolerant() {
                         return Err(format_err!("bpf perf init failure: {}", e));
                     }
                 }
@@ -97,13 +110,15 @@ impl Sampler for Cpu {
         if common.config().samplers().cpu().enabled() {
             if let Ok(mut cpu) = Cpu::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = cpu.sample().await;
-                    }
+                    cpu.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().cpu())
+            {
                 fatal!("failed to initialize cpu sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize cpu sampler");
             }
         }
@@ -122,10 +137,6 @@ impl Sampler for Cpu {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -143,7 +154,11 @@ impl Sampler for Cpu {
         let r = self.sample_cpuinfo().await;
         self.map_result(r)?;
 
-        let r = self.sample_cpu_usage().await;
+        let r = if self.cgroup.is_some() {
+            self.sample_cgroup_usage().await
+        } else {
+            self.sample_cpu_usage().await
+        };
         self.map_result(r)?;
 
         let r = self.sample_cstates().await;
@@ -181,7 +196,7 @@ impl Cpu {
                             .attach(&mut bpf)
                             .is_err()
                         {
-                            if !self.common().config().general().fault_tolerant() {
+                            if !self.fault_tolerant() {
                                 fatal!("failed to initialize perf bpf for event: {:?}", event);
                             } else {
                                 error!("failed to initialize perf bpf for event: {:?}", event);
@@ -198,14 +213,14 @@ impl Cpu {
                 .attach(&mut bpf)
                 .is_err()
             {
-                if !self.common().config().general().fault_tolerant() {
+                if !self.fault_tolerant() {
                     fatal!("failed to initialize perf bpf for cpu");
                 } else {
                     error!("failed to initialize perf bpf for cpu");
                 }
             }
             self.perf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
-        } else if !self.common().config().general().fault_tolerant() {
+        } else if !self.fault_tolerant() {
             fatal!("failed to initialize perf bpf");
         } else {
             error!("failed to initialize perf bpf. skipping cpu perf telemetry");
@@ -224,18 +239,35 @@ impl Cpu {
 
             let mut reader = BufReader::new(file);
             let mut result = HashMap::new();
+            let mut cpus = Vec::new();
             let mut buf = String::new();
             while reader.read_line(&mut buf).await? > 0 {
-                result.extend(parse_proc_stat(&buf));
+                match parse_proc_stat_line(&buf) {
+                    Some((None, values)) => result.extend(values),
+                    Some((Some(cpu), values)) => cpus.push((cpu, values)),
+                    None => {}
+                }
                 buf.clear();
             }
 
-            let time = Instant::now();
-            for stat in self.sampler_config().statistics() {
-                if let Some(value) = result.get(&stat) {
-                    let _ = self
-                        .metrics()
-                        .record_counter(&stat, time, value * self.tick_duration);
+            let time = self.common.now();
+            let statistics = self.sampler_config().statistics();
+            for stat in &statistics {
+                if let Some(value) = result.get(stat) {
+                    self.record_counter(stat, time, value * self.tick_duration);
+                }
+            }
+            if self.common.config().samplers().cpu().per_cpu() {
+                for (cpu, values) in cpus {
+                    for (stat, value) in values {
+                        if !statistics.contains(&stat) {
+                            continue;
+                        }
+                        let statistic = LabeledStatistic::new(&stat, "cpu", &cpu.to_string());
+                        self.common.register_once(&statistic);
+                        self.common
+                            .record_counter(&statistic, time, value * self.tick_duration);
+                    }
                 }
             }
         }
@@ -243,6 +275,20 @@ impl Cpu {
         Ok(())
     }
 
+    /// Used in place of `sample_cpu_usage` to report the usage of our cgroup
+    async fn sample_cgroup_usage(&mut self) -> Result<(), std::io::Error> {
+        if let Some(directory) = &self.cgroup {
+            let content = tokio::fs::read_to_string(directory.join("cpu.stat")).await?;
+            let time = self.common.now();
+            for (stat, value) in parse_cgroup_cpu_stat(&content) {
+                if self.statistics.contains(&stat) {
+                    self.record_counter(&stat, time, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn sample_cpuinfo(&mut self) -> Result<(), std::io::Error> {
         if self.proc_cpuinfo.is_none() {
             let file = File::open("/proc/cpuinfo").await?;
@@ -261,11 +307,9 @@ impl Cpu {
                 buf.clear();
             }
 
-            let time = Instant::now();
+            let time = self.common.now();
             for frequency in result {
-                let _ = self
-                    .metrics()
-                    .record_gauge(&CpuStatistic::Frequency, time, frequency);
+                self.record_gauge(&CpuStatistic::Frequency, time, frequency);
             }
         }
 
@@ -276,7 +320,7 @@ impl Cpu {
     fn sample_bpf_perf_counters(&self) -> Result<(), std::io::Error> {
         if let Some(ref bpf) = self.perf {
             let bpf = bpf.lock().unwrap();
-            let time = Instant::now();
+            let time = self.common.now();
             for stat in self.statistics.iter().filter(|s| s.table().is_some()) {
                 if let Ok(table) = &(*bpf).inner.table(stat.table().unwrap()) {
                     let map = crate::common::bpf::perf_table_to_map(table);
@@ -284,7 +328,7 @@ impl Cpu {
                     for (_cpu, count) in map.iter() {
                         total += count;
                     }
-                    let _ = self.metrics().record_counter(stat, time, total);
+                    self.record_counter(stat, time, total);
                 }
             }
         }
@@ -375,10 +419,10 @@ impl Cpu {
             }
         }
 
-        let time = Instant::now();
+        let time = self.common.now();
         for stat in &self.statistics {
             if let Some(value) = result.get(stat) {
-                let _ = self.metrics().record_counter(stat, time, *value);
+                self.record_counter(stat, time, *value);
             }
         }
 
@@ -386,15 +430,17 @@ impl Cpu {
     }
 }
 
-fn parse_proc_stat(line: &str) -> HashMap<CpuStatistic, u64> {
+/// Parses a line of `/proc/stat` which is either the aggregate `cpu` line, in
+/// which case no cpu index is returned, or one of the per-cpu `cpuN` lines
+fn parse_proc_stat_line(line: &str) -> Option<(Option<usize>, HashMap<CpuStatistic, u64>)> {
+    let mut parts = line.split_whitespace();
+    let cpu = match parts.next()?.strip_prefix("cpu")? {
+        "" => None,
+        index => Some(index.parse().ok()?),
+    };
     let mut result = HashMap::new();
-    for (id, part) in line.split_whitespace().enumerate() {
+    for (id, part) in parts.enumerate().map(|(id, part)| (id + 1, part)) {
         match id {
-            0 => {
-                if part != "cpu" {
-                    return result;
-                }
-            }
             1 => {
                 result.insert(CpuStatistic::UsageUser, part.parse().unwrap_or(0));
             }
@@ -425,6 +471,26 @@ fn parse_proc_stat(line: &str) -> HashMap<CpuStatistic, u64> {
             _ => {}
         }
     }
+    Some((cpu, result))
+}
+
+/// Parse the `cpu.stat` of a cgroup, eg: `user_usec 1234`, returning the
+/// usage in nanoseconds
+fn parse_cgroup_cpu_stat(content: &str) -> HashMap<CpuStatistic, u64> {
+    let mut result = HashMap::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(name), Some(Ok(value))) =
+            (parts.next(), parts.next().map(|v| v.parse::<u64>()))
+        {
+            let stat = match name {
+                "user_usec" => CpuStatistic::UsageUser,
+                "system_usec" => CpuStatistic::UsageSystem,
+                _ => continue,
+            };
+            result.insert(stat, value * MICROSECOND);
+        }
+    }
     result
 }
 
@@ -443,13 +509,35 @@ mod test {
 
     #[test]
     fn test_parse_proc_stat() {
-        let result = parse_proc_stat("cpu  131586 0 53564 8246483 35015 350665 4288 5632 0 0");
+        let (_, result) =
+            parse_proc_stat_line("cpu  131586 0 53564 8246483 35015 350665 4288 5632 0 0").unwrap();
         assert_eq!(result.len(), 9);
         assert_eq!(result.get(&CpuStatistic::UsageUser), Some(&131586));
         assert_eq!(result.get(&CpuStatistic::UsageNice), Some(&0));
         assert_eq!(result.get(&CpuStatistic::UsageSystem), Some(&53564));
     }
 
+    #[test]
+    fn test_parse_proc_stat_line() {
+        let (cpu, result) =
+            parse_proc_stat_line("cpu3 13158 0 5356 824648 3501 35066 428 563 0 0").unwrap();
+        assert_eq!(cpu, Some(3));
+        assert_eq!(result.get(&CpuStatistic::UsageUser), Some(&13158));
+        let (cpu, _) = parse_proc_stat_line("cpu  131586 0 53564 8246483").unwrap();
+        assert_eq!(cpu, None);
+        assert!(parse_proc_stat_line("intr 1234 0 0").is_none());
+        assert!(parse_proc_stat_line("cpufoo 1 2 3").is_none());
+    }
+
+    #[test]
+    fn test_parse_cgroup_cpu_stat() {
+        let content = "usage_usec 300\nuser_usec 200\nsystem_usec 100\nnr_periods 0\n";
+        let result = parse_cgroup_cpu_stat(content);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result.get(&CpuStatistic::UsageUser), Some(&200_000));
+        assert_eq!(result.get(&CpuStatistic::UsageSystem), Some(&100_000));
+    }
+
     #[test]
     fn test_parse_frequency() {
         let result = parse_frequency("cpu MHz         : 1979.685");
@@ -8,6 +8,7 @@ use core::str::FromStr;
 #[cfg(feature = "bpf")]
 use bcc::perf_event::*;
 
+use crate::common::unit::{StatisticUnit, Unit};
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -109,6 +110,32 @@ impl Statistic<AtomicU64, AtomicU32> for CpuStatistic {
     }
 }
 
+impl StatisticUnit for CpuStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::UsageUser
+            | Self::UsageNice
+            | Self::UsageSystem
+            | Self::UsageIdle
+            | Self::UsageIrq
+            | Self::UsageSoftirq
+            | Self::UsageSteal
+            | Self::UsageGuest
+            | Self::UsageGuestNice
+            | Self::CstateC0Time
+            | Self::CstateC1Time
+            | Self::CstateC1ETime
+            | Self::CstateC2Time
+            | Self::CstateC3Time
+            | Self::CstateC6Time
+            | Self::CstateC7Time
+            | Self::CstateC8Time => Some(Unit::Nanoseconds),
+            Self::Frequency => Some(Unit::Hertz),
+            _ => Some(Unit::Count),
+        }
+    }
+}
+
 impl CpuStatistic {
     #[cfg(feature = "bpf")]
     pub fn event(self) -> Option<Event> {
@@ -206,3 +233,18 @@ impl FromStr for CState {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        // usage is reported as time spent, not as a fraction of capacity
+        assert_eq!(CpuStatistic::UsageUser.unit(), Some(Unit::Nanoseconds));
+        assert_eq!(CpuStatistic::UsageUser.source(), Source::Counter);
+        assert_eq!(CpuStatistic::CstateC6Time.unit(), Some(Unit::Nanoseconds));
+        assert_eq!(CpuStatistic::Frequency.unit(), Some(Unit::Hertz));
+        assert_eq!(CpuStatistic::Instructions.unit(), Some(Unit::Count));
+    }
+}
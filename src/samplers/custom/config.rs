@@ -0,0 +1,244 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::BTreeMap;
+
+use rustcommon_atomics::*;
+use rustcommon_metrics::Source;
+use serde_derive::Deserialize;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+/// How the content of a custom file is parsed
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Pairs of header and values rows, such as `/proc/net/snmp`, which are
+    /// mapped by `section/key`, eg: `Tcp:/ActiveOpens`
+    #[serde(rename = "keyvalue")]
+    KeyValue,
+    /// A single integer, such as `/proc/sys/fs/nr_open`, which is mapped by
+    /// `value`
+    SingleValue,
+}
+
+/// Whether the values of a custom file are counters or gauges
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl Default for Kind {
+    fn default() -> Self {
+        Self::Gauge
+    }
+}
+
+/// A file to read, and the metric each of its values is exported as
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomFile {
+    path: String,
+    format: Format,
+    #[serde(default)]
+    kind: Kind,
+    mappings: BTreeMap<String, String>,
+}
+
+impl CustomFile {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Pairs of the key of a value within the file and the name of the metric
+    /// it is exported as
+    pub fn mappings(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.mappings
+            .iter()
+            .map(|(key, name)| (key.as_str(), name.as_str()))
+    }
+
+    pub fn source(&self) -> Source {
+        match self.kind {
+            Kind::Counter => Source::Counter,
+            Kind::Gauge => Source::Gauge,
+        }
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.mappings.is_empty() {
+            errors.push(format!(
+                "samplers.custom.files for {} must have at least one mapping",
+                self.path
+            ));
+        }
+        for (key, name) in &self.mappings {
+            let valid = match self.format {
+                Format::KeyValue => key.splitn(2, '/').all(|part| !part.is_empty()),
+                Format::SingleValue => key == "value",
+            };
+            if !valid {
+                let expected = match self.format {
+                    Format::KeyValue => "section/key",
+                    Format::SingleValue => "value",
+                };
+                errors.push(format!(
+                    "samplers.custom.files for {} maps {} which is not of the form {}",
+                    self.path, key, expected
+                ));
+            }
+            if name.is_empty() {
+                errors.push(format!(
+                    "samplers.custom.files for {} maps {} to an empty metric name",
+                    self.path, key
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomConfig {
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(default)]
+    files: Vec<CustomFile>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+}
+
+impl Default for CustomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            files: Default::default(),
+            interval: Default::default(),
+            percentiles: crate::common::default_percentiles(),
+        }
+    }
+}
+
+impl CustomConfig {
+    /// The files to read
+    pub fn files(&self) -> &[CustomFile] {
+        &self.files
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        for file in &self.files {
+            file.validate(errors);
+        }
+    }
+}
+
+impl SamplerConfig for CustomConfig {
+    type Statistic = CustomStatistic;
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        self.files
+            .iter()
+            .flat_map(|file| {
+                file.mappings()
+                    .map(move |(_, name)| CustomStatistic::new(name.to_string(), file.source()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustcommon_metrics::Statistic;
+
+    fn errors(content: &str) -> Vec<String> {
+        let config: CustomConfig = toml::from_str(content).unwrap();
+        let mut errors = Vec::new();
+        config.validate(&mut errors);
+        errors
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = r#"
+            [[files]]
+            path = "/proc/net/snmp"
+            format = "keyvalue"
+            kind = "counter"
+            mappings = { "Tcp:/ActiveOpens" = "snmp/tcp/active_opens" }
+
+            [[files]]
+            path = "/proc/sys/fs/nr_open"
+            format = "single_value"
+            mappings = { value = "fs/nr_open" }
+            "#;
+        assert!(errors(valid).is_empty());
+        let config: CustomConfig = toml::from_str(valid).unwrap();
+        let statistics = config.statistics();
+        assert_eq!(statistics.len(), 2);
+        assert_eq!(statistics[0].source(), Source::Counter);
+        assert_eq!(statistics[1].source(), Source::Gauge);
+
+        let errors = errors(
+            r#"
+            [[files]]
+            path = "/proc/net/snmp"
+            format = "keyvalue"
+            mappings = { ActiveOpens = "snmp/tcp/active_opens" }
+
+            [[files]]
+            path = "/proc/sys/fs/nr_open"
+            format = "single_value"
+            mappings = { nr_open = "" }
+
+            [[files]]
+            path = "/proc/sys/fs/file-max"
+            format = "single_value"
+            mappings = {}
+            "#,
+        );
+        assert_eq!(errors.len(), 4);
+    }
+}
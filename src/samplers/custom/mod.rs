@@ -0,0 +1,207 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use async_trait::async_trait;
+use rustcommon_metrics::Source;
+
+use crate::common::nested_map_from_str;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// Reads user specified files from `/proc` or `/sys` which there is no
+/// built-in sampler for, exporting their values under the mapped names
+pub struct Custom {
+    common: Common,
+    files: Vec<CustomFile>,
+}
+
+#[async_trait]
+impl Sampler for Custom {
+    type Statistic = CustomStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let fault_tolerant = common
+            .config()
+            .sampler_fault_tolerant(common.config().samplers().custom());
+        let files = common.config().samplers().custom().files().to_vec();
+        let sampler = Self { common, files };
+        if sampler.sampler_config().enabled() {
+            for file in &sampler.files {
+                if !Path::new(file.path()).exists() {
+                    if !fault_tolerant {
+                        return Err(format_err!("custom file does not exist: {}", file.path()));
+                    }
+                    warn!("custom file does not exist: {}", file.path());
+                }
+            }
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().custom().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().custom())
+            {
+                fatal!("failed to initialize custom sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize custom sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().custom()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        // each file is sampled independently, so that one which is missing
+        // or malformed doesn't prevent reading the others
+        for file in &self.files {
+            let r = self.sample_file(file).await;
+            self.map_result(r)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Custom {
+    async fn sample_file(&self, file: &CustomFile) -> Result<(), std::io::Error> {
+        let content = self.common.proc().read_to_string(file.path()).await?;
+        let time = self.common.now();
+        match file.format() {
+            Format::SingleValue => {
+                let value = content.trim().parse::<u64>().map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} does not contain a single value", file.path()),
+                    )
+                })?;
+                for (_, name) in file.mappings() {
+                    self.record(file, name, time, value);
+                }
+            }
+            Format::KeyValue => {
+                let parsed = nested_map_from_str(&content);
+                for (key, name) in file.mappings() {
+                    let mut parts = key.splitn(2, '/');
+                    if let (Some(section), Some(key)) = (parts.next(), parts.next()) {
+                        if let Some(value) = parsed.get(section).and_then(|s| s.get(key)) {
+                            self.record(file, name, time, *value);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, file: &CustomFile, name: &str, time: std::time::Instant, value: u64) {
+        let statistic = CustomStatistic::new(name.to_string(), file.source());
+        if file.source() == Source::Counter {
+            self.record_counter(&statistic, time, value);
+        } else {
+            self.record_gauge(&statistic, time, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::common::proc::Fixtures;
+
+    #[tokio::test]
+    async fn test_sample_file() {
+        let config = crate::config::Config::parse(
+            r#"
+            [general]
+            listen = "0.0.0.0:4242"
+
+            [[samplers.custom.files]]
+            path = "/proc/net/snmp"
+            format = "keyvalue"
+            kind = "counter"
+            mappings = { "Tcp:/ActiveOpens" = "snmp/tcp/active_opens", "Udp:/InDatagrams" = "snmp/udp/in" }
+
+            [[samplers.custom.files]]
+            path = "/proc/sys/fs/nr_open"
+            format = "single_value"
+            mappings = { value = "fs/nr_open" }
+            "#,
+        )
+        .unwrap();
+        let common = Common::with_fixtures(
+            Fixtures::default()
+                .with(
+                    "/proc/net/snmp",
+                    "Tcp: RtoAlgorithm ActiveOpens PassiveOpens\nTcp: 1 1024 2048\n",
+                )
+                .with("/proc/sys/fs/nr_open", "1048576\n"),
+        )
+        .with_config(Arc::new(config));
+        let sampler = Custom::new(common).unwrap();
+        sampler.register();
+        for file in &sampler.files {
+            sampler.sample_file(file).await.unwrap();
+        }
+
+        assert_eq!(
+            sampler.common().reading("snmp/tcp/active_opens"),
+            Some(1024)
+        );
+        assert_eq!(sampler.common().reading("fs/nr_open"), Some(1048576));
+        // the section is missing from the file
+        assert_eq!(sampler.common().reading("snmp/udp/in"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sample_file_invalid() {
+        let common = Common::with_fixtures(Fixtures::default().with("/proc/sys/fs/nr_open", "n/a"));
+        let sampler = Custom::new(common).unwrap();
+        let file: CustomFile = toml::from_str(
+            "path = \"/proc/sys/fs/nr_open\"\nformat = \"single_value\"\nmappings = { value = \"fs/nr_open\" }\n",
+        )
+        .unwrap();
+        assert!(sampler.sample_file(&file).await.is_err());
+        let file: CustomFile = toml::from_str(
+            "path = \"/proc/sys/fs/file-max\"\nformat = \"single_value\"\nmappings = { value = \"fs/file_max\" }\n",
+        )
+        .unwrap();
+        assert!(sampler.sample_file(&file).await.is_err());
+    }
+}
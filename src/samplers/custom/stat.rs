@@ -0,0 +1,32 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::common::unit::StatisticUnit;
+use crate::Statistic;
+use rustcommon_metrics::*;
+
+/// A statistic named by one of the mappings of a custom file
+#[derive(Clone, Debug)]
+pub struct CustomStatistic {
+    name: String,
+    source: Source,
+}
+
+impl CustomStatistic {
+    pub fn new(name: String, source: Source) -> Self {
+        Self { name, source }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for CustomStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        self.source
+    }
+}
+
+impl StatisticUnit for CustomStatistic {}
@@ -151,3 +151,121 @@ int do_count(struct pt_regs *ctx, struct request *req)
 
     return 0;
 }
+
+#if defined(DISK_IO_TRACEPOINTS) || defined(DISK_RQ_TRACEPOINTS)
+// newer kernels inline the block accounting functions, so requests are instead
+// tracked by device and sector using the block tracepoints
+struct rq_key_t {
+    u64 dev;
+    u64 sector;
+};
+
+BPF_HASH(queue_start_tp, struct rq_key_t);
+BPF_HASH(request_start_tp, struct rq_key_t);
+
+#define IS_WRITE(args) (args->rwbs[0] == 'W' || args->rwbs[1] == 'W')
+
+static __always_inline int trace_enqueue(u64 dev, u64 sector)
+{
+    struct rq_key_t key = {.dev = dev, .sector = sector};
+    u64 ts = bpf_ktime_get_ns();
+    queue_start_tp.update(&key, &ts);
+    return 0;
+}
+
+static __always_inline int trace_issue(u64 dev, u64 sector, u64 write)
+{
+    u64 now = bpf_ktime_get_ns();
+    struct rq_key_t key = {.dev = dev, .sector = sector};
+
+    u64 *enqueued;
+    enqueued = queue_start_tp.lookup(&key);
+    if (enqueued != 0) {
+        unsigned int index = value_to_index2((now - *enqueued) / 1000);
+        if (write) {
+            queue_latency_write.increment(index);
+        } else {
+            queue_latency_read.increment(index);
+        }
+    }
+    request_start_tp.update(&key, &now);
+    return 0;
+}
+
+static __always_inline int trace_done(u64 dev, u64 sector, u64 bytes, u64 write)
+{
+    u64 now = bpf_ktime_get_ns();
+    struct rq_key_t key = {.dev = dev, .sector = sector};
+
+    // Size
+    if (bytes > 0) {
+        unsigned int index = value_to_index2(bytes / 1024);
+        if (write) {
+            io_size_write.increment(index);
+        } else {
+            io_size_read.increment(index);
+        }
+    }
+
+    // Latency
+    u64 *enqueued, *requested;
+
+    // total latency including queued time
+    enqueued = queue_start_tp.lookup(&key);
+    if (enqueued != 0) {
+        unsigned int index = value_to_index2((now - *enqueued) / 1000);
+        if (write) {
+            latency_write.increment(index);
+        } else {
+            latency_read.increment(index);
+        }
+        queue_start_tp.delete(&key);
+    }
+
+    // request latency not including queued time
+    requested = request_start_tp.lookup(&key);
+    if (requested != 0) {
+        unsigned int index = value_to_index2((now - *requested) / 1000);
+        if (write) {
+            device_latency_write.increment(index);
+        } else {
+            device_latency_read.increment(index);
+        }
+        request_start_tp.delete(&key);
+    }
+
+    return 0;
+}
+
+TRACEPOINT_PROBE(block, block_rq_issue)
+{
+    return trace_issue(args->dev, args->sector, IS_WRITE(args));
+}
+#endif
+
+#ifdef DISK_IO_TRACEPOINTS
+// from 6.5, io is accounted from when it starts until it is done
+TRACEPOINT_PROBE(block, block_io_start)
+{
+    return trace_enqueue(args->dev, args->sector);
+}
+
+TRACEPOINT_PROBE(block, block_io_done)
+{
+    return trace_done(args->dev, args->sector, args->bytes, IS_WRITE(args));
+}
+#endif
+
+#ifdef DISK_RQ_TRACEPOINTS
+// before 6.5, requests are queued when inserted into the scheduler. requests
+// which bypass it are only seen from when they are issued
+TRACEPOINT_PROBE(block, block_rq_insert)
+{
+    return trace_enqueue(args->dev, args->sector);
+}
+
+TRACEPOINT_PROBE(block, block_rq_complete)
+{
+    return trace_done(args->dev, args->sector, (u64)args->nr_sector << 9, IS_WRITE(args));
+}
+#endif
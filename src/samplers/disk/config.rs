@@ -6,7 +6,7 @@ use rustcommon_atomics::*;
 use serde_derive::Deserialize;
 use strum::IntoEnumIterator;
 
-use crate::config::SamplerConfig;
+use crate::config::{SamplerConfig, DEFAULT_MAX_VALUE, DEFAULT_SIGFIGS};
 
 use super::stat::*;
 
@@ -16,11 +16,31 @@ pub struct DiskConfig {
     #[serde(default)]
     bpf: AtomicBool,
     #[serde(default)]
+    cumulative: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<DiskStatistic>,
+    #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(default = "default_exclude_devices")]
+    exclude_devices: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_nanoseconds"
+    )]
+    max_value: Option<u64>,
+    #[serde(default)]
+    per_device: AtomicBool,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
+    #[serde(default)]
+    sigfigs: Option<u8>,
     #[serde(default = "default_statistics")]
     statistics: Vec<DiskStatistic>,
 }
@@ -29,14 +49,42 @@ impl Default for DiskConfig {
     fn default() -> Self {
         Self {
             bpf: Default::default(),
+            cumulative: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            exclude_devices: default_exclude_devices(),
             interval: Default::default(),
+            max_value: Default::default(),
+            per_device: Default::default(),
             percentiles: crate::common::default_percentiles(),
+            sigfigs: Default::default(),
             statistics: default_statistics(),
         }
     }
 }
 
+impl DiskConfig {
+    /// whether to also export the diskstats counters for each device
+    pub fn per_device(&self) -> bool {
+        self.per_device.load(Ordering::Relaxed)
+    }
+
+    /// block devices whose name starts with any of these prefixes are skipped
+    pub fn exclude_devices(&self) -> &[String] {
+        &self.exclude_devices
+    }
+}
+
+// virtual devices, and stacked devices which would count the io of the
+// underlying devices twice
+pub(super) fn default_exclude_devices() -> Vec<String> {
+    ["dm-", "loop", "md", "ram", "zram"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+}
+
 fn default_statistics() -> Vec<DiskStatistic> {
     DiskStatistic::iter().collect()
 }
@@ -48,18 +96,41 @@ impl SamplerConfig for DiskConfig {
         self.bpf.load(Ordering::Relaxed)
     }
 
+    fn cumulative(&self) -> bool {
+        self.cumulative.load(Ordering::Relaxed)
+    }
+
     fn enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
 
+    fn max_value(&self) -> u64 {
+        self.max_value.unwrap_or(DEFAULT_MAX_VALUE)
+    }
+
     fn percentiles(&self) -> &[f64] {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn sigfigs(&self) -> u8 {
+        self.sigfigs.unwrap_or(DEFAULT_SIGFIGS)
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         let mut enabled = Vec::new();
         for statistic in self.statistics.iter() {
@@ -71,6 +142,7 @@ impl SamplerConfig for DiskConfig {
                 enabled.push(statistic.clone());
             }
         }
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
         enabled
     }
 }
@@ -2,17 +2,20 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::SeekFrom;
 use std::sync::{Arc, Mutex};
 use std::time::*;
 
 use async_trait::async_trait;
-use regex::Regex;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
 use crate::common::bpf::*;
+#[cfg(feature = "bpf")]
+use crate::common::kernel::BLOCK_IO_TRACEPOINTS;
+use crate::common::labeled::LabeledStatistic;
+use crate::common::MILLISECOND;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
 use crate::Sampler;
@@ -29,7 +32,7 @@ pub struct Disk {
     bpf_last: Arc<Mutex<Instant>>,
     common: Common,
     proc_diskstats: Option<File>,
-    disk_regex: Option<Regex>,
+    devices: HashSet<String>,
     statistics: Vec<DiskStatistic>,
 }
 
@@ -38,24 +41,24 @@ impl Sampler for Disk {
     type Statistic = DiskStatistic;
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().disk().statistics();
+        let mut devices = HashSet::new();
+        for entry in std::fs::read_dir("/sys/block")? {
+            devices.insert(entry?.file_name().to_string_lossy().to_string());
+        }
+        let devices = select_devices(devices, common.config().samplers().disk().exclude_devices());
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
             common,
             proc_diskstats: None,
-            disk_regex: None,
+            devices,
             statistics,
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
@@ -68,13 +71,15 @@ impl Sampler for Disk {
         if common.config().samplers().disk().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().disk())
+            {
                 fatal!("failed to initialize disk sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize disk sampler");
             }
         }
@@ -93,10 +98,6 @@ impl Sampler for Disk {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -106,18 +107,31 @@ impl Sampler for Disk {
         let r = self.sample_diskstats().await;
         self.map_result(r)?;
         #[cfg(feature = "bpf")]
+        self.retry_bpf();
+        #[cfg(feature = "bpf")]
         self.map_result(self.sample_bpf())?;
 
         Ok(())
     }
-}
 
-impl Disk {
+    fn drains_bpf(&self) -> bool {
+        cfg!(feature = "bpf")
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+        #[cfg(feature = "bpf")]
+        self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
     // checks that bpf is enabled in config and one or more bpf stats enabled
     #[cfg(feature = "bpf")]
     fn bpf_enabled(&self) -> bool {
         if self.sampler_config().bpf() {
-            for statistic in self.sampler_config().statistics() {
+            for statistic in &self.statistics {
                 if statistic.bpf_table().is_some() {
                     return true;
                 }
@@ -126,31 +140,77 @@ impl Disk {
         false
     }
 
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
         #[cfg(feature = "bpf")]
         {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
             if self.enabled() && self.bpf_enabled() {
                 debug!("initializing bpf");
+                // newer kernels no longer have blk_account_io_start available
+                // for kprobes, so fall back to the block tracepoints there.
+                // block_io_start and block_io_done were only added in 6.5, so
+                // older kernels use the request tracepoints instead
+                let kprobes = symbol_lookup("blk_account_io_start").is_some();
+                let (define, tracepoints) = if self.common.kernel_supports(BLOCK_IO_TRACEPOINTS) {
+                    (
+                        "DISK_IO_TRACEPOINTS",
+                        ["block_io_start", "block_rq_issue", "block_io_done"],
+                    )
+                } else {
+                    (
+                        "DISK_RQ_TRACEPOINTS",
+                        ["block_rq_insert", "block_rq_issue", "block_rq_complete"],
+                    )
+                };
                 // load the code and compile
-                let code = include_str!("bpf.c");
-                let mut bpf = bcc::BPF::new(code)?;
-                // load + attach kprobes!
-                bcc::Kprobe::new()
-                    .handler("trace_pid_start")
-                    .function("blk_account_io_start")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_req_start")
-                    .function("blk_start_request")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_req_start")
-                    .function("blk_mq_start_request")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("do_count")
-                    .function("blk_account_io_completion")
-                    .attach(&mut bpf)?;
+                let code = if kprobes {
+                    include_str!("bpf.c").to_string()
+                } else {
+                    format!("#define {}\n{}", define, include_str!("bpf.c"))
+                };
+                let mut bpf = bcc::BPF::new(&code)?;
+                if kprobes {
+                    // load + attach kprobes!
+                    bcc::Kprobe::new()
+                        .handler("trace_pid_start")
+                        .function("blk_account_io_start")
+                        .attach(&mut bpf)?;
+                    bcc::Kprobe::new()
+                        .handler("trace_req_start")
+                        .function("blk_start_request")
+                        .attach(&mut bpf)?;
+                    bcc::Kprobe::new()
+                        .handler("trace_req_start")
+                        .function("blk_mq_start_request")
+                        .attach(&mut bpf)?;
+                    bcc::Kprobe::new()
+                        .handler("do_count")
+                        .function("blk_account_io_completion")
+                        .attach(&mut bpf)?;
+                } else {
+                    debug!("using block tracepoints: {}", tracepoints.join(", "));
+                    for tracepoint in &tracepoints {
+                        bcc::Tracepoint::new()
+                            .handler(&format!("tracepoint__block__{}", tracepoint))
+                            .subsystem("block")
+                            .tracepoint(tracepoint)
+                            .attach(&mut bpf)?;
+                    }
+                }
 
                 self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
             }
@@ -158,58 +218,60 @@ impl Disk {
 
         Ok(())
     }
+}
 
+impl Disk {
     async fn sample_diskstats(&mut self) -> Result<(), std::io::Error> {
         if self.proc_diskstats.is_none() {
             let file = File::open("/proc/diskstats").await?;
             self.proc_diskstats = Some(file);
         }
 
-        if self.disk_regex.is_none() {
-            let re = Regex::new(r"^((sd[a-z]+)|(hd[a-z]+)|(nvme\d+n\d+))$")
-                .expect("failed to compile regex");
-            self.disk_regex = Some(re);
-        }
-
         if let Some(file) = &mut self.proc_diskstats {
             file.seek(SeekFrom::Start(0)).await?;
-            if let Some(re) = &mut self.disk_regex {
-                let mut reader = BufReader::new(file);
-                let mut line = String::new();
-                let mut result = HashMap::<DiskStatistic, u64>::new();
-                while reader.read_line(&mut line).await? > 0 {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if re.is_match(parts.get(2).unwrap_or(&"unknown")) {
-                        for (id, part) in parts.iter().enumerate() {
-                            if let Some(statistic) = match id {
-                                3 => Some(DiskStatistic::OperationsRead),
-                                5 => Some(DiskStatistic::BandwidthRead),
-                                7 => Some(DiskStatistic::OperationsWrite),
-                                9 => Some(DiskStatistic::BandwidthWrite),
-                                14 => Some(DiskStatistic::OperationsDiscard),
-                                16 => Some(DiskStatistic::BandwidthDiscard),
-                                _ => None,
-                            } {
-                                if !result.contains_key(&statistic) {
-                                    result.insert(statistic, 0);
-                                }
-                                let current = result.get_mut(&statistic).unwrap();
-                                *current += part.parse().unwrap_or(0);
-                            }
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            let mut result = HashMap::<DiskStatistic, u64>::new();
+            let mut devices = Vec::new();
+            while reader.read_line(&mut line).await? > 0 {
+                if let Some((device, values)) = parse_line(&line) {
+                    if self.devices.contains(&device) {
+                        devices.push((device, values));
+                    }
+                }
+                line.clear();
+            }
+            if self.statistics.contains(&DiskStatistic::ErrorsIo) {
+                for (device, values) in devices.iter_mut() {
+                    // only scsi devices report their io error count
+                    let path = format!("/sys/block/{}/device/ioerr_cnt", device);
+                    if let Ok(content) = self.common.proc().read_to_string(&path).await {
+                        if let Some(errors) = parse_hex(&content) {
+                            values.push((DiskStatistic::ErrorsIo, errors));
                         }
                     }
-                    line.clear();
                 }
-                let time = Instant::now();
-                for stat in &self.statistics {
-                    if let Some(value) = result.get(stat) {
-                        let value = match stat {
-                            DiskStatistic::BandwidthWrite
-                            | DiskStatistic::BandwidthRead
-                            | DiskStatistic::BandwidthDiscard => value * 512,
-                            _ => *value,
-                        };
-                        let _ = self.metrics().record_counter(stat, time, value);
+            }
+            for (_, values) in &devices {
+                for (statistic, value) in values {
+                    *result.entry(*statistic).or_insert(0) += value;
+                }
+            }
+            let time = self.common.now();
+            for stat in &self.statistics {
+                if let Some(value) = result.get(stat) {
+                    self.record_counter(stat, time, *value);
+                }
+            }
+            if self.common.config().samplers().disk().per_device() {
+                for (device, values) in devices {
+                    for (stat, value) in values {
+                        if !self.statistics.contains(&stat) {
+                            continue;
+                        }
+                        let statistic = LabeledStatistic::new(&stat, "device", &device);
+                        self.common.register_once(&statistic);
+                        self.common.record_counter(&statistic, time, value);
                     }
                 }
             }
@@ -220,30 +282,141 @@ impl Disk {
 
     #[cfg(feature = "bpf")]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        use std::convert::TryInto;
-        if self.bpf_last.lock().unwrap().elapsed()
-            >= Duration::new(self.general_config().window().try_into().unwrap(), 0)
-        {
-            let time = Instant::now();
+        if self.bpf_drain_due(&self.bpf_last) {
+            let time = self.common.now();
             if let Some(ref bpf) = self.bpf {
                 let bpf = bpf.lock().unwrap();
                 for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
                     if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                        for (&value, &count) in &map_from_table(&mut table) {
+                        let table =
+                            self.drain_histogram(statistic.bpf_table().unwrap(), &mut table);
+                        for (&value, &count) in &table {
                             if count > 0 {
-                                let _ = self.metrics().record_bucket(
+                                self.record_bucket(
                                     statistic,
                                     time,
-                                    value * crate::MICROSECOND,
+                                    value * statistic.bucket_scale(),
                                     count,
                                 );
                             }
                         }
+                    } else {
+                        self.common
+                            .bpf_table_error("disk", statistic.bpf_table().unwrap());
                     }
                 }
             }
-            *self.bpf_last.lock().unwrap() = Instant::now();
+            *self.bpf_last.lock().unwrap() = self.common.now();
         }
         Ok(())
     }
 }
+
+/// The block devices to sample, which are the entries of `/sys/block` that do
+/// not start with an excluded prefix. Partitions are not listed there, so only
+/// whole devices are counted.
+fn select_devices(devices: HashSet<String>, exclude: &[String]) -> HashSet<String> {
+    devices
+        .into_iter()
+        .filter(|device| {
+            !exclude
+                .iter()
+                .any(|prefix| device.starts_with(prefix.as_str()))
+        })
+        .collect()
+}
+
+/// Parses a counter which sysfs reports in hex, eg: `0x1f`
+fn parse_hex(content: &str) -> Option<u64> {
+    let content = content.trim();
+    let digits = content
+        .strip_prefix("0x")
+        .or_else(|| content.strip_prefix("0X"))?;
+    u64::from_str_radix(digits, 16).ok()
+}
+
+/// Parses a line of `/proc/diskstats` into the device name and the values of
+/// the counters it provides. Sector counts are converted to bytes, and the
+/// busy time to nanoseconds.
+fn parse_line(line: &str) -> Option<(String, Vec<(DiskStatistic, u64)>)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let device = parts.get(2)?.to_string();
+    let mut values = Vec::new();
+    for (id, part) in parts.iter().enumerate() {
+        if let Some(statistic) = match id {
+            3 => Some(DiskStatistic::OperationsRead),
+            5 => Some(DiskStatistic::BandwidthRead),
+            7 => Some(DiskStatistic::OperationsWrite),
+            9 => Some(DiskStatistic::BandwidthWrite),
+            12 => Some(DiskStatistic::BusyTime),
+            14 => Some(DiskStatistic::OperationsDiscard),
+            16 => Some(DiskStatistic::BandwidthDiscard),
+            _ => None,
+        } {
+            let value: u64 = part.parse().unwrap_or(0);
+            let value = match statistic {
+                DiskStatistic::BandwidthWrite
+                | DiskStatistic::BandwidthRead
+                | DiskStatistic::BandwidthDiscard => value * 512,
+                DiskStatistic::BusyTime => value * MILLISECOND,
+                _ => value,
+            };
+            values.push((statistic, value));
+        }
+    }
+    Some((device, values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let line = " 259       0 nvme0n1 100 0 8 25 200 0 16 50 0 75 75 5 0 24 10\n";
+        let (device, values) = parse_line(line).unwrap();
+        assert_eq!(device, "nvme0n1");
+        assert_eq!(
+            values,
+            vec![
+                (DiskStatistic::OperationsRead, 100),
+                (DiskStatistic::BandwidthRead, 4096),
+                (DiskStatistic::OperationsWrite, 200),
+                (DiskStatistic::BandwidthWrite, 8192),
+                (DiskStatistic::BusyTime, 75_000_000),
+                (DiskStatistic::OperationsDiscard, 5),
+                (DiskStatistic::BandwidthDiscard, 12288),
+            ]
+        );
+
+        // older kernels do not report discards
+        let line = "   8       0 sda 1 0 2 0 3 0 4 0 0 0 0\n";
+        let (device, values) = parse_line(line).unwrap();
+        assert_eq!(device, "sda");
+        assert_eq!(values.len(), 4);
+
+        assert_eq!(parse_line("\n"), None);
+    }
+
+    #[test]
+    fn test_select_devices() {
+        let devices: HashSet<String> = vec!["sda", "nvme0n1", "vda", "loop0", "ram1", "dm-0"]
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect();
+        let mut selected: Vec<String> =
+            select_devices(devices.clone(), &config::default_exclude_devices())
+                .into_iter()
+                .collect();
+        selected.sort();
+        assert_eq!(selected, vec!["nvme0n1", "sda", "vda"]);
+        assert_eq!(select_devices(devices, &[]).len(), 6);
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("0x1f\n"), Some(31));
+        assert_eq!(parse_hex("0x0"), Some(0));
+        assert_eq!(parse_hex("31"), None);
+    }
+}
@@ -5,6 +5,9 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES};
+use crate::common::unit::{StatisticUnit, Unit};
+use crate::common::MICROSECOND;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -37,6 +40,10 @@ pub enum DiskStatistic {
     OperationsWrite,
     #[strum(serialize = "disk/discard/operations")]
     OperationsDiscard,
+    #[strum(serialize = "disk/busy/time")]
+    BusyTime,
+    #[strum(serialize = "disk/errors/io")]
+    ErrorsIo,
     #[strum(serialize = "disk/read/latency")]
     LatencyRead,
     #[strum(serialize = "disk/write/latency")]
@@ -69,6 +76,22 @@ impl DiskStatistic {
             _ => None,
         }
     }
+
+    /// The oldest kernel able to trace the statistic, which uses kprobes
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| KPROBES)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        match self {
+            Self::IoSizeRead | Self::IoSizeWrite => 1,
+            _ => MICROSECOND,
+        }
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for DiskStatistic {
@@ -85,6 +108,15 @@ impl Statistic<AtomicU64, AtomicU32> for DiskStatistic {
     }
 }
 
+impl StatisticUnit for DiskStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::BusyTime => Some(Unit::Nanoseconds),
+            _ => None,
+        }
+    }
+}
+
 impl TryFrom<&str> for DiskStatistic {
     type Error = ParseError;
 
@@ -92,3 +124,18 @@ impl TryFrom<&str> for DiskStatistic {
         DiskStatistic::from_str(s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bucket_scale() {
+        // latency histograms have microsecond buckets and are recorded in ns
+        assert_eq!(DiskStatistic::LatencyRead.bucket_scale(), 1_000);
+        assert_eq!(DiskStatistic::QueueLatencyWrite.bucket_scale(), 1_000);
+        // size histograms are already in bytes
+        assert_eq!(DiskStatistic::IoSizeRead.bucket_scale(), 1);
+        assert_eq!(DiskStatistic::IoSizeWrite.bucket_scale(), 1);
+    }
+}
@@ -5,6 +5,9 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES};
+use crate::common::unit::StatisticUnit;
+use crate::common::MICROSECOND;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -45,6 +48,24 @@ impl Ext4Statistic {
             Self::FsyncLatency => Some("fsync"),
         }
     }
+
+    /// The oldest kernel able to trace the statistic, which uses kprobes on
+    /// the ext4 file operations
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| KPROBES)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        match self {
+            Self::ReadLatency | Self::WriteLatency | Self::OpenLatency | Self::FsyncLatency => {
+                MICROSECOND
+            }
+        }
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for Ext4Statistic {
@@ -57,6 +78,8 @@ impl Statistic<AtomicU64, AtomicU32> for Ext4Statistic {
     }
 }
 
+impl StatisticUnit for Ext4Statistic {}
+
 impl TryFrom<&str> for Ext4Statistic {
     type Error = ParseError;
 
@@ -14,8 +14,13 @@ pub struct HttpConfig {
     counters: Vec<String>,
     #[serde(default)]
     enabled: AtomicBool,
-    gauges: Vec<String>,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    gauges: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
     #[serde(default)]
     passthrough: AtomicBool,
@@ -29,6 +34,7 @@ impl Default for HttpConfig {
         Self {
             counters: Vec::new(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             gauges: Vec::new(),
             interval: Default::default(),
             passthrough: Default::default(),
@@ -70,6 +76,10 @@ impl SamplerConfig for HttpConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -78,6 +88,13 @@ impl SamplerConfig for HttpConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         // we don't know the statistics yet, register at runtime instead
         Vec::new()
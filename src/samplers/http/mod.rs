@@ -3,7 +3,6 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use std::io::{Error, ErrorKind};
-use std::time::*;
 
 use async_trait::async_trait;
 use rustcommon_metrics::*;
@@ -49,13 +48,15 @@ impl Sampler for Http {
         if common.config().samplers().http().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().http())
+            {
                 fatal!("failed to initialize http sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize http sampler");
             }
         }
@@ -74,10 +75,6 @@ impl Sampler for Http {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -89,7 +86,7 @@ impl Sampler for Http {
             ));
         }
 
-        let time = Instant::now();
+        let time = self.common.now();
         if let Ok(response) = self.client.get(self.url.as_ref().unwrap()).send() {
             if let Ok(body) = response.text() {
                 if let Ok(json) = json::parse(&body) {
@@ -125,16 +122,10 @@ impl Sampler for Http {
                                 }
                                 match statistic.source() {
                                     Source::Counter => {
-                                        let _ = self
-                                            .common()
-                                            .metrics()
-                                            .record_counter(statistic, time, value);
+                                        self.record_counter(statistic, time, value);
                                     }
                                     Source::Gauge => {
-                                        let _ = self
-                                            .common()
-                                            .metrics()
-                                            .record_gauge(statistic, time, value);
+                                        self.record_gauge(statistic, time, value);
                                     }
                                     _ => unimplemented!(),
                                 }
@@ -144,10 +135,7 @@ impl Sampler for Http {
                                 self.common()
                                     .metrics()
                                     .add_output(&statistic, Output::Reading);
-                                let _ = self
-                                    .common()
-                                    .metrics()
-                                    .record_gauge(&statistic, time, value);
+                                self.record_gauge(&statistic, time, value);
                             }
                         }
                     }
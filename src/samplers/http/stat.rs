@@ -2,6 +2,7 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use crate::common::unit::StatisticUnit;
 use crate::Statistic;
 use rustcommon_metrics::*;
 
@@ -26,3 +27,5 @@ impl Statistic<AtomicU64, AtomicU32> for HttpStatistic {
         self.source
     }
 }
+
+impl StatisticUnit for HttpStatistic {}
@@ -34,22 +34,17 @@ impl Sampler for Interrupt {
     type Statistic = InterruptStatistic;
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().interrupt().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
             common,
             statistics,
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
@@ -62,13 +57,15 @@ impl Sampler for Interrupt {
         if common.config().samplers().interrupt().enabled() {
             if let Ok(mut interrupt) = Interrupt::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = interrupt.sample().await;
-                    }
+                    interrupt.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().interrupt())
+            {
                 fatal!("failed to initialize interrupt sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize interrupt sampler");
             }
         }
@@ -87,10 +84,6 @@ impl Sampler for Interrupt {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -99,14 +92,27 @@ impl Sampler for Interrupt {
 
         self.sample_interrupt().await?;
 
+        #[cfg(feature = "bpf")]
+        self.retry_bpf();
         #[cfg(feature = "bpf")]
         self.map_result(self.sample_bpf())?;
 
         Ok(())
     }
-}
 
-impl Interrupt {
+    fn drains_bpf(&self) -> bool {
+        cfg!(feature = "bpf")
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+        #[cfg(feature = "bpf")]
+        self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
     #[cfg(feature = "bpf")]
     fn bpf_enabled(&self) -> bool {
         if self.sampler_config().bpf() {
@@ -119,33 +125,59 @@ impl Interrupt {
         false
     }
 
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
         #[cfg(feature = "bpf")]
         {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
             if self.enabled() && self.bpf_enabled() {
                 debug!("initializing bpf");
 
                 let code = include_str!("bpf.c");
                 let mut bpf = bcc::BPF::new(code)?;
 
-                bcc::Kprobe::new()
-                    .handler("hardirq_entry")
-                    .function("handle_irq_event_percpu")
-                    .attach(&mut bpf)?;
-                bcc::Kretprobe::new()
-                    .handler("hardirq_exit")
-                    .function("handle_irq_event_percpu")
-                    .attach(&mut bpf)?;
-                bcc::Tracepoint::new()
-                    .handler("softirq_entry")
-                    .subsystem("irq")
-                    .tracepoint("softirq_entry")
-                    .attach(&mut bpf)?;
-                bcc::Tracepoint::new()
-                    .handler("softirq_exit")
-                    .subsystem("irq")
-                    .tracepoint("softirq_exit")
-                    .attach(&mut bpf)?;
+                // the hardirq and softirq probes are only attached if one of
+                // their statistics remains after the kernel version gates
+                let hardirq = self.statistics.contains(&InterruptStatistic::HardIrq);
+                let softirq = self
+                    .statistics
+                    .iter()
+                    .any(|s| *s != InterruptStatistic::HardIrq && s.bpf_table().is_some());
+                if hardirq {
+                    bcc::Kprobe::new()
+                        .handler("hardirq_entry")
+                        .function("handle_irq_event_percpu")
+                        .attach(&mut bpf)?;
+                    bcc::Kretprobe::new()
+                        .handler("hardirq_exit")
+                        .function("handle_irq_event_percpu")
+                        .attach(&mut bpf)?;
+                }
+                if softirq {
+                    bcc::Tracepoint::new()
+                        .handler("softirq_entry")
+                        .subsystem("irq")
+                        .tracepoint("softirq_entry")
+                        .attach(&mut bpf)?;
+                    bcc::Tracepoint::new()
+                        .handler("softirq_exit")
+                        .subsystem("irq")
+                        .tracepoint("softirq_exit")
+                        .attach(&mut bpf)?;
+                }
 
                 self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })))
             }
@@ -153,7 +185,9 @@ impl Interrupt {
 
         Ok(())
     }
+}
 
+impl Interrupt {
     async fn sample_interrupt(&self) -> Result<(), std::io::Error> {
         let file = File::open("/proc/interrupts").await?;
         let reader = BufReader::new(file);
@@ -273,10 +307,10 @@ impl Interrupt {
             }
         }
 
-        let time = Instant::now();
+        let time = self.common.now();
         for stat in &self.statistics {
             if let Some(value) = result.get(stat) {
-                let _ = self.metrics().record_counter(stat, time, *value);
+                self.record_counter(stat, time, *value);
             }
         }
 
@@ -285,28 +319,31 @@ impl Interrupt {
 
     #[cfg(feature = "bpf")]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        if self.bpf_last.lock().unwrap().elapsed()
-            >= Duration::new(self.general_config().window() as u64, 0)
-        {
+        if self.bpf_drain_due(&self.bpf_last) {
             if let Some(ref bpf) = self.bpf {
                 let bpf = bpf.lock().unwrap();
-                let time = Instant::now();
+                let time = self.common.now();
                 for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
                     if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                        for (&value, &count) in &map_from_table(&mut table) {
+                        let table =
+                            self.drain_histogram(statistic.bpf_table().unwrap(), &mut table);
+                        for (&value, &count) in &table {
                             if count > 0 {
-                                let _ = self.metrics().record_bucket(
+                                self.record_bucket(
                                     statistic,
                                     time,
-                                    value * crate::MICROSECOND,
+                                    value * statistic.bucket_scale(),
                                     count,
                                 );
                             }
                         }
+                    } else {
+                        self.common
+                            .bpf_table_error("interrupt", statistic.bpf_table().unwrap());
                     }
                 }
             }
-            *self.bpf_last.lock().unwrap() = Instant::now();
+            *self.bpf_last.lock().unwrap() = self.common.now();
         }
         Ok(())
     }
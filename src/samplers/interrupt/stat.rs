@@ -5,6 +5,9 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES, TRACEPOINTS};
+use crate::common::unit::StatisticUnit;
+use crate::common::MICROSECOND;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -111,6 +114,23 @@ impl InterruptStatistic {
             _ => None,
         }
     }
+
+    /// The oldest kernel able to trace the statistic. Hardirqs are traced
+    /// with kprobes and softirqs with the irq tracepoints.
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        match self {
+            Self::HardIrq => Some(KPROBES),
+            _ => self.bpf_table().map(|_| TRACEPOINTS),
+        }
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        MICROSECOND
+    }
 }
 
 impl TryFrom<&str> for InterruptStatistic {
@@ -134,3 +154,5 @@ impl Statistic<AtomicU64, AtomicU32> for InterruptStatistic {
         }
     }
 }
+
+impl StatisticUnit for InterruptStatistic {}
@@ -0,0 +1,269 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use json::JsonValue;
+use rustcommon_metrics::*;
+use strum::IntoEnumIterator;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use crate::config::ListFormat;
+
+use super::cgroup::CgroupStatistic;
+use super::cpu::CpuStatistic;
+use super::disk::DiskStatistic;
+use super::ext4::Ext4Statistic;
+use super::interrupt::InterruptStatistic;
+use super::memory::MemoryStatistic;
+use super::neighbor::NeighborStatistic;
+use super::network::NetworkStatistic;
+use super::nvidia::NvidiaStatistic;
+use super::page_cache::PageCacheStatistic;
+use super::pressure::PressureStatistic;
+use super::rezolus::RezolusStatistic;
+use super::scheduler::SchedulerStatistic;
+use super::slab::SlabStatistic;
+use super::softirq::SoftirqStatistic;
+use super::softnet::SoftnetStatistic;
+use super::system::SystemStatistic;
+use super::tcp::TcpStatistic;
+use super::udp::UdpStatistic;
+use super::xdp::XdpStatistic;
+use super::xfs::XfsStatistic;
+
+/// How a statistic is read
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    /// Files under `/proc` and `/sys`
+    Proc,
+    /// BPF programs, which also require `bpf = true` for the sampler
+    Bpf,
+    /// Perf events, which also require `perf_events = true` for the sampler
+    Perf,
+    /// The NVIDIA Management Library, which is provided by the GPU driver
+    Nvml,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Proc => "proc",
+            Self::Bpf => "bpf",
+            Self::Perf => "perf",
+            Self::Nvml => "nvml",
+        }
+    }
+
+    /// The feature rezolus must be built with to read the statistic
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            Self::Proc => None,
+            Self::Bpf | Self::Perf => Some("bpf"),
+            Self::Nvml => Some("nvml"),
+        }
+    }
+}
+
+/// A statistic offered by a sampler, as printed by `--list-statistics`
+#[derive(Debug, PartialEq)]
+pub struct StatisticInfo {
+    pub sampler: &'static str,
+    pub name: String,
+    pub origin: Origin,
+    pub unit: Option<Unit>,
+}
+
+fn describe<T>(sampler: &'static str, origin: fn(T) -> Origin) -> Vec<StatisticInfo>
+where
+    T: IntoEnumIterator + Statistic<AtomicU64, AtomicU32> + StatisticUnit + Copy,
+{
+    T::iter()
+        .map(|statistic| StatisticInfo {
+            sampler,
+            name: statistic.name().to_string(),
+            origin: origin(statistic),
+            unit: statistic.unit(),
+        })
+        .collect()
+}
+
+fn bpf_or_proc(bpf_table: Option<&str>) -> Origin {
+    if bpf_table.is_some() {
+        Origin::Bpf
+    } else {
+        Origin::Proc
+    }
+}
+
+/// Every statistic of the named sampler, or of every sampler if none is
+/// named. The http and memcache samplers are not included, as their
+/// statistics are discovered from the endpoint they read, nor is the custom
+/// sampler, whose statistics are named in its config.
+pub fn list_statistics(sampler: Option<&str>) -> Result<Vec<StatisticInfo>, anyhow::Error> {
+    let samplers = vec![
+        describe::<CgroupStatistic>("cgroup", |_| Origin::Proc),
+        describe::<CpuStatistic>("cpu", |s| {
+            if s.table().is_some() {
+                Origin::Perf
+            } else {
+                Origin::Proc
+            }
+        }),
+        describe::<DiskStatistic>("disk", |s| bpf_or_proc(s.bpf_table())),
+        describe::<Ext4Statistic>("ext4", |s| bpf_or_proc(s.bpf_table())),
+        describe::<InterruptStatistic>("interrupt", |s| bpf_or_proc(s.bpf_table())),
+        describe::<MemoryStatistic>("memory", |_| Origin::Proc),
+        describe::<NeighborStatistic>("neighbor", |_| Origin::Proc),
+        describe::<NetworkStatistic>("network", |s| bpf_or_proc(s.bpf_table())),
+        describe::<NvidiaStatistic>("nvidia", |_| Origin::Nvml),
+        describe::<PageCacheStatistic>("page_cache", |s| {
+            if s.is_bpf() {
+                Origin::Bpf
+            } else {
+                Origin::Proc
+            }
+        }),
+        describe::<PressureStatistic>("pressure", |_| Origin::Proc),
+        describe::<RezolusStatistic>("rezolus", |_| Origin::Proc),
+        describe::<SchedulerStatistic>("scheduler", |s| {
+            if s.perf_table().is_some() {
+                Origin::Perf
+            } else {
+                bpf_or_proc(s.bpf_table())
+            }
+        }),
+        describe::<SlabStatistic>("slab", |_| Origin::Proc),
+        describe::<SoftirqStatistic>("softirq", |_| Origin::Proc),
+        describe::<SoftnetStatistic>("softnet", |_| Origin::Proc),
+        describe::<SystemStatistic>("system", |_| Origin::Proc),
+        describe::<TcpStatistic>("tcp", |s| bpf_or_proc(s.bpf_table())),
+        describe::<UdpStatistic>("udp", |_| Origin::Proc),
+        describe::<XdpStatistic>("xdp", |_| Origin::Bpf),
+        describe::<XfsStatistic>("xfs", |s| bpf_or_proc(s.bpf_table())),
+    ];
+    let mut statistics: Vec<StatisticInfo> = samplers.into_iter().flatten().collect();
+    if let Some(sampler) = sampler {
+        statistics.retain(|statistic| statistic.sampler == sampler);
+        if statistics.is_empty() {
+            return Err(format_err!(
+                "no statistics are known for sampler: {}",
+                sampler
+            ));
+        }
+    }
+    Ok(statistics)
+}
+
+/// Render the statistics as an aligned table or a JSON array
+pub fn render_statistics(statistics: &[StatisticInfo], format: ListFormat) -> String {
+    match format {
+        ListFormat::Json => {
+            let mut content = JsonValue::new_array();
+            for statistic in statistics {
+                let mut entry = JsonValue::new_object();
+                entry["sampler"] = statistic.sampler.into();
+                entry["name"] = statistic.name.as_str().into();
+                entry["source"] = statistic.origin.name().into();
+                entry["feature"] = statistic.origin.feature().into();
+                entry["unit"] = statistic.unit.map(|unit| unit.name()).into();
+                let _ = content.push(entry);
+            }
+            format!("{}\n", content.dump())
+        }
+        ListFormat::Table => {
+            let rows: Vec<[&str; 4]> = statistics
+                .iter()
+                .map(|statistic| {
+                    [
+                        statistic.name.as_str(),
+                        statistic.origin.name(),
+                        statistic.origin.feature().unwrap_or("-"),
+                        statistic.unit.map(|unit| unit.name()).unwrap_or("-"),
+                    ]
+                })
+                .collect();
+            let header = ["NAME", "SOURCE", "FEATURE", "UNIT"];
+            let mut widths = [0; 4];
+            for row in std::iter::once(&header).chain(rows.iter()) {
+                for (width, column) in widths.iter_mut().zip(row.iter()) {
+                    *width = (*width).max(column.len());
+                }
+            }
+            let mut content = String::new();
+            for row in std::iter::once(&header).chain(rows.iter()) {
+                let line = format!(
+                    "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+                    row[0],
+                    row[1],
+                    row[2],
+                    row[3],
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2],
+                );
+                content.push_str(line.trim_end());
+                content.push('\n');
+            }
+            content
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_list_statistics() {
+        let statistics = list_statistics(Some("tcp")).unwrap();
+        assert!(statistics.iter().all(|s| s.sampler == "tcp"));
+        let latency = statistics
+            .iter()
+            .find(|s| s.name == "tcp/connect/latency")
+            .unwrap();
+        assert_eq!(latency.origin, Origin::Bpf);
+        assert_eq!(latency.unit, Some(Unit::Nanoseconds));
+        let segments = statistics
+            .iter()
+            .find(|s| s.name == "tcp/receive/segment")
+            .unwrap();
+        assert_eq!(segments.origin, Origin::Proc);
+
+        let scheduler = list_statistics(Some("scheduler")).unwrap();
+        assert!(scheduler
+            .iter()
+            .any(|s| s.name == "scheduler/cpu_migrations" && s.origin == Origin::Perf));
+
+        assert!(list_statistics(Some("nope")).is_err());
+        assert!(list_statistics(None).unwrap().len() > statistics.len());
+    }
+
+    #[test]
+    fn test_render_statistics() {
+        let statistics = vec![
+            StatisticInfo {
+                sampler: "tcp",
+                name: "tcp/connect/latency".to_string(),
+                origin: Origin::Bpf,
+                unit: Some(Unit::Nanoseconds),
+            },
+            StatisticInfo {
+                sampler: "udp",
+                name: "udp/receive/datagrams".to_string(),
+                origin: Origin::Proc,
+                unit: None,
+            },
+        ];
+        assert_eq!(
+            render_statistics(&statistics, ListFormat::Table),
+            "NAME                   SOURCE  FEATURE  UNIT\n\
+             tcp/connect/latency    bpf     bpf      nanoseconds\n\
+             udp/receive/datagrams  proc    -        -\n"
+        );
+        assert_eq!(
+            render_statistics(&statistics, ListFormat::Json),
+            "[{\"sampler\":\"tcp\",\"name\":\"tcp/connect/latency\",\"source\":\"bpf\",\"feature\":\"bpf\",\"unit\":\"nanoseconds\"},\
+             {\"sampler\":\"udp\",\"name\":\"udp/receive/datagrams\",\"source\":\"proc\",\"feature\":null,\"unit\":null}]\n"
+        );
+    }
+}
@@ -13,6 +13,11 @@ pub struct MemcacheConfig {
     #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
@@ -23,6 +28,7 @@ impl Default for MemcacheConfig {
     fn default() -> Self {
         Self {
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
             percentiles: crate::common::default_percentiles(),
             endpoint: None,
@@ -43,6 +49,10 @@ impl SamplerConfig for MemcacheConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -51,6 +61,13 @@ impl SamplerConfig for MemcacheConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         Vec::new()
     }
@@ -7,7 +7,6 @@ use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 
 use async_trait::async_trait;
 use rustcommon_metrics::*;
-use std::time::*;
 
 use crate::config::*;
 use crate::samplers::Common;
@@ -62,13 +61,15 @@ impl Sampler for Memcache {
         if common.config().samplers().memcache().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().memcache())
+            {
                 fatal!("failed to initialize memcache sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize memcache sampler");
             }
         }
@@ -87,10 +88,6 @@ impl Sampler for Memcache {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -114,7 +111,7 @@ impl Sampler for Memcache {
                 }
                 let mut buffer = [0_u8; 65536];
                 let _ = stream.read(&mut buffer);
-                let time = Instant::now();
+                let time = self.common.now();
                 let stats = std::str::from_utf8(&buffer).unwrap().to_string();
                 let lines: Vec<&str> = stats.split("\r\n").collect();
                 for line in lines {
@@ -138,10 +135,7 @@ impl Sampler for Memcache {
                                         self.common()
                                             .metrics()
                                             .add_output(&statistic, Output::Reading);
-                                        let _ = self
-                                            .common()
-                                            .metrics()
-                                            .record_counter(&statistic, time, value);
+                                        self.record_counter(&statistic, time, value);
                                         for percentile in self.sampler_config().percentiles() {
                                             self.common().metrics().add_output(
                                                 &statistic,
@@ -160,10 +154,7 @@ impl Sampler for Memcache {
                                             .metrics()
                                             .add_output(&statistic, Output::Reading);
                                         // gauge type is used to pass-through raw metrics
-                                        let _ = self
-                                            .common()
-                                            .metrics()
-                                            .record_gauge(&statistic, time, value);
+                                        self.record_gauge(&statistic, time, value);
                                     }
                                 }
                             }
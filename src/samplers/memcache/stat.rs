@@ -4,6 +4,7 @@
 
 use crate::Statistic;
 
+use crate::common::unit::StatisticUnit;
 use rustcommon_metrics::*;
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -32,3 +33,5 @@ impl Statistic<AtomicU64, AtomicU32> for MemcacheStatistic {
         }
     }
 }
+
+impl StatisticUnit for MemcacheStatistic {}
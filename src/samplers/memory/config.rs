@@ -4,7 +4,6 @@
 
 use rustcommon_atomics::*;
 use serde_derive::Deserialize;
-use strum::IntoEnumIterator;
 
 use crate::config::SamplerConfig;
 
@@ -13,10 +12,21 @@ use super::stat::*;
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct MemoryConfig {
+    #[serde(default)]
+    cgroup: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<MemoryStatistic>,
     #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(default)]
+    per_node: AtomicBool,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
     #[serde(default = "default_statistics")]
@@ -26,16 +36,62 @@ pub struct MemoryConfig {
 impl Default for MemoryConfig {
     fn default() -> Self {
         Self {
+            cgroup: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
+            per_node: Default::default(),
             percentiles: crate::common::default_percentiles(),
             statistics: default_statistics(),
         }
     }
 }
 
+impl MemoryConfig {
+    /// whether to report the usage of the cgroup which rezolus runs in, from
+    /// its `memory.current` and `memory.stat`, rather than of the host
+    pub fn cgroup(&self) -> bool {
+        self.cgroup.load(Ordering::Relaxed)
+    }
+
+    /// whether to also export the numa statistics for each node
+    pub fn per_node(&self) -> bool {
+        self.per_node.load(Ordering::Relaxed)
+    }
+}
+
+/// The usage, numa locality, and reclaim statistics which are exported unless
+/// others are configured
 fn default_statistics() -> Vec<MemoryStatistic> {
-    MemoryStatistic::iter().collect()
+    vec![
+        MemoryStatistic::Total,
+        MemoryStatistic::Free,
+        MemoryStatistic::Available,
+        MemoryStatistic::CgroupCurrent,
+        MemoryStatistic::Buffers,
+        MemoryStatistic::Cached,
+        MemoryStatistic::Dirty,
+        MemoryStatistic::Writeback,
+        MemoryStatistic::AnonPages,
+        MemoryStatistic::Shmem,
+        MemoryStatistic::SlabTotal,
+        MemoryStatistic::SwapTotal,
+        MemoryStatistic::SwapFree,
+        MemoryStatistic::SwapUsed,
+        MemoryStatistic::NumaHit,
+        MemoryStatistic::NumaMiss,
+        MemoryStatistic::NumaLocal,
+        MemoryStatistic::NumaOther,
+        MemoryStatistic::PageFault,
+        MemoryStatistic::PageMajorFault,
+        MemoryStatistic::PageScanKswapd,
+        MemoryStatistic::PageScanDirect,
+        MemoryStatistic::PageStealKswapd,
+        MemoryStatistic::PageStealDirect,
+        MemoryStatistic::SwapIn,
+        MemoryStatistic::SwapOut,
+    ]
 }
 
 impl SamplerConfig for MemoryConfig {
@@ -44,6 +100,10 @@ impl SamplerConfig for MemoryConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -52,7 +112,16 @@ impl SamplerConfig for MemoryConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
-        self.statistics.clone()
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
     }
 }
@@ -3,7 +3,7 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use std::collections::HashMap;
-use std::time::*;
+use std::path::PathBuf;
 
 use async_trait::async_trait;
 use regex::Regex;
@@ -11,6 +11,7 @@ use rustcommon_metrics::*;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
+use crate::common::labeled::LabeledStatistic;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
 use crate::Sampler;
@@ -25,7 +26,9 @@ use stat::MemoryStatistic as Stat;
 
 #[allow(dead_code)]
 pub struct Memory {
+    cgroup: Option<PathBuf>,
     common: Common,
+    nodes: Vec<usize>,
     statistics: Vec<MemoryStatistic>,
 }
 
@@ -35,7 +38,20 @@ impl Sampler for Memory {
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
         let statistics = common.config().samplers().memory().statistics();
-        let sampler = Self { common, statistics };
+        let cgroup = if common.config().samplers().memory().cgroup() {
+            Some(
+                crate::common::cgroup::own_cgroup()
+                    .ok_or_else(|| format_err!("memory sampler cgroup mode requires cgroup v2"))?,
+            )
+        } else {
+            None
+        };
+        let sampler = Self {
+            cgroup,
+            common,
+            nodes: numa_nodes(),
+            statistics,
+        };
         if sampler.sampler_config().enabled() {
             sampler.register();
         }
@@ -46,13 +62,15 @@ impl Sampler for Memory {
         if common.config().samplers().memory().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().memory())
+            {
                 fatal!("failed to initialize memory sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize memory sampler");
             }
         }
@@ -71,10 +89,6 @@ impl Sampler for Memory {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -82,8 +96,16 @@ impl Sampler for Memory {
         debug!("sampling");
         self.register();
 
-        self.map_result(self.sample_meminfo().await)?;
+        if self.cgroup.is_some() {
+            self.map_result(self.sample_cgroup().await)?;
+        } else {
+            self.map_result(self.sample_meminfo().await)?;
+        }
         self.map_result(self.sample_vmstat().await)?;
+        if self.common.config().samplers().memory().per_node() {
+            let r = self.sample_numastat().await;
+            self.map_result(r)?;
+        }
 
         Ok(())
     }
@@ -91,86 +113,52 @@ impl Sampler for Memory {
 
 impl Memory {
     async fn sample_meminfo(&self) -> Result<(), std::io::Error> {
-        let file = File::open("/proc/meminfo").await?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-
-        let mut result = HashMap::<MemoryStatistic, u64>::new();
-
-        let re = Regex::new(r"(?P<stat>\w+):\s+(?P<value>\d+)").expect("failed to compile regex");
+        let content = tokio::fs::read_to_string("/proc/meminfo").await?;
+        self.record_meminfo(parse_meminfo(&content));
+        Ok(())
+    }
 
-        while let Some(line) = lines.next_line().await? {
-            if let Some(caps) = re.captures(&line) {
-                if let Some(Ok(value)) = caps.name("value").map(|v| v.as_str().parse()) {
-                    if let Some(Some(stat)) = caps.name("stat").map(|v| match v.as_str() {
-                        "MemTotal" => Some(Stat::Total),
-                        "MemFree" => Some(Stat::Free),
-                        "MemAvailable" => Some(Stat::Available),
-                        "Buffers" => Some(Stat::Buffers),
-                        "Cached" => Some(Stat::Cached),
-                        "SwapCached" => Some(Stat::SwapCached),
-                        "Active" => Some(Stat::Active),
-                        "Inactive" => Some(Stat::Inactive),
-                        "Active(anon)" => Some(Stat::ActiveAnon),
-                        "Inactive(anon)" => Some(Stat::InactiveAnon),
-                        "Unevictable" => Some(Stat::Unevictable),
-                        "Mlocked" => Some(Stat::Mlocked),
-                        "SwapTotal" => Some(Stat::SwapTotal),
-                        "SwapFree" => Some(Stat::SwapFree),
-                        "Dirty" => Some(Stat::Dirty),
-                        "Writeback" => Some(Stat::Writeback),
-                        "AnonPages" => Some(Stat::AnonPages),
-                        "Mapped" => Some(Stat::Mapped),
-                        "Shmem" => Some(Stat::Shmem),
-                        "Slab" => Some(Stat::SlabTotal),
-                        "SReclaimable" => Some(Stat::SlabReclaimable),
-                        "SUnreclaim" => Some(Stat::SlabUnreclaimable),
-                        "KernelStack" => Some(Stat::KernelStack),
-                        "PageTables" => Some(Stat::PageTables),
-                        "NFS_Unstable" => Some(Stat::NFSUnstable),
-                        "Bounce" => Some(Stat::Bounce),
-                        "WritebackTmp" => Some(Stat::WritebackTmp),
-                        "CommitLimit" => Some(Stat::CommitLimit),
-                        "Committed_AS" => Some(Stat::CommittedAS),
-                        "VmallocTotal" => Some(Stat::VmallocTotal),
-                        "VmallocUsed" => Some(Stat::VmallocUsed),
-                        "VmallocChunk" => Some(Stat::VmallocChunk),
-                        "HardwareCorrupted" => Some(Stat::HardwareCorrupted),
-                        "AnonHugePages" => Some(Stat::AnonHugePages),
-                        "ShmemHugePages" => Some(Stat::ShmemHugePages),
-                        "ShmemPmdMapped" => Some(Stat::ShmemPmdMapped),
-                        "HugePages_Total" => Some(Stat::HugePagesTotal),
-                        "HugePages_Free" => Some(Stat::HugePagesFree),
-                        "HugePages_Rsvd" => Some(Stat::HugePagesRsvd),
-                        "HugePages_Surp" => Some(Stat::HugePagesSurp),
-                        "Hugepagesize" => Some(Stat::Hugepagesize),
-                        "Hugetlb" => Some(Stat::Hugetlb),
-                        "DirectMap4k" => Some(Stat::DirectMap4k),
-                        "DirectMap2M" => Some(Stat::DirectMap2M),
-                        "DirectMap1G" => Some(Stat::DirectMap1G),
-                        _ => None,
-                    }) {
-                        result.insert(stat, value);
-                    }
+    /// Used in place of `sample_meminfo` to report the usage of our cgroup
+    async fn sample_cgroup(&self) -> Result<(), std::io::Error> {
+        if let Some(directory) = &self.cgroup {
+            let content = tokio::fs::read_to_string(directory.join("memory.stat")).await?;
+            let mut result = parse_cgroup_memory_stat(&content);
+            let current = tokio::fs::read_to_string(directory.join("memory.current")).await?;
+            if let Ok(value) = current.trim().parse() {
+                result.insert(Stat::CgroupCurrent, value);
+            }
+            // the limit is `max` if the cgroup is unlimited
+            let max = tokio::fs::read_to_string(directory.join("memory.max")).await?;
+            if let Ok(value) = max.trim().parse() {
+                result.insert(Stat::Total, value);
+            }
+            // absent unless swap accounting is enabled for the cgroup
+            if let Ok(swap) = tokio::fs::read_to_string(directory.join("memory.swap.current")).await
+            {
+                if let Ok(value) = swap.trim().parse() {
+                    result.insert(Stat::SwapUsed, value);
                 }
             }
+            self.record_meminfo(result);
         }
+        Ok(())
+    }
 
-        let time = Instant::now();
+    fn record_meminfo(&self, result: HashMap<MemoryStatistic, u64>) {
+        let time = self.common.now();
         for statistic in &self.statistics {
             if let Some(value) = result.get(statistic) {
                 match statistic.source() {
                     Source::Counter => {
-                        let _ = self.metrics().record_counter(statistic, time, *value);
+                        self.record_counter(statistic, time, *value);
                     }
                     Source::Gauge => {
-                        let _ = self.metrics().record_gauge(statistic, time, *value);
+                        self.record_gauge(statistic, time, *value);
                     }
                     _ => {}
                 }
             }
         }
-        Ok(())
     }
 
     async fn sample_vmstat(&self) -> Result<(), std::io::Error> {
@@ -208,6 +196,14 @@ impl Memory {
                         "compact_daemon_wake" => Some(Stat::CompactDaemonWake),
                         "compact_daemon_migrate_scanned" => Some(Stat::CompactDaemonMigrateScanned),
                         "compact_daemon_free_scanned" => Some(Stat::CompactDaemonFreeScanned),
+                        "pgfault" => Some(Stat::PageFault),
+                        "pgmajfault" => Some(Stat::PageMajorFault),
+                        "pgscan_kswapd" => Some(Stat::PageScanKswapd),
+                        "pgscan_direct" => Some(Stat::PageScanDirect),
+                        "pgsteal_kswapd" => Some(Stat::PageStealKswapd),
+                        "pgsteal_direct" => Some(Stat::PageStealDirect),
+                        "pswpin" => Some(Stat::SwapIn),
+                        "pswpout" => Some(Stat::SwapOut),
                         _ => None,
                     }) {
                         result.insert(stat, value);
@@ -216,20 +212,236 @@ impl Memory {
             }
         }
 
-        let time = Instant::now();
+        let time = self.common.now();
         for stat in &self.statistics {
             if let Some(value) = result.get(stat) {
                 if stat.source() == Source::Counter {
-                    let _ = self
-                        .metrics()
-                        .record_counter(stat, time, *value * stat.multiplier());
+                    self.record_counter(stat, time, *value * stat.multiplier());
                 } else {
-                    let _ = self
-                        .metrics()
-                        .record_gauge(stat, time, *value * stat.multiplier());
+                    self.record_gauge(stat, time, *value * stat.multiplier());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn sample_numastat(&mut self) -> Result<(), std::io::Error> {
+        let time = self.common.now();
+        for node in self.nodes.clone() {
+            let path = format!("/sys/devices/system/node/node{}/numastat", node);
+            let content = tokio::fs::read_to_string(path).await?;
+            let result = parse_numastat(&content);
+            for stat in &self.statistics {
+                if let Some(value) = result.get(stat) {
+                    let statistic = LabeledStatistic::new(stat, "node", &node.to_string());
+                    self.common.register_once(&statistic);
+                    self.common
+                        .record_counter(&statistic, time, *value * stat.multiplier());
                 }
             }
         }
         Ok(())
     }
 }
+
+/// Enumerates the numa nodes which have a directory in sysfs
+fn numa_nodes() -> Vec<usize> {
+    let mut nodes = Vec::new();
+    if let Ok(entries) = std::fs::read_dir("/sys/devices/system/node") {
+        for entry in entries.flatten() {
+            if let Some(Ok(node)) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("node"))
+                .map(|id| id.parse())
+            {
+                nodes.push(node);
+            }
+        }
+    }
+    nodes.sort_unstable();
+    nodes
+}
+
+/// Parse `/proc/meminfo`, eg: `MemTotal:       16316412 kB`. Values with a
+/// `kB` suffix are converted to bytes, the remainder, such as the number of
+/// huge pages, are returned as is.
+fn parse_meminfo(content: &str) -> HashMap<MemoryStatistic, u64> {
+    let mut result = HashMap::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        let (name, value) = match (parts.next(), parts.next().map(|v| v.parse::<u64>())) {
+            (Some(name), Some(Ok(value))) => (name.trim_end_matches(':'), value),
+            _ => continue,
+        };
+        let value = match parts.next() {
+            Some("kB") => value * 1024,
+            _ => value,
+        };
+        let stat = match name {
+            "MemTotal" => Some(Stat::Total),
+            "MemFree" => Some(Stat::Free),
+            "MemAvailable" => Some(Stat::Available),
+            "Buffers" => Some(Stat::Buffers),
+            "Cached" => Some(Stat::Cached),
+            "SwapCached" => Some(Stat::SwapCached),
+            "Active" => Some(Stat::Active),
+            "Inactive" => Some(Stat::Inactive),
+            "Active(anon)" => Some(Stat::ActiveAnon),
+            "Inactive(anon)" => Some(Stat::InactiveAnon),
+            "Active(file)" => Some(Stat::ActiveFile),
+            "Inactive(file)" => Some(Stat::InactiveFile),
+            "Unevictable" => Some(Stat::Unevictable),
+            "Mlocked" => Some(Stat::Mlocked),
+            "SwapTotal" => Some(Stat::SwapTotal),
+            "SwapFree" => Some(Stat::SwapFree),
+            "Dirty" => Some(Stat::Dirty),
+            "Writeback" => Some(Stat::Writeback),
+            "AnonPages" => Some(Stat::AnonPages),
+            "Mapped" => Some(Stat::Mapped),
+            "Shmem" => Some(Stat::Shmem),
+            "Slab" => Some(Stat::SlabTotal),
+            "SReclaimable" => Some(Stat::SlabReclaimable),
+            "SUnreclaim" => Some(Stat::SlabUnreclaimable),
+            "KernelStack" => Some(Stat::KernelStack),
+            "PageTables" => Some(Stat::PageTables),
+            "NFS_Unstable" => Some(Stat::NFSUnstable),
+            "Bounce" => Some(Stat::Bounce),
+            "WritebackTmp" => Some(Stat::WritebackTmp),
+            "CommitLimit" => Some(Stat::CommitLimit),
+            "Committed_AS" => Some(Stat::CommittedAS),
+            "VmallocTotal" => Some(Stat::VmallocTotal),
+            "VmallocUsed" => Some(Stat::VmallocUsed),
+            "VmallocChunk" => Some(Stat::VmallocChunk),
+            "HardwareCorrupted" => Some(Stat::HardwareCorrupted),
+            "AnonHugePages" => Some(Stat::AnonHugePages),
+            "ShmemHugePages" => Some(Stat::ShmemHugePages),
+            "ShmemPmdMapped" => Some(Stat::ShmemPmdMapped),
+            "HugePages_Total" => Some(Stat::HugePagesTotal),
+            "HugePages_Free" => Some(Stat::HugePagesFree),
+            "HugePages_Rsvd" => Some(Stat::HugePagesRsvd),
+            "HugePages_Surp" => Some(Stat::HugePagesSurp),
+            "Hugepagesize" => Some(Stat::Hugepagesize),
+            "Hugetlb" => Some(Stat::Hugetlb),
+            "DirectMap4k" => Some(Stat::DirectMap4k),
+            "DirectMap2M" => Some(Stat::DirectMap2M),
+            "DirectMap1G" => Some(Stat::DirectMap1G),
+            _ => None,
+        };
+        if let Some(stat) = stat {
+            result.insert(stat, value);
+        }
+    }
+    // swap in use is not reported directly, and includes the swap cache
+    if let (Some(total), Some(free)) = (result.get(&Stat::SwapTotal), result.get(&Stat::SwapFree)) {
+        let used = total.saturating_sub(*free);
+        result.insert(Stat::SwapUsed, used);
+    }
+    result
+}
+
+/// Parse the `memory.stat` of a cgroup, eg: `anon 1234`. The values are in
+/// bytes and are mapped to the equivalent `/proc/meminfo` statistic.
+fn parse_cgroup_memory_stat(content: &str) -> HashMap<MemoryStatistic, u64> {
+    let mut result = HashMap::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(name), Some(Ok(value))) = (parts.next(), parts.next().map(|v| v.parse())) {
+            let stat = match name {
+                "anon" => Stat::AnonPages,
+                "file" => Stat::Cached,
+                "kernel_stack" => Stat::KernelStack,
+                "pagetables" => Stat::PageTables,
+                "shmem" => Stat::Shmem,
+                "file_mapped" => Stat::Mapped,
+                "file_dirty" => Stat::Dirty,
+                "file_writeback" => Stat::Writeback,
+                "anon_thp" => Stat::AnonHugePages,
+                "active_anon" => Stat::ActiveAnon,
+                "inactive_anon" => Stat::InactiveAnon,
+                "active_file" => Stat::ActiveFile,
+                "inactive_file" => Stat::InactiveFile,
+                "unevictable" => Stat::Unevictable,
+                "slab" => Stat::SlabTotal,
+                "slab_reclaimable" => Stat::SlabReclaimable,
+                "slab_unreclaimable" => Stat::SlabUnreclaimable,
+                _ => continue,
+            };
+            result.insert(stat, value);
+        }
+    }
+    result
+}
+
+/// Parses the contents of a per-node `numastat` file, which uses different
+/// names than `/proc/vmstat` for some of the same counters
+fn parse_numastat(content: &str) -> HashMap<MemoryStatistic, u64> {
+    let mut result = HashMap::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(key), Some(Ok(value))) = (parts.next(), parts.next().map(|v| v.parse())) {
+            if let Some(stat) = match key {
+                "numa_hit" => Some(Stat::NumaHit),
+                "numa_miss" => Some(Stat::NumaMiss),
+                "numa_foreign" => Some(Stat::NumaForeign),
+                "interleave_hit" => Some(Stat::NumaInterleave),
+                "local_node" => Some(Stat::NumaLocal),
+                "other_node" => Some(Stat::NumaOther),
+                _ => None,
+            } {
+                result.insert(stat, value);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let content = "MemTotal:       16316412 kB\nMemFree:         1234567 kB\nActive(file):       1000 kB\nHugePages_Total:       4\nHugepagesize:       2048 kB\nUnknown:              1 kB\n";
+        let result = parse_meminfo(content);
+        assert_eq!(result.len(), 5);
+        assert_eq!(result.get(&Stat::Total), Some(&(16316412 * 1024)));
+        assert_eq!(result.get(&Stat::Free), Some(&(1234567 * 1024)));
+        assert_eq!(result.get(&Stat::ActiveFile), Some(&(1000 * 1024)));
+        assert_eq!(result.get(&Stat::HugePagesTotal), Some(&4));
+        assert_eq!(result.get(&Stat::Hugepagesize), Some(&(2048 * 1024)));
+    }
+
+    #[test]
+    fn test_parse_meminfo_swap() {
+        let content = "SwapCached:         2048 kB\nSwapTotal:       2097148 kB\nSwapFree:        1572860 kB\n";
+        let result = parse_meminfo(content);
+        assert_eq!(result.get(&Stat::SwapUsed), Some(&(524288 * 1024)));
+
+        // without swap configured
+        let content = "SwapTotal:             0 kB\nSwapFree:              0 kB\n";
+        let result = parse_meminfo(content);
+        assert_eq!(result.get(&Stat::SwapUsed), Some(&0));
+    }
+
+    #[test]
+    fn test_parse_cgroup_memory_stat() {
+        let content = "anon 4096\nfile 8192\nfile_dirty 0\npgfault 123\n";
+        let result = parse_cgroup_memory_stat(content);
+        assert_eq!(result.len(), 3);
+        assert_eq!(result.get(&Stat::AnonPages), Some(&4096));
+        assert_eq!(result.get(&Stat::Cached), Some(&8192));
+        assert_eq!(result.get(&Stat::Dirty), Some(&0));
+    }
+
+    #[test]
+    fn test_parse_numastat() {
+        let content = "numa_hit 100\nnuma_miss 2\nnuma_foreign 3\ninterleave_hit 4\nlocal_node 90\nother_node 10\n";
+        let result = parse_numastat(content);
+        assert_eq!(result.len(), 6);
+        assert_eq!(result.get(&Stat::NumaHit), Some(&100));
+        assert_eq!(result.get(&Stat::NumaInterleave), Some(&4));
+        assert_eq!(result.get(&Stat::NumaLocal), Some(&90));
+        assert_eq!(result.get(&Stat::NumaOther), Some(&10));
+    }
+}
@@ -5,6 +5,7 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::unit::StatisticUnit;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -31,6 +32,8 @@ pub enum MemoryStatistic {
     Free,
     #[strum(serialize = "memory/available")]
     Available,
+    #[strum(serialize = "memory/cgroup/current")]
+    CgroupCurrent,
     #[strum(serialize = "memory/buffers")]
     Buffers,
     #[strum(serialize = "memory/cached")]
@@ -57,6 +60,8 @@ pub enum MemoryStatistic {
     SwapTotal,
     #[strum(serialize = "memory/swap/free")]
     SwapFree,
+    #[strum(serialize = "memory/swap/used")]
+    SwapUsed,
     #[strum(serialize = "memory/dirty")]
     Dirty,
     #[strum(serialize = "memory/writeback")]
@@ -166,6 +171,23 @@ pub enum MemoryStatistic {
     CompactDaemonMigrateScanned,
     #[strum(serialize = "memory/compact/daemon/free_scanned")]
     CompactDaemonFreeScanned,
+    // Faults, reclaim, and swap
+    #[strum(serialize = "memory/fault/total")]
+    PageFault,
+    #[strum(serialize = "memory/fault/major")]
+    PageMajorFault,
+    #[strum(serialize = "memory/reclaim/scanned/kswapd")]
+    PageScanKswapd,
+    #[strum(serialize = "memory/reclaim/scanned/direct")]
+    PageScanDirect,
+    #[strum(serialize = "memory/reclaim/stolen/kswapd")]
+    PageStealKswapd,
+    #[strum(serialize = "memory/reclaim/stolen/direct")]
+    PageStealDirect,
+    #[strum(serialize = "memory/swap/in")]
+    SwapIn,
+    #[strum(serialize = "memory/swap/out")]
+    SwapOut,
 }
 
 impl MemoryStatistic {
@@ -193,7 +215,15 @@ impl MemoryStatistic {
             | Self::CompactIsolated
             | Self::CompactDaemonWake
             | Self::CompactDaemonMigrateScanned
-            | Self::CompactDaemonFreeScanned => 1,
+            | Self::CompactDaemonFreeScanned
+            | Self::PageFault
+            | Self::PageMajorFault
+            | Self::PageScanKswapd
+            | Self::PageScanDirect
+            | Self::PageStealKswapd
+            | Self::PageStealDirect
+            | Self::SwapIn
+            | Self::SwapOut => 1,
             // convert from pages to bytes
             Self::NumaHit
             | Self::NumaMiss
@@ -227,8 +257,18 @@ impl Statistic<AtomicU64, AtomicU32> for MemoryStatistic {
             | Self::NumaForeign
             | Self::NumaInterleave
             | Self::NumaLocal
-            | Self::NumaOther => Source::Counter,
+            | Self::NumaOther
+            | Self::PageFault
+            | Self::PageMajorFault
+            | Self::PageScanKswapd
+            | Self::PageScanDirect
+            | Self::PageStealKswapd
+            | Self::PageStealDirect
+            | Self::SwapIn
+            | Self::SwapOut => Source::Counter,
             _ => Source::Gauge,
         }
     }
 }
+
+impl StatisticUnit for MemoryStatistic {}
@@ -3,53 +3,432 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use crate::HardwareInfo;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::TryInto;
-use std::sync::Arc;
-use std::time::Duration;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+use dashmap::DashMap;
+use rustcommon_atomics::{Atomic, AtomicBool, Ordering};
 use rustcommon_metrics::*;
 use tokio::runtime::Handle;
-use tokio::time::{interval, Interval};
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Interval};
 
+#[cfg(feature = "bpf")]
+use crate::common::backoff::Backoff;
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+use crate::common::bpf::{
+    bpf_drain, drain_histogram, BpfDrain, BpfDrainStatistic, BpfTableErrorStatistic, HistogramMap,
+};
+use crate::common::buckets::Buckets;
+use crate::common::clock::{Clock, SystemClock};
+use crate::common::counter::CounterTracker;
+use crate::common::health::Health;
+use crate::common::kernel::KernelVersion;
+use crate::common::labeled::{Entities, LabeledStatistic};
+#[cfg(test)]
+use crate::common::proc::Fixtures;
+use crate::common::proc::{ProcSource, Procfs};
+use crate::common::ratelimit::{suppressed, LogLimiter};
+use crate::common::staleness::Updates;
+use crate::common::unit::{StatisticUnit, Unit, Units};
+use crate::common::unwind::{catch_panic, panic_message};
 use crate::config::General as GeneralConfig;
-use crate::config::{Config, SamplerConfig};
+use crate::config::{Changes, Config, SamplerConfig, DEFAULT_MAX_VALUE, DEFAULT_SIGFIGS};
 
+pub mod cgroup;
 pub mod cpu;
+pub mod custom;
 pub mod disk;
 pub mod ext4;
 pub mod http;
 pub mod interrupt;
+mod list;
 pub mod memcache;
 pub mod memory;
+pub mod neighbor;
 pub mod network;
+pub mod nvidia;
 pub mod page_cache;
+pub mod pressure;
 pub mod rezolus;
 pub mod scheduler;
+pub mod slab;
+pub mod softirq;
 pub mod softnet;
+pub mod system;
 pub mod tcp;
 pub mod udp;
+pub mod xdp;
 pub mod xfs;
 
+pub use cgroup::Cgroup;
 pub use cpu::Cpu;
+pub use custom::Custom;
 pub use disk::Disk;
 pub use ext4::Ext4;
 pub use http::Http;
 pub use interrupt::Interrupt;
+pub use list::{list_statistics, render_statistics};
 pub use memcache::Memcache;
 pub use memory::Memory;
+pub use neighbor::Neighbor;
 pub use network::Network;
+pub use nvidia::Nvidia;
 pub use page_cache::PageCache;
+pub use pressure::Pressure;
 pub use rezolus::Rezolus;
 pub use scheduler::Scheduler;
+pub use slab::Slab;
+pub use softirq::Softirq;
 pub use softnet::Softnet;
+pub use system::System;
 pub use tcp::Tcp;
 pub use udp::Udp;
+pub use xdp::Xdp;
 pub use xfs::Xfs;
 
+/// Returns a random offset within the given fraction of the period
+fn jitter(period: Duration, fraction: f64) -> Duration {
+    if fraction <= 0.0 {
+        return Duration::from_millis(0);
+    }
+    // each RandomState is seeded differently, which is enough randomness to
+    // spread the samplers out without pulling in an rng
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    Duration::from_nanos((period.as_nanos() as f64 * fraction * random) as u64)
+}
+
+/// The number of whole periods which have passed between the deadline of the
+/// tick and now, each of which the interval would otherwise fire immediately to
+/// catch up
+fn missed_ticks(lateness: Duration, period: Duration) -> u32 {
+    if period.as_nanos() == 0 {
+        return 0;
+    }
+    (lateness.as_nanos() / period.as_nanos()) as u32
+}
+
+/// The number of ticks in each sampling interval when the BPF maps are polled
+/// every `poll` ms, such that consecutive ticks are at most `poll` apart. The
+/// sampler takes a full sample on the first tick of each interval and only
+/// drains its BPF maps on the others.
+fn ticks_per_sample(interval: usize, poll: Option<usize>) -> usize {
+    match poll {
+        Some(poll) if poll > 0 && poll < interval => (interval + poll - 1) / poll,
+        _ => 1,
+    }
+}
+
+/// Whether a wake-up at `tick`, standing in for the `skipped` ticks after it
+/// which were missed, takes a full sample. It does if any of them is the first
+/// tick of an interval, so that a sampler which falls behind still samples
+/// once per interval.
+fn full_sample_due(tick: usize, skipped: u32, ticks: usize) -> bool {
+    (0..=skipped as usize).any(|i| tick.wrapping_add(i) % ticks == 0)
+}
+
+/// A sampler's name, whether it is enabled, and the functions which spawn it
+/// to sample continuously or to take a single sample
+type Candidate = (&'static str, bool, fn(Common), fn(Common) -> JoinHandle<()>);
+
+fn candidates(common: &Common) -> Vec<Candidate> {
+    let samplers = common.config().samplers();
+    vec![
+        (
+            "cgroup",
+            samplers.cgroup().enabled(),
+            Cgroup::spawn,
+            spawn_once::<Cgroup>,
+        ),
+        (
+            "cpu",
+            samplers.cpu().enabled(),
+            Cpu::spawn,
+            spawn_once::<Cpu>,
+        ),
+        (
+            "custom",
+            samplers.custom().enabled(),
+            Custom::spawn,
+            spawn_once::<Custom>,
+        ),
+        (
+            "disk",
+            samplers.disk().enabled(),
+            Disk::spawn,
+            spawn_once::<Disk>,
+        ),
+        (
+            "ext4",
+            samplers.ext4().enabled(),
+            Ext4::spawn,
+            spawn_once::<Ext4>,
+        ),
+        (
+            "http",
+            samplers.http().enabled(),
+            Http::spawn,
+            spawn_once::<Http>,
+        ),
+        (
+            "interrupt",
+            samplers.interrupt().enabled(),
+            Interrupt::spawn,
+            spawn_once::<Interrupt>,
+        ),
+        (
+            "memcache",
+            samplers.memcache().enabled(),
+            Memcache::spawn,
+            spawn_once::<Memcache>,
+        ),
+        (
+            "memory",
+            samplers.memory().enabled(),
+            Memory::spawn,
+            spawn_once::<Memory>,
+        ),
+        (
+            "page_cache",
+            samplers.page_cache().enabled(),
+            PageCache::spawn,
+            spawn_once::<PageCache>,
+        ),
+        (
+            "neighbor",
+            samplers.neighbor().enabled(),
+            Neighbor::spawn,
+            spawn_once::<Neighbor>,
+        ),
+        (
+            "network",
+            samplers.network().enabled(),
+            Network::spawn,
+            spawn_once::<Network>,
+        ),
+        (
+            "nvidia",
+            samplers.nvidia().enabled(),
+            Nvidia::spawn,
+            spawn_once::<Nvidia>,
+        ),
+        (
+            "pressure",
+            samplers.pressure().enabled(),
+            Pressure::spawn,
+            spawn_once::<Pressure>,
+        ),
+        (
+            "rezolus",
+            samplers.rezolus().enabled(),
+            Rezolus::spawn,
+            spawn_once::<Rezolus>,
+        ),
+        (
+            "scheduler",
+            samplers.scheduler().enabled(),
+            Scheduler::spawn,
+            spawn_once::<Scheduler>,
+        ),
+        (
+            "slab",
+            samplers.slab().enabled(),
+            Slab::spawn,
+            spawn_once::<Slab>,
+        ),
+        (
+            "softirq",
+            samplers.softirq().enabled(),
+            Softirq::spawn,
+            spawn_once::<Softirq>,
+        ),
+        (
+            "softnet",
+            samplers.softnet().enabled(),
+            Softnet::spawn,
+            spawn_once::<Softnet>,
+        ),
+        (
+            "system",
+            samplers.system().enabled(),
+            System::spawn,
+            spawn_once::<System>,
+        ),
+        (
+            "tcp",
+            samplers.tcp().enabled(),
+            Tcp::spawn,
+            spawn_once::<Tcp>,
+        ),
+        (
+            "udp",
+            samplers.udp().enabled(),
+            Udp::spawn,
+            spawn_once::<Udp>,
+        ),
+        (
+            "xdp",
+            samplers.xdp().enabled(),
+            Xdp::spawn,
+            spawn_once::<Xdp>,
+        ),
+        (
+            "xfs",
+            samplers.xfs().enabled(),
+            Xfs::spawn,
+            spawn_once::<Xfs>,
+        ),
+    ]
+}
+
+/// Spawn each enabled sampler which has not already been spawned, keeping the
+/// common of each so that it can be stopped. Called at startup and again after
+/// each config reload
+pub fn spawn_enabled(common: &Common, spawned: &mut HashMap<&'static str, Common>) {
+    for (name, enabled, spawn, _) in candidates(common) {
+        if enabled && !spawned.contains_key(name) {
+            let named = common.named(name);
+            spawned.insert(name, named.clone());
+            spawn(named);
+        }
+    }
+}
+
+/// Stop each spawned sampler which is no longer enabled. It stops at its next
+/// interval, and is spawned again if a later reload enables it.
+fn stop_disabled(common: &Common, spawned: &mut HashMap<&'static str, Common>) {
+    for (name, enabled, _, _) in candidates(common) {
+        if !enabled {
+            if let Some(named) = spawned.remove(name) {
+                info!("stopping {} sampler", name);
+                named.stop();
+            }
+        }
+    }
+}
+
+/// Reloads the config, stops the samplers it disables, and spawns those it
+/// newly enables. This is shared by the SIGHUP handler and the `/-/reload`
+/// endpoint of the http listeners.
+pub struct Reloader {
+    state: Mutex<(Common, HashMap<&'static str, Common>)>,
+}
+
+impl Reloader {
+    /// Takes the samplers which were spawned at startup
+    pub fn new(common: Common, spawned: HashMap<&'static str, Common>) -> Self {
+        Self {
+            state: Mutex::new((common, spawned)),
+        }
+    }
+
+    /// Re-read the config file and apply it, returning the changes, or the
+    /// error if the file could not be read or is invalid
+    pub fn reload(&self) -> Result<Changes, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let (common, spawned) = &mut *state;
+        info!("reloading config");
+        let changes = common.config().reload()?;
+        for change in &changes.applied {
+            info!("config changed: {}", change);
+        }
+        for section in &changes.restart {
+            warn!("config changed: {} requires a restart to apply", section);
+        }
+        stop_disabled(common, spawned);
+        spawn_enabled(common, spawned);
+        Ok(changes)
+    }
+
+    /// The response to a reload request, which is a 200 listing the changes,
+    /// or a 400 with the error
+    pub fn respond(&self) -> (u16, String) {
+        match self.reload() {
+            Ok(changes) if changes.is_empty() => (200, "no changes\n".to_string()),
+            Ok(changes) => {
+                let mut content = String::new();
+                for change in changes.applied {
+                    content.push_str(&format!("{}\n", change));
+                }
+                for section in changes.restart {
+                    content.push_str(&format!("{}: requires a restart to apply\n", section));
+                }
+                (200, content)
+            }
+            Err(e) => {
+                error!("failed to reload config: {}", e);
+                (400, format!("{}\n", e))
+            }
+        }
+    }
+}
+
+/// Take a single sample with each enabled sampler, for `--once`. The returned
+/// handles complete once every sampler has finished
+pub fn sample_enabled_once(common: &Common) -> Vec<JoinHandle<()>> {
+    candidates(common)
+        .into_iter()
+        .filter(|(_, enabled, _, _)| *enabled)
+        .map(|(name, _, _, once)| once(common.named(name)))
+        .collect()
+}
+
+/// Initialize the sampler and take a single sample
+fn spawn_once<S: Sampler + 'static>(common: Common) -> JoinHandle<()> {
+    let handle = common.handle.clone();
+    handle.spawn(async move {
+        let name = common.name();
+        match S::new(common) {
+            Ok(mut sampler) => sampler.sample_once().await,
+            Err(e) => error!("failed to initialize {} sampler: {}", name, e),
+        }
+    })
+}
+
+/// Register a statistic with a summary and outputs for the sampler-specific
+/// percentiles. Distributions, such as those read from BPF histograms, use a
+/// heatmap covering the window, all others use a stream of samples.
+pub fn register_statistic<T: Statistic<AtomicU64, AtomicU32>>(
+    metrics: &Metrics<AtomicU64, AtomicU32>,
+    statistic: &T,
+    percentiles: &[f64],
+    window: usize,
+    samples: usize,
+    max_value: u64,
+    sigfigs: u8,
+) {
+    metrics.add_output(statistic, Output::Reading);
+    if !percentiles.is_empty() {
+        if statistic.source() == Source::Distribution {
+            metrics.set_summary(
+                statistic,
+                Summary::heatmap(
+                    max_value,
+                    sigfigs,
+                    Duration::new(window.try_into().unwrap(), 0),
+                    Duration::new(1, 0),
+                ),
+            );
+        } else {
+            metrics.add_summary(statistic, Summary::stream(samples));
+        }
+    }
+    for percentile in percentiles {
+        metrics.add_output(statistic, Output::Percentile(*percentile));
+    }
+}
+
 #[async_trait]
 pub trait Sampler: Sized + Send {
-    type Statistic: Statistic<AtomicU64, AtomicU32>;
+    type Statistic: Statistic<AtomicU64, AtomicU32> + StatisticUnit;
 
     /// Create a new instance of the sampler
     fn new(common: Common) -> Result<Self, anyhow::Error>;
@@ -64,18 +443,227 @@ pub trait Sampler: Sized + Send {
     /// wait until next sample interval
     async fn sample(&mut self) -> Result<(), std::io::Error>;
 
+    /// Whether the sampler drains BPF histogram maps, which are then drained
+    /// at the general `bpf_poll_interval` if one is configured
+    fn drains_bpf(&self) -> bool {
+        false
+    }
+
+    /// Drain the BPF maps into the histograms without taking a full sample,
+    /// on the ticks between samples when polling more often than sampling
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// Whether BPF is enabled in the config and one or more of the enabled
+    /// statistics are read from BPF
+    fn bpf_enabled(&self) -> bool {
+        false
+    }
+
+    /// Whether the sampler's BPF programs are loaded
+    fn bpf_loaded(&self) -> bool {
+        false
+    }
+
+    /// The BPF tables which the enabled statistics are read from
+    fn bpf_tables(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// Load and attach the sampler's BPF programs, if it has any
+    fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Initialize BPF when the sampler is created. The error is returned
+    /// unless the sampler is fault tolerant, in which case every enabled table
+    /// is reported as unavailable and `retry_bpf` tries again while sampling.
+    fn initialize_bpf_tolerant(&mut self) -> Result<(), anyhow::Error> {
+        if let Err(e) = self.initialize_bpf() {
+            if !self.fault_tolerant() {
+                return Err(e);
+            }
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            for table in self.bpf_tables() {
+                self.common().bpf_table_error(self.common().name(), table);
+            }
+        }
+        Ok(())
+    }
+
+    /// Retry BPF initialization on a backoff if it previously failed or was
+    /// unavailable
+    #[cfg(feature = "bpf")]
+    fn retry_bpf(&mut self) {
+        if !self.bpf_loaded() && self.bpf_enabled() && self.common().bpf_retry_due() {
+            let result = self.initialize_bpf();
+            let initialized = self.bpf_loaded();
+            let name = self.common().name();
+            self.common_mut()
+                .bpf_retry_result(name, result, initialized);
+        }
+    }
+
+    /// The period in ms at which the BPF maps are drained, if it is decoupled
+    /// from the sampling interval
+    fn bpf_poll_interval(&self) -> Option<usize> {
+        if self.drains_bpf() && self.sampler_config().bpf() {
+            self.general_config().bpf_poll_interval()
+        } else {
+            None
+        }
+    }
+
+    /// The period in ms at which the sampler wakes up, which divides the
+    /// interval if the BPF maps are polled more often than it
+    fn tick_interval(&self) -> usize {
+        self.interval() / ticks_per_sample(self.interval(), self.bpf_poll_interval())
+    }
+
+    /// Wait for the next interval, then take a single sample and record
+    /// whether it succeeded for the readiness endpoint, and how long it took.
+    /// This is one iteration of the loop in `run`. A panic while
+    /// sampling is logged and counted as a failed sample, so the loop carries
+    /// on at the next interval. With `skip_if_behind`, a sampler which woke
+    /// up more than an interval late skips the ticks it missed, so that it
+    /// samples once for now instead of in a burst, and counts them. When the
+    /// BPF maps are polled more often than the sampler samples, the ticks in
+    /// between only drain the maps.
+    async fn sample_once(&mut self) {
+        let skip_if_behind = self.general_config().skip_if_behind();
+        let ticks = ticks_per_sample(self.interval(), self.bpf_poll_interval());
+        let period = Duration::from_millis(self.tick_interval() as u64);
+        let mut skipped = 0;
+        if let Some(ref mut delay) = self.delay() {
+            let deadline = delay.tick().await;
+            if skip_if_behind {
+                let lateness = tokio::time::Instant::now().saturating_duration_since(deadline);
+                skipped = missed_ticks(lateness, period);
+                if skipped > 0 {
+                    // keep the phase of the interval, resuming at the first
+                    // deadline which is still in the future
+                    *delay = interval_at(deadline + period * (skipped + 1), period);
+                }
+            }
+        }
+        // the sampler may have been stopped while it waited
+        if !self.common().runnable() {
+            return;
+        }
+        if skipped > 0 {
+            self.common().record_skipped(skipped);
+        }
+        let tick = self.common_mut().next_tick(skipped);
+        let poll_only = !full_sample_due(tick, skipped, ticks);
+        let start = self.common().now();
+        let result = if poll_only {
+            catch_panic(Box::pin(async { self.poll_bpf() })).await
+        } else {
+            catch_panic(self.sample()).await
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                self.common().record_panic(panic_message(payload.as_ref()));
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "sampler panicked",
+                ))
+            }
+        };
+        if !poll_only {
+            let duration = self.common().elapsed(start);
+            self.common().record_sample_duration(start, duration);
+        }
+        if result.is_ok() {
+            self.common().sampled();
+        }
+    }
+
     fn interval(&self) -> usize {
         self.sampler_config()
             .interval()
             .unwrap_or_else(|| self.general_config().interval())
     }
 
-    /// Wait until the next time to sample
+    /// The period between draining BPF maps. Uses the `bpf_poll_interval` if
+    /// one is configured, then the sampler-specific interval if one is
+    /// configured, otherwise the general window. When polling more often than
+    /// sampling, the ticks are at most the poll interval apart, so the maps are
+    /// drained on every one of them.
+    fn effective_interval(&self) -> Duration {
+        match self.bpf_poll_interval() {
+            Some(poll) if poll < self.interval() => Duration::from_millis(0),
+            Some(poll) => Duration::from_millis(poll as u64),
+            None => match self.sampler_config().interval() {
+                Some(millis) => Duration::from_millis(millis as u64),
+                None => Duration::new(self.general_config().window() as u64, 0),
+            },
+        }
+    }
+
+    /// Whether the BPF maps, last drained at `last`, are due to be drained.
+    /// Records the time since the previous drain when they are, and warns if
+    /// it exceeded `max_bpf_staleness`. With `--once` the maps are always
+    /// drained, as there is no later sample to wait for
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn bpf_drain_due(&self, last: &Mutex<Instant>) -> bool {
+        let elapsed = self.common().elapsed(*last.lock().unwrap());
+        if self.common().config().once().is_some() {
+            self.common().record_bpf_drain(elapsed);
+            return true;
+        }
+        let max = self
+            .general_config()
+            .max_bpf_staleness()
+            .map(|millis| Duration::from_millis(millis as u64));
+        match bpf_drain(elapsed, self.effective_interval(), max) {
+            BpfDrain::Wait => return false,
+            BpfDrain::Drain => {}
+            BpfDrain::Stale => {
+                if let Some(note) = self.common().log_limited("bpf_drain") {
+                    warn!(
+                        "{} bpf maps were last drained {} ms ago, forcing a drain{}",
+                        self.common().name(),
+                        elapsed.as_millis(),
+                        note
+                    );
+                }
+            }
+        }
+        self.common().record_bpf_drain(elapsed);
+        true
+    }
+
+    /// Read the bucket counts of the named BPF histogram table. The table is
+    /// cleared, unless the sampler is configured as `cumulative`, in which
+    /// case only the increase since the previous read is returned
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn drain_histogram<M: HistogramMap>(&self, name: &str, map: &mut M) -> HashMap<u64, u32> {
+        if self.sampler_config().cumulative() {
+            let mut totals = self.common().histogram_totals.lock().unwrap();
+            drain_histogram(map, Some(totals.entry(name.to_string()).or_default()))
+        } else {
+            drain_histogram(map, None)
+        }
+    }
+
+    /// Wait until the next time to sample. The interval is recreated if the
+    /// configured period has changed since the last sample. The first tick is
+    /// offset by a random jitter, which shifts the phase of the samples but not
+    /// their rate, to avoid all samplers reading at the same instant
     fn delay(&mut self) -> &mut Option<Interval> {
-        if self.common_mut().interval().is_none() {
-            let millis = self.interval() as u64;
+        let interval = self.interval();
+        self.common_mut().set_sample_interval(interval);
+        let millis = self.tick_interval();
+        if self.common().period() != Some(millis) {
+            let period = Duration::from_millis(millis as u64);
+            let start =
+                tokio::time::Instant::now() + jitter(period, self.general_config().jitter());
             self.common_mut()
-                .set_interval(Some(interval(Duration::from_millis(millis))));
+                .set_interval(Some(interval_at(start, period)));
+            self.common_mut().set_period(Some(millis));
         }
         self.common_mut().interval()
     }
@@ -92,64 +680,114 @@ pub trait Sampler: Sized + Send {
         self.common().config().general()
     }
 
-    /// Register all the statistics
+    /// Sample until shutdown, or until the sampler is stopped because a config
+    /// reload disabled it. This is the loop run by `spawn`. The statistics of
+    /// a stopped sampler keep their last values for the reload grace period,
+    /// then are deregistered unless a later reload enabled the sampler again.
+    async fn run(&mut self) {
+        while self.common().runnable() {
+            self.sample_once().await;
+        }
+        if self.common().stopped() {
+            let grace = self.general_config().reload_grace_period();
+            tokio::time::delay_for(Duration::from_secs(grace as u64)).await;
+            if !self.sampler_config().enabled() {
+                debug!("deregistering {} statistics", self.common().name());
+                self.deregister();
+            }
+        }
+    }
+
+    /// Register all the statistics, except those the running kernel does not
+    /// support
     fn register(&self) {
         for statistic in self.sampler_config().statistics() {
-            self.common()
-                .metrics()
-                .add_output(&statistic, Output::Reading);
-            let percentiles = self.sampler_config().percentiles();
-            if !percentiles.is_empty() {
-                if statistic.source() == Source::Distribution {
-                    self.common().metrics().set_summary(
-                        &statistic,
-                        Summary::heatmap(
-                            1_000_000_000,
-                            2,
-                            Duration::new(
-                                self.common()
-                                    .config()
-                                    .general()
-                                    .window()
-                                    .try_into()
-                                    .unwrap(),
-                                0,
-                            ),
-                            Duration::new(1, 0),
-                        ),
-                    );
-                } else {
-                    self.common()
-                        .metrics()
-                        .add_summary(&statistic, Summary::stream(self.samples()));
-                }
+            if !self.common().supported(statistic.name()) {
+                continue;
             }
-            for percentile in percentiles {
-                self.common()
-                    .metrics()
-                    .add_output(&statistic, Output::Percentile(*percentile));
+            register_statistic(
+                self.common().metrics(),
+                &statistic,
+                self.sampler_config().percentiles(),
+                self.general_config().window(),
+                self.samples(),
+                self.sampler_config().max_value(),
+                self.sampler_config().sigfigs(),
+            );
+            if let Some(unit) = statistic.unit() {
+                self.common().units().insert(statistic.name(), unit);
             }
         }
     }
 
+    /// Remove all the statistics from the registry, including those registered
+    /// for each entity, so that they are no longer exported
+    fn deregister(&self) {
+        for statistic in self.sampler_config().statistics() {
+            self.metrics().deregister(&statistic);
+            self.common().units().remove(statistic.name());
+        }
+        self.common().deregister_labeled();
+    }
+
+    /// The number of readings within the window, which the percentiles of
+    /// counters and gauges are computed from
     fn samples(&self) -> usize {
-        (1000 / self.interval()) * self.general_config().window()
+        std::cmp::max(1, self.general_config().window() * 1000 / self.interval())
     }
 
     fn metrics(&self) -> &Metrics<AtomicU64, AtomicU32> {
         self.common().metrics()
     }
 
+    /// Record a counter reading. The interval is skipped if the counter has
+    /// gone backwards due to a wrap or reset
+    fn record_counter(&self, statistic: &Self::Statistic, time: Instant, value: u64) {
+        self.common().record_counter(statistic, time, value)
+    }
+
+    /// Record a gauge reading
+    fn record_gauge(&self, statistic: &Self::Statistic, time: Instant, value: u64) {
+        self.common().record_gauge(statistic, time, value)
+    }
+
+    /// Record a histogram bucket of one of the sampler's distributions,
+    /// warning the first time a value exceeds the configured `max_value`, as
+    /// the histogram cannot track it
+    fn record_bucket(&self, statistic: &Self::Statistic, time: Instant, value: u64, count: u32) {
+        let max_value = self.sampler_config().max_value();
+        if value > max_value {
+            self.common()
+                .exceeded_max_value(statistic.name(), value, max_value);
+        }
+        self.common().record_bucket(statistic, time, value, count);
+    }
+
+    /// Whether errors are tolerated, see `Config::sampler_fault_tolerant`
+    fn fault_tolerant(&self) -> bool {
+        self.common()
+            .config()
+            .sampler_fault_tolerant(self.sampler_config())
+    }
+
     /// Used to map errors according to fault tolerance
     /// WouldBlock is returned as-is so that async/await behaves as expected
-    /// All other errors are handled per fault tolerance setting
+    /// All other errors are handled per fault tolerance setting. Tolerated
+    /// errors are rate limited by the line which called this, as the message
+    /// of a recurring error may differ each time, eg: by path or pid.
+    #[track_caller]
     fn map_result(&self, result: Result<(), std::io::Error>) -> Result<(), std::io::Error> {
         if let Err(e) = result {
             if e.kind() == std::io::ErrorKind::WouldBlock {
                 return Err(e);
             }
-            if self.common().config().general().fault_tolerant() {
-                debug!("error: {}", e);
+            self.common().sample_failed();
+            if self.fault_tolerant() {
+                let caller = std::panic::Location::caller();
+                let site = format!("{}:{}", caller.file(), caller.line());
+                if let Some(note) = self.common().log_limited(&site) {
+                    warn!("error: {}{}", e, note);
+                }
             } else {
                 fatal!("error: {}", e);
             }
@@ -158,22 +796,159 @@ pub trait Sampler: Sized + Send {
     }
 }
 
+/// Initial and maximum delay between attempts to initialize BPF after a failure
+#[cfg(feature = "bpf")]
+const BPF_RETRY_BASE: Duration = Duration::from_secs(5);
+#[cfg(feature = "bpf")]
+const BPF_RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Minimum time between logging the same error again, see `LogLimiter`
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Self-diagnostic distribution of the time, in nanoseconds, each call to a
+/// sampler's `sample` takes
+pub struct SampleDurationStatistic {
+    name: String,
+}
+
+impl SampleDurationStatistic {
+    pub fn new(sampler: &str) -> Self {
+        Self {
+            name: format!("rezolus/sampler/duration/{}", sampler),
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SampleDurationStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Distribution
+    }
+}
+
+/// The number of intervals a sampler skipped after falling behind
+pub struct SamplerSkippedStatistic {
+    name: String,
+}
+
+impl SamplerSkippedStatistic {
+    pub fn new(sampler: &str) -> Self {
+        Self {
+            name: format!("rezolus/sampler/skipped/{}", sampler),
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SamplerSkippedStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Counter
+    }
+}
+
+/// The number of times a sampler panicked while taking a sample
+pub struct SamplerPanicStatistic {
+    name: String,
+}
+
+impl SamplerPanicStatistic {
+    pub fn new(sampler: &str) -> Self {
+        Self {
+            name: format!("rezolus/sampler/panics/{}", sampler),
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SamplerPanicStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Counter
+    }
+}
+
 pub struct Common {
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    bpf_errors: Arc<DashMap<String, u64>>,
+    #[cfg(feature = "bpf")]
+    bpf_retry: Backoff,
+    buckets: Arc<Buckets>,
+    clock: Arc<dyn Clock>,
     config: Arc<Config>,
+    counters: Arc<CounterTracker>,
+    duration_registered: AtomicBool,
+    entities: Arc<Entities>,
+    exceeded: Mutex<HashSet<String>>,
+    failed: AtomicBool,
     handle: Handle,
     hardware_info: Arc<HardwareInfo>,
+    health: Arc<Health>,
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    histogram_totals: Mutex<HashMap<String, HashMap<u32, u64>>>,
     interval: Option<Interval>,
+    kernel: Option<KernelVersion>,
+    labeled: Mutex<HashMap<String, LabeledStatistic>>,
+    log_limiter: Arc<LogLimiter>,
     metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
+    name: &'static str,
+    panics: Mutex<u64>,
+    period: Option<usize>,
+    proc: Arc<dyn ProcSource>,
+    runnable: Arc<AtomicBool>,
+    sample_interval: Option<usize>,
+    stopped: Arc<AtomicBool>,
+    skipped: Mutex<u64>,
+    ticks: usize,
+    units: Arc<Units>,
+    unsupported: Mutex<HashSet<String>>,
+    updates: Arc<Updates>,
 }
 
 impl Clone for Common {
     fn clone(&self) -> Self {
         Self {
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            bpf_errors: self.bpf_errors.clone(),
+            #[cfg(feature = "bpf")]
+            bpf_retry: Backoff::new(self.clock.now(), BPF_RETRY_BASE, BPF_RETRY_MAX),
+            buckets: self.buckets.clone(),
+            clock: self.clock.clone(),
             config: self.config.clone(),
+            counters: self.counters.clone(),
+            duration_registered: AtomicBool::new(false),
+            entities: self.entities.clone(),
+            exceeded: Mutex::new(HashSet::new()),
+            failed: AtomicBool::new(false),
             handle: self.handle.clone(),
             hardware_info: self.hardware_info.clone(),
+            health: self.health.clone(),
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            histogram_totals: Mutex::new(HashMap::new()),
             interval: None,
+            kernel: self.kernel,
+            labeled: Mutex::new(HashMap::new()),
+            log_limiter: self.log_limiter.clone(),
             metrics: self.metrics.clone(),
+            name: self.name,
+            panics: Mutex::new(0),
+            period: None,
+            proc: self.proc.clone(),
+            runnable: self.runnable.clone(),
+            sample_interval: None,
+            stopped: self.stopped.clone(),
+            skipped: Mutex::new(0),
+            ticks: 0,
+            units: self.units.clone(),
+            unsupported: Mutex::new(HashSet::new()),
+            updates: self.updates.clone(),
         }
     }
 }
@@ -183,24 +958,251 @@ impl Common {
         config: Arc<Config>,
         metrics: Arc<Metrics<AtomicU64, AtomicU32>>,
         handle: Handle,
+        runnable: Arc<AtomicBool>,
+        units: Arc<Units>,
     ) -> Self {
+        let health = Arc::new(Health::new(
+            Duration::from_secs(config.general().readiness_staleness() as u64),
+            config.general().readiness_strict(),
+        ));
+        let counters = Arc::new(CounterTracker::new(config.general().max_counter_delta()));
+        let updates = Arc::new(Updates::new(config.exposition().stale_after()));
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
         Self {
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            bpf_errors: Arc::new(DashMap::new()),
+            #[cfg(feature = "bpf")]
+            bpf_retry: Backoff::new(clock.now(), BPF_RETRY_BASE, BPF_RETRY_MAX),
+            buckets: Arc::new(Buckets::new()),
+            clock,
             config,
+            counters,
+            duration_registered: AtomicBool::new(false),
+            entities: Arc::new(Entities::new()),
+            exceeded: Mutex::new(HashSet::new()),
+            failed: AtomicBool::new(false),
             handle,
             hardware_info: Arc::new(HardwareInfo::new()),
+            health,
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            histogram_totals: Mutex::new(HashMap::new()),
             interval: None,
+            kernel: KernelVersion::current(),
+            labeled: Mutex::new(HashMap::new()),
+            log_limiter: Arc::new(LogLimiter::new(LOG_INTERVAL)),
             metrics,
+            name: "",
+            panics: Mutex::new(0),
+            period: None,
+            proc: Arc::new(Procfs),
+            runnable,
+            sample_interval: None,
+            stopped: Arc::new(AtomicBool::new(false)),
+            skipped: Mutex::new(0),
+            ticks: 0,
+            units,
+            unsupported: Mutex::new(HashSet::new()),
+            updates,
         }
     }
 
+    /// A common with a default config which reads the given fixtures in place
+    /// of the real files, for testing samplers
+    #[cfg(test)]
+    pub fn with_fixtures(fixtures: Fixtures) -> Self {
+        let mut common = Self::new(
+            Arc::new(Config::default()),
+            Arc::new(Metrics::new()),
+            Handle::current(),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(Units::new()),
+        );
+        common.proc = Arc::new(fixtures);
+        common
+    }
+
+    /// The reading of a registered statistic by name, for testing samplers
+    #[cfg(test)]
+    pub fn reading(&self, name: &str) -> Option<u64> {
+        self.metrics
+            .snapshot()
+            .into_iter()
+            .find(|(metric, _)| {
+                metric.statistic().name() == name && matches!(metric.output(), Output::Reading)
+            })
+            .map(|(_, value)| value)
+    }
+
+    /// A copy with the given config in place of the default, for testing
+    #[cfg(test)]
+    pub fn with_config(mut self, config: Arc<Config>) -> Self {
+        self.updates = Arc::new(Updates::new(config.exposition().stale_after()));
+        self.config = config;
+        self
+    }
+
+    /// A copy which reads the time from the given clock, for testing
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        #[cfg(feature = "bpf")]
+        {
+            self.bpf_retry = Backoff::new(clock.now(), BPF_RETRY_BASE, BPF_RETRY_MAX);
+        }
+        self.clock = clock;
+        self
+    }
+
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// The configured labels, which every exporter attaches to each metric
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        self.config.labels()
+    }
+
+    /// The current time, which samplers use for their readings rather than
+    /// `Instant::now()`
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// The time since `earlier`, according to the clock
+    pub fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
     pub fn hardware_info(&self) -> &HardwareInfo {
         &self.hardware_info
     }
 
+    /// The release of the running kernel, if it could be detected
+    pub fn kernel(&self) -> Option<KernelVersion> {
+        self.kernel
+    }
+
+    /// Whether the running kernel is at least the given release. It is
+    /// assumed to be if the release is unknown.
+    pub fn kernel_supports(&self, required: KernelVersion) -> bool {
+        self.kernel.map(|kernel| kernel >= required).unwrap_or(true)
+    }
+
+    /// Drop the statistics which require a newer kernel than the one running,
+    /// so that the BPF probes they need are not attached where they do not
+    /// exist, and they are not registered. Every statistic is kept if the
+    /// kernel release is unknown.
+    pub fn retain_supported<S, F>(&self, statistics: &mut Vec<S>, min_kernel: F)
+    where
+        S: Statistic<AtomicU64, AtomicU32>,
+        F: Fn(&S) -> Option<KernelVersion>,
+    {
+        if let Some(kernel) = self.kernel {
+            let mut unsupported = self.unsupported.lock().unwrap();
+            statistics.retain(|statistic| match min_kernel(statistic) {
+                Some(required) if required > kernel => {
+                    debug!(
+                        "skipping {} which requires kernel {} but {} is running",
+                        statistic.name(),
+                        required,
+                        kernel
+                    );
+                    unsupported.insert(statistic.name().to_string());
+                    false
+                }
+                _ => true,
+            });
+        }
+    }
+
+    /// Whether the statistic was kept by `retain_supported`
+    pub fn supported(&self, name: &str) -> bool {
+        !self.unsupported.lock().unwrap().contains(name)
+    }
+
+    /// Reads the files under `/proc` and `/sys` which samplers parse
+    pub fn proc(&self) -> &dyn ProcSource {
+        self.proc.as_ref()
+    }
+
+    /// The name of the sampler, which is empty until `named` is called
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The sampler health shared by every sampler
+    pub fn health(&self) -> &Arc<Health> {
+        &self.health
+    }
+
+    /// When each statistic was last recorded, shared by every sampler
+    pub fn updates(&self) -> &Arc<Updates> {
+        &self.updates
+    }
+
+    /// The bucket totals of the distributions, shared by every sampler
+    pub fn buckets(&self) -> &Arc<Buckets> {
+        &self.buckets
+    }
+
+    /// The labeled statistics registered by every sampler, which exporters
+    /// render with their labels
+    pub fn entities(&self) -> &Arc<Entities> {
+        &self.entities
+    }
+
+    /// Record that the statistic was updated during the current sample. It
+    /// goes stale after a number of sampling intervals, rather than of the
+    /// ticks between them which only drain the BPF maps
+    fn updated(&self, name: &str, time: Instant) {
+        let interval = self
+            .sample_interval
+            .unwrap_or_else(|| self.config.general().interval());
+        self.updates
+            .update(name, time, Duration::from_millis(interval as u64));
+    }
+
+    /// A copy for the named sampler, which is tracked by the readiness
+    /// endpoint from now on, and which can be stopped on its own
+    pub fn named(&self, name: &'static str) -> Self {
+        self.health.register(name);
+        let mut common = self.clone();
+        common.name = name;
+        common.stopped = Arc::new(AtomicBool::new(false));
+        common
+    }
+
+    /// Stop the sampler at its next interval, after a config reload disabled
+    /// it. It is no longer tracked by the readiness endpoint.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.health.deregister(self.name);
+    }
+
+    /// Whether the sampler was stopped by a config reload
+    pub fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Record that the sampler failed to initialize
+    pub fn init_failed(&self) {
+        self.health.init_failed(self.name);
+    }
+
+    /// Record an error during the current sample
+    pub fn sample_failed(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    /// Record the end of a sample, which was successful if no errors were
+    /// reported while it was taken
+    pub fn sampled(&self) {
+        if self.failed.load(Ordering::Relaxed) {
+            self.failed.store(false, Ordering::Relaxed);
+        } else {
+            self.health.success(self.name, self.now());
+        }
+    }
+
     pub fn interval(&mut self) -> &mut Option<Interval> {
         &mut self.interval
     }
@@ -212,4 +1214,478 @@ impl Common {
     pub fn metrics(&self) -> &Metrics<AtomicU64, AtomicU32> {
         &self.metrics
     }
+
+    /// The period in ms of the current sampling interval
+    pub fn period(&self) -> Option<usize> {
+        self.period
+    }
+
+    pub fn set_period(&mut self, period: Option<usize>) {
+        self.period = period
+    }
+
+    /// Set the period in ms between full samples, which may be a multiple of
+    /// the period at which the sampler wakes up
+    pub fn set_sample_interval(&mut self, interval: usize) {
+        self.sample_interval = Some(interval)
+    }
+
+    /// The tick the sampler has woken up for, counting from zero. The ticks
+    /// which were skipped after it are counted too, so that the count stays in
+    /// phase with the sampling interval
+    pub fn next_tick(&mut self, skipped: u32) -> usize {
+        let tick = self.ticks;
+        self.ticks = self.ticks.wrapping_add(skipped as usize + 1);
+        tick
+    }
+
+    /// The units of the registered statistics
+    pub fn units(&self) -> &Units {
+        &self.units
+    }
+
+    /// Whether the sampler should keep running. Cleared on shutdown, or when
+    /// the sampler is stopped
+    pub fn runnable(&self) -> bool {
+        self.runnable.load(Ordering::Relaxed) && !self.stopped()
+    }
+
+    /// Whether the next attempt to initialize BPF, after it previously failed
+    /// or was unavailable, is due
+    #[cfg(feature = "bpf")]
+    pub fn bpf_retry_due(&self) -> bool {
+        self.bpf_retry.due(self.now())
+    }
+
+    /// Record the outcome of a retried BPF initialization for the named
+    /// sampler, backing off further if it was unsuccessful
+    #[cfg(feature = "bpf")]
+    pub fn bpf_retry_result(
+        &mut self,
+        sampler: &str,
+        result: Result<(), anyhow::Error>,
+        initialized: bool,
+    ) {
+        match result {
+            Ok(()) if initialized => info!("{} bpf initialized after retry", sampler),
+            Ok(()) => debug!("{} bpf is still unavailable", sampler),
+            Err(e) => debug!("failed to initialize {} bpf: {}", sampler, e),
+        }
+        let now = self.now();
+        self.bpf_retry.record(now, initialized);
+    }
+
+    /// Whether an error at the site, which is qualified by the sampler name,
+    /// should be logged now. If so, a note of the identical errors which were
+    /// suppressed since it was last logged is returned to append to the log.
+    pub fn log_limited(&self, site: &str) -> Option<String> {
+        let site = format!("{}/{}", self.name, site);
+        self.log_limiter.check(&site, self.now()).map(suppressed)
+    }
+
+    /// Increment the error count for a BPF table which could not be loaded or
+    /// read by the named sampler
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    pub fn bpf_table_error(&self, sampler: &str, table: &str) {
+        if let Some(note) = self.log_limited(&format!("bpf_table/{}", table)) {
+            warn!(
+                "failed to load or read {} bpf table {}{}",
+                sampler, table, note
+            );
+        }
+        let statistic = BpfTableErrorStatistic::new(sampler, table);
+        let count = {
+            let mut count = self
+                .bpf_errors
+                .entry(statistic.name().to_string())
+                .or_insert(0);
+            *count += 1;
+            *count
+        };
+        self.metrics.register(&statistic);
+        self.metrics.add_output(&statistic, Output::Reading);
+        let _ = self.metrics.record_counter(&statistic, self.now(), count);
+    }
+
+    /// Record the time since the previous drain of the sampler's BPF maps
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    pub fn record_bpf_drain(&self, elapsed: Duration) {
+        let statistic = BpfDrainStatistic::new(self.name);
+        self.metrics.register(&statistic);
+        self.metrics.add_output(&statistic, Output::Reading);
+        let _ = self
+            .metrics
+            .record_gauge(&statistic, self.now(), elapsed.as_nanos() as u64);
+    }
+
+    /// Remove the statistics registered for each entity from the registry
+    pub fn deregister_labeled(&self) {
+        for (name, statistic) in self.labeled.lock().unwrap().drain() {
+            self.metrics.deregister(&statistic);
+            self.units.remove(&name);
+            self.entities.remove(&name);
+        }
+    }
+
+    /// Register the statistic of an entity with a reading the first time the
+    /// entity is seen, returning whether it was newly registered
+    pub fn register_once(&self, statistic: &LabeledStatistic) -> bool {
+        let mut labeled = self.labeled.lock().unwrap();
+        if labeled.contains_key(statistic.name()) {
+            return false;
+        }
+        self.metrics.register(statistic);
+        self.metrics.add_output(statistic, Output::Reading);
+        if let Some(unit) = statistic.unit() {
+            self.units.insert(statistic.name(), unit);
+        }
+        self.entities.insert(statistic);
+        labeled.insert(statistic.name().to_string(), statistic.clone());
+        true
+    }
+
+    /// Warn, once per statistic, that a value was above the maximum its
+    /// histogram can track
+    pub fn exceeded_max_value(&self, statistic: &str, value: u64, max_value: u64) {
+        if self.exceeded.lock().unwrap().insert(statistic.to_string()) {
+            warn!(
+                "{} recorded {} which exceeds the max_value of {}, consider raising samplers.{}.max_value",
+                statistic, value, max_value, self.name
+            );
+        }
+    }
+
+    /// Record the wall time of one call to the sampler's `sample`
+    pub fn record_sample_duration(&self, time: Instant, duration: Duration) {
+        let statistic = SampleDurationStatistic::new(self.name);
+        if !self.duration_registered.load(Ordering::Relaxed) {
+            self.duration_registered.store(true, Ordering::Relaxed);
+            self.metrics.register(&statistic);
+            register_statistic(
+                &self.metrics,
+                &statistic,
+                &crate::common::default_percentiles(),
+                self.config.general().window(),
+                // only used by summaries of non-distributions
+                0,
+                DEFAULT_MAX_VALUE,
+                DEFAULT_SIGFIGS,
+            );
+            self.units.insert(statistic.name(), Unit::Nanoseconds);
+        }
+        let _ = self
+            .metrics
+            .record_bucket(&statistic, time, duration.as_nanos() as u64, 1);
+    }
+
+    /// Log a panic while sampling and count it. The count is recorded directly
+    /// rather than as an update of the sample, so that it is never stale.
+    pub fn record_panic(&self, message: &str) {
+        self.sample_failed();
+        let mut panics = self.panics.lock().unwrap();
+        *panics += 1;
+        let statistic = SamplerPanicStatistic::new(self.name);
+        self.metrics.register(&statistic);
+        self.metrics.add_output(&statistic, Output::Reading);
+        let _ = self.metrics.record_counter(&statistic, self.now(), *panics);
+        if let Some(note) = self.log_limited("panic") {
+            error!(
+                "{} sampler panicked, it will sample again at the next interval: {}{}",
+                self.name, message, note
+            );
+        }
+    }
+
+    /// Count the intervals skipped after the sampler fell behind. The count is
+    /// recorded directly, like the count of panics.
+    pub fn record_skipped(&self, ticks: u32) {
+        let mut skipped = self.skipped.lock().unwrap();
+        *skipped += ticks as u64;
+        let statistic = SamplerSkippedStatistic::new(self.name);
+        self.metrics.register(&statistic);
+        self.metrics.add_output(&statistic, Output::Reading);
+        let _ = self
+            .metrics
+            .record_counter(&statistic, self.now(), *skipped);
+        if let Some(note) = self.log_limited("skipped") {
+            warn!(
+                "{} sampler fell behind and skipped {} intervals{}",
+                self.name, ticks, note
+            );
+        }
+    }
+
+    /// Record a counter reading, skipping the interval if the counter has gone
+    /// backwards since the previous reading
+    pub fn record_counter<T: Statistic<AtomicU64, AtomicU32>>(
+        &self,
+        statistic: &T,
+        time: Instant,
+        value: u64,
+    ) {
+        self.updated(statistic.name(), time);
+        if let Some(value) = self.counters.adjust(statistic.name(), value) {
+            let _ = self.metrics.record_counter(statistic, time, value);
+        } else {
+            debug!("counter reset detected for: {}", statistic.name());
+        }
+    }
+
+    /// Record a gauge reading
+    pub fn record_gauge<T: Statistic<AtomicU64, AtomicU32>>(
+        &self,
+        statistic: &T,
+        time: Instant,
+        value: u64,
+    ) {
+        self.updated(statistic.name(), time);
+        let _ = self.metrics.record_gauge(statistic, time, value);
+    }
+
+    /// Record a histogram bucket of a distribution
+    pub fn record_bucket<T: Statistic<AtomicU64, AtomicU32>>(
+        &self,
+        statistic: &T,
+        time: Instant,
+        value: u64,
+        count: u32,
+    ) {
+        self.updated(statistic.name(), time);
+        self.buckets.record(statistic.name(), value, count as u64);
+        let _ = self.metrics.record_bucket(statistic, time, value, count);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::clock::MockClock;
+    use crate::samplers::tcp::{TcpConfig, TcpStatistic};
+
+    #[tokio::test]
+    async fn test_clock() {
+        let clock = Arc::new(MockClock::new());
+        let common = Common::with_fixtures(Fixtures::default()).with_clock(clock.clone());
+        let start = common.now();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(common.elapsed(start), Duration::from_secs(5));
+        // the copy made for each sampler reads the same clock
+        assert_eq!(common.named("tcp").now(), start + Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let common = Common::with_fixtures(Fixtures::default());
+        let cgroup = common.named("cgroup");
+        let tcp = common.named("tcp");
+        let copy = cgroup.clone();
+        cgroup.stop();
+        // the copies made by the sampler stop with it, but others carry on
+        assert!(!copy.runnable());
+        assert!(copy.stopped());
+        assert!(tcp.runnable());
+        assert!(common.runnable());
+        assert!(common.health().readiness(common.now()).1.contains("tcp"));
+        assert!(!common.health().readiness(common.now()).1.contains("cgroup"));
+    }
+
+    #[tokio::test]
+    async fn test_deregister() {
+        use crate::samplers::cgroup::{Cgroup, CgroupStatistic};
+
+        let common = Common::with_fixtures(Fixtures::default());
+        let sampler = Cgroup::new(common).unwrap();
+        sampler.register();
+        let statistic = CgroupStatistic::Total;
+        sampler.record_gauge(&statistic, sampler.common().now(), 3);
+        assert_eq!(sampler.common().reading(statistic.name()), Some(3));
+        assert!(sampler.common().units().get(statistic.name()).is_some());
+
+        sampler.deregister();
+        assert_eq!(sampler.common().reading(statistic.name()), None);
+        assert_eq!(sampler.common().units().get(statistic.name()), None);
+    }
+
+    #[tokio::test]
+    async fn test_retain_supported() {
+        let mut common = Common::with_fixtures(Fixtures::default());
+        let all = vec![TcpStatistic::ConnectLatency, TcpStatistic::Retransmits];
+
+        // the bpf statistic is skipped on a kernel older than kprobe support
+        common.kernel = Some("3.10.0-1160.el7.x86_64".parse().unwrap());
+        let mut statistics = all.clone();
+        common.retain_supported(&mut statistics, |s| s.min_kernel());
+        assert_eq!(statistics, vec![TcpStatistic::Retransmits]);
+        // and is not registered
+        assert!(!common.supported(TcpStatistic::ConnectLatency.name()));
+        assert!(common.supported(TcpStatistic::Retransmits.name()));
+        assert!(!common.kernel_supports(crate::common::kernel::KPROBES));
+
+        common.kernel = Some("5.4.0-42-generic".parse().unwrap());
+        let mut statistics = all.clone();
+        common.retain_supported(&mut statistics, |s| s.min_kernel());
+        assert_eq!(statistics, all);
+
+        // nothing is skipped if the release could not be detected
+        common.kernel = None;
+        let mut statistics = all.clone();
+        common.retain_supported(&mut statistics, |s| s.min_kernel());
+        assert_eq!(statistics, all);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let period = Duration::from_millis(1000);
+        assert_eq!(jitter(period, 0.0), Duration::from_millis(0));
+        for _ in 0..100 {
+            assert!(jitter(period, 0.1) <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_missed_ticks() {
+        let period = Duration::from_millis(1000);
+        assert_eq!(missed_ticks(Duration::from_millis(0), period), 0);
+        assert_eq!(missed_ticks(Duration::from_millis(999), period), 0);
+        assert_eq!(missed_ticks(Duration::from_millis(1000), period), 1);
+        assert_eq!(missed_ticks(Duration::from_millis(3500), period), 3);
+        assert_eq!(
+            missed_ticks(Duration::from_millis(3500), Duration::from_millis(0)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_ticks_per_sample() {
+        assert_eq!(ticks_per_sample(1000, None), 1);
+        assert_eq!(ticks_per_sample(1000, Some(1000)), 1);
+        assert_eq!(ticks_per_sample(1000, Some(5000)), 1);
+        assert_eq!(ticks_per_sample(1000, Some(250)), 4);
+        // ticks are never further apart than the poll interval
+        assert_eq!(ticks_per_sample(1000, Some(300)), 4);
+        assert_eq!(ticks_per_sample(1000, Some(0)), 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_sample_due() {
+        assert!(full_sample_due(0, 0, 4));
+        assert!(!full_sample_due(1, 0, 4));
+        // a wake-up which missed the first tick of the interval samples
+        assert!(full_sample_due(2, 2, 4));
+        assert!(!full_sample_due(1, 2, 4));
+        assert!(full_sample_due(7, 0, 1));
+
+        // the ticks skipped by a sampler which keeps falling behind are
+        // counted, so it still samples on every interval boundary
+        let mut common = Common::with_fixtures(Fixtures::default());
+        let mut samples = 0;
+        for _ in 0..4 {
+            let tick = common.next_tick(1);
+            if full_sample_due(tick, 1, 4) {
+                samples += 1;
+            }
+        }
+        assert_eq!(common.next_tick(0), 8);
+        assert_eq!(samples, 2);
+    }
+
+    #[tokio::test]
+    async fn test_record_gauge() {
+        let common = Common::with_fixtures(Fixtures::default());
+        let counter = TcpStatistic::ReceiveSegments;
+        let gauge = TcpStatistic::SocketsInuse;
+        for statistic in &[counter, gauge] {
+            common.metrics().register(statistic);
+            common.metrics().add_output(statistic, Output::Reading);
+        }
+        let start = common.now();
+        common.record_counter(&counter, start, 100);
+        common.record_gauge(&gauge, start, 25);
+        let later = start + Duration::from_secs(1);
+        // a counter which goes down has been reset, so the interval is skipped,
+        // while a gauge which goes down is recorded as it is
+        common.record_counter(&counter, later, 40);
+        common.record_gauge(&gauge, later, 12);
+        assert_eq!(common.reading(counter.name()), Some(100));
+        assert_eq!(common.reading(gauge.name()), Some(12));
+    }
+
+    #[tokio::test]
+    async fn test_staleness_interval() {
+        let config = Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\ninterval = 1000\nbpf_poll_interval = 250\n\
+             [exposition]\nstale_after = 3\n[samplers.tcp]\nbpf = true\n",
+        )
+        .unwrap();
+        let common = Common::with_fixtures(Fixtures::default()).with_config(Arc::new(config));
+        let mut sampler = crate::samplers::tcp::Tcp::new(common).unwrap();
+        sampler.delay();
+        assert_eq!(sampler.common().period(), Some(250));
+
+        // series are stale after three samples, not three bpf polls
+        let statistic = TcpStatistic::SocketsInuse;
+        let start = sampler.common().now();
+        sampler.record_gauge(&statistic, start, 25);
+        let updates = sampler.common().updates();
+        assert!(!updates.is_stale(statistic.name(), start + Duration::from_millis(2000)));
+        assert!(updates.is_stale(statistic.name(), start + Duration::from_millis(3500)));
+    }
+
+    #[tokio::test]
+    async fn test_record_skipped() {
+        let common = Common::with_fixtures(Fixtures::default()).named("tcp");
+        common.record_skipped(3);
+        common.record_skipped(1);
+        assert_eq!(common.reading("rezolus/sampler/skipped/tcp"), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_record_panic() {
+        let common = Common::with_fixtures(Fixtures::default()).named("tcp");
+        common.record_panic("index out of bounds");
+        common.record_panic("index out of bounds");
+        assert_eq!(common.reading("rezolus/sampler/panics/tcp"), Some(2));
+        // the sample in which the panic happened is not counted as a success
+        assert!(common.failed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_bpf_percentiles() {
+        let config: TcpConfig = toml::from_str(
+            r#"
+            bpf = true
+            percentiles = [50.0, 90.0, 99.0, 99.9]
+            "#,
+        )
+        .unwrap();
+        let statistic = TcpStatistic::ConnectLatency;
+        assert!(config.statistics().contains(&statistic));
+
+        let metrics = Metrics::<AtomicU64, AtomicU32>::new();
+        register_statistic(
+            &metrics,
+            &statistic,
+            config.percentiles(),
+            60,
+            60,
+            config.max_value(),
+            config.sigfigs(),
+        );
+        let time = Instant::now();
+        for value in 1..=100 {
+            let _ = metrics.record_bucket(&statistic, time, value * 1000, 1);
+        }
+
+        let mut percentiles: Vec<f64> = metrics
+            .snapshot()
+            .keys()
+            .filter(|metric| metric.statistic().name() == statistic.name())
+            .filter_map(|metric| match metric.output() {
+                Output::Percentile(percentile) => Some(percentile),
+                Output::Reading => None,
+            })
+            .collect();
+        percentiles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(percentiles, vec![50.0, 90.0, 99.0, 99.9]);
+    }
 }
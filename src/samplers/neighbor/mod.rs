@@ -0,0 +1,196 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use rustcommon_metrics::*;
+
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// Reads the statistics of the ARP and IPv6 neighbor discovery tables from
+/// `/proc/net/stat`, including the number of entries and how often the tables
+/// have overflowed
+pub struct Neighbor {
+    common: Common,
+    statistics: Vec<NeighborStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Neighbor {
+    type Statistic = NeighborStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().neighbor().statistics();
+        let sampler = Self { common, statistics };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().neighbor().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().neighbor())
+            {
+                fatal!("failed to initialize neighbor sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize neighbor sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().neighbor()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_table(Table::Arp).await;
+        self.map_result(r)?;
+
+        let r = self.sample_table(Table::Ndisc).await;
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl Neighbor {
+    async fn sample_table(&mut self, table: Table) -> Result<(), std::io::Error> {
+        if !self.statistics.iter().any(|s| s.table() == table) {
+            return Ok(());
+        }
+        let content = match self.common.proc().read_to_string(table.path()).await {
+            Ok(content) => content,
+            // the ndisc table is absent when IPv6 is disabled
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let time = self.common.now();
+        let values = parse_neighbor_stats(&content, table.path())?;
+
+        for statistic in self.statistics.iter().filter(|s| s.table() == table) {
+            if let Some(value) = values.get(statistic.column()) {
+                match statistic.source() {
+                    Source::Counter => {
+                        self.record_counter(statistic, time, *value);
+                    }
+                    Source::Gauge => {
+                        self.record_gauge(statistic, time, *value);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse one of the files under `/proc/net/stat` which hold the statistics of
+/// a neighbor table. The first line names the columns and each further line
+/// holds the values, in hex, for one cpu. The counters are summed across the
+/// cpus, but `entries` is the size of the whole table and is repeated on each
+/// line, so it is taken from the first.
+fn parse_neighbor_stats(content: &str, path: &str) -> Result<HashMap<String, u64>, Error> {
+    let invalid = |reason: &str| Error::new(ErrorKind::InvalidData, format!("{} {}", path, reason));
+    let mut lines = content.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| invalid("is missing its header"))?
+        .split_whitespace()
+        .collect();
+
+    let mut values = HashMap::new();
+    for (cpu, line) in lines.filter(|line| !line.trim().is_empty()).enumerate() {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() != header.len() {
+            return Err(invalid(&format!("has a malformed row for cpu {}", cpu)));
+        }
+        for (name, value) in header.iter().zip(columns) {
+            let value = u64::from_str_radix(value, 16)
+                .map_err(|_| invalid(&format!("has a malformed {} for cpu {}", name, cpu)))?;
+            if *name == "entries" {
+                values.entry(name.to_string()).or_insert(value);
+            } else {
+                *values.entry(name.to_string()).or_insert(0) += value;
+            }
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+    use rustcommon_metrics::*;
+
+    const ARP_CACHE: &str = "entries  allocs   destroys hash_grows lookups  hits     res_failed rcv_probes_mcast rcv_probes_ucast periodic_gc_runs forced_gc_runs unresolved_discards table_fulls
+0000000c 00000010 00000004 00000001 00000100 000000f0 00000002 00000000 00000000 00000020 00000000 00000001 00000000
+0000000c 00000008 00000002 00000000 00000080 00000070 00000001 00000000 00000000 00000000 00000003 00000000 00000005
+";
+
+    #[test]
+    fn test_parse_neighbor_stats() {
+        let values = parse_neighbor_stats(ARP_CACHE, "arp_cache").unwrap();
+        assert_eq!(values.get("entries"), Some(&12));
+        assert_eq!(values.get("allocs"), Some(&24));
+        assert_eq!(values.get("lookups"), Some(&384));
+        assert_eq!(values.get("forced_gc_runs"), Some(&3));
+        assert_eq!(values.get("table_fulls"), Some(&5));
+
+        assert!(parse_neighbor_stats("", "arp_cache").is_err());
+        assert!(parse_neighbor_stats("entries allocs\n0000000c\n", "arp_cache").is_err());
+        assert!(parse_neighbor_stats("entries allocs\n0000000c zz\n", "arp_cache").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sample_table() {
+        let common =
+            Common::with_fixtures(Fixtures::default().with("/proc/net/stat/arp_cache", ARP_CACHE));
+        let mut sampler = Neighbor::new(common).unwrap();
+        sampler.register();
+        sampler.sample_table(Table::Arp).await.unwrap();
+        // without IPv6 there is no ndisc table, which is not an error
+        sampler.sample_table(Table::Ndisc).await.unwrap();
+
+        assert_eq!(sampler.common().reading("neighbor/arp/entries"), Some(12));
+        assert_eq!(sampler.common().reading("neighbor/arp/hits"), Some(352));
+        assert_eq!(
+            sampler.common().reading("neighbor/arp/table_fulls"),
+            Some(5)
+        );
+        assert_eq!(sampler.common().reading("neighbor/ndisc/entries"), None);
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::StatisticUnit;
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// The neighbor tables, each of which has its statistics in a file under
+/// `/proc/net/stat`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Table {
+    /// IPv4 ARP
+    Arp,
+    /// IPv6 neighbor discovery
+    Ndisc,
+}
+
+impl Table {
+    pub fn path(self) -> &'static str {
+        match self {
+            Self::Arp => "/proc/net/stat/arp_cache",
+            Self::Ndisc => "/proc/net/stat/ndisc_cache",
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum NeighborStatistic {
+    #[strum(serialize = "neighbor/arp/entries")]
+    ArpEntries,
+    #[strum(serialize = "neighbor/arp/allocations")]
+    ArpAllocations,
+    #[strum(serialize = "neighbor/arp/destroys")]
+    ArpDestroys,
+    #[strum(serialize = "neighbor/arp/hash_grows")]
+    ArpHashGrows,
+    #[strum(serialize = "neighbor/arp/lookups")]
+    ArpLookups,
+    #[strum(serialize = "neighbor/arp/hits")]
+    ArpHits,
+    #[strum(serialize = "neighbor/arp/resolution_failures")]
+    ArpResolutionFailures,
+    #[strum(serialize = "neighbor/arp/gc/periodic")]
+    ArpPeriodicGc,
+    #[strum(serialize = "neighbor/arp/gc/forced")]
+    ArpForcedGc,
+    #[strum(serialize = "neighbor/arp/unresolved_discards")]
+    ArpUnresolvedDiscards,
+    #[strum(serialize = "neighbor/arp/table_fulls")]
+    ArpTableFulls,
+    #[strum(serialize = "neighbor/ndisc/entries")]
+    NdiscEntries,
+    #[strum(serialize = "neighbor/ndisc/allocations")]
+    NdiscAllocations,
+    #[strum(serialize = "neighbor/ndisc/destroys")]
+    NdiscDestroys,
+    #[strum(serialize = "neighbor/ndisc/hash_grows")]
+    NdiscHashGrows,
+    #[strum(serialize = "neighbor/ndisc/lookups")]
+    NdiscLookups,
+    #[strum(serialize = "neighbor/ndisc/hits")]
+    NdiscHits,
+    #[strum(serialize = "neighbor/ndisc/resolution_failures")]
+    NdiscResolutionFailures,
+    #[strum(serialize = "neighbor/ndisc/gc/periodic")]
+    NdiscPeriodicGc,
+    #[strum(serialize = "neighbor/ndisc/gc/forced")]
+    NdiscForcedGc,
+    #[strum(serialize = "neighbor/ndisc/unresolved_discards")]
+    NdiscUnresolvedDiscards,
+    #[strum(serialize = "neighbor/ndisc/table_fulls")]
+    NdiscTableFulls,
+}
+
+impl NeighborStatistic {
+    pub fn table(self) -> Table {
+        match self {
+            Self::ArpEntries
+            | Self::ArpAllocations
+            | Self::ArpDestroys
+            | Self::ArpHashGrows
+            | Self::ArpLookups
+            | Self::ArpHits
+            | Self::ArpResolutionFailures
+            | Self::ArpPeriodicGc
+            | Self::ArpForcedGc
+            | Self::ArpUnresolvedDiscards
+            | Self::ArpTableFulls => Table::Arp,
+            _ => Table::Ndisc,
+        }
+    }
+
+    /// The name of the statistic's column in the header of its table's file
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::ArpEntries | Self::NdiscEntries => "entries",
+            Self::ArpAllocations | Self::NdiscAllocations => "allocs",
+            Self::ArpDestroys | Self::NdiscDestroys => "destroys",
+            Self::ArpHashGrows | Self::NdiscHashGrows => "hash_grows",
+            Self::ArpLookups | Self::NdiscLookups => "lookups",
+            Self::ArpHits | Self::NdiscHits => "hits",
+            Self::ArpResolutionFailures | Self::NdiscResolutionFailures => "res_failed",
+            Self::ArpPeriodicGc | Self::NdiscPeriodicGc => "periodic_gc_runs",
+            Self::ArpForcedGc | Self::NdiscForcedGc => "forced_gc_runs",
+            Self::ArpUnresolvedDiscards | Self::NdiscUnresolvedDiscards => "unresolved_discards",
+            Self::ArpTableFulls | Self::NdiscTableFulls => "table_fulls",
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for NeighborStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        match self {
+            Self::ArpEntries | Self::NdiscEntries => Source::Gauge,
+            _ => Source::Counter,
+        }
+    }
+}
+
+impl StatisticUnit for NeighborStatistic {}
+
+impl TryFrom<&str> for NeighborStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        NeighborStatistic::from_str(s)
+    }
+}
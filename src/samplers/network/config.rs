@@ -6,7 +6,7 @@ use rustcommon_atomics::*;
 use serde_derive::Deserialize;
 use strum::IntoEnumIterator;
 
-use crate::config::SamplerConfig;
+use crate::config::{SamplerConfig, DEFAULT_MAX_VALUE, DEFAULT_SIGFIGS};
 
 use super::stat::*;
 
@@ -16,11 +16,31 @@ pub struct NetworkConfig {
     #[serde(default)]
     bpf: AtomicBool,
     #[serde(default)]
+    cumulative: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<NetworkStatistic>,
+    #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(default)]
+    interfaces: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_nanoseconds"
+    )]
+    max_value: Option<u64>,
+    #[serde(default)]
+    per_interface: AtomicBool,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
+    #[serde(default)]
+    sigfigs: Option<u8>,
     #[serde(default = "default_statistics")]
     statistics: Vec<NetworkStatistic>,
 }
@@ -29,14 +49,33 @@ impl Default for NetworkConfig {
     fn default() -> Self {
         Self {
             bpf: Default::default(),
+            cumulative: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
+            interfaces: Default::default(),
+            max_value: Default::default(),
+            per_interface: Default::default(),
             percentiles: crate::common::default_percentiles(),
+            sigfigs: Default::default(),
             statistics: default_statistics(),
         }
     }
 }
 
+impl NetworkConfig {
+    /// the interfaces to include, if empty all interfaces are included
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// whether to also export the counters for each interface
+    pub fn per_interface(&self) -> bool {
+        self.per_interface.load(Ordering::Relaxed)
+    }
+}
+
 fn default_statistics() -> Vec<NetworkStatistic> {
     NetworkStatistic::iter().collect()
 }
@@ -48,18 +87,41 @@ impl SamplerConfig for NetworkConfig {
         self.bpf.load(Ordering::Relaxed)
     }
 
+    fn cumulative(&self) -> bool {
+        self.cumulative.load(Ordering::Relaxed)
+    }
+
     fn enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
 
+    fn max_value(&self) -> u64 {
+        self.max_value.unwrap_or(DEFAULT_MAX_VALUE)
+    }
+
     fn percentiles(&self) -> &[f64] {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn sigfigs(&self) -> u8 {
+        self.sigfigs.unwrap_or(DEFAULT_SIGFIGS)
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         let mut enabled = Vec::new();
         for statistic in self.statistics.iter() {
@@ -71,6 +133,7 @@ impl SamplerConfig for NetworkConfig {
                 enabled.push(statistic.clone());
             }
         }
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
         enabled
     }
 }
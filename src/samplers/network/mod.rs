@@ -7,10 +7,13 @@ use std::sync::{Arc, Mutex};
 use std::time::*;
 
 use async_trait::async_trait;
+use rustcommon_metrics::*;
+use strum::IntoEnumIterator;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
 use crate::common::bpf::*;
+use crate::common::labeled::LabeledStatistic;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
 use crate::Sampler;
@@ -34,22 +37,17 @@ impl Sampler for Network {
     type Statistic = NetworkStatistic;
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().network().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
             common,
             statistics,
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
@@ -62,13 +60,15 @@ impl Sampler for Network {
         if common.config().samplers().network().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().network())
+            {
                 fatal!("failed to initialize network sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize network sampler");
             }
         }
@@ -87,24 +87,34 @@ impl Sampler for Network {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
 
         debug!("sampling");
-        self.map_result(self.sample_proc_net_dev().await)?;
+        let r = self.sample_proc_net_dev().await;
+        self.map_result(r)?;
+        #[cfg(feature = "bpf")]
+        self.retry_bpf();
         #[cfg(feature = "bpf")]
         self.map_result(self.sample_bpf())?;
 
         Ok(())
     }
-}
 
-impl Network {
+    fn drains_bpf(&self) -> bool {
+        cfg!(feature = "bpf")
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+        #[cfg(feature = "bpf")]
+        self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
     // checks that bpf is enabled in config and one or more bpf stats enabled
     #[cfg(feature = "bpf")]
     fn bpf_enabled(&self) -> bool {
@@ -118,9 +128,24 @@ impl Network {
         false
     }
 
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
         #[cfg(feature = "bpf")]
         {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
             if self.enabled() && self.bpf_enabled() {
                 debug!("initializing bpf");
                 // load the code and compile
@@ -144,37 +169,51 @@ impl Network {
 
         Ok(())
     }
+}
 
-    async fn sample_proc_net_dev(&self) -> Result<(), std::io::Error> {
+impl Network {
+    async fn sample_proc_net_dev(&mut self) -> Result<(), std::io::Error> {
         // sample /proc/net/dev
         let file = File::open("/proc/net/dev").await?;
         let reader = BufReader::new(file);
         let mut lines = reader.lines();
 
         let mut result = HashMap::new();
+        let mut interfaces = Vec::new();
+        let config = self.common.config().samplers().network();
 
         while let Some(line) = lines.next_line().await? {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if !parts.is_empty() && parts[1].parse::<u64>().is_ok() {
-                for statistic in &self.statistics {
-                    if let Some(field) = statistic.field_number() {
-                        if !result.contains_key(statistic) {
-                            result.insert(statistic, 0);
-                        }
-                        let current = result.get_mut(statistic).unwrap();
-                        *current += parts
-                            .get(field)
-                            .map(|v| v.parse().unwrap_or(0))
-                            .unwrap_or(0);
-                    }
+            if let Some((interface, values)) = parse_line(&line) {
+                if !config.interfaces().is_empty()
+                    && !config.interfaces().iter().any(|v| *v == interface)
+                {
+                    continue;
+                }
+                for (statistic, value) in &values {
+                    *result.entry(*statistic).or_insert(0) += value;
                 }
+                interfaces.push((interface, values));
             }
         }
 
-        let time = Instant::now();
+        // interfaces may come and go between samples, the counter tracking
+        // treats the resulting decrease in the totals as a reset
+        let time = self.common.now();
         for statistic in &self.statistics {
             if let Some(value) = result.get(statistic) {
-                let _ = self.metrics().record_counter(statistic, time, *value);
+                self.record_counter(statistic, time, *value);
+            }
+        }
+        if config.per_interface() {
+            for (interface, values) in interfaces {
+                for (stat, value) in values {
+                    if !self.statistics.contains(&stat) {
+                        continue;
+                    }
+                    let statistic = LabeledStatistic::new(&stat, "interface", &interface);
+                    self.common.register_once(&statistic);
+                    self.common.record_counter(&statistic, time, value);
+                }
             }
         }
         Ok(())
@@ -182,24 +221,83 @@ impl Network {
 
     #[cfg(feature = "bpf")]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        if self.bpf_last.lock().unwrap().elapsed()
-            >= Duration::new(self.general_config().window() as u64, 0)
-        {
-            let time = Instant::now();
+        if self.bpf_drain_due(&self.bpf_last) {
+            let time = self.common.now();
             if let Some(ref bpf) = self.bpf {
                 let bpf = bpf.lock().unwrap();
                 for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
                     if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                        for (&value, &count) in &map_from_table(&mut table) {
+                        let table =
+                            self.drain_histogram(statistic.bpf_table().unwrap(), &mut table);
+                        for (&value, &count) in &table {
                             if count > 0 {
-                                let _ = self.metrics().record_bucket(statistic, time, value, count);
+                                self.record_bucket(
+                                    statistic,
+                                    time,
+                                    value * statistic.bucket_scale(),
+                                    count,
+                                );
                             }
                         }
+                    } else {
+                        self.common
+                            .bpf_table_error("network", statistic.bpf_table().unwrap());
                     }
                 }
             }
-            *self.bpf_last.lock().unwrap() = Instant::now();
+            *self.bpf_last.lock().unwrap() = self.common.now();
         }
         Ok(())
     }
 }
+
+/// Parses an interface row of `/proc/net/dev` into the interface name and the
+/// values of the counters it provides. The name is followed by a colon which
+/// is not always followed by whitespace, as wide values may abut it. The
+/// header rows have no colon and are skipped.
+fn parse_line(line: &str) -> Option<(String, Vec<(NetworkStatistic, u64)>)> {
+    let mut split = line.splitn(2, ':');
+    let interface = split.next()?.trim();
+    let fields: Vec<&str> = split.next()?.split_whitespace().collect();
+    if interface.is_empty() || fields.first()?.parse::<u64>().is_err() {
+        return None;
+    }
+    let mut values = Vec::new();
+    for statistic in NetworkStatistic::iter() {
+        if let Some(field) = statistic.field_number() {
+            if let Some(Ok(value)) = fields.get(field - 1).map(|v| v.parse()) {
+                values.push((statistic, value));
+            }
+        }
+    }
+    Some((interface.to_string(), values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let (interface, values) =
+            parse_line("  eth0: 123456 789 1 2 0 0 0 3 654321 987 4 5 0 0 0 0").unwrap();
+        assert_eq!(interface, "eth0");
+        assert_eq!(values.len(), 16);
+        assert!(values.contains(&(NetworkStatistic::ReceiveBytes, 123456)));
+        assert!(values.contains(&(NetworkStatistic::ReceiveMulticast, 3)));
+        assert!(values.contains(&(NetworkStatistic::TransmitBytes, 654321)));
+        assert!(values.contains(&(NetworkStatistic::TransmitDrops, 5)));
+
+        let (interface, values) =
+            parse_line("bond0:12345678901234 5 0 0 0 0 0 0 1 2 0 0 0 0 0 0").unwrap();
+        assert_eq!(interface, "bond0");
+        assert!(values.contains(&(NetworkStatistic::ReceiveBytes, 12345678901234)));
+
+        assert!(parse_line("Inter-|   Receive                            |  Transmit").is_none());
+        assert!(parse_line(
+            " face |bytes    packets errs drop fifo frame compressed multicast|bytes"
+        )
+        .is_none());
+        assert!(parse_line("").is_none());
+    }
+}
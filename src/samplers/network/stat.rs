@@ -5,6 +5,8 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, TRACEPOINTS};
+use crate::common::unit::StatisticUnit;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -93,6 +95,20 @@ impl NetworkStatistic {
             _ => None,
         }
     }
+
+    /// The oldest kernel able to trace the statistic, which uses the net
+    /// tracepoints
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| TRACEPOINTS)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic. Packet sizes are already in bytes
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        1
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for NetworkStatistic {
@@ -109,6 +125,8 @@ impl Statistic<AtomicU64, AtomicU32> for NetworkStatistic {
     }
 }
 
+impl StatisticUnit for NetworkStatistic {}
+
 impl TryFrom<&str> for NetworkStatistic {
     type Error = ParseError;
 
@@ -0,0 +1,157 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::*;
+use serde_derive::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+/// How each GPU is identified in the labels of its statistics
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuId {
+    /// The index of the GPU as enumerated by NVML, eg: `gpu="0"`
+    Index,
+    /// The UUID of the GPU, eg: `uuid="GPU-5fd8e4a7-..."`, which is stable
+    /// across reboots and changes to the set of GPUs
+    Uuid,
+    /// Both the index and the UUID
+    Both,
+}
+
+impl GpuId {
+    /// The labels of the statistics of the GPU with the index and UUID
+    pub fn labels(self, index: u32, uuid: &str) -> Vec<(&'static str, String)> {
+        match self {
+            Self::Index => vec![("gpu", index.to_string())],
+            Self::Uuid => vec![("uuid", uuid.to_string())],
+            Self::Both => vec![("gpu", index.to_string()), ("uuid", uuid.to_string())],
+        }
+    }
+}
+
+impl Default for GpuId {
+    fn default() -> Self {
+        Self::Both
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NvidiaConfig {
+    #[serde(default)]
+    disabled: Vec<NvidiaStatistic>,
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(default)]
+    gpu_id: GpuId,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+    #[serde(default = "default_statistics")]
+    statistics: Vec<NvidiaStatistic>,
+}
+
+impl Default for NvidiaConfig {
+    fn default() -> Self {
+        Self {
+            disabled: Default::default(),
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            gpu_id: Default::default(),
+            interval: Default::default(),
+            percentiles: crate::common::default_percentiles(),
+            statistics: default_statistics(),
+        }
+    }
+}
+
+impl NvidiaConfig {
+    /// how each GPU is identified in the names of its statistics
+    pub fn gpu_id(&self) -> GpuId {
+        self.gpu_id
+    }
+}
+
+fn default_statistics() -> Vec<NvidiaStatistic> {
+    NvidiaStatistic::iter().collect()
+}
+
+impl SamplerConfig for NvidiaConfig {
+    type Statistic = NvidiaStatistic;
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = NvidiaConfig::default();
+        assert_eq!(config.gpu_id(), GpuId::Both);
+        assert_eq!(config.statistics().len(), 6);
+
+        let config: NvidiaConfig = toml::from_str(
+            r#"
+            gpu_id = "uuid"
+            disabled = ["nvidia/power/usage"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.gpu_id(), GpuId::Uuid);
+        assert!(!config.statistics().contains(&NvidiaStatistic::PowerUsage));
+        assert!(config.statistics().contains(&NvidiaStatistic::MemoryUsed));
+    }
+
+    #[test]
+    fn test_labels() {
+        let uuid = "GPU-5fd8e4a7-1c2b-4d3e-8f90-a1b2c3d4e5f6";
+        assert_eq!(GpuId::Index.labels(1, uuid), vec![("gpu", "1".to_string())]);
+        assert_eq!(
+            GpuId::Uuid.labels(1, uuid),
+            vec![("uuid", uuid.to_string())]
+        );
+        assert_eq!(
+            GpuId::Both.labels(1, uuid),
+            vec![("gpu", "1".to_string()), ("uuid", uuid.to_string())]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use async_trait::async_trait;
+#[cfg(feature = "nvml")]
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+#[cfg(feature = "nvml")]
+use nvml_wrapper::{Device, Nvml};
+
+#[cfg(feature = "nvml")]
+use crate::common::labeled::LabeledStatistic;
+use crate::config::SamplerConfig;
+#[cfg(feature = "nvml")]
+use crate::samplers::register_statistic;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// Reads the utilization, memory, temperature, and power draw of each NVIDIA
+/// GPU through NVML, which is loaded from the driver at runtime
+#[allow(dead_code)]
+pub struct Nvidia {
+    common: Common,
+    #[cfg(feature = "nvml")]
+    nvml: Option<Nvml>,
+    statistics: Vec<NvidiaStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Nvidia {
+    type Statistic = NvidiaStatistic;
+
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().nvidia().statistics();
+        #[allow(unused_mut)]
+        let mut sampler = Self {
+            common,
+            #[cfg(feature = "nvml")]
+            nvml: None,
+            statistics,
+        };
+
+        // the statistics of each gpu are registered once it has been found, as
+        // the number of gpus isn't known in advance
+        if sampler.sampler_config().enabled() {
+            #[cfg(feature = "nvml")]
+            {
+                match Nvml::init() {
+                    Ok(nvml) => sampler.nvml = Some(nvml),
+                    Err(e) => {
+                        if !sampler.fault_tolerant() {
+                            return Err(format_err!("failed to initialize nvml: {}", e));
+                        }
+                        warn!("failed to initialize nvml, gpus will not be sampled: {}", e);
+                    }
+                }
+            }
+            #[cfg(not(feature = "nvml"))]
+            {
+                if !sampler.fault_tolerant() {
+                    return Err(format_err!(
+                        "nvidia sampler requires building with the `nvml` feature"
+                    ));
+                }
+                warn!("rezolus was built without the `nvml` feature, gpus will not be sampled");
+            }
+        }
+
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().nvidia().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().nvidia())
+            {
+                fatal!("failed to initialize nvidia sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize nvidia sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().nvidia()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        #[cfg(feature = "nvml")]
+        {
+            let r = self.sample_nvml();
+            self.map_result(r)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "nvml")]
+impl Nvidia {
+    fn sample_nvml(&mut self) -> Result<(), std::io::Error> {
+        let nvml = match &self.nvml {
+            Some(nvml) => nvml,
+            None => return Ok(()),
+        };
+        let count = nvml.device_count().map_err(nvml_error)?;
+        let time = self.common.now();
+        for index in 0..count {
+            let device = nvml.device_by_index(index).map_err(nvml_error)?;
+            let gpu_id = self.common.config().samplers().nvidia().gpu_id();
+            let labels = match gpu_id {
+                GpuId::Index => gpu_id.labels(index, ""),
+                _ => gpu_id.labels(index, &device.uuid().map_err(nvml_error)?),
+            };
+            for statistic in &self.statistics {
+                // not every gpu supports every query, eg: power draw on older
+                // models, so those which fail are skipped
+                let value = match read(&device, *statistic) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        debug!(
+                            "failed to read {} for gpu {}: {}",
+                            statistic.name(),
+                            index,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                let statistic = LabeledStatistic::with_labels(statistic, &labels);
+                if self.common.register_once(&statistic) {
+                    // only the reading is registered, the percentiles need a summary
+                    register_statistic(
+                        self.common.metrics(),
+                        &statistic,
+                        self.sampler_config().percentiles(),
+                        self.general_config().window(),
+                        self.samples(),
+                        self.sampler_config().max_value(),
+                        self.sampler_config().sigfigs(),
+                    );
+                }
+                self.common.record_gauge(&statistic, time, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The current value of the statistic for the gpu. Temperature is in degrees
+/// Celsius and power draw in milliwatts, as reported by NVML.
+#[cfg(feature = "nvml")]
+fn read(
+    device: &Device,
+    statistic: NvidiaStatistic,
+) -> Result<u64, nvml_wrapper::error::NvmlError> {
+    Ok(match statistic {
+        NvidiaStatistic::GpuUtilization => device.utilization_rates()?.gpu as u64,
+        NvidiaStatistic::MemoryUtilization => device.utilization_rates()?.memory as u64,
+        NvidiaStatistic::MemoryUsed => device.memory_info()?.used,
+        NvidiaStatistic::MemoryTotal => device.memory_info()?.total,
+        NvidiaStatistic::Temperature => device.temperature(TemperatureSensor::Gpu)? as u64,
+        NvidiaStatistic::PowerUsage => device.power_usage()? as u64,
+    })
+}
+
+#[cfg(feature = "nvml")]
+fn nvml_error(e: nvml_wrapper::error::NvmlError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
@@ -0,0 +1,69 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum NvidiaStatistic {
+    #[strum(serialize = "nvidia/gpu/utilization")]
+    GpuUtilization,
+    #[strum(serialize = "nvidia/memory/utilization")]
+    MemoryUtilization,
+    #[strum(serialize = "nvidia/memory/used")]
+    MemoryUsed,
+    #[strum(serialize = "nvidia/memory/total")]
+    MemoryTotal,
+    #[strum(serialize = "nvidia/temperature")]
+    Temperature,
+    #[strum(serialize = "nvidia/power/usage")]
+    PowerUsage,
+}
+
+impl Statistic<AtomicU64, AtomicU32> for NvidiaStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        Source::Gauge
+    }
+}
+
+impl StatisticUnit for NvidiaStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::GpuUtilization | Self::MemoryUtilization => Some(Unit::Percent),
+            Self::MemoryUsed | Self::MemoryTotal => Some(Unit::Bytes),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for NvidiaStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        NvidiaStatistic::from_str(s)
+    }
+}
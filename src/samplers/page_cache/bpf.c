@@ -1,38 +1,47 @@
 #include <uapi/linux/ptrace.h>
 
-BPF_ARRAY(page_accessed, u64, 1);
-BPF_ARRAY(buffer_dirty, u64, 1);
-BPF_ARRAY(add_to_page_cache_lru, u64, 1);
-BPF_ARRAY(page_dirtied, u64, 1);
+// these are incremented for every page cache access, so each cpu counts into
+// its own slot to avoid contention, and the slots are summed when read. The
+// slots are padded to a cache line so that cpus do not share one. NUM_CPUS is
+// the number of possible cpus, which is defined when the program is loaded.
+struct counter {
+    u64 count;
+    u8 pad[56];
+};
+
+BPF_ARRAY(page_accessed, struct counter, NUM_CPUS);
+BPF_ARRAY(buffer_dirty, struct counter, NUM_CPUS);
+BPF_ARRAY(add_to_page_cache_lru, struct counter, NUM_CPUS);
+BPF_ARRAY(page_dirtied, struct counter, NUM_CPUS);
 
 int trace_mark_page_accessed(struct pt_regs *ctx)
 {
-    int zero = 0;
-    u64 *count = page_accessed.lookup(&zero);
-    if (count) lock_xadd(count, 1);
+    int cpu = bpf_get_smp_processor_id();
+    struct counter *counter = page_accessed.lookup(&cpu);
+    if (counter) counter->count++;
     return 0;
 }
 
 int trace_mark_buffer_dirty(struct pt_regs *ctx)
 {
-    int zero = 0;
-    u64 *count = buffer_dirty.lookup(&zero);
-    if (count) lock_xadd(count, 1);
+    int cpu = bpf_get_smp_processor_id();
+    struct counter *counter = buffer_dirty.lookup(&cpu);
+    if (counter) counter->count++;
     return 0;
 }
 
 int trace_add_to_page_cache_lru(struct pt_regs *ctx)
 {
-    int zero = 0;
-    u64 *count = add_to_page_cache_lru.lookup(&zero);
-    if (count) lock_xadd(count, 1);
+    int cpu = bpf_get_smp_processor_id();
+    struct counter *counter = add_to_page_cache_lru.lookup(&cpu);
+    if (counter) counter->count++;
     return 0;
 }
 
 int trace_account_page_dirtied(struct pt_regs *ctx)
 {
-    int zero = 0;
-    u64 *count = page_dirtied.lookup(&zero);
-    if (count) lock_xadd(count, 1);
+    int cpu = bpf_get_smp_processor_id();
+    struct counter *counter = page_dirtied.lookup(&cpu);
+    if (counter) counter->count++;
     return 0;
 }
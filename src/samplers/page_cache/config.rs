@@ -16,8 +16,15 @@ pub struct PageCacheConfig {
     #[serde(default)]
     bpf: AtomicBool,
     #[serde(default)]
+    disabled: Vec<PageCacheStatistic>,
+    #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
@@ -29,7 +36,9 @@ impl Default for PageCacheConfig {
     fn default() -> Self {
         Self {
             bpf: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
             percentiles: crate::common::default_percentiles(),
             statistics: default_statistics(),
@@ -52,6 +61,10 @@ impl SamplerConfig for PageCacheConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -60,6 +73,13 @@ impl SamplerConfig for PageCacheConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         let mut enabled = Vec::new();
         for statistic in self.statistics.iter() {
@@ -71,6 +91,7 @@ impl SamplerConfig for PageCacheConfig {
                 enabled.push(statistic.clone());
             }
         }
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
         enabled
     }
 }
@@ -18,9 +18,20 @@ mod stat;
 pub use config::*;
 pub use stat::*;
 
+/// The per-cpu counter tables of the bpf program, which have a slot for each
+/// possible cpu
+#[cfg(feature = "bpf")]
+const BPF_COUNTERS: &[&str] = &[
+    "page_accessed",
+    "buffer_dirty",
+    "add_to_page_cache_lru",
+    "page_dirtied",
+];
+
 #[allow(dead_code)]
 pub struct PageCache {
     bpf: Option<Arc<Mutex<BPF>>>,
+    bpf_totals: HashMap<&'static str, u64>,
     common: Common,
     statistics: Vec<PageCacheStatistic>,
     counters: HashMap<PageCacheStatistic, u64>,
@@ -31,22 +42,18 @@ impl Sampler for PageCache {
     type Statistic = PageCacheStatistic;
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().page_cache().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
+            bpf_totals: HashMap::new(),
             common,
             statistics,
             counters: HashMap::new(),
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
@@ -59,13 +66,15 @@ impl Sampler for PageCache {
         if common.config().samplers().page_cache().enabled() {
             if let Ok(mut interrupt) = PageCache::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = interrupt.sample().await;
-                    }
+                    interrupt.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().page_cache())
+            {
                 fatal!("failed to initialize page_cache sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize page_cache sampler");
             }
         }
@@ -84,10 +93,6 @@ impl Sampler for PageCache {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -96,15 +101,14 @@ impl Sampler for PageCache {
 
         #[cfg(feature = "bpf")]
         {
+            self.retry_bpf();
             let result = self.sample_bpf_counters();
             self.map_result(result)?;
         }
 
         Ok(())
     }
-}
 
-impl PageCache {
     #[cfg(feature = "bpf")]
     fn bpf_enabled(&self) -> bool {
         if self.sampler_config().bpf() {
@@ -117,14 +121,35 @@ impl PageCache {
         false
     }
 
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    // both statistics are computed from all of the counters
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        if self.statistics.is_empty() {
+            Vec::new()
+        } else {
+            BPF_COUNTERS.to_vec()
+        }
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
         #[cfg(feature = "bpf")]
         {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
             if self.enabled() && self.bpf_enabled() {
                 debug!("initializing bpf");
 
-                let code = include_str!("bpf.c");
-                let mut bpf = bcc::BPF::new(code)?;
+                let code = format!(
+                    "#define NUM_CPUS {}\n{}",
+                    possible_cpus(),
+                    include_str!("bpf.c")
+                );
+                let mut bpf = bcc::BPF::new(&code)?;
 
                 bcc::Kprobe::new()
                     .handler("trace_mark_page_accessed")
@@ -149,36 +174,42 @@ impl PageCache {
 
         Ok(())
     }
+}
 
+impl PageCache {
     #[cfg(feature = "bpf")]
     fn sample_bpf_counters(&mut self) -> Result<(), std::io::Error> {
         if let Some(ref bpf) = self.bpf {
             let bpf = bpf.lock().unwrap();
-            let time = std::time::Instant::now();
-            let mut page_accessed = 0;
-            let mut buffer_dirty = 0;
-            let mut add_to_page_cache_lru = 0;
-            let mut page_dirtied = 0;
-
-            // to make things simple for wraparound behavior, clear each BPF
-            // counter after reading it.
-            if let Ok(mut table) = (*bpf).inner.table("page_accessed") {
-                page_accessed = crate::common::bpf::parse_u64(table.iter().next().unwrap().value);
-                let _ = table.set(&mut [0, 0, 0, 0], &mut [0, 0, 0, 0, 0, 0, 0, 0]);
-            }
-            if let Ok(mut table) = (*bpf).inner.table("buffer_dirty") {
-                buffer_dirty = crate::common::bpf::parse_u64(table.iter().next().unwrap().value);
-                let _ = table.set(&mut [0, 0, 0, 0], &mut [0, 0, 0, 0, 0, 0, 0, 0]);
-            }
-            if let Ok(mut table) = (*bpf).inner.table("add_to_page_cache_lru") {
-                add_to_page_cache_lru =
-                    crate::common::bpf::parse_u64(table.iter().next().unwrap().value);
-                let _ = table.set(&mut [0, 0, 0, 0], &mut [0, 0, 0, 0, 0, 0, 0, 0]);
-            }
-            if let Ok(mut table) = (*bpf).inner.table("page_dirtied") {
-                page_dirtied = crate::common::bpf::parse_u64(table.iter().next().unwrap().value);
-                let _ = table.set(&mut [0, 0, 0, 0], &mut [0, 0, 0, 0, 0, 0, 0, 0]);
+            let time = self.common.now();
+
+            // the per-cpu counters are never cleared, as that would require a
+            // write for each cpu, so the increase since the last read is used.
+            // A total which went backwards was reset by reloading the program.
+            // A table which cannot be read keeps its previous total, so that
+            // the next read is not counted as one large increase.
+            let mut deltas = HashMap::new();
+            for name in BPF_COUNTERS {
+                let total = match (*bpf).inner.table(name) {
+                    Ok(table) => per_cpu_total(&table),
+                    Err(_) => {
+                        self.common.bpf_table_error("page_cache", name);
+                        deltas.insert(*name, 0);
+                        continue;
+                    }
+                };
+                let previous = self.bpf_totals.insert(*name, total).unwrap_or(0);
+                let delta = if total >= previous {
+                    total - previous
+                } else {
+                    total
+                };
+                deltas.insert(*name, delta);
             }
+            let page_accessed = deltas["page_accessed"];
+            let buffer_dirty = deltas["buffer_dirty"];
+            let add_to_page_cache_lru = deltas["add_to_page_cache_lru"];
+            let page_dirtied = deltas["page_dirtied"];
 
             // the logic here is taken from https://github.com/iovisor/bcc/blob/master/tools/cachestat.py
             let total = page_accessed.saturating_sub(buffer_dirty);
@@ -201,12 +232,12 @@ impl PageCache {
                 self.counters.insert(PageCacheStatistic::Miss, misses);
             }
 
-            let _ = self.metrics().record_counter(
+            self.record_counter(
                 &PageCacheStatistic::Hit,
                 time,
                 *self.counters.get(&PageCacheStatistic::Hit).unwrap_or(&0),
             );
-            let _ = self.metrics().record_counter(
+            self.record_counter(
                 &PageCacheStatistic::Miss,
                 time,
                 *self.counters.get(&PageCacheStatistic::Miss).unwrap_or(&0),
@@ -5,6 +5,8 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES};
+use crate::common::unit::StatisticUnit;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -35,6 +37,12 @@ impl PageCacheStatistic {
     pub fn is_bpf(&self) -> bool {
         true
     }
+
+    /// The oldest kernel able to trace the statistic with kprobes
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        Some(KPROBES)
+    }
 }
 
 impl TryFrom<&str> for PageCacheStatistic {
@@ -54,3 +62,5 @@ impl Statistic<AtomicU64, AtomicU32> for PageCacheStatistic {
         Source::Counter
     }
 }
+
+impl StatisticUnit for PageCacheStatistic {}
@@ -0,0 +1,81 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::*;
+use serde_derive::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PressureConfig {
+    #[serde(default)]
+    disabled: Vec<PressureStatistic>,
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+    #[serde(default = "default_statistics")]
+    statistics: Vec<PressureStatistic>,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            disabled: Default::default(),
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            interval: Default::default(),
+            percentiles: crate::common::default_percentiles(),
+            statistics: default_statistics(),
+        }
+    }
+}
+
+fn default_statistics() -> Vec<PressureStatistic> {
+    PressureStatistic::iter().collect()
+}
+
+impl SamplerConfig for PressureConfig {
+    type Statistic = PressureStatistic;
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
+    }
+}
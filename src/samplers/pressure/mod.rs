@@ -0,0 +1,199 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::common::MICROSECOND;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// The resources with a file in `/proc/pressure`
+const RESOURCES: &[&str] = &["cpu", "memory", "io"];
+
+pub struct Pressure {
+    available: bool,
+    common: Common,
+    files: HashMap<&'static str, File>,
+    statistics: Vec<PressureStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Pressure {
+    type Statistic = PressureStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().pressure().statistics();
+        // pressure stall information requires linux 4.20 and may be disabled
+        // at boot, in which case the directory does not exist
+        let available = Path::new("/proc/pressure").exists();
+        if !available {
+            info!("pressure stall information is not available");
+        }
+        let sampler = Self {
+            available,
+            common,
+            files: HashMap::new(),
+            statistics,
+        };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().pressure().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().pressure())
+            {
+                fatal!("failed to initialize pressure sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize pressure sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().pressure()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() || !self.available {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_pressure().await;
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl Pressure {
+    async fn sample_pressure(&mut self) -> Result<(), std::io::Error> {
+        let mut result = HashMap::<PressureStatistic, u64>::new();
+
+        for resource in RESOURCES {
+            if !self.files.contains_key(resource) {
+                let file = File::open(format!("/proc/pressure/{}", resource)).await?;
+                self.files.insert(*resource, file);
+            }
+
+            if let Some(file) = self.files.get_mut(resource) {
+                file.seek(SeekFrom::Start(0)).await?;
+                let mut reader = BufReader::new(file);
+                let mut line = String::new();
+                while reader.read_line(&mut line).await? > 0 {
+                    result.extend(parse_line(resource, &line));
+                    line.clear();
+                }
+            }
+        }
+
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some(value) = result.get(statistic) {
+                if statistic.is_total() {
+                    self.record_counter(statistic, time, *value);
+                } else {
+                    self.record_gauge(statistic, time, *value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a line from one of the files in `/proc/pressure`, eg:
+/// `some avg10=1.23 avg60=0.45 avg300=0.06 total=123456`
+///
+/// The averages are percentages with two decimal places and are returned in
+/// hundredths of a percent. The total is in microseconds and is returned in
+/// nanoseconds.
+fn parse_line(resource: &str, line: &str) -> Vec<(PressureStatistic, u64)> {
+    let mut result = Vec::new();
+    let mut parts = line.split_whitespace();
+    let kind = match parts.next() {
+        Some(kind) => kind,
+        None => return result,
+    };
+    for part in parts {
+        let mut pair = part.splitn(2, '=');
+        if let (Some(key), Some(value)) = (pair.next(), pair.next()) {
+            let name = format!("pressure/{}/{}/{}", resource, kind, key);
+            if let Ok(statistic) = PressureStatistic::from_str(&name) {
+                let value = if statistic.is_total() {
+                    value.parse::<u64>().ok().map(|v| v * MICROSECOND)
+                } else {
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .map(|v| (v * 100.0).round() as u64)
+                };
+                if let Some(value) = value {
+                    result.push((statistic, value));
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let result = parse_line(
+            "memory",
+            "some avg10=1.23 avg60=0.45 avg300=0.06 total=123456\n",
+        );
+        assert_eq!(
+            result,
+            vec![
+                (PressureStatistic::MemorySomeAvg10, 123),
+                (PressureStatistic::MemorySomeAvg60, 45),
+                (PressureStatistic::MemorySomeAvg300, 6),
+                (PressureStatistic::MemorySomeTotal, 123_456_000),
+            ]
+        );
+        let result = parse_line("io", "full avg10=0.00 avg60=0.00 avg300=0.00 total=0");
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[3], (PressureStatistic::IoFullTotal, 0));
+        assert!(parse_line("cpu", "").is_empty());
+        assert!(parse_line("cpu", "some unknown=1").is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum PressureStatistic {
+    #[strum(serialize = "pressure/cpu/some/avg10")]
+    CpuSomeAvg10,
+    #[strum(serialize = "pressure/cpu/some/avg60")]
+    CpuSomeAvg60,
+    #[strum(serialize = "pressure/cpu/some/avg300")]
+    CpuSomeAvg300,
+    #[strum(serialize = "pressure/cpu/some/total")]
+    CpuSomeTotal,
+    #[strum(serialize = "pressure/cpu/full/avg10")]
+    CpuFullAvg10,
+    #[strum(serialize = "pressure/cpu/full/avg60")]
+    CpuFullAvg60,
+    #[strum(serialize = "pressure/cpu/full/avg300")]
+    CpuFullAvg300,
+    #[strum(serialize = "pressure/cpu/full/total")]
+    CpuFullTotal,
+    #[strum(serialize = "pressure/memory/some/avg10")]
+    MemorySomeAvg10,
+    #[strum(serialize = "pressure/memory/some/avg60")]
+    MemorySomeAvg60,
+    #[strum(serialize = "pressure/memory/some/avg300")]
+    MemorySomeAvg300,
+    #[strum(serialize = "pressure/memory/some/total")]
+    MemorySomeTotal,
+    #[strum(serialize = "pressure/memory/full/avg10")]
+    MemoryFullAvg10,
+    #[strum(serialize = "pressure/memory/full/avg60")]
+    MemoryFullAvg60,
+    #[strum(serialize = "pressure/memory/full/avg300")]
+    MemoryFullAvg300,
+    #[strum(serialize = "pressure/memory/full/total")]
+    MemoryFullTotal,
+    #[strum(serialize = "pressure/io/some/avg10")]
+    IoSomeAvg10,
+    #[strum(serialize = "pressure/io/some/avg60")]
+    IoSomeAvg60,
+    #[strum(serialize = "pressure/io/some/avg300")]
+    IoSomeAvg300,
+    #[strum(serialize = "pressure/io/some/total")]
+    IoSomeTotal,
+    #[strum(serialize = "pressure/io/full/avg10")]
+    IoFullAvg10,
+    #[strum(serialize = "pressure/io/full/avg60")]
+    IoFullAvg60,
+    #[strum(serialize = "pressure/io/full/avg300")]
+    IoFullAvg300,
+    #[strum(serialize = "pressure/io/full/total")]
+    IoFullTotal,
+}
+
+impl PressureStatistic {
+    /// Whether this is the total stall time, rather than a moving average
+    pub fn is_total(self) -> bool {
+        matches!(
+            self,
+            Self::CpuSomeTotal
+                | Self::CpuFullTotal
+                | Self::MemorySomeTotal
+                | Self::MemoryFullTotal
+                | Self::IoSomeTotal
+                | Self::IoFullTotal
+        )
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for PressureStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        if self.is_total() {
+            Source::Counter
+        } else {
+            Source::Gauge
+        }
+    }
+}
+
+impl StatisticUnit for PressureStatistic {
+    fn unit(&self) -> Option<Unit> {
+        if self.is_total() {
+            Some(Unit::Nanoseconds)
+        } else {
+            None
+        }
+    }
+}
+
+impl TryFrom<&str> for PressureStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        PressureStatistic::from_str(s)
+    }
+}
@@ -13,9 +13,16 @@ use super::stat::*;
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RezolusConfig {
+    #[serde(default)]
+    disabled: Vec<RezolusStatistic>,
     #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
@@ -26,7 +33,9 @@ pub struct RezolusConfig {
 impl Default for RezolusConfig {
     fn default() -> Self {
         Self {
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
             percentiles: crate::common::default_percentiles(),
             statistics: default_statistics(),
@@ -45,6 +54,10 @@ impl SamplerConfig for RezolusConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -53,7 +66,16 @@ impl SamplerConfig for RezolusConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
-        self.statistics.clone()
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
     }
 }
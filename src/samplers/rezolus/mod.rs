@@ -6,14 +6,16 @@ use std::collections::HashMap;
 use std::io::SeekFrom;
 
 use async_trait::async_trait;
+use rustcommon_metrics::*;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+use crate::common::kernel::BPF_ENABLE_STATS;
 use crate::common::*;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
 use crate::Sampler;
-use std::time::*;
 
 mod config;
 mod stat;
@@ -27,7 +29,11 @@ pub fn nanos_per_tick() -> u64 {
     SECOND / ticks_per_second
 }
 
+#[allow(dead_code)]
 pub struct Rezolus {
+    /// Keeps the run statistics of BPF programs enabled while it is open
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    bpf_stats: Option<std::fs::File>,
     common: Common,
     nanos_per_tick: u64,
     proc_stat: Option<File>,
@@ -40,8 +46,19 @@ impl Sampler for Rezolus {
     type Statistic = RezolusStatistic;
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let statistics = common.config().samplers().rezolus().statistics();
+        let mut statistics = common.config().samplers().rezolus().statistics();
+        common.retain_supported(&mut statistics, |s| s.min_kernel());
+        #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+        let bpf_stats = if common.config().samplers().rezolus().enabled()
+            && statistics.iter().any(|s| s.is_bpf())
+        {
+            enable_bpf_stats(common.kernel_supports(BPF_ENABLE_STATS))
+        } else {
+            None
+        };
         let sampler = Self {
+            #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+            bpf_stats,
             common,
             nanos_per_tick: nanos_per_tick() as u64,
             proc_stat: None,
@@ -58,13 +75,15 @@ impl Sampler for Rezolus {
         if common.config().samplers().rezolus().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().rezolus())
+            {
                 fatal!("failed to initialize rezolus sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize rezolus sampler");
             }
         }
@@ -83,10 +102,6 @@ impl Sampler for Rezolus {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -98,6 +113,12 @@ impl Sampler for Rezolus {
         let r = self.sample_cpu().await;
         self.map_result(r)?;
 
+        let r = self.sample_fds().await;
+        self.map_result(r)?;
+
+        let r = self.sample_bpf_programs().await;
+        self.map_result(r)?;
+
         Ok(())
     }
 }
@@ -117,20 +138,22 @@ impl Rezolus {
             let mut result = HashMap::new();
             let mut line = String::new();
             if reader.read_line(&mut line).await? > 0 {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                let user = parts.get(13).map(|v| v.parse().unwrap_or(0)).unwrap_or(0)
-                    + parts.get(15).map(|v| v.parse().unwrap_or(0)).unwrap_or(0);
-                let system = parts.get(14).map(|v| v.parse().unwrap_or(0)).unwrap_or(0)
-                    + parts.get(16).map(|v| v.parse().unwrap_or(0)).unwrap_or(0);
-                result.insert(RezolusStatistic::CpuUser, user * self.nanos_per_tick);
-                result.insert(RezolusStatistic::CpuSystem, system * self.nanos_per_tick);
+                if let Some((user, system, threads)) = parse_stat(&line) {
+                    result.insert(RezolusStatistic::CpuUser, user * self.nanos_per_tick);
+                    result.insert(RezolusStatistic::CpuSystem, system * self.nanos_per_tick);
+                    result.insert(RezolusStatistic::Threads, threads);
+                }
                 line.clear();
             }
 
-            let time = Instant::now();
+            let time = self.common.now();
             for statistic in &self.statistics {
                 if let Some(value) = result.get(statistic) {
-                    let _ = self.metrics().record_counter(statistic, time, *value);
+                    if statistic.source() == Source::Counter {
+                        self.record_counter(statistic, time, *value);
+                    } else {
+                        self.record_gauge(statistic, time, *value);
+                    }
                 }
             }
         }
@@ -160,14 +183,156 @@ impl Rezolus {
                 line.clear();
             }
 
-            let time = Instant::now();
+            let time = self.common.now();
             for statistic in &self.statistics {
                 if let Some(value) = result_memory.get(statistic) {
-                    let _ = self.metrics().record_gauge(statistic, time, *value * 4096);
+                    self.record_gauge(statistic, time, *value * 4096);
                 }
             }
         }
 
         Ok(())
     }
+
+    async fn sample_fds(&mut self) -> Result<(), std::io::Error> {
+        if !self.statistics.contains(&RezolusStatistic::OpenFiles) {
+            return Ok(());
+        }
+        let mut entries = tokio::fs::read_dir("/proc/self/fd").await?;
+        let mut count = 0;
+        while entries.next_entry().await?.is_some() {
+            count += 1;
+        }
+        // the directory handle used to count the entries is itself included
+        let count = count.saturating_sub(1);
+        self.record_gauge(&RezolusStatistic::OpenFiles, self.common.now(), count);
+        Ok(())
+    }
+
+    /// Sum the run count and time of the BPF programs which Rezolus has
+    /// loaded, each of which is a `bpf-prog` among its open descriptors
+    async fn sample_bpf_programs(&mut self) -> Result<(), std::io::Error> {
+        if !self.statistics.iter().any(|s| s.is_bpf()) {
+            return Ok(());
+        }
+        let mut programs = 0;
+        let mut runs = 0;
+        let mut run_time = 0;
+        let mut entries = tokio::fs::read_dir("/proc/self/fd").await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // descriptors may be closed while they are listed
+            match tokio::fs::read_link(entry.path()).await {
+                Ok(target) if target.to_str() == Some("anon_inode:bpf-prog") => {}
+                _ => continue,
+            }
+            let path = format!("/proc/self/fdinfo/{}", entry.file_name().to_string_lossy());
+            if let Ok(content) = self.common.proc().read_to_string(&path).await {
+                let (program_runs, program_run_time) = parse_prog_fdinfo(&content);
+                programs += 1;
+                runs += program_runs;
+                run_time += program_run_time;
+            }
+        }
+
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            match statistic {
+                RezolusStatistic::BpfPrograms => self.record_gauge(statistic, time, programs),
+                RezolusStatistic::BpfRuns => self.record_counter(statistic, time, runs),
+                RezolusStatistic::BpfRunTime => self.record_counter(statistic, time, run_time),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turn on the run statistics of BPF programs, unless the sysctl already has.
+/// Kernels older than 5.8 can only turn them on with the sysctl.
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+fn enable_bpf_stats(enable_stats: bool) -> Option<std::fs::File> {
+    let enabled = std::fs::read_to_string("/proc/sys/kernel/bpf_stats_enabled")
+        .map(|value| value.trim() == "1")
+        .unwrap_or(false);
+    if enabled {
+        return None;
+    }
+    if !enable_stats {
+        warn!(
+            "bpf program run statistics are zero unless `sysctl kernel.bpf_stats_enabled=1`, \
+             as the kernel is older than {}",
+            BPF_ENABLE_STATS
+        );
+        return None;
+    }
+    match crate::common::bpf::enable_run_time_stats() {
+        Ok(file) => Some(file),
+        Err(e) => {
+            warn!(
+                "failed to enable bpf program statistics, which need kernel 5.8 or \
+                 `sysctl kernel.bpf_stats_enabled=1`: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Parse the run count and total run time, in nanoseconds, from the fdinfo of
+/// a BPF program. Both are zero unless BPF statistics are enabled, and absent
+/// before kernel 5.1.
+fn parse_prog_fdinfo(content: &str) -> (u64, u64) {
+    let mut runs = 0;
+    let mut run_time = 0;
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next().and_then(|v| v.parse().ok())) {
+            (Some("run_cnt:"), Some(value)) => runs = value,
+            (Some("run_time_ns:"), Some(value)) => run_time = value,
+            _ => {}
+        }
+    }
+    (runs, run_time)
+}
+
+/// Parse the user and system cpu ticks, including those of waited-for
+/// children, and the number of threads from the contents of `/proc/[pid]/stat`.
+/// The command name may contain spaces and parentheses, so the remaining fields
+/// are located after the last closing parenthesis.
+fn parse_stat(line: &str) -> Option<(u64, u64, u64)> {
+    let fields: Vec<u64> = line[(line.rfind(')')? + 1)..]
+        .split_whitespace()
+        .map(|v| v.parse().unwrap_or(0))
+        .collect();
+    // fields are numbered from the state, which is the third field in proc(5)
+    let field = |index: usize| fields.get(index - 3).copied();
+    let user = field(14)? + field(16)?;
+    let system = field(15)? + field(17)?;
+    let threads = field(20)?;
+    Some((user, system, threads))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let line = "1234 (rezo lus) S 1 1234 1234 0 -1 4194560 2619 0 0 0 \
+            150 25 3 2 20 0 7 0 1234567 123456789 2048 18446744073709551615";
+        assert_eq!(parse_stat(line), Some((153, 27, 7)));
+        assert_eq!(parse_stat("1234 (rezolus) S 1"), None);
+        assert_eq!(parse_stat(""), None);
+    }
+
+    #[test]
+    fn test_parse_prog_fdinfo() {
+        let content = "pos:\t0\nflags:\t02000002\nmnt_id:\t15\nino:\t1057\n\
+            prog_type:\t2\nprog_jited:\t1\nprog_tag:\t6deef7357e7b4530\n\
+            memlock:\t4096\nprog_id:\t42\nrun_time_ns:\t183472\nrun_cnt:\t1290\n\
+            recursion_misses:\t0\n";
+        assert_eq!(parse_prog_fdinfo(content), (1290, 183472));
+        // kernels before 5.1 do not report the statistics
+        assert_eq!(parse_prog_fdinfo("prog_type:\t2\nprog_id:\t42\n"), (0, 0));
+    }
 }
@@ -5,6 +5,8 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, BPF_RUN_STATS};
+use crate::common::unit::{StatisticUnit, Unit};
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -33,6 +35,32 @@ pub enum RezolusStatistic {
     MemoryVirtual,
     #[strum(serialize = "rezolus/memory/resident")]
     MemoryResident,
+    #[strum(serialize = "rezolus/open_files")]
+    OpenFiles,
+    #[strum(serialize = "rezolus/threads")]
+    Threads,
+    #[strum(serialize = "rezolus/bpf/programs")]
+    BpfPrograms,
+    #[strum(serialize = "rezolus/bpf/runs")]
+    BpfRuns,
+    #[strum(serialize = "rezolus/bpf/run_time")]
+    BpfRunTime,
+}
+
+impl RezolusStatistic {
+    /// Whether the statistic describes the BPF programs Rezolus has loaded
+    pub fn is_bpf(self) -> bool {
+        matches!(self, Self::BpfPrograms | Self::BpfRuns | Self::BpfRunTime)
+    }
+
+    /// The oldest kernel which reports the statistic. The run count and time
+    /// of BPF programs are only in their fdinfo from 5.1
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        match self {
+            Self::BpfRuns | Self::BpfRunTime => Some(BPF_RUN_STATS),
+            _ => None,
+        }
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for RezolusStatistic {
@@ -42,12 +70,26 @@ impl Statistic<AtomicU64, AtomicU32> for RezolusStatistic {
 
     fn source(&self) -> Source {
         match self {
-            Self::MemoryVirtual | Self::MemoryResident => Source::Gauge,
+            Self::MemoryVirtual
+            | Self::MemoryResident
+            | Self::OpenFiles
+            | Self::Threads
+            | Self::BpfPrograms => Source::Gauge,
             _ => Source::Counter,
         }
     }
 }
 
+impl StatisticUnit for RezolusStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::BpfRuns => Some(Unit::Count),
+            Self::BpfRunTime => Some(Unit::Nanoseconds),
+            _ => None,
+        }
+    }
+}
+
 impl TryFrom<&str> for RezolusStatistic {
     type Error = ParseError;
 
@@ -20,8 +20,6 @@ BPF_TABLE("hash", u32, u64, start, 65536);
 // value_to_index() gives us from 0-460 as the index
 BPF_HISTOGRAM(runqueue_latency, int, 461);
 
-struct rq;
-
 // from /sys/kernel/debug/tracing/events/sched/sched_wakeup/format
 struct sched_wakeup_arg {
     u64 __unused__;
@@ -32,22 +30,21 @@ struct sched_wakeup_arg {
     int target_cpu;
 };
 
-static int trace_enqueue(u32 tgid, u32 pid)
+static int trace_enqueue(u32 pid)
 {
     u64 ts = bpf_ktime_get_ns();
     start.update(&pid, &ts);
     return 0;
 }
 
-int trace_wake_up_new_task(struct pt_regs *ctx, struct task_struct *p)
+int trace_sched_wakeup(struct sched_wakeup_arg *args)
 {
-    return trace_enqueue(p->tgid, p->pid);
+    return trace_enqueue(args->pid);
 }
 
-int trace_ttwu_do_wakeup(struct pt_regs *ctx, struct rq *rq, struct task_struct *p,
-    int wake_flags)
+int trace_sched_wakeup_new(struct sched_wakeup_arg *args)
 {
-    return trace_enqueue(p->tgid, p->pid);
+    return trace_enqueue(args->pid);
 }
 
 // from /sys/kernel/debug/tracing/events/sched/sched_switch/format
@@ -96,19 +93,15 @@ static unsigned int value_to_index2(unsigned int value) {
     return index;
 }
 
-int trace_run(struct pt_regs *ctx, struct task_struct *prev)
+int trace_sched_switch(struct sched_switch_arg *args)
 {
     // handle involuntary context switch
-    if (prev->state == TASK_RUNNING) {
-        u32 tgid = prev->tgid;
-        u32 pid = prev->pid;
-        u64 ts = bpf_ktime_get_ns();
-        start.update(&pid, &ts);
+    if (args->prev_state == TASK_RUNNING) {
+        trace_enqueue(args->prev_pid);
     }
 
-    // get tgid and pid
-    u32 tgid = bpf_get_current_pid_tgid() >> 32;
-    u32 pid = bpf_get_current_pid_tgid();
+    // the task being switched in
+    u32 pid = args->next_pid;
 
     // lookup start time
     u64 *tsp = start.lookup(&pid);
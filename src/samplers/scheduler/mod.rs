@@ -41,13 +41,15 @@ pub struct Scheduler {
 impl Sampler for Scheduler {
     type Statistic = SchedulerStatistic;
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
+        let fault_tolerant = common
+            .config()
+            .sampler_fault_tolerant(common.config().samplers().scheduler());
         let statistics = common.config().samplers().scheduler().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
             common,
             perf: None,
             proc_stat: None,
@@ -58,11 +60,7 @@ impl Sampler for Scheduler {
             sampler.register();
         }
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         // we initialize perf last so we can delay
         if sampler.sampler_config().enabled() && sampler.sampler_config().perf_events() {
@@ -89,13 +87,15 @@ impl Sampler for Scheduler {
         if common.config().samplers().scheduler().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().scheduler())
+            {
                 fatal!("failed to initialize scheduler sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize scheduler sampler");
             }
         }
@@ -114,10 +114,6 @@ impl Sampler for Scheduler {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -131,8 +127,89 @@ impl Sampler for Scheduler {
 
         let r = self.sample_proc_stat().await;
         self.map_result(r)?;
+        let r = self.sample_loadavg().await;
+        self.map_result(r)?;
+        #[cfg(feature = "bpf")]
+        self.retry_bpf();
+        #[cfg(feature = "bpf")]
+        self.map_result(self.sample_bpf())?;
+
+        Ok(())
+    }
+
+    fn drains_bpf(&self) -> bool {
+        cfg!(feature = "bpf")
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
         #[cfg(feature = "bpf")]
         self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
+    // checks that bpf is enabled in config and one or more bpf stats enabled
+    #[cfg(feature = "bpf")]
+    fn bpf_enabled(&self) -> bool {
+        if self.sampler_config().bpf() {
+            for statistic in &self.statistics {
+                match statistic {
+                    SchedulerStatistic::RunqueueLatency => {
+                        return true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
+    fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
+        #[cfg(feature = "bpf")]
+        {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
+            if self.enabled() && self.bpf_enabled() {
+                debug!("initializing bpf");
+                // load the code and compile
+                let code = include_str!("bpf.c");
+                let mut bpf = bcc::BPF::new(code)?;
+
+                // attach to the stable sched tracepoints, as with runqlat
+                bcc::Tracepoint::new()
+                    .handler("trace_sched_wakeup")
+                    .subsystem("sched")
+                    .tracepoint("sched_wakeup")
+                    .attach(&mut bpf)?;
+                bcc::Tracepoint::new()
+                    .handler("trace_sched_wakeup_new")
+                    .subsystem("sched")
+                    .tracepoint("sched_wakeup_new")
+                    .attach(&mut bpf)?;
+                bcc::Tracepoint::new()
+                    .handler("trace_sched_switch")
+                    .subsystem("sched")
+                    .tracepoint("sched_switch")
+                    .attach(&mut bpf)?;
+
+                self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
+            }
+        }
 
         Ok(())
     }
@@ -166,7 +243,7 @@ impl Scheduler {
                             .attach(&mut bpf)
                             .is_err()
                         {
-                            if !self.common().config().general().fault_tolerant() {
+                            if !self.fault_tolerant() {
                                 fatal!("failed to initialize perf bpf for event: {:?}", event);
                             } else {
                                 error!("failed to initialize perf bpf for event: {:?}", event);
@@ -183,14 +260,14 @@ impl Scheduler {
                 .attach(&mut bpf)
                 .is_err()
             {
-                if !self.common().config().general().fault_tolerant() {
+                if !self.fault_tolerant() {
                     fatal!("failed to initialize perf bpf for cpu");
                 } else {
                     error!("failed to initialize perf bpf for cpu");
                 }
             }
             self.perf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
-        } else if !self.common().config().general().fault_tolerant() {
+        } else if !self.fault_tolerant() {
             fatal!("failed to initialize perf bpf");
         } else {
             error!("failed to initialize perf bpf. skipping scheduler perf telemetry");
@@ -224,15 +301,15 @@ impl Scheduler {
                 }
                 line.clear();
             }
-            let time = Instant::now();
+            let time = self.common.now();
             for statistic in &self.statistics {
                 if let Some(value) = result.get(statistic) {
                     match statistic.source() {
                         Source::Counter => {
-                            let _ = self.metrics().record_counter(statistic, time, *value);
+                            self.record_counter(statistic, time, *value);
                         }
                         Source::Gauge => {
-                            let _ = self.metrics().record_gauge(statistic, time, *value);
+                            self.record_gauge(statistic, time, *value);
                         }
                         _ => {}
                     }
@@ -243,34 +320,55 @@ impl Scheduler {
         Ok(())
     }
 
+    async fn sample_loadavg(&mut self) -> Result<(), std::io::Error> {
+        if !self.statistics.iter().any(|s| s.loadavg_field().is_some()) {
+            return Ok(());
+        }
+        let content = self.common.proc().read_to_string("/proc/loadavg").await?;
+        let values = parse_loadavg(&content).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "failed to parse /proc/loadavg",
+            )
+        })?;
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some(field) = statistic.loadavg_field() {
+                self.record_gauge(statistic, time, values[field]);
+            }
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "bpf")]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        use crate::common::MICROSECOND;
-
         // sample bpf
         {
-            if self.bpf_last.lock().unwrap().elapsed()
-                >= Duration::new(self.general_config().window() as u64, 0)
-            {
+            if self.bpf_drain_due(&self.bpf_last) {
                 if let Some(ref bpf) = self.bpf {
                     let bpf = bpf.lock().unwrap();
-                    let time = Instant::now();
+                    let time = self.common.now();
                     for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
                         if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                            for (&value, &count) in &map_from_table(&mut table) {
+                            let table =
+                                self.drain_histogram(statistic.bpf_table().unwrap(), &mut table);
+                            for (&value, &count) in &table {
                                 if count > 0 {
-                                    let _ = self.metrics().record_bucket(
+                                    self.record_bucket(
                                         statistic,
                                         time,
-                                        value * MICROSECOND,
+                                        value * statistic.bucket_scale(),
                                         count,
                                     );
                                 }
                             }
+                        } else {
+                            self.common
+                                .bpf_table_error("scheduler", statistic.bpf_table().unwrap());
                         }
                     }
                 }
-                *self.bpf_last.lock().unwrap() = Instant::now();
+                *self.bpf_last.lock().unwrap() = self.common.now();
             }
         }
 
@@ -281,7 +379,7 @@ impl Scheduler {
     fn sample_bpf_perf_counters(&self) -> Result<(), std::io::Error> {
         if let Some(ref bpf) = self.perf {
             let bpf = bpf.lock().unwrap();
-            let time = Instant::now();
+            let time = self.common.now();
             for stat in self.statistics.iter().filter(|s| s.perf_table().is_some()) {
                 if let Ok(table) = &(*bpf).inner.table(stat.perf_table().unwrap()) {
                     let map = crate::common::bpf::perf_table_to_map(table);
@@ -289,56 +387,84 @@ impl Scheduler {
                     for (_cpu, count) in map.iter() {
                         total += count;
                     }
-                    let _ = self.metrics().record_counter(stat, time, total);
+                    self.record_counter(stat, time, total);
+                } else {
+                    self.common
+                        .bpf_table_error("scheduler", stat.perf_table().unwrap());
                 }
             }
         }
         Ok(())
     }
+}
 
-    // checks that bpf is enabled in config and one or more bpf stats enabled
-    #[cfg(feature = "bpf")]
-    fn bpf_enabled(&self) -> bool {
-        if self.sampler_config().bpf() {
-            for statistic in &self.statistics {
-                match statistic {
-                    SchedulerStatistic::RunqueueLatency => {
-                        return true;
-                    }
-                    _ => {}
-                }
-            }
-        }
-        false
-    }
-
-    fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
-        #[cfg(feature = "bpf")]
-        {
-            if self.enabled() && self.bpf_enabled() {
-                debug!("initializing bpf");
-                // load the code and compile
-                let code = include_str!("bpf.c");
-                let mut bpf = bcc::BPF::new(code)?;
+/// Parse the 1, 5, and 15 minute load averages, in hundredths, followed by the
+/// number of runnable and total threads, from `/proc/loadavg`, eg:
+/// `0.52 0.58 0.59 2/1234 5678`. The last field is the most recent pid.
+fn parse_loadavg(content: &str) -> Option<[u64; 5]> {
+    let mut fields = content.split_whitespace();
+    let mut average = || {
+        fields
+            .next()?
+            .parse::<f64>()
+            .ok()
+            .map(|v| (v * 100.0).round() as u64)
+    };
+    let (one, five, fifteen) = (average()?, average()?, average()?);
+    let mut threads = fields.next()?.split('/');
+    let runnable = threads.next()?.parse().ok()?;
+    let total = threads.next()?.parse().ok()?;
+    Some([one, five, fifteen, runnable, total])
+}
 
-                // load + attach kprobes!
-                bcc::Kprobe::new()
-                    .handler("trace_run")
-                    .function("finish_task_switch")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_ttwu_do_wakeup")
-                    .function("ttwu_do_wakeup")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_wake_up_new_task")
-                    .function("wake_up_new_task")
-                    .attach(&mut bpf)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
 
-                self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
-            }
-        }
+    #[test]
+    fn test_parse_loadavg() {
+        assert_eq!(
+            parse_loadavg("0.52 0.58 12.09 2/1234 5678\n"),
+            Some([52, 58, 1209, 2, 1234])
+        );
+        assert_eq!(parse_loadavg("0.52 0.58 0.59\n"), None);
+        assert_eq!(parse_loadavg("0.52 0.58 0.59 2 5678\n"), None);
+        assert_eq!(parse_loadavg(""), None);
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_sample_loadavg() {
+        let common = Common::with_fixtures(
+            Fixtures::default().with("/proc/loadavg", "1.50 0.75 0.25 3/412 9001\n"),
+        );
+        let mut sampler = Scheduler::new(common).unwrap();
+        sampler.register();
+        sampler.sample_loadavg().await.unwrap();
+
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SchedulerStatistic::LoadAverage1.name()),
+            Some(150)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SchedulerStatistic::LoadAverage15.name()),
+            Some(25)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SchedulerStatistic::ThreadsRunnable.name()),
+            Some(3)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SchedulerStatistic::ThreadsTotal.name()),
+            Some(412)
+        );
     }
 }
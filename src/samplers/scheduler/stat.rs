@@ -7,6 +7,9 @@ use crate::common::SECOND;
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, TRACEPOINTS};
+use crate::common::unit::StatisticUnit;
+use crate::common::MICROSECOND;
 #[cfg(feature = "bpf")]
 use bcc::perf_event::*;
 use rustcommon_metrics::*;
@@ -41,6 +44,16 @@ pub enum SchedulerStatistic {
     ProcessesRunning,
     #[strum(serialize = "scheduler/processes/blocked")]
     ProcessesBlocked,
+    #[strum(serialize = "scheduler/load_average/1m")]
+    LoadAverage1,
+    #[strum(serialize = "scheduler/load_average/5m")]
+    LoadAverage5,
+    #[strum(serialize = "scheduler/load_average/15m")]
+    LoadAverage15,
+    #[strum(serialize = "scheduler/threads/runnable")]
+    ThreadsRunnable,
+    #[strum(serialize = "scheduler/threads/total")]
+    ThreadsTotal,
 }
 
 impl SchedulerStatistic {
@@ -52,6 +65,36 @@ impl SchedulerStatistic {
         }
     }
 
+    /// The oldest kernel able to trace the statistic, which uses the sched
+    /// tracepoints
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| TRACEPOINTS)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        match self {
+            Self::RunqueueLatency => MICROSECOND,
+            _ => 1,
+        }
+    }
+
+    /// The index of the statistic's value among those parsed from
+    /// `/proc/loadavg`, see `parse_loadavg`
+    pub fn loadavg_field(self) -> Option<usize> {
+        match self {
+            Self::LoadAverage1 => Some(0),
+            Self::LoadAverage5 => Some(1),
+            Self::LoadAverage15 => Some(2),
+            Self::ThreadsRunnable => Some(3),
+            Self::ThreadsTotal => Some(4),
+            _ => None,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn perf_table(self) -> Option<&'static str> {
         match self {
@@ -84,12 +127,20 @@ impl Statistic<AtomicU64, AtomicU32> for SchedulerStatistic {
     fn source(&self) -> Source {
         match *self {
             Self::RunqueueLatency => Source::Distribution,
-            Self::ProcessesRunning | Self::ProcessesBlocked => Source::Gauge,
+            Self::ProcessesRunning
+            | Self::ProcessesBlocked
+            | Self::LoadAverage1
+            | Self::LoadAverage5
+            | Self::LoadAverage15
+            | Self::ThreadsRunnable
+            | Self::ThreadsTotal => Source::Gauge,
             _ => Source::Counter,
         }
     }
 }
 
+impl StatisticUnit for SchedulerStatistic {}
+
 impl TryFrom<&str> for SchedulerStatistic {
     type Error = ParseError;
 
@@ -0,0 +1,143 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::*;
+use serde_derive::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlabConfig {
+    #[serde(default = "default_caches")]
+    caches: Vec<String>,
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+    #[serde(default = "default_statistics")]
+    statistics: Vec<SlabStatistic>,
+}
+
+impl Default for SlabConfig {
+    fn default() -> Self {
+        Self {
+            caches: default_caches(),
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            interval: Default::default(),
+            percentiles: crate::common::default_percentiles(),
+            statistics: default_statistics(),
+        }
+    }
+}
+
+fn default_caches() -> Vec<String> {
+    vec![
+        "dentry".to_string(),
+        "inode_cache".to_string(),
+        "kmalloc-*".to_string(),
+    ]
+}
+
+fn default_statistics() -> Vec<SlabStatistic> {
+    SlabStatistic::iter().collect()
+}
+
+impl SlabConfig {
+    /// The names of the caches which are also exported individually. A name
+    /// ending in `*` matches every cache it is a prefix of, eg: `kmalloc-*`.
+    pub fn caches(&self) -> &[String] {
+        &self.caches
+    }
+
+    /// Whether the cache is one of those exported individually
+    pub fn exports_cache(&self, cache: &str) -> bool {
+        self.caches
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => cache.starts_with(prefix),
+                None => cache == pattern,
+            })
+    }
+
+    /// Add a description of each unusable setting to `errors`
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        for pattern in &self.caches {
+            let name = pattern.strip_suffix('*').unwrap_or(pattern);
+            if name.contains('*') || name.contains(char::is_whitespace) {
+                errors.push(format!(
+                    "samplers.slab.caches contains {} which is not a cache name with an optional trailing *",
+                    pattern
+                ));
+            }
+        }
+    }
+}
+
+impl SamplerConfig for SlabConfig {
+    type Statistic = SlabStatistic;
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        self.statistics.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_caches() {
+        let config = SlabConfig::default();
+        assert!(config.exports_cache("dentry"));
+        assert!(config.exports_cache("kmalloc-64"));
+        assert!(!config.exports_cache("dentry_cache"));
+        assert!(!config.exports_cache("dma-kmalloc-64"));
+
+        let config: SlabConfig = toml::from_str("caches = [\"*\"]\n").unwrap();
+        assert!(config.exports_cache("task_struct"));
+        let mut errors = Vec::new();
+        config.validate(&mut errors);
+        assert!(errors.is_empty());
+
+        let config: SlabConfig = toml::from_str("caches = [\"kmalloc-*-rcl\"]\n").unwrap();
+        let mut errors = Vec::new();
+        config.validate(&mut errors);
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use rustcommon_metrics::*;
+
+use crate::common::labeled::LabeledStatistic;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// Reads the object counts and memory of the kernel's slab caches from
+/// `/proc/slabinfo`, in total and for each of the configured caches
+pub struct Slab {
+    common: Common,
+    page_size: u64,
+    statistics: Vec<SlabStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Slab {
+    type Statistic = SlabStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().slab().statistics();
+        let page_size = sysconf::raw::sysconf(sysconf::raw::SysconfVariable::ScPagesize)
+            .map_err(|_| format_err!("failed to get the page size"))?
+            as u64;
+        let sampler = Self {
+            common,
+            page_size,
+            statistics,
+        };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().slab().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().slab())
+            {
+                fatal!("failed to initialize slab sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize slab sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().slab()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_slabinfo().await;
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl Slab {
+    async fn sample_slabinfo(&mut self) -> Result<(), std::io::Error> {
+        let content = self.common.proc().read_to_string("/proc/slabinfo").await?;
+        let time = self.common.now();
+        let caches = parse_slabinfo(&content, self.page_size)?;
+
+        for statistic in &self.statistics {
+            let total = caches.iter().map(|cache| cache.value(*statistic)).sum();
+            self.record_gauge(statistic, time, total);
+        }
+
+        // there are hundreds of caches, so only those which are configured
+        // are exported individually
+        let config = self.common.config().samplers().slab();
+        for cache in caches
+            .iter()
+            .filter(|cache| config.exports_cache(&cache.name))
+        {
+            for statistic in &self.statistics {
+                let value = cache.value(*statistic);
+                let statistic = LabeledStatistic::new(statistic, "cache", &cache.name);
+                self.common.register_once(&statistic);
+                self.common.record_gauge(&statistic, time, value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A row of `/proc/slabinfo`
+#[derive(Debug, PartialEq)]
+struct Cache {
+    name: String,
+    active_objects: u64,
+    objects: u64,
+    /// bytes of memory held by the slabs of the cache
+    size: u64,
+}
+
+impl Cache {
+    fn value(&self, statistic: SlabStatistic) -> u64 {
+        match statistic {
+            SlabStatistic::ActiveObjects => self.active_objects,
+            SlabStatistic::Objects => self.objects,
+            SlabStatistic::Size => self.size,
+        }
+    }
+}
+
+/// Parse `/proc/slabinfo`. The first line names the version of the format, of
+/// which only 2.x is understood, and the second names the columns, which are
+/// located by name as the set of tunables has varied between allocators. The
+/// size of a cache is that of its slabs, each of which is a number of pages.
+fn parse_slabinfo(content: &str, page_size: u64) -> Result<Vec<Cache>, Error> {
+    let invalid =
+        |reason: &str| Error::new(ErrorKind::InvalidData, format!("/proc/slabinfo {}", reason));
+    let mut lines = content.lines();
+    match lines
+        .next()
+        .and_then(|line| line.strip_prefix("slabinfo - version: "))
+    {
+        Some(version) if version.starts_with("2.") => {}
+        Some(version) => return Err(invalid(&format!("has unknown version {}", version))),
+        None => return Err(invalid("is missing its version")),
+    }
+    let header: Vec<&str> = lines
+        .next()
+        .and_then(|line| line.strip_prefix('#'))
+        .ok_or_else(|| invalid("is missing its header"))?
+        .split_whitespace()
+        .collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| invalid(&format!("is missing the {} column", name)))
+    };
+    let active_objects = column("<active_objs>")?;
+    let objects = column("<num_objs>")?;
+    let pages_per_slab = column("<pagesperslab>")?;
+    let slabs = column("<num_slabs>")?;
+
+    let mut caches = Vec::new();
+    for line in lines {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.is_empty() {
+            continue;
+        }
+        let value = |index: usize| {
+            columns
+                .get(index)
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or_else(|| invalid(&format!("has a malformed row for {}", columns[0])))
+        };
+        caches.push(Cache {
+            name: columns[0].to_string(),
+            active_objects: value(active_objects)?,
+            objects: value(objects)?,
+            size: value(slabs)? * value(pages_per_slab)? * page_size,
+        });
+    }
+    Ok(caches)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+    use rustcommon_metrics::*;
+
+    const SLABINFO: &str = "slabinfo - version: 2.1
+# name            <active_objs> <num_objs> <objsize> <objperslab> <pagesperslab> : tunables <limit> <batchcount> <sharedfactor> : slabdata <active_slabs> <num_slabs> <sharedavail>
+dentry            185430 187929    192   21    1 : tunables    0    0    0 : slabdata   8949   8949      0
+inode_cache        25326  25704    600   27    4 : tunables    0    0    0 : slabdata    952    952      0
+kmalloc-64         30208  30272     64   64    1 : tunables    0    0    0 : slabdata    473    473      0
+task_struct         1012   1050   6080    5    8 : tunables    0    0    0 : slabdata    210    210      0
+";
+
+    #[test]
+    fn test_parse_slabinfo() {
+        let caches = parse_slabinfo(SLABINFO, 4096).unwrap();
+        assert_eq!(caches.len(), 4);
+        assert_eq!(
+            caches[1],
+            Cache {
+                name: "inode_cache".to_string(),
+                active_objects: 25326,
+                objects: 25704,
+                size: 952 * 4 * 4096,
+            }
+        );
+
+        assert!(parse_slabinfo("slabinfo - version: 1.1\n", 4096).is_err());
+        assert!(parse_slabinfo("slabinfo - version: 2.1\n", 4096).is_err());
+        assert!(parse_slabinfo(
+            "slabinfo - version: 2.1\n# name <active_objs> <num_objs>\n",
+            4096
+        )
+        .is_err());
+        let truncated = format!("{}kmalloc-8 512\n", SLABINFO);
+        assert!(parse_slabinfo(&truncated, 4096).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sample_slabinfo() {
+        let common = Common::with_fixtures(Fixtures::default().with("/proc/slabinfo", SLABINFO));
+        let mut sampler = Slab::new(common).unwrap();
+        sampler.page_size = 4096;
+        sampler.register();
+        sampler.sample_slabinfo().await.unwrap();
+
+        assert_eq!(
+            sampler.common().reading("slab/active_objects"),
+            Some(185430 + 25326 + 30208 + 1012)
+        );
+        assert_eq!(
+            sampler.common().reading("slab/size"),
+            Some((8949 + 952 * 4 + 473 + 210 * 8) * 4096)
+        );
+        assert_eq!(
+            sampler.common().reading("slab/objects/dentry"),
+            Some(187929)
+        );
+        assert_eq!(
+            sampler.common().reading("slab/size/kmalloc-64"),
+            Some(473 * 4096)
+        );
+        // not one of the default caches
+        assert_eq!(sampler.common().reading("slab/objects/task_struct"), None);
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum SlabStatistic {
+    #[strum(serialize = "slab/active_objects")]
+    ActiveObjects,
+    #[strum(serialize = "slab/objects")]
+    Objects,
+    #[strum(serialize = "slab/size")]
+    Size,
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SlabStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        Source::Gauge
+    }
+}
+
+impl StatisticUnit for SlabStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::Size => Some(Unit::Bytes),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for SlabStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        SlabStatistic::from_str(s)
+    }
+}
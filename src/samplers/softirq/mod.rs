@@ -0,0 +1,159 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+
+use async_trait::async_trait;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::common::labeled::LabeledStatistic;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+pub struct Softirq {
+    common: Common,
+    proc_softirqs: Option<File>,
+    statistics: Vec<SoftirqStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Softirq {
+    type Statistic = SoftirqStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().softirq().statistics();
+        let sampler = Self {
+            common,
+            proc_softirqs: None,
+            statistics,
+        };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().softirq().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().softirq())
+            {
+                fatal!("failed to initialize softirq sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize softirq sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().softirq()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_softirqs().await;
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl Softirq {
+    async fn sample_softirqs(&mut self) -> Result<(), std::io::Error> {
+        if self.proc_softirqs.is_none() {
+            let file = File::open("/proc/softirqs").await?;
+            self.proc_softirqs = Some(file);
+        }
+
+        if let Some(file) = &mut self.proc_softirqs {
+            file.seek(SeekFrom::Start(0)).await?;
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            let mut result = HashMap::new();
+
+            // the first line is a header of cpu names, each following line is
+            // a row of per-cpu counts for one type of softirq
+            reader.read_line(&mut line).await?;
+            line.clear();
+            while reader.read_line(&mut line).await? > 0 {
+                if let Some((key, counts)) = parse_line(&line) {
+                    result.insert(key, counts);
+                }
+                line.clear();
+            }
+
+            let time = self.common.now();
+            let per_cpu = self.common.config().samplers().softirq().per_cpu();
+            for statistic in &self.statistics {
+                if let Some(counts) = result.get(statistic.key()) {
+                    self.record_counter(statistic, time, counts.iter().sum());
+                    if per_cpu {
+                        for (cpu, count) in counts.iter().enumerate() {
+                            let statistic =
+                                LabeledStatistic::new(statistic, "cpu", &cpu.to_string());
+                            self.common.register_once(&statistic);
+                            self.common.record_counter(&statistic, time, *count);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a row of `/proc/softirqs` into the softirq type and the count for
+/// each cpu, eg: `NET_RX:  10  20` becomes `("NET_RX", [10, 20])`
+fn parse_line(line: &str) -> Option<(String, Vec<u64>)> {
+    let mut parts = line.split_whitespace();
+    let key = parts.next()?.strip_suffix(':')?.to_string();
+    let counts = parts.map(|v| v.parse().unwrap_or(0)).collect();
+    Some((key, counts))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("      NET_RX:     123456       7890\n"),
+            Some(("NET_RX".to_string(), vec![123456, 7890]))
+        );
+        assert_eq!(
+            parse_line("    IRQ_POLL:          0          0\n"),
+            Some(("IRQ_POLL".to_string(), vec![0, 0]))
+        );
+        assert_eq!(parse_line("        CPU0       CPU1\n"), None);
+    }
+}
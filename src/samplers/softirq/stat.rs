@@ -0,0 +1,87 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::StatisticUnit;
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum SoftirqStatistic {
+    #[strum(serialize = "softirq/hi")]
+    Hi,
+    #[strum(serialize = "softirq/timer")]
+    Timer,
+    #[strum(serialize = "softirq/net_tx")]
+    NetTx,
+    #[strum(serialize = "softirq/net_rx")]
+    NetRx,
+    #[strum(serialize = "softirq/block")]
+    Block,
+    #[strum(serialize = "softirq/irq_poll")]
+    IrqPoll,
+    #[strum(serialize = "softirq/tasklet")]
+    Tasklet,
+    #[strum(serialize = "softirq/sched")]
+    Sched,
+    #[strum(serialize = "softirq/hr_timer")]
+    HrTimer,
+    #[strum(serialize = "softirq/rcu")]
+    Rcu,
+}
+
+impl SoftirqStatistic {
+    /// The row label in `/proc/softirqs`
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Hi => "HI",
+            Self::Timer => "TIMER",
+            Self::NetTx => "NET_TX",
+            Self::NetRx => "NET_RX",
+            Self::Block => "BLOCK",
+            Self::IrqPoll => "IRQ_POLL",
+            Self::Tasklet => "TASKLET",
+            Self::Sched => "SCHED",
+            Self::HrTimer => "HRTIMER",
+            Self::Rcu => "RCU",
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SoftirqStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        Source::Counter
+    }
+}
+
+impl StatisticUnit for SoftirqStatistic {}
+
+impl TryFrom<&str> for SoftirqStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        SoftirqStatistic::from_str(s)
+    }
+}
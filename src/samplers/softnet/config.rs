@@ -13,10 +13,19 @@ use super::stat::*;
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SoftnetConfig {
+    #[serde(default)]
+    disabled: Vec<SoftnetStatistic>,
     #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(default)]
+    per_cpu: AtomicBool,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
     #[serde(default = "default_statistics")]
@@ -26,14 +35,25 @@ pub struct SoftnetConfig {
 impl Default for SoftnetConfig {
     fn default() -> Self {
         Self {
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
+            per_cpu: Default::default(),
             percentiles: crate::common::default_percentiles(),
             statistics: default_statistics(),
         }
     }
 }
 
+impl SoftnetConfig {
+    /// whether to also export the processed, dropped, and time squeezed
+    /// counters for each cpu
+    pub fn per_cpu(&self) -> bool {
+        self.per_cpu.load(Ordering::Relaxed)
+    }
+}
+
 fn default_statistics() -> Vec<SoftnetStatistic> {
     SoftnetStatistic::iter().collect()
 }
@@ -45,6 +65,10 @@ impl SamplerConfig for SoftnetConfig {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
@@ -53,7 +77,16 @@ impl SamplerConfig for SoftnetConfig {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
-        self.statistics.clone()
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
     }
 }
@@ -3,14 +3,11 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use std::collections::HashMap;
-use std::io::SeekFrom;
-use std::time::*;
 
 use async_trait::async_trait;
+use rustcommon_metrics::*;
 
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, BufReader};
-
+use crate::common::labeled::LabeledStatistic;
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
 use crate::Sampler;
@@ -23,7 +20,6 @@ pub use stat::*;
 
 pub struct Softnet {
     common: Common,
-    softnet_stat: Option<File>,
     statistics: Vec<SoftnetStatistic>,
 }
 
@@ -32,11 +28,7 @@ impl Sampler for Softnet {
     type Statistic = SoftnetStatistic;
     fn new(common: Common) -> Result<Self, anyhow::Error> {
         let statistics = common.config().samplers().softnet().statistics();
-        let sampler = Self {
-            common,
-            softnet_stat: None,
-            statistics,
-        };
+        let sampler = Self { common, statistics };
         if sampler.sampler_config().enabled() {
             sampler.register();
         }
@@ -47,13 +39,15 @@ impl Sampler for Softnet {
         if common.config().samplers().softnet().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().softnet())
+            {
                 fatal!("failed to initialize softnet sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize softnet sampler");
             }
         }
@@ -72,10 +66,6 @@ impl Sampler for Softnet {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -91,38 +81,100 @@ impl Sampler for Softnet {
 
 impl Softnet {
     async fn sample_softnet_stats(&mut self) -> Result<(), std::io::Error> {
-        if self.softnet_stat.is_none() {
-            let file = File::open("/proc/net/softnet_stat").await?;
-            self.softnet_stat = Some(file);
+        let content = self
+            .common
+            .proc()
+            .read_to_string("/proc/net/softnet_stat")
+            .await?;
+        let rows = parse_softnet_stat(&content);
+        let time = self.common.now();
+
+        let mut totals = HashMap::<SoftnetStatistic, u64>::new();
+        for (_, row) in &rows {
+            for (statistic, value) in row {
+                *totals.entry(*statistic).or_insert(0) += value;
+            }
+        }
+        for statistic in &self.statistics {
+            if let Some(value) = totals.get(statistic) {
+                self.record_counter(statistic, time, *value);
+            }
         }
 
-        if let Some(file) = &mut self.softnet_stat {
-            file.seek(SeekFrom::Start(0)).await?;
-            let mut reader = BufReader::new(file);
-            let mut line = String::new();
-            let mut result = HashMap::<SoftnetStatistic, u64>::new();
-
-            while reader.read_line(&mut line).await? > 0 {
-                for (id, part) in line.split_whitespace().enumerate() {
-                    if let Some(statistic) = num::FromPrimitive::from_usize(id) {
-                        if !result.contains_key(&statistic) {
-                            result.insert(statistic, 0);
-                        }
-                        let current = result.get_mut(&statistic).unwrap();
-                        *current += u64::from_str_radix(part, 16).unwrap_or(0);
+        if self.common.config().samplers().softnet().per_cpu() {
+            for (cpu, row) in &rows {
+                for statistic in self.statistics.iter().filter(|s| s.per_cpu()) {
+                    if let Some(value) = row.get(statistic) {
+                        let statistic = LabeledStatistic::new(statistic, "cpu", &cpu.to_string());
+                        self.common.register_once(&statistic);
+                        self.common.record_counter(&statistic, time, *value);
                     }
                 }
-                line.clear();
             }
+        }
 
-            let time = Instant::now();
-            for statistic in &self.statistics {
-                if let Some(value) = result.get(statistic) {
-                    let _ = self.metrics().record_counter(statistic, time, *value);
+        Ok(())
+    }
+}
+
+/// Column of the cpu id, which is only present on kernels since 5.10
+const CPU_COLUMN: usize = 12;
+
+/// Parse `/proc/net/softnet_stat`, which has a row of hexadecimal counters for
+/// each online cpu. The counters are identified by their column. Rows are
+/// returned with their cpu id, or their index if the kernel does not report it.
+fn parse_softnet_stat(content: &str) -> Vec<(usize, HashMap<SoftnetStatistic, u64>)> {
+    let mut rows = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.is_empty() {
+            continue;
+        }
+        let cpu = columns
+            .get(CPU_COLUMN)
+            .and_then(|v| usize::from_str_radix(v, 16).ok())
+            .unwrap_or(index);
+        let mut row = HashMap::new();
+        for (column, value) in columns.iter().enumerate() {
+            if let Some(statistic) = num::FromPrimitive::from_usize(column) {
+                if let Ok(value) = u64::from_str_radix(value, 16) {
+                    row.insert(statistic, value);
                 }
             }
         }
+        rows.push((cpu, row));
+    }
+    rows
+}
 
-        Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_softnet_stat() {
+        // cpu 1 is offline, so the second row is cpu 2
+        let content = "\
+000f4240 00000002 0000001a 00000000 00000000 00000000 00000000 00000000 00000003 00000004 00000005 00000000 00000000
+000000ff 00000000 00000001 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000002
+";
+        let rows = parse_softnet_stat(content);
+        assert_eq!(rows.len(), 2);
+        let (cpu, row) = &rows[0];
+        assert_eq!(*cpu, 0);
+        assert_eq!(row.get(&SoftnetStatistic::Processed), Some(&1_000_000));
+        assert_eq!(row.get(&SoftnetStatistic::Dropped), Some(&2));
+        assert_eq!(row.get(&SoftnetStatistic::TimeSqueezed), Some(&26));
+        assert_eq!(row.get(&SoftnetStatistic::CpuCollision), Some(&3));
+        assert_eq!(row.get(&SoftnetStatistic::ReceivedRps), Some(&4));
+        assert_eq!(row.get(&SoftnetStatistic::FlowLimitCount), Some(&5));
+        let (cpu, row) = &rows[1];
+        assert_eq!(*cpu, 2);
+        assert_eq!(row.get(&SoftnetStatistic::Processed), Some(&255));
+
+        // older kernels have neither the backlog length nor the cpu id
+        let rows = parse_softnet_stat("00000010 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000\n00000020 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000\n");
+        assert_eq!(rows[1].0, 1);
+        assert_eq!(rows[1].1.get(&SoftnetStatistic::Processed), Some(&32));
     }
 }
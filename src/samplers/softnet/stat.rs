@@ -5,12 +5,14 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::unit::StatisticUnit;
 use num_derive::FromPrimitive;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
 use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 
+/// The discriminant is the column of the counter in `/proc/net/softnet_stat`
 #[derive(
     Clone,
     Copy,
@@ -34,11 +36,18 @@ pub enum SoftnetStatistic {
     #[strum(serialize = "softnet/time_squeezed")]
     TimeSqueezed = 2,
     #[strum(serialize = "softnet/cpu_collision")]
-    CpuCollision = 3,
+    CpuCollision = 8,
     #[strum(serialize = "softnet/received_rps")]
-    ReceivedRps = 4,
+    ReceivedRps = 9,
     #[strum(serialize = "softnet/flow_limit_count")]
-    FlowLimitCount = 5,
+    FlowLimitCount = 10,
+}
+
+impl SoftnetStatistic {
+    /// Whether the statistic is also exported for each cpu with `per_cpu`
+    pub fn per_cpu(self) -> bool {
+        matches!(self, Self::Processed | Self::Dropped | Self::TimeSqueezed)
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for SoftnetStatistic {
@@ -51,6 +60,8 @@ impl Statistic<AtomicU64, AtomicU32> for SoftnetStatistic {
     }
 }
 
+impl StatisticUnit for SoftnetStatistic {}
+
 impl TryFrom<&str> for SoftnetStatistic {
     type Error = ParseError;
 
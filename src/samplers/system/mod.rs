@@ -0,0 +1,378 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+
+use async_trait::async_trait;
+use strum::IntoEnumIterator;
+
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+/// The largest record which can be read from `/dev/kmsg`. A smaller buffer
+/// fails the read rather than truncating the record.
+const KMSG_RECORD_MAX: usize = 8192;
+
+pub struct System {
+    common: Common,
+    kernel_events: HashMap<SystemStatistic, u64>,
+    kmsg: Option<std::fs::File>,
+    proc_uptime: Option<File>,
+    statistics: Vec<SystemStatistic>,
+}
+
+#[async_trait]
+impl Sampler for System {
+    type Statistic = SystemStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().system().statistics();
+        let sampler = Self {
+            common,
+            kernel_events: HashMap::new(),
+            kmsg: None,
+            proc_uptime: None,
+            statistics,
+        };
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().system().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().system())
+            {
+                fatal!("failed to initialize system sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize system sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().system()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        let r = self.sample_uptime().await;
+        self.map_result(r)?;
+
+        let r = self.sample_entropy().await;
+        self.map_result(r)?;
+
+        let r = self.sample_fs().await;
+        self.map_result(r)?;
+
+        let r = self.sample_kernel_log();
+        self.map_result(r)?;
+
+        Ok(())
+    }
+}
+
+impl System {
+    async fn sample_uptime(&mut self) -> Result<(), std::io::Error> {
+        if self.proc_uptime.is_none() {
+            let file = File::open("/proc/uptime").await?;
+            self.proc_uptime = Some(file);
+        }
+
+        if let Some(file) = &mut self.proc_uptime {
+            file.seek(SeekFrom::Start(0)).await?;
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? > 0 {
+                // the first field is the seconds since boot, with a fractional
+                // part which we discard
+                let uptime: Option<u64> = line
+                    .split_whitespace()
+                    .next()
+                    .and_then(|v| v.split('.').next())
+                    .and_then(|v| v.parse().ok());
+
+                let time = self.common.now();
+                if let Some(uptime) = uptime {
+                    for statistic in &self.statistics {
+                        if *statistic == SystemStatistic::Uptime {
+                            self.record_gauge(statistic, time, uptime);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn sample_entropy(&mut self) -> Result<(), std::io::Error> {
+        for statistic in &self.statistics {
+            if let Some(path) = statistic.entropy_file() {
+                let content = self.common.proc().read_to_string(path).await?;
+                if let Ok(value) = content.trim().parse::<u64>() {
+                    self.record_gauge(statistic, self.common.now(), value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the file handle and inode usage from `/proc/sys/fs`. Each file is
+    /// only read once, even if it holds more than one enabled statistic.
+    async fn sample_fs(&mut self) -> Result<(), std::io::Error> {
+        let mut files: HashMap<&str, String> = HashMap::new();
+        for statistic in &self.statistics {
+            if let Some((path, _)) = statistic.fs_field() {
+                if !files.contains_key(path) {
+                    let content = self.common.proc().read_to_string(path).await?;
+                    files.insert(path, content);
+                }
+            }
+        }
+
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some((path, index)) = statistic.fs_field() {
+                let value = files
+                    .get(path)
+                    .and_then(|content| content.split_whitespace().nth(index))
+                    .and_then(|v| v.parse::<u64>().ok());
+                if let Some(value) = value {
+                    self.record_gauge(statistic, time, value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count the soft lockups and hung tasks reported in the kernel log. The
+    /// log is read from `/dev/kmsg`, which returns one record per read. The
+    /// first read starts from the oldest record still in the ring buffer and
+    /// each later one continues from where the previous sample stopped. It is
+    /// opened non-blocking, so a read fails with `WouldBlock` once every
+    /// record has been read, rather than waiting for the next one.
+    fn sample_kernel_log(&mut self) -> Result<(), std::io::Error> {
+        if !self
+            .statistics
+            .iter()
+            .any(|s| s.kernel_log_pattern().is_some())
+        {
+            return Ok(());
+        }
+
+        if self.kmsg.is_none() {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open("/dev/kmsg");
+            match file {
+                Ok(file) => self.kmsg = Some(file),
+                Err(e) => return self.kernel_log_unavailable(e),
+            }
+        }
+
+        if let Some(file) = &mut self.kmsg {
+            let mut buf = vec![0; KMSG_RECORD_MAX];
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => {
+                        let record = String::from_utf8_lossy(&buf[..len]);
+                        if let Some(statistic) = kernel_event(&record) {
+                            *self.kernel_events.entry(statistic).or_insert(0) += 1;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    // records were overwritten before they could be read, and
+                    // the next read continues from the oldest remaining one
+                    Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if statistic.kernel_log_pattern().is_some() {
+                let count = self.kernel_events.get(statistic).copied().unwrap_or(0);
+                self.record_counter(statistic, time, count);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop counting the kernel log events if `/dev/kmsg` cannot be opened
+    /// because it is restricted or missing, eg: with `kernel.dmesg_restrict`
+    /// and without `CAP_SYSLOG`, or in most containers. This is warned about
+    /// once, rather than failing every sample. Other errors are returned.
+    fn kernel_log_unavailable(&mut self, e: std::io::Error) -> Result<(), std::io::Error> {
+        match e.kind() {
+            ErrorKind::PermissionDenied | ErrorKind::NotFound => {
+                warn!(
+                    "disabling the kernel log statistics, as /dev/kmsg cannot be read: {}",
+                    e
+                );
+                self.statistics
+                    .retain(|statistic| statistic.kernel_log_pattern().is_none());
+                Ok(())
+            }
+            _ => Err(e),
+        }
+    }
+}
+
+/// The statistic which counts the event reported by a `/dev/kmsg` record, if
+/// any. Records are a header of comma separated fields followed by a `;` and
+/// the message, eg: `0,1127,2841377,-;watchdog: BUG: soft lockup - CPU#3 ...`
+fn kernel_event(record: &str) -> Option<SystemStatistic> {
+    let message = record.splitn(2, ';').nth(1)?;
+    SystemStatistic::iter().find(|statistic| {
+        statistic
+            .kernel_log_pattern()
+            .map(|pattern| message.contains(pattern))
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+    use rustcommon_metrics::*;
+
+    #[tokio::test]
+    async fn test_sample_entropy() {
+        let common = Common::with_fixtures(
+            Fixtures::default()
+                .with("/proc/sys/kernel/random/entropy_avail", "3754\n")
+                .with("/proc/sys/kernel/random/poolsize", "4096\n"),
+        );
+        let mut sampler = System::new(common).unwrap();
+        sampler.register();
+        sampler.sample_entropy().await.unwrap();
+
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SystemStatistic::EntropyAvailable.name()),
+            Some(3754)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SystemStatistic::EntropyPoolsize.name()),
+            Some(4096)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sample_fs() {
+        let common = Common::with_fixtures(
+            Fixtures::default()
+                .with("/proc/sys/fs/file-nr", "12640\t0\t9223372036854775807\n")
+                .with("/proc/sys/fs/inode-nr", "348912\t71245\n"),
+        );
+        let mut sampler = System::new(common).unwrap();
+        sampler.register();
+        sampler.sample_fs().await.unwrap();
+
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SystemStatistic::FileDescriptorsAllocated.name()),
+            Some(12640)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SystemStatistic::FileDescriptorsMax.name()),
+            Some(9223372036854775807)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(SystemStatistic::InodesAllocated.name()),
+            Some(348912)
+        );
+        assert_eq!(
+            sampler.common().reading(SystemStatistic::InodesFree.name()),
+            Some(71245)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kernel_log_unavailable() {
+        let common = Common::with_fixtures(Fixtures::default());
+        let mut sampler = System::new(common).unwrap();
+        assert!(sampler.statistics.contains(&SystemStatistic::SoftLockups));
+
+        let other = std::io::Error::new(ErrorKind::Other, "failed");
+        assert!(sampler.kernel_log_unavailable(other).is_err());
+        assert!(sampler.statistics.contains(&SystemStatistic::SoftLockups));
+
+        // the statistics are dropped, so the log is not opened again
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(sampler.kernel_log_unavailable(denied).is_ok());
+        assert!(!sampler.statistics.contains(&SystemStatistic::SoftLockups));
+        assert!(!sampler.statistics.contains(&SystemStatistic::HungTasks));
+        assert!(sampler.sample_kernel_log().is_ok());
+        assert!(sampler.kmsg.is_none());
+    }
+
+    #[test]
+    fn test_kernel_event() {
+        assert_eq!(
+            kernel_event("0,1127,2841377,-;watchdog: BUG: soft lockup - CPU#3 stuck for 22s!\n"),
+            Some(SystemStatistic::SoftLockups)
+        );
+        assert_eq!(
+            kernel_event(
+                "3,1130,2961377,-;INFO: task jbd2/sda1-8:312 blocked for more than 120 seconds.\n"
+            ),
+            Some(SystemStatistic::HungTasks)
+        );
+        assert_eq!(
+            kernel_event("6,1131,2961400,-;e1000e: eth0 NIC Link is Up 1000 Mbps Full Duplex\n"),
+            None
+        );
+        // continuation lines of a record are not messages
+        assert_eq!(kernel_event(" SUBSYSTEM=soft lockup\n"), None);
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::StatisticUnit;
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum SystemStatistic {
+    #[strum(serialize = "system/uptime")]
+    Uptime,
+    #[strum(serialize = "system/entropy/available")]
+    EntropyAvailable,
+    #[strum(serialize = "system/entropy/poolsize")]
+    EntropyPoolsize,
+    #[strum(serialize = "system/lockup/soft")]
+    SoftLockups,
+    #[strum(serialize = "system/lockup/hung_task")]
+    HungTasks,
+    #[strum(serialize = "system/file_descriptors/allocated")]
+    FileDescriptorsAllocated,
+    #[strum(serialize = "system/file_descriptors/max")]
+    FileDescriptorsMax,
+    #[strum(serialize = "system/inodes/allocated")]
+    InodesAllocated,
+    #[strum(serialize = "system/inodes/free")]
+    InodesFree,
+}
+
+impl SystemStatistic {
+    /// The file under `/proc/sys/kernel/random` which holds the value of an
+    /// entropy statistic
+    pub fn entropy_file(self) -> Option<&'static str> {
+        match self {
+            Self::EntropyAvailable => Some("/proc/sys/kernel/random/entropy_avail"),
+            Self::EntropyPoolsize => Some("/proc/sys/kernel/random/poolsize"),
+            _ => None,
+        }
+    }
+
+    /// The file under `/proc/sys/fs` and the index of the whitespace separated
+    /// field in it which holds the value of the statistic
+    pub fn fs_field(self) -> Option<(&'static str, usize)> {
+        match self {
+            Self::FileDescriptorsAllocated => Some(("/proc/sys/fs/file-nr", 0)),
+            Self::FileDescriptorsMax => Some(("/proc/sys/fs/file-nr", 2)),
+            Self::InodesAllocated => Some(("/proc/sys/fs/inode-nr", 0)),
+            Self::InodesFree => Some(("/proc/sys/fs/inode-nr", 1)),
+            _ => None,
+        }
+    }
+
+    /// Text which identifies a kernel log message as an event counted by the
+    /// statistic
+    pub fn kernel_log_pattern(self) -> Option<&'static str> {
+        match self {
+            // eg: `watchdog: BUG: soft lockup - CPU#3 stuck for 22s!`
+            Self::SoftLockups => Some("soft lockup"),
+            // eg: `INFO: task kworker/1:2:123 blocked for more than 120 seconds.`
+            Self::HungTasks => Some("blocked for more than"),
+            _ => None,
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for SystemStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        if self.kernel_log_pattern().is_some() {
+            Source::Counter
+        } else {
+            Source::Gauge
+        }
+    }
+}
+
+impl StatisticUnit for SystemStatistic {}
+
+impl TryFrom<&str> for SystemStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        SystemStatistic::from_str(s)
+    }
+}
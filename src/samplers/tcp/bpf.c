@@ -7,6 +7,8 @@
 #include <uapi/linux/ptrace.h>
 #include <net/sock.h>
 #include <net/tcp_states.h>
+#include <linux/tcp.h>
+#include <net/tcp.h>
 #include <bcc/proto.h>
 
 struct info_t {
@@ -18,6 +20,20 @@ struct info_t {
 BPF_HASH(start, struct sock *, struct info_t);
 
 BPF_HISTOGRAM(connlat, int, 461);
+BPF_HISTOGRAM(srtt, int, 461);
+
+// single element counters
+BPF_ARRAY(connfail, u64, 1);
+BPF_ARRAY(connect, u64, 1);
+BPF_ARRAY(accept, u64, 1);
+BPF_ARRAY(retransmit, u64, 1);
+
+// connections established by congestion control algorithm name
+struct ca_name_t {
+    char name[TCP_CA_NAME_MAX];
+};
+
+BPF_HASH(congestion, struct ca_name_t, u64, 64);
 
 // histogram indexing
 static unsigned int value_to_index2(unsigned int value) {
@@ -60,14 +76,28 @@ int trace_connect(struct pt_regs *ctx, struct sock *sk)
     info.ts = bpf_ktime_get_ns();
     bpf_get_current_comm(&info.task, sizeof(info.task));
     start.update(&sk, &info);
+    int index = 0;
+    connect.increment(index);
     return 0;
 };
 
+// counts connections returned by accept(), which is shared with the other
+// connection oriented inet protocols, but these are rarely in use
+int trace_accept(struct pt_regs *ctx)
+{
+    struct sock *newsk = (struct sock *)PT_REGS_RC(ctx);
+    if (newsk == NULL)
+        return 0;
+    int index = 0;
+    accept.increment(index);
+    return 0;
+}
+
 // See tcp_v4_do_rcv() and tcp_v6_do_rcv(). So TCP_ESTBALISHED and TCP_LISTEN
 // are fast path and processed elsewhere, and leftovers are processed by
 // tcp_rcv_state_process(). We can trace this for handshake completion.
 // This should all be switched to static tracepoints when available.
-int trace_tcp_rcv_state_process(struct pt_regs *ctx, struct sock *skp)
+int trace_tcp_rcv_state_process(struct pt_regs *ctx, struct sock *skp, struct sk_buff *skb)
 {
     // will be in TCP_SYN_SENT for handshake
     if (skp->__sk_common.skc_state != TCP_SYN_SENT)
@@ -77,6 +107,16 @@ int trace_tcp_rcv_state_process(struct pt_regs *ctx, struct sock *skp)
     if (infop == 0) {
         return 0;   // missed entry or filtered
     }
+    // a reset in reply to the syn means the connection was refused, which is
+    // counted as a failure rather than included in the latency
+    struct tcphdr th = {};
+    bpf_probe_read(&th, sizeof(th), skb->head + skb->transport_header);
+    if (th.rst) {
+        int index = 0;
+        connfail.increment(index);
+        start.delete(&skp);
+        return 0;
+    }
     u64 ts = infop->ts;
     u64 now = bpf_ktime_get_ns();
     u64 delta_us = (now - ts) / 1000ul;
@@ -85,4 +125,40 @@ int trace_tcp_rcv_state_process(struct pt_regs *ctx, struct sock *skp)
 
     start.delete(&skp);
     return 0;
-}
\ No newline at end of file
+}
+
+int trace_retransmit(struct pt_regs *ctx, struct sock *sk)
+{
+    int index = 0;
+    retransmit.increment(index);
+    return 0;
+}
+
+// sampled on each segment received on an established connection, so busier
+// connections are weighted more heavily
+int trace_rcv_established(struct pt_regs *ctx, struct sock *sk)
+{
+    struct tcp_sock *tp = tcp_sk(sk);
+    u32 srtt_us = 0;
+    bpf_probe_read(&srtt_us, sizeof(srtt_us), &tp->srtt_us);
+    // the kernel keeps the smoothed rtt in microseconds shifted left by 3
+    u64 index = value_to_index2(srtt_us >> 3);
+    srtt.increment(index);
+    return 0;
+}
+
+int trace_set_state(struct pt_regs *ctx, struct sock *sk, int state)
+{
+    if (state != TCP_ESTABLISHED)
+        return 0;
+    struct ca_name_t key = {};
+    struct inet_connection_sock *icsk = inet_csk(sk);
+    const struct tcp_congestion_ops *ops = NULL;
+    bpf_probe_read(&ops, sizeof(ops), &icsk->icsk_ca_ops);
+    // the algorithm is counted as unknown if it can't be read
+    if (ops == NULL || bpf_probe_read_str(&key.name, sizeof(key.name), ops->name) <= 1) {
+        __builtin_memcpy(&key.name, "unknown", 8);
+    }
+    congestion.increment(key);
+    return 0;
+}
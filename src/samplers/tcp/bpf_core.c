@@ -0,0 +1,194 @@
+// Copyright 2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+// CO-RE version of bpf.c which is compiled ahead of time and relocated
+// against the running kernel's BTF at load time.
+
+#include "vmlinux.h"
+#include <bpf/bpf_core_read.h>
+#include <bpf/bpf_helpers.h>
+#include <bpf/bpf_tracing.h>
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 10240);
+    __type(key, struct sock *);
+    __type(value, u64);
+} start SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 461);
+    __type(key, u32);
+    __type(value, u64);
+} connlat SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 461);
+    __type(key, u32);
+    __type(value, u64);
+} srtt SEC(".maps");
+
+// single element counters
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 1);
+    __type(key, u32);
+    __type(value, u64);
+} connfail SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 1);
+    __type(key, u32);
+    __type(value, u64);
+} connect SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 1);
+    __type(key, u32);
+    __type(value, u64);
+} accept SEC(".maps");
+
+struct {
+    __uint(type, BPF_MAP_TYPE_ARRAY);
+    __uint(max_entries, 1);
+    __type(key, u32);
+    __type(value, u64);
+} retransmit SEC(".maps");
+
+// connections established by congestion control algorithm name
+struct ca_name_t {
+    char name[16];
+};
+
+struct {
+    __uint(type, BPF_MAP_TYPE_HASH);
+    __uint(max_entries, 64);
+    __type(key, struct ca_name_t);
+    __type(value, u64);
+} congestion SEC(".maps");
+
+static __always_inline void increment(void *map)
+{
+    u32 index = 0;
+    u64 *count = bpf_map_lookup_elem(map, &index);
+    if (count) {
+        __sync_fetch_and_add(count, 1);
+    }
+}
+
+// histogram indexing, see bpf.c
+static __always_inline u32 value_to_index2(u64 value) {
+    if (value < 100) {
+        return value;
+    } else if (value < 1000) {
+        return 90 + value / 10;
+    } else if (value < 10000) {
+        return 180 + value / 100;
+    } else if (value < 100000) {
+        return 270 + value / 1000;
+    } else if (value < 1000000) {
+        return 360 + value / 10000;
+    }
+    return 460;
+}
+
+SEC("kprobe/tcp_v4_connect")
+int BPF_KPROBE(trace_connect, struct sock *sk)
+{
+    u64 ts = bpf_ktime_get_ns();
+    bpf_map_update_elem(&start, &sk, &ts, BPF_ANY);
+    increment(&connect);
+    return 0;
+}
+
+// counts connections returned by accept(), see bpf.c
+SEC("kretprobe/inet_csk_accept")
+int BPF_KRETPROBE(trace_accept, struct sock *newsk)
+{
+    if (newsk == NULL)
+        return 0;
+    increment(&accept);
+    return 0;
+}
+
+SEC("kprobe/tcp_rcv_state_process")
+int BPF_KPROBE(trace_tcp_rcv_state_process, struct sock *sk, struct sk_buff *skb)
+{
+    // will be in TCP_SYN_SENT for handshake
+    if (BPF_CORE_READ(sk, __sk_common.skc_state) != TCP_SYN_SENT)
+        return 0;
+    // check start and calculate delta
+    u64 *tsp = bpf_map_lookup_elem(&start, &sk);
+    if (tsp == 0) {
+        return 0;   // missed entry or filtered
+    }
+    // a reset in reply to the syn is a failure, see bpf.c
+    struct tcphdr th = {};
+    unsigned char *head = BPF_CORE_READ(skb, head);
+    u16 offset = BPF_CORE_READ(skb, transport_header);
+    bpf_probe_read_kernel(&th, sizeof(th), head + offset);
+    if (th.rst) {
+        increment(&connfail);
+        bpf_map_delete_elem(&start, &sk);
+        return 0;
+    }
+    u64 delta_us = (bpf_ktime_get_ns() - *tsp) / 1000ul;
+    u32 index = value_to_index2(delta_us);
+    u64 *count = bpf_map_lookup_elem(&connlat, &index);
+    if (count) {
+        __sync_fetch_and_add(count, 1);
+    }
+
+    bpf_map_delete_elem(&start, &sk);
+    return 0;
+}
+
+SEC("kprobe/tcp_retransmit_skb")
+int BPF_KPROBE(trace_retransmit, struct sock *sk)
+{
+    increment(&retransmit);
+    return 0;
+}
+
+// sampled on each segment received on an established connection, see bpf.c
+SEC("kprobe/tcp_rcv_established")
+int BPF_KPROBE(trace_rcv_established, struct sock *sk)
+{
+    struct tcp_sock *tp = (struct tcp_sock *)sk;
+    // the kernel keeps the smoothed rtt in microseconds shifted left by 3
+    u32 index = value_to_index2(BPF_CORE_READ(tp, srtt_us) >> 3);
+    u64 *count = bpf_map_lookup_elem(&srtt, &index);
+    if (count) {
+        __sync_fetch_and_add(count, 1);
+    }
+    return 0;
+}
+
+SEC("kprobe/tcp_set_state")
+int BPF_KPROBE(trace_set_state, struct sock *sk, int state)
+{
+    if (state != TCP_ESTABLISHED)
+        return 0;
+    struct ca_name_t key = {};
+    struct inet_connection_sock *icsk = (struct inet_connection_sock *)sk;
+    const struct tcp_congestion_ops *ops = BPF_CORE_READ(icsk, icsk_ca_ops);
+    // the algorithm is counted as unknown if it can't be read, see bpf.c
+    if (ops == NULL || bpf_probe_read_kernel_str(&key.name, sizeof(key.name), ops->name) <= 1) {
+        __builtin_memcpy(&key.name, "unknown", 8);
+    }
+    u64 one = 1;
+    u64 *count = bpf_map_lookup_elem(&congestion, &key);
+    if (count) {
+        __sync_fetch_and_add(count, 1);
+    } else {
+        bpf_map_update_elem(&congestion, &key, &one, BPF_NOEXIST);
+    }
+    return 0;
+}
+
+char LICENSE[] SEC("license") = "GPL";
@@ -6,7 +6,7 @@ use rustcommon_atomics::*;
 use serde_derive::Deserialize;
 use strum::IntoEnumIterator;
 
-use crate::config::SamplerConfig;
+use crate::config::{PercentileMode, SamplerConfig, DEFAULT_MAX_VALUE, DEFAULT_SIGFIGS};
 
 use super::stat::*;
 
@@ -16,11 +16,36 @@ pub struct TcpConfig {
     #[serde(default)]
     bpf: AtomicBool,
     #[serde(default)]
+    cumulative: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<TcpStatistic>,
+    #[serde(default)]
     enabled: AtomicBool,
     #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
     interval: Option<AtomicUsize>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_nanoseconds"
+    )]
+    max_value: Option<u64>,
+    #[serde(default)]
+    percentile_mode: PercentileMode,
+    #[serde(
+        default = "default_percentile_half_life",
+        deserialize_with = "crate::config::duration::milliseconds"
+    )]
+    percentile_half_life: AtomicUsize,
     #[serde(default = "crate::common::default_percentiles")]
     percentiles: Vec<f64>,
+    #[serde(default)]
+    ports: Vec<u16>,
+    #[serde(default)]
+    sigfigs: Option<u8>,
     #[serde(default = "default_statistics")]
     statistics: Vec<TcpStatistic>,
 }
@@ -29,14 +54,44 @@ impl Default for TcpConfig {
     fn default() -> Self {
         Self {
             bpf: Default::default(),
+            cumulative: Default::default(),
+            disabled: Default::default(),
             enabled: Default::default(),
+            fault_tolerant: Default::default(),
             interval: Default::default(),
+            max_value: Default::default(),
+            percentile_mode: Default::default(),
+            percentile_half_life: default_percentile_half_life(),
             percentiles: crate::common::default_percentiles(),
+            ports: Default::default(),
+            sigfigs: Default::default(),
             statistics: default_statistics(),
         }
     }
 }
 
+impl TcpConfig {
+    /// Whether percentiles are also computed from a decaying histogram
+    pub fn percentile_mode(&self) -> PercentileMode {
+        self.percentile_mode
+    }
+
+    /// Half-life in ms of the bucket counts when `percentile_mode` is `ewma`
+    pub fn percentile_half_life(&self) -> usize {
+        self.percentile_half_life.load(Ordering::Relaxed)
+    }
+
+    /// Local ports whose sockets are counted by state. None are counted if
+    /// this is empty.
+    pub fn ports(&self) -> &[u16] {
+        &self.ports
+    }
+}
+
+fn default_percentile_half_life() -> AtomicUsize {
+    AtomicUsize::new(10_000)
+}
+
 fn default_statistics() -> Vec<TcpStatistic> {
     TcpStatistic::iter().collect()
 }
@@ -48,18 +103,41 @@ impl SamplerConfig for TcpConfig {
         self.bpf.load(Ordering::Relaxed)
     }
 
+    fn cumulative(&self) -> bool {
+        self.cumulative.load(Ordering::Relaxed)
+    }
+
     fn enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
     fn interval(&self) -> Option<usize> {
         self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
     }
 
+    fn max_value(&self) -> u64 {
+        self.max_value.unwrap_or(DEFAULT_MAX_VALUE)
+    }
+
     fn percentiles(&self) -> &[f64] {
         &self.percentiles
     }
 
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn sigfigs(&self) -> u8 {
+        self.sigfigs.unwrap_or(DEFAULT_SIGFIGS)
+    }
+
     fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
         let mut enabled = Vec::new();
         for statistic in self.statistics.iter() {
@@ -71,6 +149,7 @@ impl SamplerConfig for TcpConfig {
                 enabled.push(statistic.clone());
             }
         }
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
         enabled
     }
 }
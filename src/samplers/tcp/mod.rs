@@ -2,14 +2,17 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
 use std::sync::{Arc, Mutex};
 use std::time::*;
-use tokio::fs::File;
 
 use async_trait::async_trait;
+use rustcommon_metrics::*;
 
 use crate::common::bpf::*;
-use crate::config::SamplerConfig;
+use crate::common::ewma::EwmaHistogram;
+use crate::config::{PercentileMode, SamplerConfig};
 use crate::samplers::{Common, Sampler};
 
 mod config;
@@ -18,13 +21,35 @@ mod stat;
 pub use config::*;
 pub use stat::*;
 
+/// Pairs of bpf handler and the kernel function it is attached to
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+const KPROBES: &[(&str, &str)] = &[
+    ("trace_connect", "tcp_v4_connect"),
+    ("trace_connect", "tcp_v6_connect"),
+    ("trace_tcp_rcv_state_process", "tcp_rcv_state_process"),
+    ("trace_retransmit", "tcp_retransmit_skb"),
+    ("trace_rcv_established", "tcp_rcv_established"),
+    ("trace_set_state", "tcp_set_state"),
+];
+
+/// Pairs of bpf handler and the kernel function whose return it is attached to
+#[cfg(any(feature = "bpf", feature = "bpf_core"))]
+const KRETPROBES: &[(&str, &str)] = &[("trace_accept", "inet_csk_accept")];
+
+#[cfg(feature = "bpf_core")]
+const CORE_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/tcp.bpf.o"));
+
 #[allow(dead_code)]
 pub struct Tcp {
     bpf: Option<Arc<Mutex<BPF>>>,
     bpf_last: Arc<Mutex<Instant>>,
+    bpf_totals: Arc<Mutex<HashMap<TcpStatistic, u64>>>,
     common: Common,
-    proc_net_snmp: Option<File>,
-    proc_net_netstat: Option<File>,
+    congestion: Arc<Mutex<HashSet<String>>>,
+    #[cfg(feature = "bpf_core")]
+    core: Option<Arc<Mutex<CoreBPF>>>,
+    ewma: Arc<Mutex<HashMap<TcpStatistic, EwmaHistogram>>>,
+    port_states: HashSet<(u16, &'static str)>,
     statistics: Vec<TcpStatistic>,
 }
 
@@ -32,27 +57,27 @@ pub struct Tcp {
 impl Sampler for Tcp {
     type Statistic = TcpStatistic;
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().tcp().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
+            bpf_totals: Arc::new(Mutex::new(HashMap::new())),
             common,
-            proc_net_snmp: None,
-            proc_net_netstat: None,
+            congestion: Arc::new(Mutex::new(HashSet::new())),
+            #[cfg(feature = "bpf_core")]
+            core: None,
+            ewma: Arc::new(Mutex::new(HashMap::new())),
+            port_states: HashSet::new(),
             statistics,
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
+            sampler.register_ewma();
         }
 
         Ok(sampler)
@@ -62,13 +87,15 @@ impl Sampler for Tcp {
         if common.config().samplers().tcp().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().tcp())
+            {
                 fatal!("failed to initialize tcp sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize tcp sampler");
             }
         }
@@ -87,10 +114,6 @@ impl Sampler for Tcp {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -103,20 +126,39 @@ impl Sampler for Tcp {
         let r = self.sample_netstat().await;
         self.map_result(r)?;
 
+        let r = self.sample_sockstat().await;
+        self.map_result(r)?;
+
+        let r = self.sample_ports().await;
+        self.map_result(r)?;
+
         // sample bpf
         #[cfg(feature = "bpf")]
+        self.retry_bpf();
+        #[cfg(any(feature = "bpf", feature = "bpf_core"))]
         self.map_result(self.sample_bpf())?;
 
         Ok(())
     }
-}
 
-impl Tcp {
+    fn drains_bpf(&self) -> bool {
+        cfg!(any(feature = "bpf", feature = "bpf_core"))
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+        #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+        self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
     // checks that bpf is enabled in config and one or more bpf stats enabled
-    #[cfg(feature = "bpf")]
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
     fn bpf_enabled(&self) -> bool {
         if self.sampler_config().bpf() {
-            for statistic in self.sampler_config().statistics() {
+            for statistic in &self.statistics {
                 if statistic.bpf_table().is_some() {
                     return true;
                 }
@@ -125,7 +167,48 @@ impl Tcp {
         false
     }
 
+    // whether either the bcc or CO-RE programs are loaded
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        #[cfg(feature = "bpf_core")]
+        {
+            if self.core.is_some() {
+                return true;
+            }
+        }
+        self.bpf.is_some()
+    }
+
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
+        #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+        self.common
+            .retain_supported(&mut self.statistics, |s| s.min_kernel());
+
+        // prefer the pre-compiled CO-RE object and fall back to runtime
+        // compilation with bcc if it cannot be loaded
+        #[cfg(feature = "bpf_core")]
+        {
+            if self.enabled() && self.bpf_enabled() {
+                match self.initialize_bpf_core() {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        if cfg!(not(feature = "bpf")) {
+                            return Err(e);
+                        }
+                        debug!("failed to load CO-RE bpf, falling back to bcc: {}", e);
+                    }
+                }
+            }
+        }
+
         #[cfg(feature = "bpf")]
         {
             if self.enabled() && self.bpf_enabled() {
@@ -134,19 +217,37 @@ impl Tcp {
                 let code = include_str!("bpf.c");
                 let mut bpf = bcc::BPF::new(code)?;
 
-                // load + attach kprobes!
-                bcc::Kprobe::new()
-                    .handler("trace_connect")
-                    .function("tcp_v4_connect")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_connect")
-                    .function("tcp_v6_connect")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_tcp_rcv_state_process")
-                    .function("tcp_rcv_state_process")
-                    .attach(&mut bpf)?;
+                // load + attach kprobes! each probe is attached independently
+                // so that eg: a kernel without ipv6 still gets ipv4 latency
+                let mut attached = 0;
+                for (handler, function) in KPROBES {
+                    match bcc::Kprobe::new()
+                        .handler(handler)
+                        .function(function)
+                        .attach(&mut bpf)
+                    {
+                        Ok(()) => attached += 1,
+                        Err(e) => warn!("failed to attach kprobe {}: {}", function, e),
+                    }
+                }
+                for (handler, function) in KRETPROBES {
+                    match bcc::Kretprobe::new()
+                        .handler(handler)
+                        .function(function)
+                        .attach(&mut bpf)
+                    {
+                        Ok(()) => attached += 1,
+                        Err(e) => warn!("failed to attach kretprobe {}: {}", function, e),
+                    }
+                }
+                if attached == 0 {
+                    return Err(format_err!("failed to attach any tcp probes"));
+                }
+                debug!(
+                    "attached {} of {} tcp probes",
+                    attached,
+                    KPROBES.len() + KRETPROBES.len()
+                );
 
                 self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })))
             }
@@ -154,43 +255,112 @@ impl Tcp {
 
         Ok(())
     }
+}
 
-    async fn sample_snmp(&mut self) -> Result<(), std::io::Error> {
-        if self.proc_net_snmp.is_none() {
-            let file = File::open("/proc/net/snmp").await?;
-            self.proc_net_snmp = Some(file);
+impl Tcp {
+    /// Register the decaying percentiles of each distribution, if enabled
+    fn register_ewma(&self) {
+        let config = self.common.config().samplers().tcp();
+        if config.percentile_mode() != PercentileMode::Ewma {
+            return;
         }
-        if let Some(file) = &mut self.proc_net_snmp {
-            let parsed = crate::common::nested_map_from_file(file).await?;
-            let time = Instant::now();
-            for statistic in &self.statistics {
-                if let Some((pkey, lkey)) = statistic.keys() {
-                    if let Some(inner) = parsed.get(pkey) {
-                        if let Some(value) = inner.get(lkey) {
-                            let _ = self.metrics().record_counter(statistic, time, *value);
-                        }
-                    }
+        let half_life = Duration::from_millis(config.percentile_half_life() as u64);
+        let mut ewma = self.ewma.lock().unwrap();
+        for statistic in &self.statistics {
+            if statistic.source() != Source::Distribution {
+                continue;
+            }
+            ewma.insert(*statistic, EwmaHistogram::new(half_life));
+            for percentile in config.percentiles() {
+                let statistic = TcpEwmaStatistic::new(*statistic, *percentile);
+                self.metrics().register(&statistic);
+                self.metrics().add_output(&statistic, Output::Reading);
+            }
+        }
+    }
+
+    #[cfg(feature = "bpf_core")]
+    fn initialize_bpf_core(&mut self) -> Result<(), anyhow::Error> {
+        debug!("initializing CO-RE bpf");
+        let mut object = libbpf_rs::ObjectBuilder::default()
+            .open_memory("tcp", CORE_OBJECT)?
+            .load()?;
+
+        // load + attach kprobes!
+        let mut links = Vec::new();
+        for (retprobe, probes) in &[(false, KPROBES), (true, KRETPROBES)] {
+            for (handler, function) in probes.iter() {
+                let program = object
+                    .prog(handler)
+                    .ok_or_else(|| format_err!("missing bpf program: {}", handler))?;
+                match program.attach_kprobe(*retprobe, function) {
+                    Ok(link) => links.push(link),
+                    Err(e) => warn!("failed to attach kprobe {}: {}", function, e),
                 }
             }
         }
+        if links.is_empty() {
+            return Err(format_err!("failed to attach any tcp probes"));
+        }
+        debug!(
+            "attached {} of {} tcp probes",
+            links.len(),
+            KPROBES.len() + KRETPROBES.len()
+        );
 
+        self.core = Some(Arc::new(Mutex::new(CoreBPF { object, links })));
+        Ok(())
+    }
+
+    async fn sample_snmp(&mut self) -> Result<(), std::io::Error> {
+        let content = self.common.proc().read_to_string("/proc/net/snmp").await?;
+        let parsed = crate::common::nested_map_from_str(&content);
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some((pkey, lkey)) = statistic.keys() {
+                if let Some(inner) = parsed.get(pkey) {
+                    if let Some(value) = inner.get(lkey) {
+                        self.record_counter(statistic, time, *value);
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
     async fn sample_netstat(&mut self) -> Result<(), std::io::Error> {
-        if self.proc_net_netstat.is_none() {
-            let file = File::open("/proc/net/netstat").await?;
-            self.proc_net_netstat = Some(file);
+        let content = self
+            .common
+            .proc()
+            .read_to_string("/proc/net/netstat")
+            .await?;
+        let parsed = crate::common::nested_map_from_str(&content);
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some((pkey, lkey)) = statistic.keys() {
+                if let Some(inner) = parsed.get(pkey) {
+                    if let Some(value) = inner.get(lkey) {
+                        self.record_counter(statistic, time, *value);
+                    }
+                }
+            }
         }
-        if let Some(file) = &mut self.proc_net_netstat {
-            let parsed = crate::common::nested_map_from_file(file).await?;
-            let time = Instant::now();
+        Ok(())
+    }
+
+    async fn sample_sockstat(&mut self) -> Result<(), std::io::Error> {
+        let content = self
+            .common
+            .proc()
+            .read_to_string("/proc/net/sockstat")
+            .await?;
+        let parsed = crate::common::paired_map_from_str(&content);
+        let time = self.common.now();
+        if let Some(inner) = parsed.get("TCP:") {
             for statistic in &self.statistics {
-                if let Some((pkey, lkey)) = statistic.keys() {
-                    if let Some(inner) = parsed.get(pkey) {
-                        if let Some(value) = inner.get(lkey) {
-                            let _ = self.metrics().record_counter(statistic, time, *value);
-                        }
+                if let Some(key) = statistic.sockstat_key() {
+                    if let Some(value) = inner.get(key) {
+                        self.record_gauge(statistic, time, *value);
                     }
                 }
             }
@@ -198,31 +368,462 @@ impl Tcp {
         Ok(())
     }
 
-    #[cfg(feature = "bpf")]
+    /// Count the sockets of each allow-listed local port by state. Once a
+    /// port and state has been seen it is reported on every sample, so that
+    /// it drops to zero rather than keeping its last count.
+    async fn sample_ports(&mut self) -> Result<(), std::io::Error> {
+        let ports = self.common.config().samplers().tcp().ports();
+        if ports.is_empty() {
+            return Ok(());
+        }
+        let mut counts = HashMap::new();
+        for path in &["/proc/net/tcp", "/proc/net/tcp6"] {
+            match self.common.proc().read_to_string(path).await {
+                Ok(content) => count_sockets(&content, ports, &mut counts),
+                // there is no tcp6 file if ipv6 is disabled
+                Err(e) if e.kind() == ErrorKind::NotFound && *path == "/proc/net/tcp6" => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let time = self.common.now();
+        for key in counts.keys() {
+            if self.port_states.insert(*key) {
+                self.common.register_once(&port_statistic(key.0, key.1));
+            }
+        }
+        for (port, state) in &self.port_states {
+            let statistic = port_statistic(*port, state);
+            let count = counts.get(&(*port, *state)).copied().unwrap_or(0);
+            self.common.record_gauge(&statistic, time, count);
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        if self.bpf_last.lock().unwrap().elapsed()
-            >= Duration::new(self.general_config().window() as u64, 0)
+        let elapsed = self.common.elapsed(*self.bpf_last.lock().unwrap());
+        if self.bpf_drain_due(&self.bpf_last) {
+            let time = self.common.now();
+            for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
+                if *statistic == TcpStatistic::CongestionControl {
+                    self.sample_congestion(time);
+                    continue;
+                }
+                let table = self.drain_bpf_table(statistic.bpf_table().unwrap());
+                if statistic.source() == Source::Counter {
+                    // the tables are cleared on each read, so the counter is
+                    // kept as a running total
+                    let mut totals = self.bpf_totals.lock().unwrap();
+                    let total = totals.entry(*statistic).or_insert(0);
+                    *total += table.values().map(|count| *count as u64).sum::<u64>();
+                    self.record_counter(statistic, time, *total);
+                    continue;
+                }
+                for (&value, &count) in &table {
+                    if count > 0 {
+                        self.record_bucket(
+                            statistic,
+                            time,
+                            value * statistic.bucket_scale(),
+                            count,
+                        );
+                    }
+                }
+                self.record_ewma(statistic, &table, elapsed, time);
+            }
+            *self.bpf_last.lock().unwrap() = self.common.now();
+        }
+        Ok(())
+    }
+
+    /// Record the connections established with each congestion control
+    /// algorithm, along with their total. The map is never cleared, so its
+    /// counts are already running totals.
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn sample_congestion(&self, time: Instant) {
+        let counts = self.read_congestion();
+        let mut registered = self.congestion.lock().unwrap();
+        for (algorithm, count) in &counts {
+            let statistic = TcpCongestionStatistic::new(algorithm);
+            if registered.insert(algorithm.clone()) {
+                self.metrics().register(&statistic);
+                self.metrics().add_output(&statistic, Output::Reading);
+            }
+            self.common.record_counter(&statistic, time, *count);
+        }
+        self.record_counter(
+            &TcpStatistic::CongestionControl,
+            time,
+            counts.values().sum(),
+        );
+    }
+
+    // reads the congestion control counts from whichever bpf implementation
+    // is loaded
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn read_congestion(&self) -> HashMap<String, u64> {
+        let mut counts = HashMap::new();
+        #[cfg(feature = "bpf_core")]
+        {
+            if let Some(ref core) = self.core {
+                let mut core = core.lock().unwrap();
+                if let Some(map) = core.object.map("congestion") {
+                    let keys: Vec<Vec<u8>> = map.keys().collect();
+                    for key in keys {
+                        if let Ok(Some(value)) = map.lookup(&key, libbpf_rs::MapFlags::ANY) {
+                            *counts.entry(congestion_algorithm(&key)).or_insert(0) +=
+                                parse_u64(value);
+                        }
+                    }
+                } else {
+                    self.common.bpf_table_error("tcp", "congestion");
+                }
+                return counts;
+            }
+        }
+        #[cfg(feature = "bpf")]
         {
             if let Some(ref bpf) = self.bpf {
                 let bpf = bpf.lock().unwrap();
-                let time = Instant::now();
-                for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
-                    if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                        for (&value, &count) in &map_from_table(&mut table) {
-                            if count > 0 {
-                                let _ = self.metrics().record_bucket(
-                                    statistic,
-                                    time,
-                                    value * 1000,
-                                    count,
-                                );
-                            }
-                        }
+                if let Ok(table) = (*bpf).inner.table("congestion") {
+                    for entry in table.iter() {
+                        *counts.entry(congestion_algorithm(&entry.key)).or_insert(0) +=
+                            parse_u64(entry.value);
                     }
+                } else {
+                    self.common.bpf_table_error("tcp", "congestion");
                 }
             }
-            *self.bpf_last.lock().unwrap() = Instant::now();
         }
-        Ok(())
+        counts
+    }
+
+    /// Add the drained buckets to the decaying histogram and record its
+    /// percentiles
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn record_ewma(
+        &self,
+        statistic: &TcpStatistic,
+        table: &HashMap<u64, u32>,
+        elapsed: Duration,
+        time: Instant,
+    ) {
+        let mut ewma = self.ewma.lock().unwrap();
+        if let Some(histogram) = ewma.get_mut(statistic) {
+            histogram.decay(elapsed);
+            for (&value, &count) in table {
+                histogram.increment(value * statistic.bucket_scale(), count);
+            }
+            for percentile in self.sampler_config().percentiles() {
+                if let Some(value) = histogram.percentile(*percentile) {
+                    self.common.record_gauge(
+                        &TcpEwmaStatistic::new(*statistic, *percentile),
+                        time,
+                        value,
+                    );
+                }
+            }
+        }
+    }
+
+    // reads a histogram from whichever bpf implementation is loaded
+    #[cfg(any(feature = "bpf", feature = "bpf_core"))]
+    fn drain_bpf_table(&self, name: &str) -> std::collections::HashMap<u64, u32> {
+        #[cfg(feature = "bpf_core")]
+        {
+            if let Some(ref core) = self.core {
+                let mut core = core.lock().unwrap();
+                if let Some(map) = core.object.map(name) {
+                    return self.drain_histogram(name, map);
+                }
+                self.common.bpf_table_error("tcp", name);
+                return Default::default();
+            }
+        }
+        #[cfg(feature = "bpf")]
+        {
+            if let Some(ref bpf) = self.bpf {
+                let bpf = bpf.lock().unwrap();
+                if let Ok(mut table) = (*bpf).inner.table(name) {
+                    return self.drain_histogram(name, &mut table);
+                }
+                self.common.bpf_table_error("tcp", name);
+            }
+        }
+        Default::default()
+    }
+}
+
+/// Count the sockets of `/proc/net/tcp` or `/proc/net/tcp6` whose local port
+/// is one of `ports`, by port and state. After the header, the second field of
+/// each line is the local address and port and the fourth is the state, all in
+/// hex, eg: `0: 0100007F:1F90 00000000:0000 0A ...` is listening on port 8080.
+fn count_sockets(content: &str, ports: &[u16], counts: &mut HashMap<(u16, &'static str), u64>) {
+    for line in content.lines().skip(1) {
+        let mut fields = line.split_whitespace();
+        let local = fields.nth(1);
+        let state = fields.nth(1);
+        if let (Some(local), Some(state)) = (local, state) {
+            let port = local
+                .rsplit(':')
+                .next()
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            let state = u8::from_str_radix(state, 16).ok().and_then(tcp_state);
+            if let (Some(port), Some(state)) = (port, state) {
+                if ports.contains(&port) {
+                    *counts.entry((port, state)).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The name of a socket state, as numbered in `include/net/tcp_states.h`
+fn tcp_state(state: u8) -> Option<&'static str> {
+    match state {
+        0x01 => Some("established"),
+        0x02 => Some("syn_sent"),
+        0x03 => Some("syn_recv"),
+        0x04 => Some("fin_wait1"),
+        0x05 => Some("fin_wait2"),
+        0x06 => Some("time_wait"),
+        0x07 => Some("close"),
+        0x08 => Some("close_wait"),
+        0x09 => Some("last_ack"),
+        0x0A => Some("listen"),
+        0x0B => Some("closing"),
+        0x0C => Some("new_syn_recv"),
+        _ => None,
+    }
+}
+
+/// The algorithm name in a key of the `congestion` map, which is padded with
+/// NUL bytes. An empty name is counted as unknown.
+#[allow(dead_code)]
+fn congestion_algorithm(key: &[u8]) -> String {
+    let end = key.iter().position(|byte| *byte == 0).unwrap_or(key.len());
+    let name = String::from_utf8_lossy(&key[..end]);
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+    use strum::IntoEnumIterator;
+
+    const SNMP: &str = "Tcp: RtoAlgorithm RtoMin RtoMax MaxConn ActiveOpens PassiveOpens AttemptFails EstabResets CurrEstab InSegs OutSegs RetransSegs InErrs OutRsts InCsumErrors
+Tcp: 1 200 120000 -1 1000 2000 3 4 50 123456 234567 89 0 12 0
+";
+
+    const NETSTAT: &str = "TcpExt: SyncookiesSent SyncookiesRecv SyncookiesFailed EmbryonicRsts PruneCalled RcvPruned OfoPruned OutOfWindowIcmps LockDroppedIcmps ArpFilter TW TWRecycled TWKilled PAWSActive PAWSEstab DelayedACKs DelayedACKLocked DelayedACKLost ListenOverflows ListenDrops TCPHPHits TCPPureAcks TCPHPAcks TCPRenoRecovery TCPSackRecovery TCPSACKReneging TCPSACKReorder TCPRenoReorder TCPTSReorder TCPFullUndo TCPPartialUndo TCPDSACKUndo TCPLossUndo TCPLostRetransmit TCPRenoFailures TCPSackFailures TCPLossFailures TCPFastRetrans TCPSlowStartRetrans TCPTimeouts TCPLossProbes TCPLossProbeRecovery TCPRenoRecoveryFail TCPSackRecoveryFail TCPRcvCollapsed TCPDSACKOldSent TCPDSACKOfoSent TCPDSACKRecv TCPDSACKOfoRecv TCPAbortOnData TCPAbortOnClose TCPAbortOnMemory TCPAbortOnTimeout TCPAbortOnLinger TCPAbortFailed TCPMemoryPressures TCPReqQFullDoCookies TCPReqQFullDrop
+TcpExt: 31 29 2 0 0 0 0 0 0 0 40512 3 128 0 0 9051 0 0 17 23 512339 80127 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 5 0 0 0 0 0 7 11
+IpExt: InNoRoutes InTruncatedPkts InMcastPkts OutMcastPkts InBcastPkts OutBcastPkts InOctets OutOctets
+IpExt: 0 0 0 0 8 0 9871234 8765432
+";
+
+    const SOCKSTAT: &str = "sockets: used 294
+TCP: inuse 25 orphan 3 tw 41 alloc 30 mem 12
+UDP: inuse 4 mem 2
+UDPLITE: inuse 0
+RAW: inuse 0
+FRAG: inuse 0 memory 0
+";
+
+    const PROC_TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:01BB 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 21339 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:01BB 0100007F:C350 01 00000000:00000000 00:00000000 00000000     0        0 31338 1 0000000000000000 20 4 30 10 -1
+   2: 0100007F:01BB 0100007F:C351 01 00000000:00000000 00:00000000 00000000     0        0 31339 1 0000000000000000 20 4 30 10 -1
+   3: 0100007F:01BB 0100007F:C352 06 00000000:00000000 03:00001770 00000000     0        0 0 3 0000000000000000
+   4: 0100007F:0016 0100007F:C353 01 00000000:00000000 00:00000000 00000000     0        0 31340 1 0000000000000000 20 4 30 10 -1
+";
+
+    const PROC_TCP6: &str = "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000001000000:01BB 00000000000000000000000001000000:D431 01 00000000:00000000 00:00000000 00000000     0        0 41337 1 0000000000000000 20 4 30 10 -1
+";
+
+    #[tokio::test]
+    async fn test_sample_proc() {
+        let common = Common::with_fixtures(
+            Fixtures::default()
+                .with("/proc/net/snmp", SNMP)
+                .with("/proc/net/netstat", NETSTAT)
+                .with("/proc/net/sockstat", SOCKSTAT),
+        );
+        let mut sampler = Tcp::new(common).unwrap();
+        sampler.register();
+        sampler.sample_snmp().await.unwrap();
+        sampler.sample_netstat().await.unwrap();
+        sampler.sample_sockstat().await.unwrap();
+
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::ReceiveSegments.name()),
+            Some(123456)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::TransmitSegments.name()),
+            Some(234567)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::ReceiveListenOverflows.name()),
+            Some(17)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::ReceiveListenDrops.name()),
+            Some(23)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SyncookiesSent.name()),
+            Some(31)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SyncookiesRecieved.name()),
+            Some(29)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SyncookiesFailed.name()),
+            Some(2)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SyncookiesRequestQueueFull.name()),
+            Some(7)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::ReceiveRequestQueueFullDrops.name()),
+            Some(11)
+        );
+        assert_eq!(
+            sampler.common().reading(TcpStatistic::AbortOnClose.name()),
+            Some(5)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::TimeWaitFinished.name()),
+            Some(40512)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::TimeWaitRecycled.name()),
+            Some(3)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::TimeWaitKilled.name()),
+            Some(128)
+        );
+        assert_eq!(
+            sampler.common().reading(TcpStatistic::SocketsInuse.name()),
+            Some(25)
+        );
+        assert_eq!(
+            sampler.common().reading(TcpStatistic::SocketsOrphan.name()),
+            Some(3)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SocketsTimeWait.name()),
+            Some(41)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(TcpStatistic::SocketsAllocated.name()),
+            Some(30)
+        );
+        assert_eq!(
+            sampler.common().reading(TcpStatistic::MemoryPages.name()),
+            Some(12)
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        // every key is present in the files as the kernel writes them, so a
+        // misspelled key doesn't silently go unreported
+        let snmp = crate::common::nested_map_from_str(SNMP);
+        let netstat = crate::common::nested_map_from_str(NETSTAT);
+        for statistic in TcpStatistic::iter() {
+            if let Some((pkey, lkey)) = statistic.keys() {
+                let parsed = if pkey == "Tcp:" { &snmp } else { &netstat };
+                assert!(
+                    parsed.get(pkey).and_then(|inner| inner.get(lkey)).is_some(),
+                    "{} {}",
+                    pkey,
+                    lkey
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_sockets() {
+        let mut counts = HashMap::new();
+        count_sockets(PROC_TCP, &[443], &mut counts);
+        count_sockets(PROC_TCP6, &[443], &mut counts);
+        assert_eq!(counts.get(&(443, "listen")), Some(&1));
+        assert_eq!(counts.get(&(443, "established")), Some(&3));
+        assert_eq!(counts.get(&(443, "time_wait")), Some(&1));
+        // port 22 is not allow-listed
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sample_ports() {
+        let config = crate::config::Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[samplers.tcp]\nports = [22, 443]\n",
+        )
+        .unwrap();
+        let common = Common::with_fixtures(Fixtures::default().with("/proc/net/tcp", PROC_TCP))
+            .with_config(Arc::new(config));
+        let mut sampler = Tcp::new(common).unwrap();
+        sampler.sample_ports().await.unwrap();
+        assert_eq!(
+            sampler
+                .common()
+                .reading(port_statistic(443, "established").name()),
+            Some(2)
+        );
+        assert_eq!(
+            sampler
+                .common()
+                .reading(port_statistic(22, "established").name()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_congestion_algorithm() {
+        let mut key = [0_u8; 16];
+        key[..3].copy_from_slice(b"bbr");
+        assert_eq!(congestion_algorithm(&key), "bbr");
+        key[..5].copy_from_slice(b"cubic");
+        assert_eq!(congestion_algorithm(&key), "cubic");
+        assert_eq!(congestion_algorithm(&[0_u8; 16]), "unknown");
     }
 }
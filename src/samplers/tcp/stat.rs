@@ -5,6 +5,10 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES};
+use crate::common::labeled::LabeledStatistic;
+use crate::common::unit::{StatisticUnit, Unit};
+use crate::common::MICROSECOND;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -27,6 +31,22 @@ use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 pub enum TcpStatistic {
     #[strum(serialize = "tcp/connect/latency")]
     ConnectLatency,
+    /// Sampled from the kernel's `srtt_us` on each segment received, and
+    /// recorded in nanoseconds like the other latencies
+    #[strum(serialize = "tcp/rtt/smoothed")]
+    SmoothedRttMicroseconds,
+    #[strum(serialize = "tcp/connect/failed")]
+    ConnectFailed,
+    /// Active opens counted as `connect()` is called, so that the rate of new
+    /// connections doesn't depend on differencing `ActiveOpens` between samples
+    #[strum(serialize = "tcp/connect/rate")]
+    ConnectRate,
+    #[strum(serialize = "tcp/accept/rate")]
+    AcceptRate,
+    #[strum(serialize = "tcp/transmit/retransmit_events")]
+    RetransmitEvents,
+    #[strum(serialize = "tcp/congestion_control")]
+    CongestionControl,
     #[strum(serialize = "tcp/receive/segment")]
     ReceiveSegments,
     #[strum(serialize = "tcp/transmit/segment")]
@@ -49,6 +69,9 @@ pub enum TcpStatistic {
     SyncookiesRecieved,
     #[strum(serialize = "tcp/syncookies/failed")]
     SyncookiesFailed,
+    /// SYN cookies sent because the SYN queue of a listener was full
+    #[strum(serialize = "tcp/syncookies/request_queue_full")]
+    SyncookiesRequestQueueFull,
     #[strum(serialize = "tcp/receive/pruned")]
     ReceivePruned,
     #[strum(serialize = "tcp/receive/ofo_pruned")]
@@ -59,6 +82,16 @@ pub enum TcpStatistic {
     ReceiveListenOverflows,
     #[strum(serialize = "tcp/receive/listen_drops")]
     ReceiveListenDrops,
+    /// SYNs dropped because the SYN queue of a listener was full and SYN
+    /// cookies are disabled
+    #[strum(serialize = "tcp/receive/request_queue_full_drop")]
+    ReceiveRequestQueueFullDrops,
+    #[strum(serialize = "tcp/time_wait/finished")]
+    TimeWaitFinished,
+    #[strum(serialize = "tcp/time_wait/recycled")]
+    TimeWaitRecycled,
+    #[strum(serialize = "tcp/time_wait/killed")]
+    TimeWaitKilled,
     #[strum(serialize = "tcp/abort/failed")]
     AbortFailed,
     #[strum(serialize = "tcp/abort/on_close")]
@@ -71,6 +104,16 @@ pub enum TcpStatistic {
     AbortOnMemory,
     #[strum(serialize = "tcp/abort/on_timeout")]
     AbortOnTimeout,
+    #[strum(serialize = "tcp/sockets/inuse")]
+    SocketsInuse,
+    #[strum(serialize = "tcp/sockets/orphan")]
+    SocketsOrphan,
+    #[strum(serialize = "tcp/sockets/time_wait")]
+    SocketsTimeWait,
+    #[strum(serialize = "tcp/sockets/allocated")]
+    SocketsAllocated,
+    #[strum(serialize = "tcp/memory/pages")]
+    MemoryPages,
 }
 
 impl TcpStatistic {
@@ -93,11 +136,28 @@ impl TcpStatistic {
             Self::SyncookiesSent => Some(("TcpExt:", "SyncookiesSent")),
             Self::SyncookiesRecieved => Some(("TcpExt:", "SyncookiesRecv")),
             Self::SyncookiesFailed => Some(("TcpExt:", "SyncookiesFailed")),
+            Self::SyncookiesRequestQueueFull => Some(("TcpExt:", "TCPReqQFullDoCookies")),
             Self::ReceivePruned => Some(("TcpExt:", "RcvPruned")),
             Self::ReceiveOfoPruned => Some(("TcpExt:", "OfoPruned")),
             Self::TransmitDelayedAcks => Some(("TcpExt:", "DelayedACKs")),
             Self::ReceiveListenOverflows => Some(("TcpExt:", "ListenOverflows")),
             Self::ReceiveListenDrops => Some(("TcpExt:", "ListenDrops")),
+            Self::ReceiveRequestQueueFullDrops => Some(("TcpExt:", "TCPReqQFullDrop")),
+            Self::TimeWaitFinished => Some(("TcpExt:", "TW")),
+            Self::TimeWaitRecycled => Some(("TcpExt:", "TWRecycled")),
+            Self::TimeWaitKilled => Some(("TcpExt:", "TWKilled")),
+            _ => None,
+        }
+    }
+
+    /// The key of the statistic in the `TCP:` line of `/proc/net/sockstat`
+    pub fn sockstat_key(self) -> Option<&'static str> {
+        match self {
+            Self::SocketsInuse => Some("inuse"),
+            Self::SocketsOrphan => Some("orphan"),
+            Self::SocketsTimeWait => Some("tw"),
+            Self::SocketsAllocated => Some("alloc"),
+            Self::MemoryPages => Some("mem"),
             _ => None,
         }
     }
@@ -105,9 +165,31 @@ impl TcpStatistic {
     pub fn bpf_table(self) -> Option<&'static str> {
         match self {
             Self::ConnectLatency => Some("connlat"),
+            Self::SmoothedRttMicroseconds => Some("srtt"),
+            Self::ConnectFailed => Some("connfail"),
+            Self::ConnectRate => Some("connect"),
+            Self::AcceptRate => Some("accept"),
+            Self::RetransmitEvents => Some("retransmit"),
+            Self::CongestionControl => Some("congestion"),
             _ => None,
         }
     }
+
+    /// The oldest kernel able to trace the statistic, which uses kprobes
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| KPROBES)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic, eg: microsecond buckets to nanoseconds
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        match self {
+            Self::ConnectLatency | Self::SmoothedRttMicroseconds => MICROSECOND,
+            _ => 1,
+        }
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for TcpStatistic {
@@ -116,10 +198,25 @@ impl Statistic<AtomicU64, AtomicU32> for TcpStatistic {
     }
 
     fn source(&self) -> Source {
-        if self.bpf_table().is_some() {
-            Source::Distribution
-        } else {
-            Source::Counter
+        match self {
+            Self::ConnectLatency | Self::SmoothedRttMicroseconds => Source::Distribution,
+            Self::SocketsInuse
+            | Self::SocketsOrphan
+            | Self::SocketsTimeWait
+            | Self::SocketsAllocated
+            | Self::MemoryPages => Source::Gauge,
+            _ => Source::Counter,
+        }
+    }
+}
+
+impl StatisticUnit for TcpStatistic {
+    fn unit(&self) -> Option<Unit> {
+        match self {
+            Self::ConnectLatency | Self::SmoothedRttMicroseconds => Some(Unit::Nanoseconds),
+            // pages are not a count of events
+            Self::MemoryPages => None,
+            _ => Some(Unit::Count),
         }
     }
 }
@@ -131,3 +228,95 @@ impl TryFrom<&str> for TcpStatistic {
         TcpStatistic::from_str(s)
     }
 }
+
+/// A percentile of a distribution computed from a decaying histogram, eg:
+/// `tcp/connect/latency/ewma/p99`
+pub struct TcpEwmaStatistic {
+    name: String,
+}
+
+impl TcpEwmaStatistic {
+    pub fn new(statistic: TcpStatistic, percentile: f64) -> Self {
+        // fractional percentiles such as 99.9 become `p99_9`
+        let suffix = format!("p{}", percentile).replace('.', "_");
+        Self {
+            name: format!("{}/ewma/{}", statistic.name(), suffix),
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for TcpEwmaStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Gauge
+    }
+}
+
+/// Sockets with a local port in a state, eg: `tcp/connections` with
+/// `port="443"` and `state="established"`
+pub fn port_statistic(port: u16, state: &str) -> LabeledStatistic {
+    LabeledStatistic::with_name(
+        "tcp/connections",
+        Source::Gauge,
+        &[("port", port.to_string()), ("state", state.to_string())],
+    )
+}
+
+/// Connections established using a congestion control algorithm, eg:
+/// `tcp/congestion_control/bbr`
+pub struct TcpCongestionStatistic {
+    name: String,
+}
+
+impl TcpCongestionStatistic {
+    pub fn new(algorithm: &str) -> Self {
+        Self {
+            name: format!("{}/{}", TcpStatistic::CongestionControl.name(), algorithm),
+        }
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for TcpCongestionStatistic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Counter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bucket_scale() {
+        assert_eq!(TcpStatistic::ConnectLatency.bucket_scale(), 1_000);
+    }
+
+    #[test]
+    fn test_ewma_name() {
+        let statistic = TcpEwmaStatistic::new(TcpStatistic::ConnectLatency, 99.9);
+        assert_eq!(statistic.name(), "tcp/connect/latency/ewma/p99_9");
+    }
+
+    #[test]
+    fn test_congestion_name() {
+        let statistic = TcpCongestionStatistic::new("bbr");
+        assert_eq!(statistic.name(), "tcp/congestion_control/bbr");
+    }
+
+    #[test]
+    fn test_source() {
+        assert_eq!(TcpStatistic::ConnectLatency.source(), Source::Distribution);
+        assert_eq!(TcpStatistic::ConnectFailed.source(), Source::Counter);
+        assert_eq!(TcpStatistic::RetransmitEvents.source(), Source::Counter);
+        assert_eq!(TcpStatistic::ConnectRate.source(), Source::Counter);
+        assert_eq!(TcpStatistic::AcceptRate.source(), Source::Counter);
+        assert_eq!(TcpStatistic::MemoryPages.source(), Source::Gauge);
+    }
+}
@@ -3,8 +3,7 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use async_trait::async_trait;
-use std::time::Instant;
-use tokio::fs::File;
+use std::path::Path;
 
 use crate::config::SamplerConfig;
 use crate::samplers::Common;
@@ -19,8 +18,7 @@ pub use stat::UdpStatistic;
 #[allow(dead_code)]
 pub struct Udp {
     common: Common,
-    proc_net_snmp: Option<File>,
-    proc_net_netstat: Option<File>,
+    snmp6_available: bool,
     statistics: Vec<UdpStatistic>,
 }
 
@@ -30,11 +28,15 @@ impl Sampler for Udp {
 
     fn new(common: Common) -> Result<Self, anyhow::Error> {
         let statistics = common.config().samplers().udp().statistics();
+        // the file does not exist if ipv6 is disabled
+        let snmp6_available = Path::new("/proc/net/snmp6").exists();
+        if !snmp6_available {
+            info!("ipv6 statistics are not available");
+        }
 
         let sampler = Self {
             common,
-            proc_net_snmp: None,
-            proc_net_netstat: None,
+            snmp6_available,
             statistics,
         };
         if sampler.sampler_config().enabled() {
@@ -47,13 +49,15 @@ impl Sampler for Udp {
         if common.config().samplers().udp().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().udp())
+            {
                 fatal!("failed to initialize udp sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize udp sampler");
             }
         }
@@ -68,10 +72,6 @@ impl Sampler for Udp {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -84,6 +84,11 @@ impl Sampler for Udp {
         let r = self.sample_netstat().await;
         self.map_result(r)?;
 
+        if self.snmp6_available {
+            let r = self.sample_snmp6().await;
+            self.map_result(r)?;
+        }
+
         Ok(())
     }
 
@@ -94,41 +99,46 @@ impl Sampler for Udp {
 
 impl Udp {
     async fn sample_snmp(&mut self) -> Result<(), std::io::Error> {
-        if self.proc_net_snmp.is_none() {
-            let file = File::open("/proc/net/snmp").await?;
-            self.proc_net_snmp = Some(file);
-        }
-        if let Some(file) = &mut self.proc_net_snmp {
-            let parsed = crate::common::nested_map_from_file(file).await?;
-            let time = Instant::now();
-            for statistic in &self.statistics {
-                if let Some((pkey, lkey)) = statistic.keys() {
-                    if let Some(inner) = parsed.get(pkey) {
-                        if let Some(value) = inner.get(lkey) {
-                            let _ = self.metrics().record_counter(statistic, time, *value);
-                        }
+        let content = self.common.proc().read_to_string("/proc/net/snmp").await?;
+        let parsed = crate::common::nested_map_from_str(&content);
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some((pkey, lkey)) = statistic.keys() {
+                if let Some(inner) = parsed.get(pkey) {
+                    if let Some(value) = inner.get(lkey) {
+                        self.record_counter(statistic, time, *value);
                     }
                 }
             }
         }
+        Ok(())
+    }
 
+    async fn sample_snmp6(&mut self) -> Result<(), std::io::Error> {
+        let content = self.common.proc().read_to_string("/proc/net/snmp6").await?;
+        let parsed = crate::common::flat_map_from_str(&content);
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some(value) = statistic.snmp6_key().and_then(|key| parsed.get(key)) {
+                self.record_counter(statistic, time, *value);
+            }
+        }
         Ok(())
     }
 
     async fn sample_netstat(&mut self) -> Result<(), std::io::Error> {
-        if self.proc_net_netstat.is_none() {
-            let file = File::open("/proc/net/netstat").await?;
-            self.proc_net_netstat = Some(file);
-        }
-        if let Some(file) = &mut self.proc_net_netstat {
-            let parsed = crate::common::nested_map_from_file(file).await?;
-            let time = Instant::now();
-            for statistic in &self.statistics {
-                if let Some((pkey, lkey)) = statistic.keys() {
-                    if let Some(inner) = parsed.get(pkey) {
-                        if let Some(value) = inner.get(lkey) {
-                            let _ = self.metrics().record_counter(statistic, time, *value);
-                        }
+        let content = self
+            .common
+            .proc()
+            .read_to_string("/proc/net/netstat")
+            .await?;
+        let parsed = crate::common::nested_map_from_str(&content);
+        let time = self.common.now();
+        for statistic in &self.statistics {
+            if let Some((pkey, lkey)) = statistic.keys() {
+                if let Some(inner) = parsed.get(pkey) {
+                    if let Some(value) = inner.get(lkey) {
+                        self.record_counter(statistic, time, *value);
                     }
                 }
             }
@@ -136,3 +146,52 @@ impl Udp {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rustcommon_metrics::Statistic;
+
+    use super::*;
+    use crate::common::proc::Fixtures;
+
+    const SNMP: &str = "Udp: InDatagrams NoPorts InErrors OutDatagrams RcvbufErrors SndbufErrors InCsumErrors IgnoredMulti MemErrors
+Udp: 1000 12 3 900 2 1 0 0 0
+";
+
+    const SNMP6: &str = "Udp6InDatagrams                 \t400
+Udp6NoPorts                     \t4
+Udp6InErrors                    \t0
+Udp6OutDatagrams                \t350
+";
+
+    #[tokio::test]
+    async fn test_sample_twice() {
+        let config = crate::config::Config::parse(
+            "[general]\nlisten = \"0.0.0.0:4242\"\n[samplers.udp]\nenabled = true\n",
+        )
+        .unwrap();
+        let common = Common::with_fixtures(
+            Fixtures::default()
+                .with("/proc/net/snmp", SNMP)
+                .with("/proc/net/netstat", "")
+                .with("/proc/net/snmp6", SNMP6),
+        )
+        .with_config(Arc::new(config));
+        let mut sampler = Udp::new(common).unwrap();
+
+        // each sample reads the file again from the start, so the second
+        // sample finds the same values as the first
+        sampler.sample().await.unwrap();
+        sampler.sample().await.unwrap();
+        assert_eq!(
+            sampler.common().reading(UdpStatistic::InDatagrams.name()),
+            Some(1000)
+        );
+        assert_eq!(
+            sampler.common().reading(UdpStatistic::OutDatagrams.name()),
+            Some(900)
+        );
+    }
+}
@@ -5,6 +5,7 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::unit::StatisticUnit;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -29,8 +30,26 @@ pub enum UdpStatistic {
     InDatagrams,
     #[strum(serialize = "udp/receive/errors")]
     InErrors,
+    #[strum(serialize = "udp/receive/buffer_errors")]
+    RcvbufErrors,
+    #[strum(serialize = "udp/receive/no_port")]
+    NoPorts,
     #[strum(serialize = "udp/transmit/datagrams")]
     OutDatagrams,
+    #[strum(serialize = "udp/transmit/buffer_errors")]
+    SndbufErrors,
+    #[strum(serialize = "udp6/receive/datagrams")]
+    Ipv6InDatagrams,
+    #[strum(serialize = "udp6/receive/errors")]
+    Ipv6InErrors,
+    #[strum(serialize = "udp6/receive/buffer_errors")]
+    Ipv6RcvbufErrors,
+    #[strum(serialize = "udp6/receive/no_port")]
+    Ipv6NoPorts,
+    #[strum(serialize = "udp6/transmit/datagrams")]
+    Ipv6OutDatagrams,
+    #[strum(serialize = "udp6/transmit/buffer_errors")]
+    Ipv6SndbufErrors,
 }
 
 impl UdpStatistic {
@@ -38,7 +57,24 @@ impl UdpStatistic {
         match self {
             Self::InDatagrams => Some(("Udp:", "InDatagrams")),
             Self::InErrors => Some(("Udp:", "InErrors")),
+            Self::RcvbufErrors => Some(("Udp:", "RcvbufErrors")),
+            Self::NoPorts => Some(("Udp:", "NoPorts")),
             Self::OutDatagrams => Some(("Udp:", "OutDatagrams")),
+            Self::SndbufErrors => Some(("Udp:", "SndbufErrors")),
+            _ => None,
+        }
+    }
+
+    /// The key in the flat `/proc/net/snmp6` layout
+    pub fn snmp6_key(self) -> Option<&'static str> {
+        match self {
+            Self::Ipv6InDatagrams => Some("Udp6InDatagrams"),
+            Self::Ipv6InErrors => Some("Udp6InErrors"),
+            Self::Ipv6RcvbufErrors => Some("Udp6RcvbufErrors"),
+            Self::Ipv6NoPorts => Some("Udp6NoPorts"),
+            Self::Ipv6OutDatagrams => Some("Udp6OutDatagrams"),
+            Self::Ipv6SndbufErrors => Some("Udp6SndbufErrors"),
+            _ => None,
         }
     }
 }
@@ -53,6 +89,8 @@ impl Statistic<AtomicU64, AtomicU32> for UdpStatistic {
     }
 }
 
+impl StatisticUnit for UdpStatistic {}
+
 impl TryFrom<&str> for UdpStatistic {
     type Error = ParseError;
 
@@ -0,0 +1,11 @@
+// Nothing is attached by this program. It only declares the stats maps which
+// the user's XDP programs have pinned so that they can be read. A table for
+// each interface is appended when the sampler is initialized, eg:
+//
+// BPF_TABLE_PINNED("array", u32, u64, xdp_0, XDP_ACTIONS, "/sys/fs/bpf/eth0/xdp_stats_map");
+//
+// Each map is an array of packet counts indexed by the `enum xdp_action`.
+
+#include <uapi/linux/bpf.h>
+
+#define XDP_ACTIONS (XDP_REDIRECT + 1)
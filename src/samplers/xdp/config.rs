@@ -0,0 +1,127 @@
+// Copyright 2019-2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use rustcommon_atomics::*;
+use serde_derive::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::config::SamplerConfig;
+
+use super::stat::*;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct XdpConfig {
+    #[serde(default)]
+    bpf: AtomicBool,
+    #[serde(default)]
+    disabled: Vec<XdpStatistic>,
+    #[serde(default)]
+    enabled: AtomicBool,
+    #[serde(default)]
+    fault_tolerant: Option<bool>,
+    #[serde(default)]
+    interfaces: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::config::duration::optional_milliseconds"
+    )]
+    interval: Option<AtomicUsize>,
+    #[serde(default = "default_map")]
+    map: String,
+    #[serde(default = "crate::common::default_percentiles")]
+    percentiles: Vec<f64>,
+    #[serde(default = "default_pin_path")]
+    pin_path: String,
+    #[serde(default = "default_statistics")]
+    statistics: Vec<XdpStatistic>,
+}
+
+impl Default for XdpConfig {
+    fn default() -> Self {
+        Self {
+            bpf: Default::default(),
+            disabled: Default::default(),
+            enabled: Default::default(),
+            fault_tolerant: Default::default(),
+            interfaces: Default::default(),
+            interval: Default::default(),
+            map: default_map(),
+            percentiles: crate::common::default_percentiles(),
+            pin_path: default_pin_path(),
+            statistics: default_statistics(),
+        }
+    }
+}
+
+impl XdpConfig {
+    /// The interfaces to read, all interfaces with a pinned map if empty
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// The name of the stats map pinned by the XDP program
+    pub fn map(&self) -> &str {
+        &self.map
+    }
+
+    /// The directory under which the maps are pinned, in a subdirectory for
+    /// each interface, eg: `/sys/fs/bpf/eth0/xdp_stats_map`
+    pub fn pin_path(&self) -> &str {
+        &self.pin_path
+    }
+}
+
+fn default_map() -> String {
+    "xdp_stats_map".to_string()
+}
+
+fn default_pin_path() -> String {
+    "/sys/fs/bpf".to_string()
+}
+
+fn default_statistics() -> Vec<XdpStatistic> {
+    XdpStatistic::iter().collect()
+}
+
+impl SamplerConfig for XdpConfig {
+    type Statistic = XdpStatistic;
+
+    fn bpf(&self) -> bool {
+        self.bpf.load(Ordering::Relaxed)
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn fault_tolerant(&self) -> Option<bool> {
+        self.fault_tolerant
+    }
+
+    fn interval(&self) -> Option<usize> {
+        self.interval.as_ref().map(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn percentiles(&self) -> &[f64] {
+        &self.percentiles
+    }
+
+    fn reload(&self, other: &Self) {
+        self.enabled.store(other.enabled(), Ordering::Relaxed);
+        if let (Some(current), Some(interval)) = (&self.interval, other.interval()) {
+            current.store(interval, Ordering::Relaxed);
+        }
+    }
+
+    fn statistics(&self) -> Vec<<Self as SamplerConfig>::Statistic> {
+        // every statistic is read from the map shared with the xdp program
+        if !self.bpf() {
+            return Vec::new();
+        }
+        let mut enabled = self.statistics.clone();
+        enabled.retain(|statistic| !self.disabled.contains(statistic));
+        enabled
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2019-2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rustcommon_metrics::*;
+
+use crate::common::bpf::*;
+use crate::common::labeled::LabeledStatistic;
+use crate::config::SamplerConfig;
+use crate::samplers::Common;
+use crate::Sampler;
+
+mod config;
+mod stat;
+
+pub use config::*;
+pub use stat::*;
+
+#[allow(dead_code)]
+pub struct Xdp {
+    bpf: Option<Arc<Mutex<BPF>>>,
+    common: Common,
+    /// Pairs of interface and the bpf table its pinned map is declared as
+    maps: Vec<(String, String)>,
+    statistics: Vec<XdpStatistic>,
+}
+
+#[async_trait]
+impl Sampler for Xdp {
+    type Statistic = XdpStatistic;
+    fn new(common: Common) -> Result<Self, anyhow::Error> {
+        let statistics = common.config().samplers().xdp().statistics();
+
+        #[allow(unused_mut)]
+        let mut sampler = Self {
+            bpf: None,
+            common,
+            maps: Vec::new(),
+            statistics,
+        };
+
+        sampler.initialize_bpf_tolerant()?;
+
+        if sampler.sampler_config().enabled() {
+            sampler.register();
+        }
+
+        Ok(sampler)
+    }
+
+    fn spawn(common: Common) {
+        if common.config().samplers().xdp().enabled() {
+            if let Ok(mut sampler) = Self::new(common.clone()) {
+                common.handle.spawn(async move {
+                    sampler.run().await;
+                });
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().xdp())
+            {
+                fatal!("failed to initialize xdp sampler");
+            } else {
+                common.init_failed();
+                error!("failed to initialize xdp sampler");
+            }
+        }
+    }
+
+    fn common(&self) -> &Common {
+        &self.common
+    }
+
+    fn common_mut(&mut self) -> &mut Common {
+        &mut self.common
+    }
+
+    fn sampler_config(&self) -> &dyn SamplerConfig<Statistic = Self::Statistic> {
+        self.common.config().samplers().xdp()
+    }
+
+    async fn sample(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+
+        debug!("sampling");
+
+        // sample bpf
+        #[cfg(feature = "bpf")]
+        self.retry_bpf();
+        #[cfg(feature = "bpf")]
+        {
+            let r = self.sample_bpf();
+            self.map_result(r)?;
+        }
+
+        Ok(())
+    }
+
+    // checks that bpf is enabled in config and one or more stats enabled
+    #[cfg(feature = "bpf")]
+    fn bpf_enabled(&self) -> bool {
+        self.sampler_config().bpf() && !self.statistics.is_empty()
+    }
+
+    // retrying bpf initialization while it is not loaded also picks up xdp
+    // programs which were attached after rezolus started
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    // the map is unavailable if bpf failed to initialize
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        vec![self.common.config().samplers().xdp().map()]
+    }
+
+    fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
+        #[cfg(feature = "bpf")]
+        {
+            if self.enabled() && self.bpf_enabled() {
+                let config = self.common.config().samplers().xdp();
+                let maps = pinned_maps(config.pin_path(), config.map(), config.interfaces());
+                // interfaces without an xdp program have no map pinned
+                if maps.is_empty() {
+                    info!("no pinned xdp maps found, skipping bpf initialization");
+                    return Ok(());
+                }
+                debug!("initializing bpf");
+                let code = bpf_code(include_str!("bpf.c"), &maps);
+                let bpf = bcc::BPF::new(&code)?;
+                debug!("opened pinned xdp maps for {} interfaces", maps.len());
+
+                self.maps = maps
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (interface, _))| (interface, table_name(index)))
+                    .collect();
+                self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Xdp {
+    #[cfg(feature = "bpf")]
+    fn sample_bpf(&mut self) -> Result<(), std::io::Error> {
+        let mut readings = Vec::new();
+        if let Some(ref bpf) = self.bpf {
+            let bpf = bpf.lock().unwrap();
+            for (interface, table) in &self.maps {
+                // the counters belong to the xdp program, so they are read
+                // without being cleared
+                if let Ok(table) = (*bpf).inner.table(table) {
+                    readings.push((interface.clone(), perf_table_to_map(&table)));
+                } else {
+                    self.common.bpf_table_error("xdp", interface);
+                }
+            }
+        }
+        self.record_readings(readings);
+        Ok(())
+    }
+
+    /// Record the count of each action for each interface, and the totals
+    /// across interfaces
+    fn record_readings(&mut self, readings: Vec<(String, HashMap<u32, u64>)>) {
+        let time = self.common.now();
+        let mut totals = HashMap::new();
+        for (interface, counts) in &readings {
+            for statistic in &self.statistics {
+                if let Some(count) = counts.get(&statistic.action()) {
+                    *totals.entry(*statistic).or_insert(0) += count;
+                    let statistic = LabeledStatistic::new(statistic, "interface", interface);
+                    self.common.register_once(&statistic);
+                    self.common.record_counter(&statistic, time, *count);
+                }
+            }
+        }
+        // xdp programs may be attached and detached between samples, the
+        // counter tracking treats the resulting decrease as a reset
+        for statistic in &self.statistics {
+            if let Some(total) = totals.get(statistic) {
+                self.record_counter(statistic, time, *total);
+            }
+        }
+    }
+}
+
+/// Finds the stats map pinned for each interface, as pairs of interface and
+/// path. Interfaces without a map, which have no xdp program attached, are
+/// skipped.
+#[cfg(feature = "bpf")]
+fn pinned_maps(pin_path: &str, map: &str, interfaces: &[String]) -> Vec<(String, String)> {
+    let interfaces: Vec<String> = if interfaces.is_empty() {
+        match std::fs::read_dir(pin_path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect(),
+            Err(e) => {
+                debug!("failed to read {}: {}", pin_path, e);
+                return Vec::new();
+            }
+        }
+    } else {
+        interfaces.to_vec()
+    };
+    let mut maps = Vec::new();
+    for interface in interfaces {
+        let path = std::path::Path::new(pin_path).join(&interface).join(map);
+        if path.exists() {
+            maps.push((interface, path.to_string_lossy().to_string()));
+        } else {
+            debug!("no xdp map pinned for {}", interface);
+        }
+    }
+    maps.sort();
+    maps
+}
+
+/// The name of the table the `index`th pinned map is declared as. Interface
+/// names are not always valid identifiers, so they are not used
+#[allow(dead_code)]
+fn table_name(index: usize) -> String {
+    format!("xdp_{}", index)
+}
+
+/// Appends a declaration of each pinned map to the program
+#[allow(dead_code)]
+fn bpf_code(template: &str, maps: &[(String, String)]) -> String {
+    let mut code = template.to_string();
+    for (index, (_, path)) in maps.iter().enumerate() {
+        code.push_str(&format!(
+            "BPF_TABLE_PINNED(\"array\", u32, u64, {}, XDP_ACTIONS, \"{}\");\n",
+            table_name(index),
+            path
+        ));
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::proc::Fixtures;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn test_bpf_code() {
+        let maps = vec![
+            (
+                "eth0".to_string(),
+                "/sys/fs/bpf/eth0/xdp_stats_map".to_string(),
+            ),
+            (
+                "veth-1".to_string(),
+                "/sys/fs/bpf/veth-1/xdp_stats_map".to_string(),
+            ),
+        ];
+        let code = bpf_code("", &maps);
+        assert_eq!(
+            code,
+            "BPF_TABLE_PINNED(\"array\", u32, u64, xdp_0, XDP_ACTIONS, \"/sys/fs/bpf/eth0/xdp_stats_map\");\n\
+             BPF_TABLE_PINNED(\"array\", u32, u64, xdp_1, XDP_ACTIONS, \"/sys/fs/bpf/veth-1/xdp_stats_map\");\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_readings() {
+        let mut sampler = Xdp::new(Common::with_fixtures(Fixtures::default())).unwrap();
+        sampler.statistics = XdpStatistic::iter().collect();
+        sampler.register();
+
+        let eth0: HashMap<u32, u64> = vec![(1, 10), (2, 1000)].into_iter().collect();
+        let eth1: HashMap<u32, u64> = vec![(1, 5), (2, 500), (4, 7)].into_iter().collect();
+        sampler.record_readings(vec![("eth0".to_string(), eth0), ("eth1".to_string(), eth1)]);
+
+        assert_eq!(sampler.common().reading("xdp/drop"), Some(15));
+        assert_eq!(sampler.common().reading("xdp/pass"), Some(1500));
+        assert_eq!(sampler.common().reading("xdp/redirect"), Some(7));
+        assert_eq!(sampler.common().reading("xdp/drop/eth0"), Some(10));
+        assert_eq!(sampler.common().reading("xdp/pass/eth1"), Some(500));
+        assert_eq!(sampler.common().reading("xdp/redirect/eth0"), None);
+    }
+}
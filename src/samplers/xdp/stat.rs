@@ -0,0 +1,84 @@
+// Copyright 2019-2020 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::convert::TryFrom;
+use core::str::FromStr;
+
+use crate::common::unit::{StatisticUnit, Unit};
+use rustcommon_metrics::*;
+use serde_derive::{Deserialize, Serialize};
+use strum::ParseError;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// The discriminant is the `enum xdp_action` value which indexes the counter
+/// in the XDP program's stats map
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Eq,
+    IntoStaticStr,
+    PartialEq,
+    Hash,
+    Serialize,
+)]
+#[serde(deny_unknown_fields, try_from = "&str", into = "&str")]
+pub enum XdpStatistic {
+    #[strum(serialize = "xdp/aborted")]
+    Aborted = 0,
+    #[strum(serialize = "xdp/drop")]
+    Drop = 1,
+    #[strum(serialize = "xdp/pass")]
+    Pass = 2,
+    #[strum(serialize = "xdp/tx")]
+    Tx = 3,
+    #[strum(serialize = "xdp/redirect")]
+    Redirect = 4,
+}
+
+impl XdpStatistic {
+    /// The key of the statistic in the stats map
+    pub fn action(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Statistic<AtomicU64, AtomicU32> for XdpStatistic {
+    fn name(&self) -> &str {
+        (*self).into()
+    }
+
+    fn source(&self) -> Source {
+        Source::Counter
+    }
+}
+
+impl StatisticUnit for XdpStatistic {
+    fn unit(&self) -> Option<Unit> {
+        Some(Unit::Count)
+    }
+}
+
+impl TryFrom<&str> for XdpStatistic {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        XdpStatistic::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_action() {
+        assert_eq!(XdpStatistic::Aborted.action(), 0);
+        assert_eq!(XdpStatistic::Drop.action(), 1);
+        assert_eq!(XdpStatistic::Redirect.action(), 4);
+    }
+}
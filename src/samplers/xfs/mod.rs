@@ -18,6 +18,23 @@ mod stat;
 pub use config::*;
 pub use stat::*;
 
+/// Pairs of bpf handler and the kernel function it is attached to
+#[cfg(feature = "bpf")]
+const KPROBES: &[(&str, &str)] = &[
+    ("trace_entry", "xfs_file_read_iter"),
+    ("trace_entry", "xfs_file_write_iter"),
+    ("trace_entry", "xfs_file_open"),
+    ("trace_entry", "xfs_file_fsync"),
+];
+
+#[cfg(feature = "bpf")]
+const KRETPROBES: &[(&str, &str)] = &[
+    ("trace_read_return", "xfs_file_read_iter"),
+    ("trace_write_return", "xfs_file_write_iter"),
+    ("trace_open_return", "xfs_file_open"),
+    ("trace_fsync_return", "xfs_file_fsync"),
+];
+
 #[allow(dead_code)]
 pub struct Xfs {
     bpf: Option<Arc<Mutex<BPF>>>,
@@ -30,22 +47,17 @@ pub struct Xfs {
 impl Sampler for Xfs {
     type Statistic = XfsStatistic;
     fn new(common: Common) -> Result<Self, anyhow::Error> {
-        let fault_tolerant = common.config.general().fault_tolerant();
         let statistics = common.config().samplers().xfs().statistics();
 
         #[allow(unused_mut)]
         let mut sampler = Self {
             bpf: None,
-            bpf_last: Arc::new(Mutex::new(Instant::now())),
+            bpf_last: Arc::new(Mutex::new(common.now())),
             common,
             statistics,
         };
 
-        if let Err(e) = sampler.initialize_bpf() {
-            if !fault_tolerant {
-                return Err(e);
-            }
-        }
+        sampler.initialize_bpf_tolerant()?;
 
         if sampler.sampler_config().enabled() {
             sampler.register();
@@ -58,13 +70,15 @@ impl Sampler for Xfs {
         if common.config().samplers().xfs().enabled() {
             if let Ok(mut sampler) = Self::new(common.clone()) {
                 common.handle.spawn(async move {
-                    loop {
-                        let _ = sampler.sample().await;
-                    }
+                    sampler.run().await;
                 });
-            } else if !common.config.fault_tolerant() {
+            } else if !common
+                .config()
+                .sampler_fault_tolerant(common.config().samplers().xfs())
+            {
                 fatal!("failed to initialize xfs sampler");
             } else {
+                common.init_failed();
                 error!("failed to initialize xfs sampler");
             }
         }
@@ -83,10 +97,6 @@ impl Sampler for Xfs {
     }
 
     async fn sample(&mut self) -> Result<(), std::io::Error> {
-        if let Some(ref mut delay) = self.delay() {
-            delay.tick().await;
-        }
-
         if !self.sampler_config().enabled() {
             return Ok(());
         }
@@ -95,13 +105,26 @@ impl Sampler for Xfs {
 
         // sample bpf
         #[cfg(feature = "bpf")]
+        self.retry_bpf();
+        #[cfg(feature = "bpf")]
         self.map_result(self.sample_bpf())?;
 
         Ok(())
     }
-}
 
-impl Xfs {
+    fn drains_bpf(&self) -> bool {
+        cfg!(feature = "bpf")
+    }
+
+    fn poll_bpf(&mut self) -> Result<(), std::io::Error> {
+        if !self.sampler_config().enabled() {
+            return Ok(());
+        }
+        #[cfg(feature = "bpf")]
+        self.map_result(self.sample_bpf())?;
+        Ok(())
+    }
+
     // checks that bpf is enabled in config and one or more bpf stats enabled
     #[cfg(feature = "bpf")]
     fn bpf_enabled(&self) -> bool {
@@ -115,80 +138,104 @@ impl Xfs {
         false
     }
 
+    #[cfg(feature = "bpf")]
+    fn bpf_loaded(&self) -> bool {
+        self.bpf.is_some()
+    }
+
+    #[cfg(feature = "bpf")]
+    fn bpf_tables(&self) -> Vec<&str> {
+        self.statistics
+            .iter()
+            .filter_map(|s| s.bpf_table())
+            .collect()
+    }
+
     fn initialize_bpf(&mut self) -> Result<(), anyhow::Error> {
         #[cfg(feature = "bpf")]
         {
+            self.common
+                .retain_supported(&mut self.statistics, |s| s.min_kernel());
             if self.enabled() && self.bpf_enabled() {
+                // there is nothing to trace on hosts without xfs
+                if !filesystem_available("xfs") {
+                    info!("xfs is not available, skipping bpf initialization");
+                    return Ok(());
+                }
                 debug!("initializing bpf");
 
                 // load the code and compile
                 let code = include_str!("bpf.c");
                 let mut bpf = bcc::BPF::new(code)?;
 
-                // load + attach kprobes!
-                bcc::Kprobe::new()
-                    .handler("trace_entry")
-                    .function("xfs_file_read_iter")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_entry")
-                    .function("xfs_file_write_iter")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_entry")
-                    .function("xfs_file_open")
-                    .attach(&mut bpf)?;
-                bcc::Kprobe::new()
-                    .handler("trace_entry")
-                    .function("xfs_file_fsync")
-                    .attach(&mut bpf)?;
-                bcc::Kretprobe::new()
-                    .handler("trace_read_return")
-                    .function("xfs_file_read_iter")
-                    .attach(&mut bpf)?;
-                bcc::Kretprobe::new()
-                    .handler("trace_write_return")
-                    .function("xfs_file_write_iter")
-                    .attach(&mut bpf)?;
-                bcc::Kretprobe::new()
-                    .handler("trace_open_return")
-                    .function("xfs_file_open")
-                    .attach(&mut bpf)?;
-                bcc::Kretprobe::new()
-                    .handler("trace_fsync_return")
-                    .function("xfs_file_fsync")
-                    .attach(&mut bpf)?;
+                // load + attach kprobes! each probe is attached independently
+                // so that a kernel missing one function still gets the others
+                let mut attached = 0;
+                for (handler, function) in KPROBES {
+                    match bcc::Kprobe::new()
+                        .handler(handler)
+                        .function(function)
+                        .attach(&mut bpf)
+                    {
+                        Ok(()) => attached += 1,
+                        Err(e) => warn!("failed to attach kprobe {}: {}", function, e),
+                    }
+                }
+                for (handler, function) in KRETPROBES {
+                    match bcc::Kretprobe::new()
+                        .handler(handler)
+                        .function(function)
+                        .attach(&mut bpf)
+                    {
+                        Ok(()) => attached += 1,
+                        Err(e) => warn!("failed to attach kretprobe {}: {}", function, e),
+                    }
+                }
+                if attached == 0 {
+                    return Err(format_err!("failed to attach any xfs probes"));
+                }
+                debug!(
+                    "attached {} of {} xfs probes",
+                    attached,
+                    KPROBES.len() + KRETPROBES.len()
+                );
+
                 self.bpf = Some(Arc::new(Mutex::new(BPF { inner: bpf })));
             }
         }
 
         Ok(())
     }
+}
 
+impl Xfs {
     #[cfg(feature = "bpf")]
     fn sample_bpf(&self) -> Result<(), std::io::Error> {
-        if self.bpf_last.lock().unwrap().elapsed()
-            >= Duration::new(self.general_config().window() as u64, 0)
-        {
+        if self.bpf_drain_due(&self.bpf_last) {
             if let Some(ref bpf) = self.bpf {
                 let bpf = bpf.lock().unwrap();
-                let time = Instant::now();
+                let time = self.common.now();
                 for statistic in self.statistics.iter().filter(|s| s.bpf_table().is_some()) {
                     if let Ok(mut table) = (*bpf).inner.table(statistic.bpf_table().unwrap()) {
-                        for (&value, &count) in &map_from_table(&mut table) {
+                        let table =
+                            self.drain_histogram(statistic.bpf_table().unwrap(), &mut table);
+                        for (&value, &count) in &table {
                             if count > 0 {
-                                let _ = self.metrics().record_bucket(
+                                self.record_bucket(
                                     statistic,
                                     time,
-                                    value * crate::MICROSECOND,
+                                    value * statistic.bucket_scale(),
                                     count,
                                 );
                             }
                         }
+                    } else {
+                        self.common
+                            .bpf_table_error("xfs", statistic.bpf_table().unwrap());
                     }
                 }
             }
-            *self.bpf_last.lock().unwrap() = Instant::now();
+            *self.bpf_last.lock().unwrap() = self.common.now();
         }
         Ok(())
     }
@@ -5,6 +5,9 @@
 use core::convert::TryFrom;
 use core::str::FromStr;
 
+use crate::common::kernel::{KernelVersion, KPROBES};
+use crate::common::unit::StatisticUnit;
+use crate::common::MICROSECOND;
 use rustcommon_metrics::*;
 use serde_derive::{Deserialize, Serialize};
 use strum::ParseError;
@@ -45,6 +48,24 @@ impl XfsStatistic {
             Self::FsyncLatency => Some("fsync"),
         }
     }
+
+    /// The oldest kernel able to trace the statistic, which uses kprobes on
+    /// the xfs file operations
+    #[allow(dead_code)]
+    pub fn min_kernel(self) -> Option<KernelVersion> {
+        self.bpf_table().map(|_| KPROBES)
+    }
+
+    /// Multiplier which converts the BPF histogram buckets into the units of
+    /// the statistic
+    #[allow(dead_code)]
+    pub fn bucket_scale(self) -> u64 {
+        match self {
+            Self::ReadLatency | Self::WriteLatency | Self::OpenLatency | Self::FsyncLatency => {
+                MICROSECOND
+            }
+        }
+    }
 }
 
 impl Statistic<AtomicU64, AtomicU32> for XfsStatistic {
@@ -57,6 +78,8 @@ impl Statistic<AtomicU64, AtomicU32> for XfsStatistic {
     }
 }
 
+impl StatisticUnit for XfsStatistic {}
+
 impl TryFrom<&str> for XfsStatistic {
     type Error = ParseError;
 